@@ -0,0 +1,8 @@
+#!/bin/sh
+# Builds the feature combinations that have to keep compiling: the core
+# on its own for library users, and everything
+set -e
+cargo build --no-default-features --features core
+cargo build --all-features
+cargo clippy --all-targets --all-features -- -D warnings
+cargo test --all-features
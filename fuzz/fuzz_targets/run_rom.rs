@@ -0,0 +1,50 @@
+//! Runs arbitrary bytes as a ROM, with quirks and keys picked from a hash
+//! of them so a crash always reproduces from its input
+#![no_main]
+use chip8::emu::{self, Chip8, Fault, Quirks, StepOutcome};
+use libfuzzer_sys::fuzz_target;
+
+const STEPS: u32 = 10_000;
+
+// Steps between keypad changes
+const KEY_EVERY: u32 = 8;
+
+fuzz_target!(|rom: &[u8]| {
+    let hash = chip8::state::rom_hash(rom);
+    let quirks = Quirks {
+        wrap_tex: hash & 1 != 0,
+        hp_shift: hash & 2 != 0,
+        mem_inc: hash & 4 != 0,
+        key_release: hash & 8 != 0,
+    };
+    // Too big for RAM is fine, it just isn't interesting
+    let mut c8 = match Chip8::builder()
+        .rom(rom)
+        .quirks(quirks)
+        .seed(hash)
+        .clock(emu::step_clock(600))
+        .validate(true)
+        .build()
+    {
+        Ok(c8) => c8,
+        Err(_) => return,
+    };
+    // xorshift, so the keys are the same on every run
+    let mut keys = hash | 1;
+    for n in 0..STEPS {
+        if n.is_multiple_of(KEY_EVERY) {
+            keys ^= keys << 13;
+            keys ^= keys >> 7;
+            keys ^= keys << 17;
+            c8.set_keys(keys as u16);
+        }
+        match c8.step() {
+            Ok(StepOutcome::Ran) | Ok(StepOutcome::WaitingForKey) => {}
+            Ok(StepOutcome::Halted) => break,
+            // Only reached with debug assertions off, otherwise it panics
+            Err(f @ Fault::Invariant { .. }) => panic!("{}", f),
+            // Bad ROMs are meant to fault
+            Err(_) => break,
+        }
+    }
+});
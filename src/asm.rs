@@ -0,0 +1,464 @@
+//! Programs built up an instruction at a time, for tests that would
+//! otherwise be hand assembled bytes. Jumps, calls and `LD I` can name a
+//! label instead of an address, worked out once everything has been
+//! added so a label can be used before it's placed.
+//!
+//! There's a method for every instruction the emulator runs, each going
+//! through the same encoding it decodes, so an instruction added to the
+//! emulator needs one here too.
+use crate::emu::{Addr, Instruction, VReg};
+use crate::layout::PROGRAM_START;
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AsmError {
+    UndefinedLabel(String),
+    DuplicateLabel(String),
+    /// What the operand was for, its value and the most it can be
+    OutOfRange {
+        what: &'static str,
+        value: u16,
+        max: u16,
+    },
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::UndefinedLabel(l) => write!(f, "label {} is never defined", l),
+            Self::DuplicateLabel(l) => write!(f, "label {} is defined twice", l),
+            Self::OutOfRange { what, value, max } => {
+                write!(f, "{} {:#X} is over {:#X}", what, value, max)
+            }
+        }
+    }
+}
+
+impl std::error::Error for AsmError {}
+
+/// The bytes of a built program and where its labels ended up
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Assembled {
+    pub bytes: Vec<u8>,
+    /// Labels and their addresses, in the order they were defined
+    pub symbols: Vec<(String, u16)>,
+}
+
+impl Assembled {
+    pub fn symbol(&self, label: &str) -> Option<u16> {
+        self.symbols
+            .iter()
+            .find(|(l, _)| l == label)
+            .map(|&(_, a)| a)
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Item {
+    Op(Instruction),
+    /// An instruction taking the address of a label
+    Ref(fn(Addr) -> Instruction, String),
+    Data(Vec<u8>),
+}
+
+impl Item {
+    fn len(&self) -> usize {
+        match self {
+            Self::Op(_) | Self::Ref(..) => 2,
+            Self::Data(d) => d.len(),
+        }
+    }
+}
+
+/// A program to be loaded at 0x200 unless `origin` says otherwise
+///
+/// ```
+/// use chip8::asm::{AsmError, RomBuilder};
+/// use chip8::emu::disassemble;
+///
+/// let rom = RomBuilder::new()
+///     .ld_v(3, 0x1F)
+///     .ld_i_label("sprite")
+///     .label("loop")
+///     .drw(0, 1, 2)
+///     .call_label("sub")
+///     .jp_label("loop")
+///     .label("sub")
+///     .ret()
+///     .label("sprite")
+///     .data(&[0xFF, 0x81])
+///     .build()
+///     .unwrap();
+/// let ops: Vec<String> = rom.bytes[..12]
+///     .chunks(2)
+///     .map(|w| disassemble(u16::from_be_bytes([w[0], w[1]])))
+///     .collect();
+/// assert_eq!(ops, ["LD V3, 1F", "LD I, 20C", "DRW V0, V1, 2", "CALL 20A", "JP 204", "RET"]);
+/// assert_eq!(rom.bytes[12..], [0xFF, 0x81]);
+/// assert_eq!(rom.symbol("loop"), Some(0x204));
+/// assert_eq!(rom.symbols.len(), 3);
+///
+/// // Mistakes only come out when it's built, the first one added first
+/// let err = |b: RomBuilder| b.build().unwrap_err();
+/// assert_eq!(err(RomBuilder::new().jp_label("nowhere")), AsmError::UndefinedLabel("nowhere".into()));
+/// assert_eq!(err(RomBuilder::new().label("a").cls().label("a")), AsmError::DuplicateLabel("a".into()));
+/// assert_eq!(err(RomBuilder::new().drw(0, 16, 5).jp(0x1000)).to_string(), "register 0x10 is over 0xF");
+/// assert_eq!(err(RomBuilder::new().drw(0, 1, 16)).to_string(), "sprite height 0x10 is over 0xF");
+/// // Labels have to land where a 12-bit address can reach
+/// let far = RomBuilder::new().origin(0xFFE).jp_label("end").label("end");
+/// assert_eq!(err(far).to_string(), "address 0x1000 is over 0xFFF");
+/// ```
+#[derive(Debug, Clone)]
+pub struct RomBuilder {
+    origin: u16,
+    items: Vec<Item>,
+    labels: Vec<(String, u16)>,
+    // Where the next item goes
+    at: usize,
+    error: Option<AsmError>,
+}
+
+impl Default for RomBuilder {
+    fn default() -> Self {
+        Self {
+            origin: PROGRAM_START,
+            items: Vec::new(),
+            labels: Vec::new(),
+            at: PROGRAM_START as usize,
+            error: None,
+        }
+    }
+}
+
+impl RomBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Where the program will be loaded, which labels count from. Set it
+    /// before adding anything.
+    pub fn origin(mut self, addr: u16) -> Self {
+        self.at = self.at - self.origin as usize + addr as usize;
+        self.origin = addr;
+        self
+    }
+
+    /// Names the address the next item goes at
+    pub fn label(mut self, name: &str) -> Self {
+        if self.labels.iter().any(|(l, _)| l == name) {
+            self.fail(AsmError::DuplicateLabel(name.to_string()));
+        } else {
+            self.labels.push((name.to_string(), self.at as u16));
+        }
+        self
+    }
+
+    /// Bytes as they are, such as a sprite
+    pub fn data(self, bytes: &[u8]) -> Self {
+        self.item(Item::Data(bytes.to_vec()))
+    }
+
+    /// The program and its labels, or the first mistake made building it
+    pub fn build(self) -> Result<Assembled, AsmError> {
+        if let Some(e) = self.error {
+            return Err(e);
+        }
+        let mut bytes = Vec::with_capacity(self.at - self.origin as usize);
+        for item in &self.items {
+            let op = match item {
+                Item::Op(i) => i.encode(),
+                Item::Ref(make, label) => {
+                    let addr = self
+                        .labels
+                        .iter()
+                        .find(|(l, _)| l == label)
+                        .map(|&(_, a)| a)
+                        .ok_or_else(|| AsmError::UndefinedLabel(label.clone()))?;
+                    make(Addr(check("address", addr, 0xFFF)?)).encode()
+                }
+                Item::Data(d) => {
+                    bytes.extend_from_slice(d);
+                    continue;
+                }
+            };
+            bytes.extend_from_slice(&op.to_be_bytes());
+        }
+        Ok(Assembled {
+            bytes,
+            symbols: self.labels,
+        })
+    }
+
+    fn fail(&mut self, e: AsmError) {
+        self.error.get_or_insert(e);
+    }
+
+    fn item(mut self, item: Item) -> Self {
+        self.at += item.len();
+        // Kept to 16 bits, well out of range for any label placed there
+        self.at = self.at.min(0xFFFF);
+        self.items.push(item);
+        self
+    }
+
+    fn op(self, i: Instruction) -> Self {
+        self.item(Item::Op(i))
+    }
+
+    fn reference(self, make: fn(Addr) -> Instruction, label: &str) -> Self {
+        self.item(Item::Ref(make, label.to_string()))
+    }
+
+    fn reg(&mut self, x: u8) -> VReg {
+        self.checked("register", x as u16, 0xF);
+        VReg(x)
+    }
+
+    fn addr(&mut self, a: u16) -> Addr {
+        self.checked("address", a, 0xFFF);
+        Addr(a)
+    }
+
+    fn checked(&mut self, what: &'static str, value: u16, max: u16) {
+        if let Err(e) = check(what, value, max) {
+            self.fail(e);
+        }
+    }
+
+    /// SYS addr
+    pub fn sys(mut self, addr: u16) -> Self {
+        let a = self.addr(addr);
+        self.op(Instruction::SYS(a))
+    }
+
+    /// CLS
+    pub fn cls(self) -> Self {
+        self.op(Instruction::CLS)
+    }
+
+    /// RET
+    pub fn ret(self) -> Self {
+        self.op(Instruction::RET)
+    }
+
+    /// JP addr
+    pub fn jp(mut self, addr: u16) -> Self {
+        let a = self.addr(addr);
+        self.op(Instruction::JP(a))
+    }
+
+    /// JP to a label
+    pub fn jp_label(self, label: &str) -> Self {
+        self.reference(Instruction::JP, label)
+    }
+
+    /// CALL addr
+    pub fn call(mut self, addr: u16) -> Self {
+        let a = self.addr(addr);
+        self.op(Instruction::CALL(a))
+    }
+
+    /// CALL a label
+    pub fn call_label(self, label: &str) -> Self {
+        self.reference(Instruction::CALL, label)
+    }
+
+    /// SE Vx, byte
+    pub fn se(mut self, x: u8, kk: u8) -> Self {
+        let x = self.reg(x);
+        self.op(Instruction::SEB(x, kk))
+    }
+
+    /// SNE Vx, byte
+    pub fn sne(mut self, x: u8, kk: u8) -> Self {
+        let x = self.reg(x);
+        self.op(Instruction::SNEB(x, kk))
+    }
+
+    /// SE Vx, Vy
+    pub fn se_vy(mut self, x: u8, y: u8) -> Self {
+        let (x, y) = (self.reg(x), self.reg(y));
+        self.op(Instruction::SEV(x, y))
+    }
+
+    /// LD Vx, byte
+    pub fn ld_v(mut self, x: u8, kk: u8) -> Self {
+        let x = self.reg(x);
+        self.op(Instruction::LDB(x, kk))
+    }
+
+    /// ADD Vx, byte
+    pub fn add(mut self, x: u8, kk: u8) -> Self {
+        let x = self.reg(x);
+        self.op(Instruction::ADDB(x, kk))
+    }
+
+    /// LD Vx, Vy
+    pub fn ld_vy(mut self, x: u8, y: u8) -> Self {
+        let (x, y) = (self.reg(x), self.reg(y));
+        self.op(Instruction::LDV(x, y))
+    }
+
+    /// OR Vx, Vy
+    pub fn or(mut self, x: u8, y: u8) -> Self {
+        let (x, y) = (self.reg(x), self.reg(y));
+        self.op(Instruction::OR(x, y))
+    }
+
+    /// AND Vx, Vy
+    pub fn and(mut self, x: u8, y: u8) -> Self {
+        let (x, y) = (self.reg(x), self.reg(y));
+        self.op(Instruction::AND(x, y))
+    }
+
+    /// XOR Vx, Vy
+    pub fn xor(mut self, x: u8, y: u8) -> Self {
+        let (x, y) = (self.reg(x), self.reg(y));
+        self.op(Instruction::XOR(x, y))
+    }
+
+    /// ADD Vx, Vy
+    pub fn add_vy(mut self, x: u8, y: u8) -> Self {
+        let (x, y) = (self.reg(x), self.reg(y));
+        self.op(Instruction::ADDC(x, y))
+    }
+
+    /// SUB Vx, Vy
+    pub fn sub(mut self, x: u8, y: u8) -> Self {
+        let (x, y) = (self.reg(x), self.reg(y));
+        self.op(Instruction::SUB(x, y))
+    }
+
+    /// SHR Vx, Vy
+    pub fn shr(mut self, x: u8, y: u8) -> Self {
+        let (x, y) = (self.reg(x), self.reg(y));
+        self.op(Instruction::SHR(x, y))
+    }
+
+    /// SUBN Vx, Vy
+    pub fn subn(mut self, x: u8, y: u8) -> Self {
+        let (x, y) = (self.reg(x), self.reg(y));
+        self.op(Instruction::SUBN(x, y))
+    }
+
+    /// SHL Vx, Vy
+    pub fn shl(mut self, x: u8, y: u8) -> Self {
+        let (x, y) = (self.reg(x), self.reg(y));
+        self.op(Instruction::SHL(x, y))
+    }
+
+    /// SNE Vx, Vy
+    pub fn sne_vy(mut self, x: u8, y: u8) -> Self {
+        let (x, y) = (self.reg(x), self.reg(y));
+        self.op(Instruction::SNEV(x, y))
+    }
+
+    /// LD I, addr
+    pub fn ld_i(mut self, addr: u16) -> Self {
+        let a = self.addr(addr);
+        self.op(Instruction::LDI(a))
+    }
+
+    /// LD I with a label
+    pub fn ld_i_label(self, label: &str) -> Self {
+        self.reference(Instruction::LDI, label)
+    }
+
+    /// JP V0, addr
+    pub fn jp_v0(mut self, addr: u16) -> Self {
+        let a = self.addr(addr);
+        self.op(Instruction::JPV(a))
+    }
+
+    /// JP V0 plus a label
+    pub fn jp_v0_label(self, label: &str) -> Self {
+        self.reference(Instruction::JPV, label)
+    }
+
+    /// RND Vx, byte
+    pub fn rnd(mut self, x: u8, kk: u8) -> Self {
+        let x = self.reg(x);
+        self.op(Instruction::RND(x, kk))
+    }
+
+    /// DRW Vx, Vy, nibble
+    pub fn drw(mut self, x: u8, y: u8, n: u8) -> Self {
+        let (x, y) = (self.reg(x), self.reg(y));
+        self.checked("sprite height", n as u16, 0xF);
+        self.op(Instruction::DRW(x, y, n))
+    }
+
+    /// SKP Vx
+    pub fn skp(mut self, x: u8) -> Self {
+        let x = self.reg(x);
+        self.op(Instruction::SKP(x))
+    }
+
+    /// SKNP Vx
+    pub fn sknp(mut self, x: u8) -> Self {
+        let x = self.reg(x);
+        self.op(Instruction::SKNP(x))
+    }
+
+    /// LD Vx, DT
+    pub fn ld_v_dt(mut self, x: u8) -> Self {
+        let x = self.reg(x);
+        self.op(Instruction::LDVD(x))
+    }
+
+    /// LD Vx, K
+    pub fn ld_v_k(mut self, x: u8) -> Self {
+        let x = self.reg(x);
+        self.op(Instruction::LDK(x))
+    }
+
+    /// LD DT, Vx
+    pub fn ld_dt(mut self, x: u8) -> Self {
+        let x = self.reg(x);
+        self.op(Instruction::LDDV(x))
+    }
+
+    /// LD ST, Vx
+    pub fn ld_st(mut self, x: u8) -> Self {
+        let x = self.reg(x);
+        self.op(Instruction::LDSV(x))
+    }
+
+    /// ADD I, Vx
+    pub fn add_i(mut self, x: u8) -> Self {
+        let x = self.reg(x);
+        self.op(Instruction::ADDI(x))
+    }
+
+    /// LD F, Vx
+    pub fn ld_f(mut self, x: u8) -> Self {
+        let x = self.reg(x);
+        self.op(Instruction::LDIS(x))
+    }
+
+    /// LD B, Vx
+    pub fn ld_b(mut self, x: u8) -> Self {
+        let x = self.reg(x);
+        self.op(Instruction::LDD(x))
+    }
+
+    /// LD [I], Vx
+    pub fn store(mut self, x: u8) -> Self {
+        let x = self.reg(x);
+        self.op(Instruction::LDMV(x))
+    }
+
+    /// LD Vx, [I]
+    pub fn load(mut self, x: u8) -> Self {
+        let x = self.reg(x);
+        self.op(Instruction::LDVM(x))
+    }
+}
+
+fn check(what: &'static str, value: u16, max: u16) -> Result<u16, AsmError> {
+    if value > max {
+        Err(AsmError::OutOfRange { what, value, max })
+    } else {
+        Ok(value)
+    }
+}
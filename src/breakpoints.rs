@@ -0,0 +1,182 @@
+//! Addresses to stop at, for running to a line of a listing. A breakpoint
+//! is either kept until it's removed, or temporary, and gone the next
+//! time a run stops whether or not it was reached.
+use crate::emu::{Chip8, Fault, StepOutcome};
+
+/// Instructions `Breakpoints::run_to` gives up after unless told otherwise
+pub const DEFAULT_LIMIT: u32 = 100_000;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Breakpoint {
+    pub addr: u16,
+    /// Removed when the run it was set for stops
+    pub temporary: bool,
+}
+
+/// Why a run stopped, with the instructions it ran
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Stop {
+    /// PC reached the breakpoint at this address
+    Breakpoint { addr: u16, steps: u32 },
+    /// Ran the limit without reaching one
+    Limit { steps: u32 },
+    /// The program halted or a hook paused it, as the step said
+    Outcome { outcome: StepOutcome, steps: u32 },
+    /// Stepping back, the journal ran out
+    Start { steps: u32 },
+}
+
+impl Stop {
+    pub fn steps(self) -> u32 {
+        match self {
+            Self::Breakpoint { steps, .. }
+            | Self::Limit { steps }
+            | Self::Outcome { steps, .. }
+            | Self::Start { steps } => steps,
+        }
+    }
+}
+
+/// Breakpoints in the order they were set, one per address
+///
+/// ```
+/// use chip8::breakpoints::{Breakpoints, Stop};
+/// use chip8::emu::Chip8;
+///
+/// let rom = [
+///     0x60, 0x00, // 200: LD V0, 00
+///     0x70, 0x01, // 202: ADD V0, 01
+///     0x30, 0x05, // 204: SE V0, 05
+///     0x12, 0x02, // 206: JP 202
+///     0x12, 0x08, // 208: JP 208
+/// ];
+/// let mut c8 = Chip8::builder().rom(&rom).build().unwrap();
+/// let mut bps = Breakpoints::new();
+///
+/// // Hit, and gone after
+/// assert_eq!(bps.run_to(&mut c8, 0x204, 100, Chip8::step), Ok(Stop::Breakpoint { addr: 0x204, steps: 2 }));
+/// assert_eq!((c8.pc(), c8.v()[0], bps.is_empty()), (0x204, 1, true));
+///
+/// // Missed within the limit, and gone all the same
+/// assert_eq!(bps.run_to(&mut c8, 0x208, 5, Chip8::step), Ok(Stop::Limit { steps: 5 }));
+/// assert!(bps.is_empty());
+///
+/// // Already a breakpoint there, which stays
+/// bps.add(0x206);
+/// assert_eq!(bps.run_to(&mut c8, 0x206, 100, Chip8::step).map(|s| s.steps()), Ok(2));
+/// assert!(bps.contains(0x206) && !bps.iter().next().unwrap().temporary);
+/// // and stops the next run short of where it's going
+/// assert_eq!(bps.run_to(&mut c8, 0x208, 100, Chip8::step), Ok(Stop::Breakpoint { addr: 0x206, steps: 3 }));
+/// assert_eq!(bps.len(), 1);
+/// bps.remove(0x206);
+/// assert_eq!(bps.run_to(&mut c8, 0x208, 100, Chip8::step).map(|s| s.steps()), Ok(3));
+/// assert_eq!((c8.pc(), c8.v()[0]), (0x208, 5));
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Breakpoints {
+    points: Vec<Breakpoint>,
+}
+
+impl Breakpoints {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets a breakpoint at `addr`, keeping one already there
+    pub fn add(&mut self, addr: u16) {
+        match self.points.iter_mut().find(|b| b.addr == addr) {
+            Some(b) => b.temporary = false,
+            None => self.points.push(Breakpoint {
+                addr,
+                temporary: false,
+            }),
+        }
+    }
+
+    /// Sets a breakpoint at `addr` for the next run only, unless there's
+    /// one there already
+    pub fn add_temporary(&mut self, addr: u16) {
+        if !self.contains(addr) {
+            self.points.push(Breakpoint {
+                addr,
+                temporary: true,
+            });
+        }
+    }
+
+    /// Removes the breakpoint at `addr`, returning whether there was one
+    pub fn remove(&mut self, addr: u16) -> bool {
+        let len = self.points.len();
+        self.points.retain(|b| b.addr != addr);
+        self.points.len() != len
+    }
+
+    pub fn contains(&self, addr: u16) -> bool {
+        self.points.iter().any(|b| b.addr == addr)
+    }
+
+    pub fn len(&self) -> usize {
+        self.points.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.points.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Breakpoint> {
+        self.points.iter()
+    }
+
+    /// Removes the temporary breakpoints, reached or not
+    pub fn clear_temporary(&mut self) {
+        self.points.retain(|b| !b.temporary);
+    }
+
+    /// Runs `c8` a `step` at a time until PC is at a breakpoint, the
+    /// program halts or `limit` instructions have run, then clears the
+    /// temporary breakpoints. `step` is `Chip8::step`, or whatever else
+    /// runs one for the frontend, such as `FrameAdvance::advance`.
+    pub fn run<F>(&mut self, c8: &mut Chip8, limit: u32, mut step: F) -> Result<Stop, Fault>
+    where
+        F: FnMut(&mut Chip8) -> Result<StepOutcome, Fault>,
+    {
+        let mut stop = Stop::Limit { steps: limit };
+        for steps in 1..=limit {
+            let outcome = match step(c8) {
+                Ok(outcome) => outcome,
+                Err(f) => {
+                    self.clear_temporary();
+                    return Err(f);
+                }
+            };
+            if self.contains(c8.pc()) {
+                stop = Stop::Breakpoint {
+                    addr: c8.pc(),
+                    steps,
+                };
+                break;
+            }
+            if matches!(outcome, StepOutcome::Halted | StepOutcome::Paused) {
+                stop = Stop::Outcome { outcome, steps };
+                break;
+            }
+        }
+        self.clear_temporary();
+        Ok(stop)
+    }
+
+    /// `run` with a temporary breakpoint at `addr`
+    pub fn run_to<F>(
+        &mut self,
+        c8: &mut Chip8,
+        addr: u16,
+        limit: u32,
+        step: F,
+    ) -> Result<Stop, Fault>
+    where
+        F: FnMut(&mut Chip8) -> Result<StepOutcome, Fault>,
+    {
+        self.add_temporary(addr);
+        self.run(c8, limit, step)
+    }
+}
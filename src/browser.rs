@@ -0,0 +1,136 @@
+use crate::rom::{RomError, RomSource, Zip};
+#[cfg(feature = "terminal")]
+use crate::term;
+use std::io;
+use std::path::Path;
+
+/// Extensions recognised as ROMs
+pub const EXTENSIONS: [&str; 5] = ["ch8", "c8", "sc8", "xo8", "hex"];
+
+pub struct RomEntry {
+    pub name: String,
+    pub source: RomSource,
+    pub size: u64,
+}
+
+/// Whether the path has a ROM extension
+pub fn is_rom(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| EXTENSIONS.iter().any(|x| x.eq_ignore_ascii_case(e)))
+}
+
+/// ROM files directly inside `dir`, sorted by name
+pub fn list_roms(dir: &Path) -> io::Result<Vec<RomEntry>> {
+    let mut roms = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        let meta = entry.metadata()?;
+        if !meta.is_file() || !is_rom(&path) {
+            continue;
+        }
+        roms.push(RomEntry {
+            name: entry.file_name().to_string_lossy().into_owned(),
+            source: RomSource::File(path),
+            size: meta.len(),
+        });
+    }
+    roms.sort_by_key(|r| r.name.to_lowercase());
+    Ok(roms)
+}
+
+/// ROMs inside a zip archive, sorted by name
+pub fn zip_roms(path: &Path) -> Result<Vec<RomEntry>, RomError> {
+    let zip = Zip::open(path)?;
+    let mut roms: Vec<_> = zip
+        .roms()
+        .into_iter()
+        .map(|e| RomEntry {
+            name: e.name.clone(),
+            source: RomSource::Zip(path.to_path_buf(), Some(e.name.clone())),
+            size: e.size,
+        })
+        .collect();
+    roms.sort_by_key(|r| r.name.to_lowercase());
+    Ok(roms)
+}
+
+/// `height` lines of the list scrolled so `selected` is visible, which is
+/// shown in reverse video
+pub fn menu_lines(roms: &[RomEntry], selected: usize, height: usize) -> Vec<String> {
+    let height = height.max(1);
+    let top = (selected + 1).saturating_sub(height);
+    roms.iter()
+        .enumerate()
+        .skip(top)
+        .take(height)
+        .map(|(n, r)| {
+            let line = format!("{:<40} {:>6} B", r.name, r.size);
+            if n == selected {
+                format!("\x1b[7m{}\x1b[27m", line)
+            } else {
+                line
+            }
+        })
+        .collect()
+}
+
+/// Lets the user pick a ROM with the arrow keys (or j/k) and Enter,
+/// returning None if they quit with Escape or q
+#[cfg(feature = "terminal")]
+pub fn choose(roms: &[RomEntry], selected: usize, running: &dyn Fn() -> bool) -> Option<usize> {
+    use std::io::Write;
+    if roms.is_empty() {
+        return None;
+    }
+    term::enable_raw();
+    term::hide_cursor();
+    let mut selected = selected.min(roms.len() - 1);
+    let mut dirty = true;
+    let choice = loop {
+        if !running() {
+            break None;
+        }
+        if dirty {
+            let rows = term::size().map_or(24, |(_, r)| r);
+            print!("\x1b[2J\x1b[1;1HPick a ROM (Enter to play, Esc to quit)\r\n\r\n");
+            for line in menu_lines(roms, selected, rows.saturating_sub(3)) {
+                print!("{}\r\n", line);
+            }
+            let _ = io::stdout().flush();
+            dirty = false;
+        }
+        let input = term::read_input();
+        let mut bytes = input.iter().copied().peekable();
+        let mut picked = None;
+        while let Some(b) = bytes.next() {
+            let before = selected;
+            match b {
+                0x1B => match (bytes.next(), bytes.next()) {
+                    (Some(b'['), Some(b'A')) | (Some(b'O'), Some(b'A')) => {
+                        selected = selected.saturating_sub(1)
+                    }
+                    (Some(b'['), Some(b'B')) | (Some(b'O'), Some(b'B')) => {
+                        selected = (selected + 1).min(roms.len() - 1)
+                    }
+                    (None, _) => picked = Some(None),
+                    _ => {}
+                },
+                b'k' => selected = selected.saturating_sub(1),
+                b'j' => selected = (selected + 1).min(roms.len() - 1),
+                b'q' => picked = Some(None),
+                b'\r' | b'\n' => picked = Some(Some(selected)),
+                _ => {}
+            }
+            dirty |= before != selected;
+        }
+        if let Some(p) = picked {
+            break p;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(20));
+    };
+    print!("\x1b[2J\x1b[1;1H");
+    term::restore();
+    choice
+}
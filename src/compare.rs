@@ -0,0 +1,122 @@
+//! Two screens side by side with a divider between them, for
+//! `chip8 compare`. Cells where the screens differ can be picked out in
+//! red, with ones that are blank on this side shaded so they still show.
+use crate::layout::{LORES_HEIGHT, LORES_WIDTH};
+use crate::text::{lines as screen_lines, RenderStyle};
+
+/// Between the two screens
+pub const DIVIDER: char = '\u{2502}';
+/// Drawn in a differing cell that's blank on this side
+pub const SHADE: char = '\u{2591}';
+
+const RED: &str = "\x1b[31m";
+const RESET: &str = "\x1b[39m";
+
+/// Terminal cells (columns, rows) for both screens and the divider
+pub fn cells(style: RenderStyle) -> (usize, usize) {
+    let (c, r) = style.cells(LORES_WIDTH, LORES_HEIGHT);
+    (c * 2 + 1, r)
+}
+
+/// Largest style the pair fits in, with `extra` rows left under it
+///
+/// ```
+/// use chip8::compare::{cells, pick_style};
+/// use chip8::text::RenderStyle;
+///
+/// assert_eq!(cells(RenderStyle::Full), (129, 32));
+/// assert_eq!(cells(RenderStyle::Braille), (65, 8));
+/// assert_eq!(pick_style(129, 33, 1), Some(RenderStyle::Full));
+/// assert_eq!(pick_style(128, 33, 1), Some(RenderStyle::Braille));
+/// assert_eq!(pick_style(129, 32, 1), Some(RenderStyle::Half));
+/// assert_eq!(pick_style(80, 24, 1), Some(RenderStyle::Braille));
+/// assert_eq!(pick_style(64, 24, 1), None);
+/// ```
+pub fn pick_style(cols: usize, rows: usize, extra: usize) -> Option<RenderStyle> {
+    [RenderStyle::Full, RenderStyle::Half, RenderStyle::Braille]
+        .iter()
+        .copied()
+        .find(|&s| {
+            let (c, r) = cells(s);
+            c <= cols && r + extra <= rows
+        })
+}
+
+fn blank(c: char) -> bool {
+    c == ' ' || c == '\u{2800}'
+}
+
+fn bit(screen: &[u64; 32], x: usize, y: usize) -> bool {
+    screen[y] >> (63 - x) & 1 == 1
+}
+
+/// Lines of `left` and `right` next to each other. With `highlight`
+/// cells holding a pixel that differs are red on both sides.
+///
+/// ```
+/// use chip8::compare::lines;
+/// use chip8::text::RenderStyle;
+///
+/// let mut left = [0u64; 32];
+/// let mut right = [0u64; 32];
+/// left[0] = 0b11 << 62;
+/// right[0] = 0b10 << 62;
+/// right[31] = 1;
+///
+/// let plain = lines(RenderStyle::Full, &left, &right, false);
+/// assert_eq!(plain.len(), 32);
+/// assert_eq!(plain[0], format!("██{}│█{}", " ".repeat(62), " ".repeat(63)));
+/// assert_eq!(plain[31], format!("{}│{}█", " ".repeat(64), " ".repeat(63)));
+///
+/// // Both of the differing pixels show on both sides
+/// let diff = lines(RenderStyle::Full, &left, &right, true);
+/// let red = |c| format!("\x1b[31m{}\x1b[39m", c);
+/// assert_eq!(diff[0], format!("█{}{}│█{}{}", red('█'), " ".repeat(62), red('░'), " ".repeat(62)));
+/// assert_eq!(diff[31], format!("{}{}│{}{}", " ".repeat(63), red('░'), " ".repeat(63), red('█')));
+/// assert_eq!(diff[1], plain[1]);
+///
+/// // Two rows a line, the same cell holds both pixels of the top row
+/// let half = lines(RenderStyle::Half, &left, &right, true);
+/// assert_eq!(half.len(), 16);
+/// assert!(half[0].starts_with(&format!("▀{}", red('▀'))));
+/// let braille = lines(RenderStyle::Braille, &left, &right, true);
+/// assert_eq!((braille.len(), braille[0].chars().filter(|&c| c == '│').count()), (8, 1));
+/// assert!(braille[0].starts_with(&red('⠉')));
+/// ```
+pub fn lines(
+    style: RenderStyle,
+    left: &[u64; 32],
+    right: &[u64; 32],
+    highlight: bool,
+) -> Vec<String> {
+    let side = |s: &[u64; 32]| screen_lines(style, |x, y| bit(s, x, y));
+    let diff = screen_lines(style, |x, y| bit(left, x, y) != bit(right, x, y));
+    let mut out = Vec::with_capacity(diff.len());
+    for ((l, r), d) in side(left).iter().zip(&side(right)).zip(&diff) {
+        let mut line = String::new();
+        for (n, s) in [l, r].iter().enumerate() {
+            if n == 1 {
+                line.push(DIVIDER);
+            }
+            for (c, d) in s.chars().zip(d.chars()) {
+                if highlight && !blank(d) {
+                    line.push_str(RED);
+                    line.push(if blank(c) { SHADE } else { c });
+                    line.push_str(RESET);
+                } else {
+                    line.push(c);
+                }
+            }
+        }
+        out.push(line);
+    }
+    out
+}
+
+/// Pixels lit on one screen and not the other
+pub fn count_differing(left: &[u64; 32], right: &[u64; 32]) -> u32 {
+    left.iter()
+        .zip(right)
+        .map(|(l, r)| (l ^ r).count_ones())
+        .sum()
+}
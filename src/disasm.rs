@@ -0,0 +1,242 @@
+//! Listings of a ROM for `chip8 disasm`. Code is decoded a word at a
+//! time. Ranges given as data are `.byte` rows of 8 instead, each byte
+//! drawn after them as the 8 pixels it would be in a sprite, which is
+//! where graphics stand out from level maps and tables.
+//!
+//! Also hex dumps of memory for `chip8 hexdump`. Lines in both start with
+//! the `layout::Region` they're in.
+use crate::emu;
+use crate::layout::{MemoryMap, Region};
+use std::fmt;
+use std::str::FromStr;
+
+/// Bytes in a `.byte` row
+pub const ROW: usize = 8;
+
+/// Addresses from `start` to `end`, both included, given as `0x200-0x4FF`
+/// or in decimal
+///
+/// ```
+/// use chip8::disasm::{Range, RangeError};
+///
+/// assert_eq!("0x200-0x4FF".parse(), Ok(Range { start: 0x200, end: 0x4FF }));
+/// assert_eq!("512-513".parse(), Ok(Range { start: 0x200, end: 0x201 }));
+/// assert_eq!("0x300-0x300".parse::<Range>().map(|r| r.contains(0x300)), Ok(true));
+/// let err = |s: &str| s.parse::<Range>().unwrap_err().to_string();
+/// assert_eq!(err("0x4FF-0x200"), "4FF-200 ends before it starts");
+/// assert_eq!(err("0x200"), "expected START-END, not 0x200");
+/// assert_eq!(err("0x200-0xZZ"), "bad address 0xZZ");
+/// assert_eq!("0x200-0x10000".parse::<Range>(), Err(RangeError::Address("0x10000".to_string())));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Range {
+    pub start: u16,
+    pub end: u16,
+}
+
+impl Range {
+    pub fn contains(&self, addr: u16) -> bool {
+        (self.start..=self.end).contains(&addr)
+    }
+}
+
+/// `MemoryMap` for `len` bytes at `load`, with `data` marked
+pub fn memory_map(load: u16, len: usize, data: &[Range]) -> MemoryMap {
+    data.iter()
+        .fold(MemoryMap::new(load, len), |m, d| m.data(d.start..=d.end))
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RangeError {
+    /// Not two addresses with a `-` between them
+    Syntax(String),
+    Address(String),
+    Backwards {
+        start: u16,
+        end: u16,
+    },
+}
+
+impl fmt::Display for RangeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Syntax(s) => write!(f, "expected START-END, not {}", s),
+            Self::Address(s) => write!(f, "bad address {}", s),
+            Self::Backwards { start, end } => {
+                write!(f, "{:03X}-{:03X} ends before it starts", start, end)
+            }
+        }
+    }
+}
+
+impl std::error::Error for RangeError {}
+
+fn addr(s: &str) -> Result<u16, RangeError> {
+    let n = match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => u16::from_str_radix(hex, 16),
+        None => s.parse(),
+    };
+    n.map_err(|_| RangeError::Address(s.to_string()))
+}
+
+impl FromStr for Range {
+    type Err = RangeError;
+
+    fn from_str(s: &str) -> Result<Self, RangeError> {
+        let (start, end) = s
+            .split_once('-')
+            .ok_or_else(|| RangeError::Syntax(s.to_string()))?;
+        let (start, end) = (addr(start.trim())?, addr(end.trim())?);
+        if end < start {
+            return Err(RangeError::Backwards { start, end });
+        }
+        Ok(Self { start, end })
+    }
+}
+
+/// `bytes` as a sprite would draw them, 8 blocks and spaces a byte with
+/// a bar between bytes
+fn preview(bytes: &[u8]) -> String {
+    let mut out = String::from("|");
+    for b in bytes {
+        for bit in (0..8).rev() {
+            out.push(if b >> bit & 1 != 0 { '\u{2588}' } else { ' ' });
+        }
+        out.push('|');
+    }
+    out
+}
+
+// A `.byte` line for up to `ROW` bytes at `at`, padded so the previews
+// line up
+fn data_line(region: Region, at: u16, bytes: &[u8]) -> String {
+    let hex: Vec<String> = bytes.iter().map(|b| format!("{:02X}", b)).collect();
+    format!(
+        "{} {:03X}: .byte {:<w$}  {}",
+        region,
+        at,
+        hex.join(" "),
+        preview(bytes),
+        w = ROW * 3 - 1
+    )
+}
+
+/// Lines listing `rom` as loaded at `load`, only within `range` if
+/// given. Addresses in any of `data` are listed as bytes rather than
+/// decoded, and so is a lone byte left before data or the end.
+///
+/// ```
+/// use chip8::disasm::{listing, Range};
+///
+/// let rom = [
+///     0xA2, 0x08, // 200: LD I, 208
+///     0xD0, 0x13, // 202: DRW V0, V1, 3
+///     0x12, 0x04, // 204: JP 204
+///     0xFF,       // 206: padding
+///     0x3C,       // 207: not code either
+///     0x3C, 0x42, 0x81, 0x81, 0x81, 0x81, 0x42, 0x3C, 0x18, // 208: a circle and more
+/// ];
+/// let data = [Range { start: 0x207, end: 0x210 }];
+/// let lines = listing(&rom, 0x200, None, &data);
+/// assert_eq!(lines[..4], ["PROG 200: A208  LD I, 208", "PROG 202: D013  DRW V0, V1, 3", "PROG 204: 1204  JP 204", "PROG 206: .byte FF                       |████████|"]);
+/// assert_eq!(lines[4], "DATA 207: .byte 3C 3C 42 81 81 81 81 42  |  ████  |  ████  | █    █ |█      █|█      █|█      █|█      █| █    █ |");
+/// assert_eq!(lines[5], "DATA 20F: .byte 3C 18                    |  ████  |   ██   |");
+/// assert_eq!(lines.len(), 6);
+///
+/// // Only part of it, starting partway into the data
+/// let lines = listing(&rom, 0x200, Some(Range { start: 0x204, end: 0x209 }), &data);
+/// assert_eq!(lines[..2], ["PROG 204: 1204  JP 204", "PROG 206: .byte FF                       |████████|"]);
+/// assert_eq!(lines[2..], ["DATA 207: .byte 3C 3C 42                 |  ████  |  ████  | █    █ |"]);
+/// // Nothing to list outside the ROM
+/// assert!(listing(&rom, 0x200, Some(Range { start: 0x100, end: 0x1FF }), &[]).is_empty());
+/// ```
+pub fn listing(rom: &[u8], load: u16, range: Option<Range>, data: &[Range]) -> Vec<String> {
+    let mut lines = Vec::new();
+    if rom.is_empty() {
+        return lines;
+    }
+    let last = (load as usize + rom.len() - 1).min(u16::MAX as usize) as u16;
+    let (from, to) = match range {
+        Some(r) => (r.start.max(load), r.end.min(last)),
+        None => (load, last),
+    };
+    let map = memory_map(load, rom.len(), data);
+    let byte = |a: u16| rom[(a - load) as usize];
+    let in_data = |a: u16| data.iter().find(|d| d.contains(a));
+    let mut a = from as u32;
+    while a <= to as u32 {
+        let at = a as u16;
+        if let Some(d) = in_data(at) {
+            let end = d.end.min(to);
+            let bytes: Vec<u8> = (at..=end).map(byte).collect();
+            for (n, row) in bytes.chunks(ROW).enumerate() {
+                let at = at + (n * ROW) as u16;
+                lines.push(data_line(map.annotate(at), at, row));
+            }
+            a = end as u32 + 1;
+        } else if at == to || in_data(at + 1).is_some() {
+            lines.push(data_line(map.annotate(at), at, &[byte(at)]));
+            a += 1;
+        } else {
+            let op = u16::from_be_bytes([byte(at), byte(at + 1)]);
+            lines.push(format!(
+                "{} {:03X}: {:04X}  {}",
+                map.annotate(at),
+                at,
+                op,
+                emu::disassemble(op)
+            ));
+            a += 2;
+        }
+    }
+    lines
+}
+
+/// Bytes in a hex dump row
+pub const HEX_ROW: usize = 16;
+
+/// `ram` in rows of `HEX_ROW` bytes, broken where `map` changes region
+/// so each row is one region. A row the same as the one before, in the
+/// same region, is a `*` however many times it repeats, as in hexdump.
+///
+/// ```
+/// use chip8::disasm::hexdump;
+/// use chip8::layout::MemoryMap;
+///
+/// let mut ram = vec![0u8; 0x1000];
+/// ram[0x50..0x55].copy_from_slice(&[0xF0, 0x90, 0x90, 0x90, 0xF0]);
+/// ram[0x200..0x206].copy_from_slice(&[0xA2, 0x04, 0xD0, 0x15, 0xFF, 0xFF]);
+/// let lines = hexdump(&ram, &MemoryMap::new(0x200, 6).data(0x204..=0x205));
+/// assert_eq!(lines[..3], ["FREE 000: 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00", "*", "FONT 050: F0 90 90 90 F0 00 00 00 00 00 00 00 00 00 00 00"]);
+/// let at = |addr: &str| lines.iter().position(|l| l.get(5..).is_some_and(|l| l.starts_with(addr))).unwrap();
+/// assert_eq!(lines[at("200")..at("200") + 3], ["PROG 200: A2 04 D0 15", "DATA 204: FF FF", "FREE 206: 00 00 00 00 00 00 00 00 00 00"]);
+/// assert_eq!(lines[at("EA0")], "STCK EA0: 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00");
+/// assert_eq!(lines[lines.len() - 2..], ["FREE F00: 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00", "*"]);
+/// ```
+pub fn hexdump(ram: &[u8], map: &MemoryMap) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut last: Option<(Region, &[u8])> = None;
+    let mut repeated = false;
+    let mut at = 0;
+    while at < ram.len().min(u16::MAX as usize + 1) {
+        let region = map.annotate(at as u16);
+        let mut end = (at / HEX_ROW + 1) * HEX_ROW;
+        end = (at + 1..end.min(ram.len()))
+            .find(|&a| map.annotate(a as u16) != region)
+            .unwrap_or(end.min(ram.len()));
+        let row = &ram[at..end];
+        if last == Some((region, row)) && row.len() == HEX_ROW {
+            if !repeated {
+                lines.push("*".to_string());
+                repeated = true;
+            }
+        } else {
+            let hex: Vec<String> = row.iter().map(|b| format!("{:02X}", b)).collect();
+            lines.push(format!("{} {:03X}: {}", region, at, hex.join(" ")));
+            repeated = false;
+        }
+        last = Some((region, row));
+        at = end;
+    }
+    lines
+}
@@ -0,0 +1,150 @@
+//! Files dropped on the window. Terminals paste the path of a dropped
+//! file, quoted the way their shell would want it, so the paths are dug
+//! out of the pasted text. What the file holds is sniffed from its first
+//! bytes rather than trusted to the extension, then it's read through
+//! `RomSource` like a ROM given on the command line.
+use crate::rom::{RomError, RomSource};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+/// What a file looks like from its first bytes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    Rom,
+    Zip,
+    /// A GIF, which for a ROM means an OctoCart
+    OctoCart,
+    /// Hex text, going by the extension as text has no magic number
+    Hex,
+}
+
+/// ```
+/// use chip8::dropped::{sniff, Kind};
+/// use std::path::Path;
+///
+/// assert_eq!(sniff(b"PK\x03\x04\x14\x00", Path::new("games.bin")), Kind::Zip);
+/// assert_eq!(sniff(b"GIF89a", Path::new("pong.ch8")), Kind::OctoCart);
+/// assert_eq!(sniff(b"00E0 A22A", Path::new("ibm.hex")), Kind::Hex);
+/// assert_eq!(sniff(&[0x00, 0xE0, 0xA2, 0x2A], Path::new("ibm.zip")), Kind::Rom);
+/// assert_eq!(sniff(b"PK", Path::new("tiny.ch8")), Kind::Rom);
+/// ```
+pub fn sniff(head: &[u8], path: &Path) -> Kind {
+    if head.starts_with(b"PK\x03\x04") {
+        Kind::Zip
+    } else if head.starts_with(b"GIF8") {
+        Kind::OctoCart
+    } else if path
+        .extension()
+        .is_some_and(|e| e.eq_ignore_ascii_case("hex"))
+    {
+        Kind::Hex
+    } else {
+        Kind::Rom
+    }
+}
+
+/// Where to read a dropped file from
+///
+/// ```
+/// use chip8::dropped::source;
+/// use chip8::rom::{RomError, RomSource};
+/// use std::path::Path;
+///
+/// let dir = std::env::temp_dir().join(format!("chip8-drop-{}", std::process::id()));
+/// std::fs::create_dir_all(&dir).unwrap();
+/// let zip = dir.join("games.bin");
+/// std::fs::write(&zip, b"PK\x03\x04").unwrap();
+/// assert_eq!(source(&zip).unwrap(), RomSource::Zip(zip.clone(), None));
+///
+/// // An OctoCart is read like any file, a missing file is an error
+/// let cart = dir.join("cart.ch8");
+/// std::fs::write(&cart, b"GIF89a").unwrap();
+/// assert_eq!(source(&cart).unwrap(), RomSource::File(cart.clone()));
+/// assert!(matches!(source(&cart).unwrap().resolve(), Err(RomError::OctoCart(_))));
+/// assert!(matches!(source(&dir.join("gone.ch8")), Err(RomError::Io(_))));
+/// let empty = dir.join("empty.ch8");
+/// std::fs::write(&empty, b"").unwrap();
+/// assert!(matches!(source(&empty).unwrap().resolve(), Err(RomError::Empty)));
+/// std::fs::remove_dir_all(&dir).unwrap();
+/// ```
+pub fn source(path: &Path) -> Result<RomSource, RomError> {
+    let mut head = Vec::new();
+    std::fs::File::open(path)?.take(4).read_to_end(&mut head)?;
+    match sniff(&head, path) {
+        Kind::Zip => Ok(RomSource::Zip(path.to_path_buf(), None)),
+        Kind::Rom | Kind::Hex | Kind::OctoCart => Ok(RomSource::File(path.to_path_buf())),
+    }
+}
+
+/// Paths in text pasted by dropping files on a terminal. Each is either
+/// quoted or has its spaces escaped with backslashes, and some terminals
+/// send `file://` URLs instead.
+///
+/// ```
+/// use chip8::dropped::paths;
+/// use std::path::PathBuf;
+///
+/// let p = |s: &str| PathBuf::from(s);
+/// assert_eq!(paths("/roms/pong.ch8"), [p("/roms/pong.ch8")]);
+/// assert_eq!(paths("'/my roms/it'\\''s.ch8' \n"), [p("/my roms/it's.ch8")]);
+/// assert_eq!(paths("/my\\ roms/a.ch8 \"/b c.ch8\""), [p("/my roms/a.ch8"), p("/b c.ch8")]);
+/// assert_eq!(paths("file:///my%20roms/a.ch8\r\n"), [p("/my roms/a.ch8")]);
+/// assert!(paths("  \n").is_empty());
+/// ```
+pub fn paths(pasted: &str) -> Vec<PathBuf> {
+    let mut out = Vec::new();
+    let mut word: Option<String> = None;
+    let mut chars = pasted.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => out.extend(word.take()),
+            '\'' => {
+                let w = word.get_or_insert_with(String::new);
+                w.extend(chars.by_ref().take_while(|&c| c != '\''));
+            }
+            '"' => {
+                let w = word.get_or_insert_with(String::new);
+                while let Some(c) = chars.next() {
+                    match c {
+                        '"' => break,
+                        '\\' => w.extend(chars.next()),
+                        c => w.push(c),
+                    }
+                }
+            }
+            '\\' => word.get_or_insert_with(String::new).extend(chars.next()),
+            c => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+    out.extend(word);
+    out.into_iter()
+        .map(|w| match w.strip_prefix("file://") {
+            Some(url) => PathBuf::from(unescape_url(url)),
+            None => PathBuf::from(w),
+        })
+        .collect()
+}
+
+// %20 and the like back to bytes
+fn unescape_url(url: &str) -> String {
+    let bytes = url.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut n = 0;
+    while n < bytes.len() {
+        let hex = bytes
+            .get(n + 1..n + 3)
+            .and_then(|h| std::str::from_utf8(h).ok())
+            .and_then(|h| u8::from_str_radix(h, 16).ok());
+        match (bytes[n], hex) {
+            (b'%', Some(b)) => {
+                out.push(b);
+                n += 3;
+            }
+            (b, _) => {
+                out.push(b);
+                n += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
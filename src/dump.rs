@@ -0,0 +1,325 @@
+//! `--fault-dump`, written when a program faults so it can be looked at
+//! somewhere else: the fault, the whole machine, the instructions that
+//! led up to it and how the emulator was set up. `--load-dump` puts the
+//! machine back, paused on the instruction that faulted.
+//!
+//! It's JSON, with the machine as the hex of `SaveState::to_bytes` and
+//! also as `Chip8::dump_state` for reading.
+use crate::emu::{self, Chip8, Fault, OnOddPc, OnUnknown, Quirks};
+use crate::json::{self, Value};
+use crate::rom;
+use crate::state::{SaveState, StateError};
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Instructions kept for `FaultDump::recent`
+pub const RECENT: usize = 64;
+
+// Names of `Quirks`, as `Quirks::toggle` knows them. Toggled in this
+// order from all off, `mem_inc_x` has to come after `mem_inc`.
+const QUIRKS: [&str; 6] = [
+    "clip_x",
+    "clip_y",
+    "hp_shift",
+    "mem_inc",
+    "mem_inc_x",
+    "key_release",
+];
+// Added later, so missing means off
+const NEWER_QUIRKS: [&str; 1] = ["mem_inc_x"];
+
+#[derive(Debug)]
+pub enum DumpError {
+    Io(io::Error),
+    Json(json::ParseError),
+    State(StateError),
+    /// A field that's missing or isn't what it should be
+    Field(&'static str),
+}
+
+impl fmt::Display for DumpError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "{}", e),
+            Self::Json(e) => write!(f, "{}", e),
+            Self::State(e) => write!(f, "state: {}", e),
+            Self::Field(name) => write!(f, "missing or bad {}", name),
+        }
+    }
+}
+
+impl std::error::Error for DumpError {}
+
+impl From<io::Error> for DumpError {
+    fn from(e: io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+impl From<json::ParseError> for DumpError {
+    fn from(e: json::ParseError) -> Self {
+        Self::Json(e)
+    }
+}
+
+impl From<StateError> for DumpError {
+    fn from(e: StateError) -> Self {
+        Self::State(e)
+    }
+}
+
+/// How the emulator was set up, where it changes how the state runs
+#[derive(Debug, Clone, PartialEq)]
+pub struct Config {
+    /// As given on the command line
+    pub rom: String,
+    pub quirks: Quirks,
+    pub on_unknown: OnUnknown,
+    pub on_odd_pc: OnOddPc,
+}
+
+/// Everything known about a fault
+///
+/// ```
+/// use chip8::dump::{Config, FaultDump};
+/// use chip8::emu::{Chip8, OnOddPc, OnUnknown, Quirks};
+///
+/// let rom = [
+///     0x60, 0x05, // 200: LD V0, 05
+///     0xA2, 0x08, // 202: LD I, 208
+///     0xF0, 0x1E, // 204: ADD I, V0
+///     0xF0, 0x75, // 206: a SUPER-CHIP instruction
+/// ];
+/// let mut c8 = Chip8::builder().rom(&rom).build().unwrap();
+/// c8.set_smc_monitor(true);
+/// let mut recent = Vec::new();
+/// let fault = loop {
+///     let pc = c8.pc();
+///     match c8.step() {
+///         Ok(_) => recent.push((pc, c8.peek_word(pc))),
+///         Err(f) => break f,
+///     }
+/// };
+/// let config = Config {
+///     rom: "scroll.ch8".to_string(),
+///     quirks: Quirks::preset("schip").unwrap(),
+///     on_unknown: OnUnknown::Halt,
+///     on_odd_pc: OnOddPc::Fault,
+/// };
+/// let dump = FaultDump::new(&mut c8, &fault, recent, config);
+/// let json = dump.to_json();
+/// assert!(json.contains(r#""recent":[{"pc":"200","op":"6005","asm":"LD V0, 05"},"#));
+/// assert!(json.contains(r#""coverage":[["200","207"]]"#));
+///
+/// // Read back it's the same, and running it faults the same way
+/// let back = FaultDump::parse(&json).unwrap();
+/// assert_eq!(back, dump);
+/// let mut again = back.restore();
+/// assert_eq!((again.pc(), again.i(), again.v()[0]), (0x206, 0x20D, 5));
+/// assert_eq!(again.quirks(), Quirks::preset("schip").unwrap());
+/// assert_eq!(again.step().unwrap_err(), fault);
+///
+/// let err = |j: &str| FaultDump::parse(j).unwrap_err().to_string();
+/// assert_eq!(err(&json.replace("\"halt\"", "\"stop\"")), "missing or bad config.on_unknown");
+/// assert_eq!(err(&json.replace("\"state\":\"", "\"state\":\"00")), "state: not a save state");
+/// assert_eq!(err("{"), "bad JSON at byte 1");
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct FaultDump {
+    /// As the fault shows it
+    pub fault: String,
+    /// The machine when it stopped
+    pub state: SaveState,
+    /// (PC, opcode) of the last instructions run, oldest first
+    pub recent: Vec<(u16, u16)>,
+    /// `Chip8::coverage`, with `--smc`
+    pub coverage: Option<Vec<(u16, u16)>>,
+    pub config: Config,
+}
+
+impl FaultDump {
+    /// `c8` having just stopped with `fault`. A broken invariant is only
+    /// seen after the instruction ran, so it's undone with the journal if
+    /// there is one to leave PC on it again.
+    pub fn new(c8: &mut Chip8, fault: &Fault, recent: Vec<(u16, u16)>, config: Config) -> Self {
+        if let Fault::Invariant { pc, .. } = *fault {
+            if c8.pc() != pc {
+                c8.step_back();
+            }
+        }
+        Self {
+            fault: fault.to_string(),
+            state: c8.save_state(),
+            recent,
+            coverage: c8.coverage(),
+            config,
+        }
+    }
+
+    /// A machine set up as this one was. The RNG and keys aren't kept, so
+    /// a fault they led to may not come again.
+    pub fn restore(&self) -> Chip8 {
+        let mut c8 = Chip8::builder()
+            .quirks(self.config.quirks)
+            .on_unknown_opcode(self.config.on_unknown)
+            .on_odd_pc(self.config.on_odd_pc)
+            .build()
+            .unwrap();
+        c8.load_state(&self.state);
+        c8
+    }
+
+    pub fn to_json(&self) -> String {
+        let hex = |n: u16| format!("{:03X}", n);
+        let recent = json::array(self.recent.iter().map(|&(pc, op)| {
+            json::Object::new()
+                .str("pc", &hex(pc))
+                .str("op", &format!("{:04X}", op))
+                .str("asm", &emu::disassemble(op))
+                .to_string()
+        }));
+        let coverage = match &self.coverage {
+            Some(runs) => json::array(
+                runs.iter()
+                    .map(|&(a, b)| json::array([json::string(&hex(a)), json::string(&hex(b))])),
+            ),
+            None => "null".to_string(),
+        };
+        let config = json::Object::new()
+            .str("rom", &self.config.rom)
+            .raw("quirks", self.config.quirks.to_json())
+            .str("on_unknown", on_unknown_name(self.config.on_unknown))
+            .str("odd_pc", odd_pc_name(self.config.on_odd_pc));
+        let text = Chip8::from_state(&self.state, self.config.quirks).dump_state();
+        let state: String = self
+            .state
+            .to_bytes()
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect();
+        json::Object::new()
+            .str("fault", &self.fault)
+            .str("machine", &text)
+            .raw("recent", recent)
+            .raw("coverage", coverage)
+            .raw("config", config.to_string())
+            .str("state", &state)
+            .to_string()
+    }
+
+    pub fn parse(src: &str) -> Result<Self, DumpError> {
+        let v = json::parse(src)?;
+        let str_field = |v: &Value, key, name| {
+            v.get(key)
+                .and_then(Value::as_str)
+                .map(str::to_string)
+                .ok_or(DumpError::Field(name))
+        };
+        let addr = |v: &Value, name| {
+            v.as_str()
+                .and_then(|s| u16::from_str_radix(s, 16).ok())
+                .ok_or(DumpError::Field(name))
+        };
+        let fault = str_field(&v, "fault", "fault")?;
+        let bytes = rom::parse_hex(&str_field(&v, "state", "state")?)
+            .map_err(|_| DumpError::Field("state"))?;
+        let state = SaveState::from_bytes(&bytes)?;
+        let recent = v
+            .get("recent")
+            .and_then(Value::as_array)
+            .ok_or(DumpError::Field("recent"))?
+            .iter()
+            .map(|r| {
+                let field = |k| r.get(k).ok_or(DumpError::Field("recent"));
+                Ok((addr(field("pc")?, "recent")?, addr(field("op")?, "recent")?))
+            })
+            .collect::<Result<_, DumpError>>()?;
+        let coverage = match v.get("coverage") {
+            Some(Value::Null) | None => None,
+            Some(c) => Some(
+                c.as_array()
+                    .ok_or(DumpError::Field("coverage"))?
+                    .iter()
+                    .map(|run| match run.as_array() {
+                        Some([a, b]) => Ok((addr(a, "coverage")?, addr(b, "coverage")?)),
+                        _ => Err(DumpError::Field("coverage")),
+                    })
+                    .collect::<Result<_, DumpError>>()?,
+            ),
+        };
+        let c = v.get("config").ok_or(DumpError::Field("config"))?;
+        let mut quirks = Quirks::from_bits(0);
+        for q in QUIRKS.iter() {
+            let on = c
+                .get("quirks")
+                .and_then(|qs| qs.get(q))
+                .and_then(Value::as_bool);
+            match on {
+                Some(true) => {
+                    quirks.toggle(q);
+                }
+                Some(false) => {}
+                None if NEWER_QUIRKS.contains(q) => {}
+                None => return Err(DumpError::Field("config.quirks")),
+            }
+        }
+        let on_unknown = str_field(c, "on_unknown", "config.on_unknown")?;
+        let on_unknown = [OnUnknown::Skip, OnUnknown::Halt, OnUnknown::Warn]
+            .iter()
+            .copied()
+            .find(|&o| on_unknown_name(o) == on_unknown)
+            .ok_or(DumpError::Field("config.on_unknown"))?;
+        let odd_pc = str_field(c, "odd_pc", "config.odd_pc")?;
+        let on_odd_pc = [OnOddPc::Allow, OnOddPc::Fault]
+            .iter()
+            .copied()
+            .find(|&o| odd_pc_name(o) == odd_pc)
+            .ok_or(DumpError::Field("config.odd_pc"))?;
+        Ok(Self {
+            fault,
+            state,
+            recent,
+            coverage,
+            config: Config {
+                rom: str_field(c, "rom", "config.rom")?,
+                quirks,
+                on_unknown,
+                on_odd_pc,
+            },
+        })
+    }
+
+    pub fn load(path: &Path) -> Result<Self, DumpError> {
+        Self::parse(&std::fs::read_to_string(path)?)
+    }
+
+    /// Writes this to a new file in `dir`, named for when it was, and
+    /// returns its path
+    pub fn write(&self, dir: &Path) -> io::Result<PathBuf> {
+        std::fs::create_dir_all(dir)?;
+        let secs = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        let path = dir.join(format!("fault-{}-{}.json", secs, std::process::id()));
+        std::fs::write(&path, self.to_json())?;
+        Ok(path)
+    }
+}
+
+// As `--on-unknown` takes them
+fn on_unknown_name(o: OnUnknown) -> &'static str {
+    match o {
+        OnUnknown::Skip => "skip",
+        OnUnknown::Halt => "halt",
+        OnUnknown::Warn => "warn",
+    }
+}
+
+// As `--odd-pc` takes them
+fn odd_pc_name(o: OnOddPc) -> &'static str {
+    match o {
+        OnOddPc::Allow => "allow",
+        OnOddPc::Fault => "fault",
+    }
+}
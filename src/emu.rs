@@ -1,13 +1,400 @@
-use std::convert::TryInto;
+use crate::font::{self, FontError};
+use crate::json;
+use crate::layout::{
+    self, LORES_HEIGHT, LORES_WIDTH, PROGRAM_START, RAM_SIZE_CLASSIC, STACK_DEPTH_CLASSIC,
+};
+use crate::rom::RomError;
+use crate::state::{self, SaveState};
+use std::collections::VecDeque;
+
+/// RAM sizes `Chip8Builder::ram_size` takes, from classic CHIP-8's 4 KB
+/// up to XO-CHIP's 64 KB
+pub const MIN_RAM: usize = RAM_SIZE_CLASSIC;
+pub const MAX_RAM: usize = 0x10000;
+
+/// Where Fx29 finds the digit glyphs, `layout::FONT_START`
+pub const FONT_ADDR: u16 = layout::FONT_START;
+
+/// Behaviours that differ between CHIP-8 interpreters
+///
+/// DRW always wraps where a sprite starts onto the screen. The clip flags
+/// then decide whether the parts hanging off an edge are dropped or come
+/// back round the other side:
+///
+/// ```
+/// use chip8::emu::{Chip8, Quirks};
+///
+/// let rom = [
+///     0xA2, 0x10, // LD I, 210
+///     0xD0, 0x01, // DRW V0, V0, 1, a dot at (0, 0)
+///     0x60, 0x3E, // LD V0, 3E
+///     0x61, 0x1E, // LD V1, 1E
+///     0xA2, 0x11, // LD I, 211
+///     0xD0, 0x13, // DRW V0, V1, 3 at (62, 30)
+///     0x12, 0x0C, // JP 20C
+///     0x00, 0x00,
+///     0x80, 0xFF, 0xFF, 0xFF, // 210: the dot, then an 8x3 block
+/// ];
+/// // Lit (x, y) pixels and VF for each setting of (clip_x, clip_y)
+/// let draw = |clip_x, clip_y| {
+///     let quirks = Quirks { clip_x, clip_y, ..Quirks::default() };
+///     let mut c8 = Chip8::builder().rom(&rom).quirks(quirks).build().unwrap();
+///     c8.run_n(7).unwrap();
+///     let lit: Vec<(u32, usize)> = (0..32)
+///         .flat_map(|y| (0..64).map(move |x| (x, y)))
+///         .filter(|&(x, y)| c8.screen[y] >> (63 - x) & 1 == 1)
+///         .collect();
+///     (lit, c8.v()[0xF])
+/// };
+/// let block = |xs: &[u32], ys: &[usize]| {
+///     let mut lit: Vec<_> = ys.iter().flat_map(|&y| xs.iter().map(move |&x| (x, y))).collect();
+///     lit.sort_by_key(|&(x, y)| (y, x));
+///     lit
+/// };
+/// let right = [62, 63];
+/// let round = [0, 1, 2, 3, 4, 5, 62, 63];
+/// let dot = |mut lit: Vec<_>| {
+///     lit.insert(0, (0, 0));
+///     lit
+/// };
+/// assert_eq!(draw(true, true), (dot(block(&right, &[30, 31])), 0));
+/// assert_eq!(draw(false, true), (dot(block(&round, &[30, 31])), 0));
+/// assert_eq!(draw(true, false), (dot(block(&right, &[0, 30, 31])), 0));
+/// // Only wrapping both ways reaches the dot, which is erased
+/// let mut both = block(&round, &[0, 30, 31]);
+/// both.remove(0);
+/// assert_eq!(draw(false, false), (both, 1));
+/// ```
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Quirks {
+    /// DRW drops the parts of sprites past the right edge, rather than
+    /// wrapping them round to the left
+    pub clip_x: bool,
+    /// DRW drops the rows of sprites past the bottom, rather than wrapping
+    /// them round to the top
+    pub clip_y: bool,
+    /// SHR and SHL shift Vx itself instead of copying Vy
+    pub hp_shift: bool,
+    /// What Fx55 and Fx65 leave I pointing at
+    pub mem_inc: LoadStoreIncrement,
+    /// Fx0A finishes when the key is let go rather than when it goes down,
+    /// like the COSMAC VIP
+    pub key_release: bool,
+}
+
+impl Default for Quirks {
+    fn default() -> Self {
+        Self {
+            clip_x: true,
+            clip_y: true,
+            hp_shift: true,
+            mem_inc: LoadStoreIncrement::XPlusOne,
+            key_release: false,
+        }
+    }
+}
+
+/// How far Fx55 and Fx65 move I after storing or loading V0 to Vx
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum LoadStoreIncrement {
+    /// I is left where it was, as on SUPER-CHIP 1.1
+    None,
+    /// I points at the last register's byte, as on CHIP-48
+    X,
+    /// I points past the last register's byte, as on the COSMAC VIP
+    XPlusOne,
+}
+
+impl LoadStoreIncrement {
+    /// What's added to I after Fx55 or Fx65 with `x` as the last register
+    pub fn amount(self, x: u8) -> u16 {
+        match self {
+            Self::None => 0,
+            Self::X => x as u16,
+            Self::XPlusOne => x as u16 + 1,
+        }
+    }
+}
+
+impl Quirks {
+    /// Names `preset` knows
+    pub const PRESETS: [&'static str; 5] = ["default", "chip8", "vip", "chip48", "schip"];
+
+    /// How an interpreter behaved. `chip8` and `vip` are both the COSMAC
+    /// VIP's original, `chip48` is the HP-48 port SUPER-CHIP grew from, and
+    /// `schip` is SUPER-CHIP 1.1 (its quirks, not its extra instructions).
+    ///
+    /// ```
+    /// use chip8::emu::{LoadStoreIncrement, Quirks};
+    ///
+    /// let vip = Quirks::preset("VIP").unwrap();
+    /// assert!(!vip.hp_shift && vip.key_release);
+    /// assert_eq!(vip.mem_inc, LoadStoreIncrement::XPlusOne);
+    /// assert_eq!(Quirks::preset("chip8"), Some(vip));
+    /// let chip48 = Quirks::preset("chip48").unwrap();
+    /// assert!(chip48.hp_shift && !chip48.key_release);
+    /// assert_eq!(chip48.mem_inc, LoadStoreIncrement::X);
+    /// let mut schip = Quirks::preset("schip").unwrap();
+    /// assert!(schip.hp_shift && !schip.key_release);
+    /// assert_eq!(schip.mem_inc, LoadStoreIncrement::None);
+    ///
+    /// // mem_inc turns moving I on and off, mem_inc_x the +1 when it moves
+    /// assert!(schip.toggle("mem_inc"));
+    /// assert_eq!(schip.mem_inc, LoadStoreIncrement::XPlusOne);
+    /// assert!(schip.toggle("mem_inc_x"));
+    /// assert_eq!(schip, chip48);
+    /// schip.toggle("mem_inc");
+    /// assert_eq!(schip.mem_inc, LoadStoreIncrement::None);
+    /// assert!(!schip.toggle("wrap_tex"));
+    /// assert_eq!(Quirks::preset("xochip"), None);
+    /// ```
+    pub fn preset(name: &str) -> Option<Self> {
+        let clip = |hp_shift, mem_inc, key_release| Self {
+            clip_x: true,
+            clip_y: true,
+            hp_shift,
+            mem_inc,
+            key_release,
+        };
+        match name.to_ascii_lowercase().as_str() {
+            "default" => Some(Self::default()),
+            "chip8" | "vip" => Some(clip(false, LoadStoreIncrement::XPlusOne, true)),
+            "chip48" => Some(clip(true, LoadStoreIncrement::X, false)),
+            "schip" => Some(clip(true, LoadStoreIncrement::None, false)),
+            _ => None,
+        }
+    }
+
+    /// Flips the quirk with the field name `name`, returning false if
+    /// there isn't one. `mem_inc` flips whether I moves at all, and
+    /// `mem_inc_x` whether it stops at the last register.
+    pub fn toggle(&mut self, name: &str) -> bool {
+        use LoadStoreIncrement::*;
+        let flag = match name {
+            "clip_x" => &mut self.clip_x,
+            "clip_y" => &mut self.clip_y,
+            "hp_shift" => &mut self.hp_shift,
+            "key_release" => &mut self.key_release,
+            "mem_inc" => {
+                self.mem_inc = if self.mem_inc == None { XPlusOne } else { None };
+                return true;
+            }
+            "mem_inc_x" => {
+                self.mem_inc = if self.mem_inc == X { XPlusOne } else { X };
+                return true;
+            }
+            _ => return false,
+        };
+        *flag = !*flag;
+        true
+    }
+
+    /// Clips or wraps sprites on both axes, as the single flag this
+    /// replaced did
+    #[deprecated(note = "set clip_x and clip_y")]
+    pub fn set_wrap_tex(&mut self, wrap: bool) {
+        self.clip_x = !wrap;
+        self.clip_y = !wrap;
+    }
+
+    /// Moves I past the last register or leaves it, as the flag the
+    /// three-way `mem_inc` replaced did
+    #[deprecated(note = "set mem_inc to a LoadStoreIncrement")]
+    pub fn set_mem_inc(&mut self, inc: bool) {
+        self.mem_inc = if inc {
+            LoadStoreIncrement::XPlusOne
+        } else {
+            LoadStoreIncrement::None
+        };
+    }
+
+    /// One bit per flag. `clip_y` was added after the others so it's bit
+    /// 4, and I stopping at the last register after that in bit 5, with
+    /// bit 2 still whether it moves at all.
+    ///
+    /// ```
+    /// use chip8::emu::{LoadStoreIncrement, Quirks};
+    ///
+    /// for mem_inc in [LoadStoreIncrement::None, LoadStoreIncrement::X, LoadStoreIncrement::XPlusOne] {
+    ///     let q = Quirks { mem_inc, ..Quirks::default() };
+    ///     assert_eq!(Quirks::from_bits(q.to_bits()), q);
+    /// }
+    /// assert_eq!(Quirks::default().to_bits(), 0b10111);
+    /// ```
+    pub fn to_bits(self) -> u8 {
+        self.clip_x as u8
+            | (self.hp_shift as u8) << 1
+            | ((self.mem_inc != LoadStoreIncrement::None) as u8) << 2
+            | (self.key_release as u8) << 3
+            | (self.clip_y as u8) << 4
+            | ((self.mem_inc == LoadStoreIncrement::X) as u8) << 5
+    }
+
+    pub fn from_bits(bits: u8) -> Self {
+        Self {
+            clip_x: bits & 1 != 0,
+            clip_y: bits & 16 != 0,
+            hp_shift: bits & 2 != 0,
+            mem_inc: match (bits & 4 != 0, bits & 32 != 0) {
+                (false, _) => LoadStoreIncrement::None,
+                (true, true) => LoadStoreIncrement::X,
+                (true, false) => LoadStoreIncrement::XPlusOne,
+            },
+            key_release: bits & 8 != 0,
+        }
+    }
+
+    /// Each quirk as a bool under its field name, with `mem_inc` and
+    /// `mem_inc_x` as `toggle` takes them
+    pub fn to_json(&self) -> String {
+        let bits = self.to_bits();
+        QUIRK_BITS
+            .iter()
+            .fold(json::Object::new(), |o, &(name, bit)| {
+                o.raw(name, (bits & bit != 0).to_string())
+            })
+            .to_string()
+    }
+
+    /// Quirks that aren't there keep their default, so files from before
+    /// one was added still read
+    ///
+    /// ```
+    /// use chip8::emu::{LoadStoreIncrement, Quirks};
+    /// use chip8::json;
+    ///
+    /// let schip = Quirks::preset("schip").unwrap();
+    /// assert_eq!(
+    ///     schip.to_json(),
+    ///     r#"{"clip_x":true,"clip_y":true,"hp_shift":true,"mem_inc":false,"mem_inc_x":false,"key_release":false}"#
+    /// );
+    /// assert_eq!(Quirks::from_json(&json::parse(&schip.to_json()).unwrap()), Ok(schip));
+    /// let old = Quirks::from_json(&json::parse(r#"{"hp_shift":false,"mem_inc":true}"#).unwrap()).unwrap();
+    /// assert_eq!(old, Quirks { hp_shift: false, ..Quirks::default() });
+    /// assert!(Quirks::from_json(&json::parse(r#"{"clip_x":1}"#).unwrap()).is_err());
+    /// ```
+    pub fn from_json(v: &json::Value) -> Result<Self, json::ReadError> {
+        if !matches!(v, json::Value::Object(_)) {
+            return Err(json::ReadError::Field("quirks"));
+        }
+        let mut bits = Self::default().to_bits();
+        for &(name, bit) in QUIRK_BITS.iter() {
+            match v.get(name).map(json::Value::as_bool) {
+                Some(Some(true)) => bits |= bit,
+                Some(Some(false)) => bits &= !bit,
+                Some(None) => return Err(json::ReadError::Field(name)),
+                None => {}
+            }
+        }
+        Ok(Self::from_bits(bits))
+    }
+}
+
+// Field names for `Quirks::to_bits`, in the order they're written
+const QUIRK_BITS: [(&str, u8); 6] = [
+    ("clip_x", 1),
+    ("clip_y", 16),
+    ("hp_shift", 2),
+    ("mem_inc", 4),
+    ("mem_inc_x", 32),
+    ("key_release", 8),
+];
+
+/// Where the timers get the time from, in seconds since any fixed point
+pub type Clock = Box<dyn FnMut() -> f64>;
+
+/// Told true when ST starts and false when it stops
+pub type SoundListener = Box<dyn FnMut(bool)>;
+
+/// Shown the machine, PC and opcode before each step, and says whether
+/// to run it
+pub type PreExecHook = Box<dyn FnMut(&Chip8, u16, u16) -> HookAction>;
+
+/// Shown the machine after each step, with the PC and opcode it ran and
+/// how it went
+pub type PostExecHook = Box<dyn FnMut(&Chip8, u16, u16, &Result<StepOutcome, Fault>)>;
+
+/// What a pre-exec hook wants done with the instruction it was shown
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum HookAction {
+    Continue,
+    /// Don't run it or tick the timers, and stop with
+    /// `StepOutcome::Paused`
+    Pause,
+    /// Step past it without running it
+    Skip,
+}
+
+#[derive(Default)]
+struct Hooks {
+    pre: Option<PreExecHook>,
+    post: Option<PostExecHook>,
+}
+
+#[cfg(feature = "core")]
+fn wall_clock() -> Clock {
+    let start = std::time::Instant::now();
+    Box::new(move || start.elapsed().as_secs_f64())
+}
+
+// Nothing to tell the time by, so no time ever passes
+#[cfg(not(feature = "core"))]
+fn wall_clock() -> Clock {
+    Box::new(|| 0.0)
+}
+
+/// A clock that moves on by one step's worth of time every time it's
+/// read, which is once an instruction. The timers then run the same on
+/// every run, however fast the host is, and stand still while nothing
+/// is stepped.
+pub fn step_clock(steps_per_second: u32) -> Clock {
+    let rate = steps_per_second.max(1) as f64;
+    let mut reads = 0u64;
+    Box::new(move || {
+        reads += 1;
+        reads as f64 / rate
+    })
+}
+
+// The RNG, counting the bytes drawn from it so replays can tell when a
+// program drew more or fewer than it did when recorded
+struct CountingRng {
+    inner: Box<dyn rand::RngCore>,
+    drawn: u64,
+}
+
+impl CountingRng {
+    fn new(inner: Box<dyn rand::RngCore>) -> Self {
+        Self { inner, drawn: 0 }
+    }
+
+    fn byte(&mut self) -> u8 {
+        let mut val = [0u8; 1];
+        self.inner.fill_bytes(&mut val);
+        self.drawn += 1;
+        val[0]
+    }
+}
+
+// 60ths of a second since the clock started, a little is added so times
+// made by dividing (like a step clock's) land on whole ticks
+fn ticks(t: f64) -> f64 {
+    (t * 60.0 + 1e-6).floor()
+}
 
 pub struct Chip8 {
-    wrap_tex: bool,
-    hp_shift: bool,
-    mem_inc: bool,
+    quirks: Quirks,
+    clock: Clock,
     // Time of last decrement (timers)
-    last_dec: std::time::Instant,
+    last_dec: f64,
+    // Last time read off the clock
+    now: f64,
+    // When `pause` was called, and instructions stepped since
+    paused: Option<f64>,
+    paused_steps: u32,
+    steps_per_frame: u32,
     // RNG
-    rng: Box<dyn rand::RngCore>,
+    rng: CountingRng,
     // V registers
     v: [u8; 16], // Possibly provide more registers than vanilla
     // I register
@@ -18,28 +405,276 @@ pub struct Chip8 {
     st: u8,
     // Program counter
     pc: u16,
+    // Where ROMs are loaded and where execution begins
+    load: u16,
+    start: u16,
+    // Hash of the ROM last loaded
+    rom_hash: u64,
     // Stack pointer
     sp: u8,
     // Stack
-    stk: [u16; 16], // Bigger stack?
-    // RAM
-    ram: [u8; 4096],
-    pub screen: [u64; 32],
+    stk: [u16; STACK_DEPTH_CLASSIC], // Bigger stack?
+    // Levels of the stack CALL may use
+    stack_depth: u8,
+    // RAM, a power of two from `MIN_RAM` to `MAX_RAM` bytes. PC and I
+    // wrap around at the end.
+    ram: Vec<u8>,
+    // Bit n set while key n is down
+    keys: u16,
+    // Between `begin_frame` and `end_frame`, and keys set in between,
+    // which wait for the frame to end
+    in_frame: bool,
+    next_keys: Option<u16>,
+    // Frames ended, instructions retired, and retired since the frame began
+    frames: u64,
+    retired: u64,
+    frame_step: u32,
+    // Key Fx0A saw go down and is waiting to come up, with `key_release`
+    waiting_key: Option<u8>,
+    pub screen: [u64; LORES_HEIGHT],
+    // Bit n set when screen row n may have changed since `take_dirty`
+    dirty: u32,
+    // Undo information for recent instructions, newest last
+    journal: Option<Journal>,
+    // Watches for the program writing over its own code
+    smc: Option<Box<SmcMonitor>>,
+    // Tried in order on words that aren't instructions
+    extensions: Vec<Extension>,
+    // Check invariants after every instruction
+    validate: bool,
+    // Run DT wait loops without decoding them, and steps that were
+    idle_skip: bool,
+    idle_steps: u64,
+    on_unknown: OnUnknown,
+    on_odd_pc: OnOddPc,
+    // SYS faults, with an interpreter image
+    native_calls: bool,
+    sound_listener: Option<SoundListener>,
+    // Called every time the timers tick
+    on_vblank: Option<Box<dyn FnMut()>>,
+    // Boxed so a machine without them only checks for null
+    hooks: Option<Box<Hooks>>,
+}
+
+// Something an instruction is about to overwrite, so it can be put back
+#[derive(Clone)]
+enum Old {
+    Ram(u16, u8),
+    Stack(u8, u16),
+    Row(u8, u64),
+}
+
+// Enough to undo one instruction. Registers and timers are small so they
+// are always kept, memory, the stack and the screen only where written.
+#[derive(Clone)]
+struct Undo {
+    pc: u16,
+    i: u16,
+    sp: u8,
+    dt: u8,
+    st: u8,
+    v: [u8; 16],
+    frames: u64,
+    retired: u64,
+    frame_step: u32,
+    old: Vec<Old>,
+}
+
+#[derive(Clone)]
+struct Journal {
+    entries: VecDeque<Undo>,
+    cap: usize,
+}
+
+/// A write by the program to memory it had already run as code
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct SmcWrite {
+    /// Instruction that did the writing
+    pub pc: u16,
+    pub addr: u16,
+    pub old: u8,
+    pub new: u8,
+}
+
+#[derive(Clone)]
+struct SmcMonitor {
+    // Bit per byte of RAM fetched as part of an instruction
+    executed: Vec<u64>,
+    writes: Vec<SmcWrite>,
+}
+
+impl SmcMonitor {
+    fn new(ram: usize) -> SmcMonitor {
+        SmcMonitor {
+            executed: vec![0; ram.div_ceil(64)],
+            writes: Vec::new(),
+        }
+    }
+
+    fn executed(&self, addr: usize) -> bool {
+        self.executed
+            .get(addr / 64)
+            .is_some_and(|w| w >> (addr % 64) & 1 == 1)
+    }
+
+    fn mark(&mut self, addr: usize) {
+        self.executed[addr / 64] |= 1 << (addr % 64);
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Fault {
+    /// Word at `pc` isn't an instruction
+    UnknownOpcode { pc: u16, opcode: u16 },
+    /// PC is past the end of RAM
+    PcOutOfRange(u16),
+    /// PC is odd, with `OnOddPc::Fault`
+    MisalignedPc(u16),
+    /// SYS to `addr`, which with an interpreter image would run its
+    /// machine code
+    NativeCall { pc: u16, addr: u16 },
+    /// An extension handler gave up on the word at `pc`
+    Extension {
+        pc: u16,
+        opcode: u16,
+        msg: &'static str,
+    },
+    /// Running the word at `pc` left the machine in a state it should
+    /// never be in, which is a bug in the emulator or an extension
+    Invariant {
+        pc: u16,
+        opcode: u16,
+        broken: &'static str,
+    },
+}
+
+impl std::fmt::Display for Fault {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::UnknownOpcode { pc, opcode } => {
+                write!(f, "unknown opcode {:04X} at {:03X}", opcode, pc)?;
+                match crate::info::hint(*opcode) {
+                    Some(hint) => write!(f, " ({})", hint),
+                    None => Ok(()),
+                }
+            }
+            Self::PcOutOfRange(pc) => write!(f, "PC {:04X} is outside of RAM", pc),
+            Self::MisalignedPc(pc) => write!(f, "PC {:04X} is odd", pc),
+            Self::NativeCall { pc, addr } => write!(
+                f,
+                "SYS {:03X} at {:03X}: native interpreter calls are not emulated",
+                addr, pc
+            ),
+            Self::Extension { pc, opcode, msg } => {
+                write!(f, "extension opcode {:04X} at {:03X}: {}", opcode, pc, msg)
+            }
+            Self::Invariant { pc, opcode, broken } => {
+                write!(
+                    f,
+                    "{:04X} at {:03X} broke an invariant: {}",
+                    opcode, pc, broken
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for Fault {}
+
+/// What an extension opcode can see and change
+pub struct Chip8Context<'a> {
+    pub v: &'a mut [u8; 16],
+    pub i: &'a mut u16,
+    pub dt: &'a mut u8,
+    pub st: &'a mut u8,
+    pub ram: &'a mut [u8],
+    pub screen: &'a mut [u64; LORES_HEIGHT],
+    /// Bit n set while key n is down
+    pub keys: u16,
+    /// `Chip8::frames`, `Chip8::retired` and `Chip8::frame_step`, not
+    /// counting this instruction
+    pub frames: u64,
+    pub retired: u64,
+    pub frame_step: u32,
+}
+
+/// How an extension opcode finished
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ExtResult {
+    Done,
+    /// Skip the next instruction, like SE and SKP do
+    Skip,
+    /// Stop with `Fault::Extension`
+    Fault(&'static str),
+}
+
+pub type ExtHandler = Box<dyn FnMut(&mut Chip8Context, u16) -> ExtResult>;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ExtensionError {
+    /// The pattern has bits set outside the mask so can never match
+    OutsideMask,
+    /// Some word would match both this and the extension with this mask
+    /// and pattern
+    Overlaps { mask: u16, pattern: u16 },
+}
+
+impl std::fmt::Display for ExtensionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::OutsideMask => write!(f, "pattern has bits outside the mask"),
+            Self::Overlaps { mask, pattern } => write!(
+                f,
+                "overlaps the extension with mask {:04X} and pattern {:04X}",
+                mask, pattern
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ExtensionError {}
+
+struct Extension {
+    mask: u16,
+    pattern: u16,
+    handler: ExtHandler,
+}
+
+/// `op` in the usual assembly syntax, or `???` if it isn't an instruction
+pub fn disassemble(op: u16) -> String {
+    match Instruction::decode(&op) {
+        Some(i) => i.to_string(),
+        None => "???".to_string(),
+    }
+}
+
+/// Which instruction `op` is, with its operands written out as in
+/// Cowgod's reference (`ADD Vx, byte`), or `None` if it isn't one
+pub fn mnemonic(op: u16) -> Option<&'static str> {
+    Instruction::decode(&op).map(|i| i.form())
+}
+
+/// Whether `op` decodes to an instruction this emulator can run
+pub fn is_instruction(op: u16) -> bool {
+    Instruction::decode(&op).is_some()
 }
 
 // Memory address (12 bits)
 #[repr(transparent)]
 #[derive(Debug, Copy, Clone)]
-struct Addr(pub u16);
+pub(crate) struct Addr(pub u16);
 
 // V register (4 bits)
 #[repr(transparent)]
 #[derive(Debug, Copy, Clone)]
-struct VReg(pub u8);
+pub(crate) struct VReg(pub u8);
 
 #[non_exhaustive]
+#[allow(clippy::upper_case_acronyms, dead_code)]
 #[derive(Debug, Copy, Clone)]
-enum Instruction {
+pub(crate) enum Instruction {
+    /// Call native interpreter routine (ignored)
+    SYS(Addr),
     /// Clear screen
     CLS,
     /// Return from subroutine
@@ -86,9 +721,9 @@ enum Instruction {
     RND(VReg, u8),
     /// Display (nibble)
     DRW(VReg, VReg, u8),
-    /// Skip next if key Vx is pressed
+    /// Skip next if key Vx is pressed, only the low nibble of Vx counts
     SKP(VReg),
-    /// Skip next if key Vx is not pressed
+    /// Skip next if key Vx is not pressed, only the low nibble of Vx counts
     SKNP(VReg),
     /// Vx = DT
     LDVD(VReg),
@@ -110,7 +745,138 @@ enum Instruction {
     LDVM(VReg),
 }
 
+impl std::fmt::Display for Instruction {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        use Instruction::*;
+        match *self {
+            SYS(a) => write!(f, "SYS {:03X}", a.0),
+            CLS => write!(f, "CLS"),
+            RET => write!(f, "RET"),
+            JP(a) => write!(f, "JP {:03X}", a.0),
+            CALL(a) => write!(f, "CALL {:03X}", a.0),
+            SEB(x, b) => write!(f, "SE V{:X}, {:02X}", x.0, b),
+            SNEB(x, b) => write!(f, "SNE V{:X}, {:02X}", x.0, b),
+            SEV(x, y) => write!(f, "SE V{:X}, V{:X}", x.0, y.0),
+            LDB(x, b) => write!(f, "LD V{:X}, {:02X}", x.0, b),
+            ADDB(x, b) => write!(f, "ADD V{:X}, {:02X}", x.0, b),
+            LDV(x, y) => write!(f, "LD V{:X}, V{:X}", x.0, y.0),
+            OR(x, y) => write!(f, "OR V{:X}, V{:X}", x.0, y.0),
+            AND(x, y) => write!(f, "AND V{:X}, V{:X}", x.0, y.0),
+            XOR(x, y) => write!(f, "XOR V{:X}, V{:X}", x.0, y.0),
+            ADDC(x, y) => write!(f, "ADD V{:X}, V{:X}", x.0, y.0),
+            SUB(x, y) => write!(f, "SUB V{:X}, V{:X}", x.0, y.0),
+            SHR(x, y) => write!(f, "SHR V{:X}, V{:X}", x.0, y.0),
+            SUBN(x, y) => write!(f, "SUBN V{:X}, V{:X}", x.0, y.0),
+            SHL(x, y) => write!(f, "SHL V{:X}, V{:X}", x.0, y.0),
+            SNEV(x, y) => write!(f, "SNE V{:X}, V{:X}", x.0, y.0),
+            LDI(a) => write!(f, "LD I, {:03X}", a.0),
+            JPV(a) => write!(f, "JP V0, {:03X}", a.0),
+            RND(x, b) => write!(f, "RND V{:X}, {:02X}", x.0, b),
+            DRW(x, y, n) => write!(f, "DRW V{:X}, V{:X}, {:X}", x.0, y.0, n),
+            SKP(x) => write!(f, "SKP V{:X}", x.0),
+            SKNP(x) => write!(f, "SKNP V{:X}", x.0),
+            LDVD(x) => write!(f, "LD V{:X}, DT", x.0),
+            LDK(x) => write!(f, "LD V{:X}, K", x.0),
+            LDDV(x) => write!(f, "LD DT, V{:X}", x.0),
+            LDSV(x) => write!(f, "LD ST, V{:X}", x.0),
+            ADDI(x) => write!(f, "ADD I, V{:X}", x.0),
+            LDIS(x) => write!(f, "LD F, V{:X}", x.0),
+            LDD(x) => write!(f, "LD B, V{:X}", x.0),
+            LDMV(x) => write!(f, "LD [I], V{:X}", x.0),
+            LDVM(x) => write!(f, "LD V{:X}, [I]", x.0),
+        }
+    }
+}
+
 impl Instruction {
+    fn form(&self) -> &'static str {
+        use Instruction::*;
+        match self {
+            SYS(_) => "SYS addr",
+            CLS => "CLS",
+            RET => "RET",
+            JP(_) => "JP addr",
+            CALL(_) => "CALL addr",
+            SEB(..) => "SE Vx, byte",
+            SNEB(..) => "SNE Vx, byte",
+            SEV(..) => "SE Vx, Vy",
+            LDB(..) => "LD Vx, byte",
+            ADDB(..) => "ADD Vx, byte",
+            LDV(..) => "LD Vx, Vy",
+            OR(..) => "OR Vx, Vy",
+            AND(..) => "AND Vx, Vy",
+            XOR(..) => "XOR Vx, Vy",
+            ADDC(..) => "ADD Vx, Vy",
+            SUB(..) => "SUB Vx, Vy",
+            SHR(..) => "SHR Vx, Vy",
+            SUBN(..) => "SUBN Vx, Vy",
+            SHL(..) => "SHL Vx, Vy",
+            SNEV(..) => "SNE Vx, Vy",
+            LDI(_) => "LD I, addr",
+            JPV(_) => "JP V0, addr",
+            RND(..) => "RND Vx, byte",
+            DRW(..) => "DRW Vx, Vy, nibble",
+            SKP(_) => "SKP Vx",
+            SKNP(_) => "SKNP Vx",
+            LDVD(_) => "LD Vx, DT",
+            LDK(_) => "LD Vx, K",
+            LDDV(_) => "LD DT, Vx",
+            LDSV(_) => "LD ST, Vx",
+            ADDI(_) => "ADD I, Vx",
+            LDIS(_) => "LD F, Vx",
+            LDD(_) => "LD B, Vx",
+            LDMV(_) => "LD [I], Vx",
+            LDVM(_) => "LD Vx, [I]",
+        }
+    }
+
+    // The word `decode` gives this back from. Out of range operands are
+    // cut down to their bits.
+    pub fn encode(&self) -> u16 {
+        use Instruction::*;
+        let x = |op: u16, x: VReg| op | (x.0 as u16 & 0xF) << 8;
+        let xy = |op: u16, vx: VReg, y: VReg| x(op, vx) | (y.0 as u16 & 0xF) << 4;
+        let xkk = |op: u16, vx: VReg, kk: u8| x(op, vx) | kk as u16;
+        let a = |op: u16, a: Addr| op | a.0 & 0xFFF;
+        match *self {
+            SYS(addr) => a(0x0000, addr),
+            CLS => 0x00E0,
+            RET => 0x00EE,
+            JP(addr) => a(0x1000, addr),
+            CALL(addr) => a(0x2000, addr),
+            SEB(vx, kk) => xkk(0x3000, vx, kk),
+            SNEB(vx, kk) => xkk(0x4000, vx, kk),
+            SEV(vx, y) => xy(0x5000, vx, y),
+            LDB(vx, kk) => xkk(0x6000, vx, kk),
+            ADDB(vx, kk) => xkk(0x7000, vx, kk),
+            LDV(vx, y) => xy(0x8000, vx, y),
+            OR(vx, y) => xy(0x8001, vx, y),
+            AND(vx, y) => xy(0x8002, vx, y),
+            XOR(vx, y) => xy(0x8003, vx, y),
+            ADDC(vx, y) => xy(0x8004, vx, y),
+            SUB(vx, y) => xy(0x8005, vx, y),
+            SHR(vx, y) => xy(0x8006, vx, y),
+            SUBN(vx, y) => xy(0x8007, vx, y),
+            SHL(vx, y) => xy(0x800E, vx, y),
+            SNEV(vx, y) => xy(0x9000, vx, y),
+            LDI(addr) => a(0xA000, addr),
+            JPV(addr) => a(0xB000, addr),
+            RND(vx, kk) => xkk(0xC000, vx, kk),
+            DRW(vx, y, n) => xy(0xD000, vx, y) | (n as u16 & 0xF),
+            SKP(vx) => x(0xE09E, vx),
+            SKNP(vx) => x(0xE0A1, vx),
+            LDVD(vx) => x(0xF007, vx),
+            LDK(vx) => x(0xF00A, vx),
+            LDDV(vx) => x(0xF015, vx),
+            LDSV(vx) => x(0xF018, vx),
+            ADDI(vx) => x(0xF01E, vx),
+            LDIS(vx) => x(0xF029, vx),
+            LDD(vx) => x(0xF033, vx),
+            LDMV(vx) => x(0xF055, vx),
+            LDVM(vx) => x(0xF065, vx),
+        }
+    }
+
     pub fn decode(ins: &u16) -> Option<Self> {
         use Instruction::*;
         match ins {
@@ -126,8 +892,7 @@ impl Instruction {
         let y = VReg(((ins & 0x00F0) >> 4u16) as u8);
         let kk = (ins & 0x00FF) as u8;
         match i {
-            // SYS
-            0x0000 => None,
+            0x0000 => Some(SYS(addr)),
             0x1000 => Some(JP(addr)),
             0x2000 => Some(CALL(addr)),
             0x3000 => Some(SEB(x, kk)),
@@ -174,13 +939,17 @@ impl Instruction {
     pub fn execute(&self, c8: &mut Chip8) {
         use Instruction::*;
         match self {
-            CLS => c8.screen = [0u64; 32],
+            SYS(_) => {}
+            CLS => {
+                c8.screen = [0; LORES_HEIGHT];
+                c8.dirty = u32::MAX;
+            }
             RET => {
-                let sp = c8.sp as usize;
-                if sp > 0 {
+                if c8.sp > 0 {
+                    c8.sp -= 1;
+                    let sp = c8.sp as usize;
                     c8.pc = c8.stk[sp];
                     c8.stk[sp] = 0;
-                    c8.sp -= 1;
                 }
             }
             JP(addr) => {
@@ -190,7 +959,7 @@ impl Instruction {
             }
             CALL(addr) => {
                 let sp = c8.sp as usize;
-                if sp < c8.stk.len() {
+                if sp < c8.stack_depth as usize {
                     c8.stk[sp] = c8.pc;
                     c8.pc = addr.0;
                     c8.sp += 1;
@@ -198,17 +967,17 @@ impl Instruction {
             }
             SEB(x, kk) => {
                 if *c8.get_v(x) == *kk {
-                    c8.pc += 2
+                    c8.skip_next()
                 }
             }
             SNEB(x, kk) => {
                 if *c8.get_v(x) != *kk {
-                    c8.pc += 2
+                    c8.skip_next()
                 }
             }
             SEV(x, y) => {
                 if *c8.get_v(x) == *c8.get_v(y) {
-                    c8.pc += 2
+                    c8.skip_next()
                 }
             }
             LDB(x, kk) => *c8.get_v(x) = *kk,
@@ -231,13 +1000,10 @@ impl Instruction {
                 c8.v[15] = if flag { 0 } else { 1 };
             }
             SHR(x, y) => {
-                if c8.hp_shift {
-                    c8.v[15] = *c8.get_v(x) & 1;
-                    *c8.get_v(x) = *c8.get_v(x) >> 1;
-                } else {
-                    c8.v[15] = *c8.get_v(y) & 1;
-                    *c8.get_v(x) = *c8.get_v(y) >> 1;
-                }
+                let src = *c8.get_v(if c8.quirks.hp_shift { x } else { y });
+                // The flag goes last, so it wins when Vx is VF
+                *c8.get_v(x) = src >> 1;
+                c8.v[15] = src & 1;
             }
             SUBN(x, y) => {
                 let (v, flag) = c8.get_v(y).overflowing_sub(*c8.get_v(x));
@@ -245,123 +1011,1590 @@ impl Instruction {
                 c8.v[15] = if flag { 0 } else { 1 };
             }
             SHL(x, y) => {
-                if c8.hp_shift {
-                    c8.v[15] = *c8.get_v(x) >> 7;
-                    *c8.get_v(x) = *c8.get_v(x) << 1;
-                } else {
-                    c8.v[15] = *c8.get_v(y) >> 7;
-                    *c8.get_v(x) = *c8.get_v(y) << 1;
-                }
+                let src = *c8.get_v(if c8.quirks.hp_shift { x } else { y });
+                *c8.get_v(x) = src << 1;
+                c8.v[15] = src >> 7;
             }
             SNEV(x, y) => {
                 if *c8.get_v(x) != *c8.get_v(y) {
-                    c8.pc += 2
+                    c8.skip_next()
                 }
             }
-            LDI(addr) => {
-                if (addr.0 as usize) < c8.ram.len() {
-                    c8.i = addr.0
-                }
-            }
-            JPV(addr) => c8.pc = addr.0 + c8.v[0] as u16,
+            LDI(addr) => c8.i = addr.0 & c8.addr_mask(),
+            JPV(addr) => c8.pc = (addr.0 + c8.v[0] as u16) & c8.addr_mask(),
             RND(x, kk) => {
-                let mut val = [0u8; 1];
-                c8.rng.fill_bytes(&mut val);
-                *c8.get_v(x) = val[0] & kk;
+                *c8.get_v(x) = c8.rng.byte() & kk;
             }
             DRW(x, y, n) => {
-                let i = c8.i as usize;
                 let sz = *n as usize;
-                if sz > 0 && i + sz <= c8.ram.len() {
-                    let (px, py) = if c8.wrap_tex {
-                        (*c8.get_v(x) as usize, *c8.get_v(y) as usize)
-                    } else {
-                        (
-                            ((*c8.get_v(x)) % 64) as usize,
-                            ((*c8.get_v(y)) % 32) as usize,
-                        )
-                    };
+                if sz > 0 {
+                    let px = (*c8.get_v(x) as usize % LORES_WIDTH) as u32;
+                    let py = *c8.get_v(y) as usize % LORES_HEIGHT;
                     c8.v[15] = 0;
-                    for r in 0..sz {
-                        let sprite = c8.ram[i + r];
-                        let cy = py + r;
-                        if cy < c8.screen.len() {
-                            c8.screen[cy] ^= if px > 56 {
-                                (sprite as u64) >> (px - 56)
-                            } else {
-                                (sprite as u64) << (56 - px)
-                            };
+                    for (r, cy) in c8.sprite_rows(py, sz) {
+                        // The sprite at the left edge, then moved over
+                        let row = (c8.ram[c8.at_i(r)] as u64) << 56;
+                        let bits = if c8.quirks.clip_x {
+                            row >> px
+                        } else {
+                            row.rotate_right(px)
+                        };
+                        c8.dirty |= 1 << cy;
+                        if c8.screen[cy] & bits != 0 {
+                            c8.v[15] = 1;
                         }
+                        c8.screen[cy] ^= bits;
                     }
                 }
             }
-            SKP(_) => {}           // TODO
-            SKNP(_) => c8.pc += 2, // TODO
+            SKP(x) => {
+                let k = *c8.get_v(x);
+                if c8.key_down(k) {
+                    c8.skip_next()
+                }
+            }
+            SKNP(x) => {
+                let k = *c8.get_v(x);
+                if !c8.key_down(k) {
+                    c8.skip_next()
+                }
+            }
             LDVD(x) => *c8.get_v(x) = c8.dt,
-            LDK(_) => unimplemented!(),
+            LDK(x) => match c8.waiting_key {
+                // Run this again until the key comes back up
+                Some(k) if c8.key_down(k) => c8.repeat(),
+                Some(k) => {
+                    *c8.get_v(x) = k;
+                    c8.waiting_key = None;
+                }
+                None => match c8.first_pressed_key() {
+                    // Run this again until a key is down
+                    None => c8.repeat(),
+                    Some(k) if c8.quirks.key_release => {
+                        c8.waiting_key = Some(k);
+                        c8.repeat();
+                    }
+                    Some(k) => *c8.get_v(x) = k,
+                },
+            },
             LDDV(x) => c8.dt = *c8.get_v(x),
             LDSV(x) => c8.st = *c8.get_v(x),
-            ADDI(x) => c8.i += *c8.get_v(x) as u16,
-            LDIS(x) => c8.i = 0x050 + (*c8.get_v(x) * 5) as u16,
+            ADDI(x) => c8.i = c8.i.wrapping_add(*c8.get_v(x) as u16) & c8.addr_mask(),
+            LDIS(x) => c8.i = layout::font_sprite_addr(*c8.get_v(x)) & c8.addr_mask(),
             LDD(x) => {
-                let i = c8.i as usize;
-                if i + 2 < c8.ram.len() {
-                    let num = *c8.get_v(x);
-                    c8.ram[i] = num / 100;
-                    c8.ram[i + 1] = (num / 10) % 10;
-                    c8.ram[i + 2] = num % 10;
+                let num = *c8.get_v(x);
+                let digits = [num / 100, (num / 10) % 10, num % 10];
+                for (k, &d) in digits.iter().enumerate() {
+                    let a = c8.at_i(k);
+                    c8.ram[a] = d;
                 }
             }
             LDMV(x) => {
-                let i = c8.i as usize;
-                let space = x.0 as usize;
-                if i + space < c8.ram.len() && space < c8.v.len() {
-                    c8.ram[i..=i + space].copy_from_slice(&c8.v[0..=space]);
-                    if c8.mem_inc {
-                        c8.i += x.0 as u16 + 1;
-                    }
+                for r in 0..=x.0 as usize {
+                    let a = c8.at_i(r);
+                    c8.ram[a] = c8.v[r];
                 }
+                c8.i = c8.i.wrapping_add(c8.quirks.mem_inc.amount(x.0)) & c8.addr_mask();
             }
             LDVM(x) => {
-                let i = c8.i as usize;
-                let space = x.0 as usize;
-                if i + space < c8.ram.len() && space < c8.v.len() {
-                    c8.v[0..=space].copy_from_slice(&c8.ram[i..=i + space]);
-                    if c8.mem_inc {
-                        c8.i += x.0 as u16 + 1;
-                    }
+                for r in 0..=x.0 as usize {
+                    c8.v[r] = c8.ram[c8.at_i(r)];
                 }
+                c8.i = c8.i.wrapping_add(c8.quirks.mem_inc.amount(x.0)) & c8.addr_mask();
             }
         }
     }
 }
 
-impl Chip8 {
-    pub fn new(int: &[u8], rom: &[u8], rng: Box<dyn rand::RngCore>) -> Self {
-        let mut ram: Vec<u8> = Vec::with_capacity(4096);
-        ram.extend_from_slice(&int);
-        ram.resize(512, 0);
-        ram.extend_from_slice(&rom);
-        ram.resize(4096, 0);
+/// What a step did, when it didn't fault
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum StepOutcome {
+    Ran,
+    /// Fx0A is still waiting for a key, so PC didn't move
+    WaitingForKey,
+    /// Jumped to itself, which programs do when they're finished
+    Halted,
+    /// Passed over a word that isn't an instruction, with `OnUnknown::Warn`
+    Skipped {
+        pc: u16,
+        opcode: u16,
+    },
+    /// A pre-exec hook stopped before the instruction at PC, which didn't
+    /// run
+    Paused,
+}
 
-        Self {
-            wrap_tex: true,
-            hp_shift: true,
-            mem_inc: true,
-            last_dec: std::time::Instant::now(),
-            rng: Box::new(rng),
+/// What to do on reaching a word that isn't an instruction or extension
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum OnUnknown {
+    /// Carry on with the next word as if it were SYS
+    Skip,
+    /// Stop with `Fault::UnknownOpcode`
+    Halt,
+    /// Skip it, but say so with `StepOutcome::Skipped`
+    Warn,
+}
+
+/// What to do on fetching from an odd address
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum OnOddPc {
+    /// Fetch the two bytes there, straddling the words either side
+    Allow,
+    /// Stop with `Fault::MisalignedPc`
+    Fault,
+}
+
+/// Iterator over steps, from `Chip8::steps`
+pub struct Steps<'a> {
+    c8: &'a mut Chip8,
+    done: bool,
+}
+
+impl Iterator for Steps<'_> {
+    type Item = Result<StepOutcome, Fault>;
+
+    /// Steps once. Halting, pausing and faults are the last thing given.
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let res = self.c8.step();
+        self.done = matches!(res, Ok(StepOutcome::Halted | StepOutcome::Paused) | Err(_));
+        Some(res)
+    }
+}
+
+/// Why `Chip8Builder::build` refused
+#[derive(Debug)]
+pub enum BuildError {
+    /// The ROM doesn't fit at the load address
+    Rom(RomError),
+    /// More bytes than fit below 0x200
+    FontTooLarge(usize),
+    /// Not somewhere an instruction can be read from
+    BadStart(u16),
+    /// The ROM would be loaded over the font
+    LoadOverFont { load: u16, font: usize },
+    /// Only 1 to 16 levels are supported
+    StackDepth(usize),
+    /// Digit glyphs that aren't a whole font
+    Glyphs(FontError),
+    /// Not a power of two from `MIN_RAM` to `MAX_RAM`
+    RamSize(usize),
+}
+
+impl std::fmt::Display for BuildError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::Rom(e) => write!(f, "{}", e),
+            Self::FontTooLarge(n) => write!(f, "{} byte font doesn't fit below 0x200", n),
+            Self::BadStart(a) => write!(f, "can't start running at {:#05X}", a),
+            Self::LoadOverFont { load, font } => write!(
+                f,
+                "loading at {:#05X} would overwrite the {} byte font",
+                load, font
+            ),
+            Self::StackDepth(n) => write!(f, "stack depth {} isn't between 1 and 16", n),
+            Self::Glyphs(e) => write!(f, "{}", e),
+            Self::RamSize(n) => write!(
+                f,
+                "{} bytes of RAM isn't a power of two from {} to {}",
+                n, MIN_RAM, MAX_RAM
+            ),
+        }
+    }
+}
+
+impl std::error::Error for BuildError {}
+
+impl From<RomError> for BuildError {
+    fn from(e: RomError) -> Self {
+        Self::Rom(e)
+    }
+}
+
+/// Whether a machine can have `n` bytes of RAM
+pub fn is_ram_size(n: usize) -> bool {
+    n.is_power_of_two() && (MIN_RAM..=MAX_RAM).contains(&n)
+}
+
+/// Settings for a new `Chip8`, from `Chip8::builder`
+pub struct Chip8Builder<'a> {
+    font: &'a [u8],
+    glyphs: &'a [u8],
+    font_overlay: bool,
+    native_calls: bool,
+    rom: &'a [u8],
+    quirks: Quirks,
+    rng: Option<Box<dyn rand::RngCore>>,
+    clock: Option<Clock>,
+    seed: u64,
+    load: u16,
+    start: Option<u16>,
+    stack_depth: usize,
+    ram_size: usize,
+    validate: bool,
+    on_unknown: OnUnknown,
+    on_odd_pc: OnOddPc,
+    steps_per_frame: u32,
+}
+
+impl<'a> Chip8Builder<'a> {
+    /// Copied to the start of RAM, the interpreter area
+    pub fn font(mut self, font: &'a [u8]) -> Self {
+        self.font = font;
+        self
+    }
+
+    /// An original interpreter's image, up to 512 bytes copied to the
+    /// start of RAM like `font`. Its machine code isn't run, so SYS stops
+    /// with `Fault::NativeCall` rather than being ignored. An empty image
+    /// is the same as none.
+    ///
+    /// ```
+    /// use chip8::emu::{Chip8, Fault};
+    /// use chip8::font;
+    ///
+    /// let mut own = [0u8; 0x200];
+    /// own[0x50..0xA0].copy_from_slice(&font::VIP);
+    /// own[0x1F0] = 0xEE;
+    /// let bare = [0xEEu8; 0x100];
+    /// let sys = [0x01, 0xF2]; // SYS 1F2
+    ///
+    /// let glyphs = |c8: &Chip8| (0x50..0xA0).map(|a| c8.peek(a)).collect::<Vec<_>>();
+    /// let build = |image: &[u8], overlay| {
+    ///     Chip8::builder().interpreter(image).font_overlay(overlay).rom(&sys).build().unwrap()
+    /// };
+    /// // An image with its own digits keeps them, overlay or not
+    /// assert_eq!(glyphs(&build(&own, true)), font::VIP);
+    /// assert_eq!(glyphs(&build(&own, false)), font::VIP);
+    /// // One without gets the built-in ones, unless that's turned off
+    /// let mut c8 = build(&bare, true);
+    /// assert_eq!(glyphs(&c8), font::DEFAULT);
+    /// assert_eq!((c8.peek(0x4F), c8.peek(0xA0)), (0xEE, 0xEE));
+    /// assert_eq!(glyphs(&build(&bare, false)), [0xEE; 80]);
+    ///
+    /// // SYS can't call into the image
+    /// let err = c8.step().unwrap_err();
+    /// assert_eq!(err, Fault::NativeCall { pc: 0x200, addr: 0x1F2 });
+    /// assert_eq!(err.to_string(), "SYS 1F2 at 200: native interpreter calls are not emulated");
+    /// assert_eq!(c8.run_steps_fast(1), Err(err));
+    ///
+    /// // Without an image SYS does nothing, and the digits are built in
+    /// for mut c8 in [Chip8::builder().rom(&sys).build().unwrap(), build(&[], true)] {
+    ///     assert!(c8.step().is_ok());
+    ///     assert_eq!(glyphs(&c8), font::DEFAULT);
+    /// }
+    /// ```
+    pub fn interpreter(mut self, image: &'a [u8]) -> Self {
+        self.font = image;
+        self.native_calls = !image.is_empty();
+        self
+    }
+
+    /// Digit glyphs for Fx29, written at `FONT_ADDR` over whatever `font`
+    /// put there. `font::SMALL_LEN` bytes, or `font::BIG_LEN` more with the
+    /// big digits after them.
+    pub fn glyphs(mut self, glyphs: &'a [u8]) -> Self {
+        self.glyphs = glyphs;
+        self
+    }
+
+    /// Whether `font::DEFAULT` is written at `FONT_ADDR` when `glyphs`
+    /// isn't given and `font` has none there, on unless set
+    pub fn font_overlay(mut self, on: bool) -> Self {
+        self.font_overlay = on;
+        self
+    }
+
+    pub fn rom(mut self, rom: &'a [u8]) -> Self {
+        self.rom = rom;
+        self
+    }
+
+    pub fn quirks(mut self, quirks: Quirks) -> Self {
+        self.quirks = quirks;
+        self
+    }
+
+    /// Seeds the default RNG, ignored if `rng` is given
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    pub fn rng(mut self, rng: Box<dyn rand::RngCore>) -> Self {
+        self.rng = Some(rng);
+        self
+    }
+
+    /// The wall clock unless set
+    pub fn clock(mut self, clock: Clock) -> Self {
+        self.clock = Some(clock);
+        self
+    }
+
+    /// Instructions run in a 60th of a second, 10 unless set. Only used to
+    /// tick the timers when stepping while paused.
+    pub fn steps_per_frame(mut self, n: u32) -> Self {
+        self.steps_per_frame = n.max(1);
+        self
+    }
+
+    /// Where the ROM goes, 0x200 unless set
+    pub fn load_address(mut self, load: u16) -> Self {
+        self.load = load;
+        self
+    }
+
+    /// Where running starts, the load address unless set
+    pub fn start_address(mut self, start: u16) -> Self {
+        self.start = Some(start);
+        self
+    }
+
+    /// Bytes of RAM, a power of two from `MIN_RAM` (the default) to
+    /// `MAX_RAM`. PC and I wrap around at the end of it, so XO-CHIP
+    /// programs can use all 64 KB.
+    ///
+    /// ```
+    /// use chip8::emu::{BuildError, Chip8};
+    /// use chip8::state::SaveState;
+    ///
+    /// let rom = [
+    ///     0xAF, 0xFF, // LD I, FFF
+    ///     0x60, 0x11, // LD V0, 11
+    ///     0xF0, 0x1E, // ADD I, V0
+    ///     0x60, 0xAB, // LD V0, AB
+    ///     0x61, 0xCD, // LD V1, CD
+    ///     0xF1, 0x55, // LD [I], V1
+    ///     0x60, 0x00, // LD V0, 00
+    ///     0x61, 0x00, // LD V1, 00
+    ///     0xAF, 0xFF, // LD I, FFF
+    ///     0x62, 0x11, // LD V2, 11
+    ///     0xF2, 0x1E, // ADD I, V2
+    ///     0xF1, 0x65, // LD V1, [I]
+    /// ];
+    /// let run = |size| {
+    ///     let mut c8 = Chip8::builder().rom(&rom).ram_size(size).build().unwrap();
+    ///     c8.run_n(12).unwrap();
+    ///     (c8.v()[0], c8.v()[1], c8.i())
+    /// };
+    /// // Stored and read back above 0x1000
+    /// assert_eq!(run(0x10000), (0xAB, 0xCD, 0x1012));
+    /// // With 4 KB I wraps to 0x010, over the font
+    /// assert_eq!(run(0x1000), (0xAB, 0xCD, 0x012));
+    ///
+    /// // Save states keep the size, and a machine loading one takes it on
+    /// let mut c8 = Chip8::builder().rom(&rom).ram_size(0x10000).build().unwrap();
+    /// c8.run_n(6).unwrap();
+    /// let bytes = c8.save_state().to_bytes();
+    /// let state = SaveState::from_bytes(&bytes).unwrap();
+    /// assert_eq!(state.ram.len(), 0x10000);
+    /// let mut small = Chip8::builder().rom(&rom).build().unwrap();
+    /// small.load_state(&state);
+    /// assert_eq!((small.peek(0x1010), small.peek(0x1011)), (0xAB, 0xCD));
+    /// assert!(matches!(
+    ///     Chip8::builder().ram_size(6000).build(),
+    ///     Err(BuildError::RamSize(6000))
+    /// ));
+    /// ```
+    pub fn ram_size(mut self, bytes: usize) -> Self {
+        self.ram_size = bytes;
+        self
+    }
+
+    /// How many calls can be nested, 16 unless set
+    pub fn stack_depth(mut self, depth: usize) -> Self {
+        self.stack_depth = depth;
+        self
+    }
+
+    /// Checks the machine after every instruction, which is slow. A
+    /// broken invariant panics in debug builds and is `Fault::Invariant`
+    /// in release builds. On by default in debug builds only.
+    ///
+    /// ```should_panic
+    /// use chip8::emu::{Chip8, ExtResult};
+    ///
+    /// let mut c8 = Chip8::builder().rom(&[0x50, 0x01]).validate(true).build().unwrap();
+    /// // A buggy extension that points I past the end of RAM
+    /// c8.register_extension(0xFFFF, 0x5001, Box::new(|ctx, _| {
+    ///     *ctx.i = 0x1234;
+    ///     ExtResult::Done
+    /// }))
+    /// .unwrap();
+    /// c8.step().unwrap();
+    /// ```
+    pub fn validate(mut self, validate: bool) -> Self {
+        self.validate = validate;
+        self
+    }
+
+    /// `OnUnknown::Halt` unless set
+    ///
+    /// ```
+    /// use chip8::emu::{Chip8, Fault, OnUnknown, StepOutcome};
+    ///
+    /// // F075 is SUPER-CHIP's LD R, V0
+    /// let rom = [0xF0, 0x75, 0x12, 0x02];
+    /// let run = |on| {
+    ///     let mut c8 = Chip8::builder().rom(&rom).on_unknown_opcode(on).build().unwrap();
+    ///     (c8.step(), c8.pc())
+    /// };
+    /// assert_eq!(run(OnUnknown::Skip), (Ok(StepOutcome::Ran), 0x202));
+    /// assert_eq!(
+    ///     run(OnUnknown::Warn),
+    ///     (Ok(StepOutcome::Skipped { pc: 0x200, opcode: 0xF075 }), 0x202)
+    /// );
+    /// assert_eq!(
+    ///     run(OnUnknown::Halt),
+    ///     (Err(Fault::UnknownOpcode { pc: 0x200, opcode: 0xF075 }), 0x200)
+    /// );
+    /// ```
+    pub fn on_unknown_opcode(mut self, on: OnUnknown) -> Self {
+        self.on_unknown = on;
+        self
+    }
+
+    /// `OnOddPc::Allow` unless set
+    ///
+    /// ```
+    /// use chip8::emu::{Chip8, Fault, OnOddPc, StepOutcome};
+    ///
+    /// // JP 203; LD V0, 62; LD V1, 64; JP 206. From 203 the bytes pair up
+    /// // the other way, as LD V2, 61 then LD V4, 12
+    /// let rom = [0x12, 0x03, 0x60, 0x62, 0x61, 0x64, 0x12, 0x06];
+    /// let mut c8 = Chip8::builder().rom(&rom).build().unwrap();
+    /// c8.run_n(3).unwrap();
+    /// assert_eq!((c8.pc(), c8.v()[0], c8.v()[2], c8.v()[4]), (0x207, 0, 0x61, 0x12));
+    ///
+    /// let mut c8 = Chip8::builder().rom(&rom).on_odd_pc(OnOddPc::Fault).build().unwrap();
+    /// assert_eq!(c8.step(), Ok(StepOutcome::Ran));
+    /// assert_eq!(c8.step(), Err(Fault::MisalignedPc(0x203)));
+    /// assert_eq!(c8.pc(), 0x203);
+    ///
+    /// // The last word in RAM runs and PC wraps, while a fetch half past
+    /// // the end stops
+    /// let mut c8 = Chip8::builder().rom(&[0x1F, 0xFE]).build().unwrap();
+    /// c8.step().unwrap();
+    /// assert_eq!(c8.step(), Ok(StepOutcome::Ran));
+    /// assert_eq!(c8.pc(), 0);
+    /// for (on, fault) in [
+    ///     (OnOddPc::Allow, Fault::PcOutOfRange(0xFFF)),
+    ///     (OnOddPc::Fault, Fault::MisalignedPc(0xFFF)),
+    /// ] {
+    ///     let mut c8 = Chip8::builder().rom(&[0x1F, 0xFF]).on_odd_pc(on).build().unwrap();
+    ///     c8.step().unwrap();
+    ///     assert_eq!(c8.step(), Err(fault));
+    ///     assert_eq!(c8.run_steps_fast(10), Err(fault));
+    /// }
+    /// ```
+    pub fn on_odd_pc(mut self, on: OnOddPc) -> Self {
+        self.on_odd_pc = on;
+        self
+    }
+
+    pub fn build(self) -> Result<Chip8, BuildError> {
+        use rand::SeedableRng;
+        if self.font.len() > PROGRAM_START as usize {
+            return Err(BuildError::FontTooLarge(self.font.len()));
+        }
+        if !self.glyphs.is_empty() {
+            font::check(self.glyphs).map_err(BuildError::Glyphs)?;
+        }
+        let glyphs: &[u8] =
+            if self.glyphs.is_empty() && self.font_overlay && !font::present(self.font) {
+                &font::DEFAULT
+            } else {
+                self.glyphs
+            };
+        let font_end = match glyphs.len() {
+            0 => self.font.len(),
+            n => self.font.len().max(FONT_ADDR as usize + n),
+        };
+        if (self.load as usize) < font_end {
+            return Err(BuildError::LoadOverFont {
+                load: self.load,
+                font: font_end,
+            });
+        }
+        let start = self.start.unwrap_or(self.load);
+        let size = self.ram_size;
+        if !is_ram_size(size) {
+            return Err(BuildError::RamSize(size));
+        }
+        // An instruction is two bytes, so the last byte of RAM can't be one
+        if start as usize >= size - 1 {
+            return Err(BuildError::BadStart(start));
+        }
+        if !(1..=16).contains(&self.stack_depth) {
+            return Err(BuildError::StackDepth(self.stack_depth));
+        }
+        let mut ram = vec![0u8; size];
+        ram[..self.font.len()].copy_from_slice(self.font);
+        let at = FONT_ADDR as usize;
+        ram[at..at + glyphs.len()].copy_from_slice(glyphs);
+        let seed = self.seed;
+        let rng = self
+            .rng
+            .unwrap_or_else(|| Box::new(rand::rngs::StdRng::seed_from_u64(seed)));
+
+        let mut clock = self.clock.unwrap_or_else(wall_clock);
+        let now = clock();
+        let mut c8 = Chip8 {
+            quirks: self.quirks,
+            last_dec: now,
+            now,
+            clock,
+            paused: None,
+            paused_steps: 0,
+            steps_per_frame: self.steps_per_frame,
+            rng: CountingRng::new(rng),
             v: [0; 16],
             i: 0,
             dt: 0,
             st: 0,
-            pc: 0x200,
+            pc: start,
+            load: self.load,
+            start,
+            rom_hash: 0,
             sp: 0,
-            stk: [0; 16],
-            ram: ram.try_into().unwrap(),
-            screen: [0u64; 32],
+            stk: [0; STACK_DEPTH_CLASSIC],
+            stack_depth: self.stack_depth as u8,
+            ram,
+            keys: 0,
+            in_frame: false,
+            next_keys: None,
+            frames: 0,
+            retired: 0,
+            frame_step: 0,
+            waiting_key: None,
+            screen: [0; LORES_HEIGHT],
+            dirty: u32::MAX,
+            journal: None,
+            smc: None,
+            extensions: Vec::new(),
+            validate: self.validate,
+            idle_skip: false,
+            idle_steps: 0,
+            on_unknown: self.on_unknown,
+            on_odd_pc: self.on_odd_pc,
+            native_calls: self.native_calls,
+            sound_listener: None,
+            on_vblank: None,
+            hooks: None,
+        };
+        c8.load_rom(self.rom)?;
+        Ok(c8)
+    }
+}
+
+impl Chip8 {
+    /// A ROM that doesn't fit is an error rather than a panic
+    ///
+    /// ```
+    /// use chip8::emu::{BuildError, Chip8};
+    /// use chip8::rom::RomError;
+    ///
+    /// let rng = || Box::new(rand::rngs::mock::StepRng::new(0, 1));
+    /// assert_eq!(Chip8::new(&[], &[0x12, 0x00], rng()).unwrap().pc(), 0x200);
+    /// assert!(matches!(
+    ///     Chip8::new(&[], &[0; 0xE01], rng()),
+    ///     Err(BuildError::Rom(RomError::DoesNotFit { load: 0x200, len: 0xE01 }))
+    /// ));
+    /// ```
+    pub fn new(int: &[u8], rom: &[u8], rng: Box<dyn rand::RngCore>) -> Result<Self, BuildError> {
+        Self::with_addrs(int, rom, PROGRAM_START, PROGRAM_START, rng)
+    }
+
+    /// Loads the ROM at `load` and starts running at `start`, ETI-660
+    /// programs for example use 0x600 for both. Anything in `int` past
+    /// 0x200 is ignored.
+    pub fn with_addrs(
+        int: &[u8],
+        rom: &[u8],
+        load: u16,
+        start: u16,
+        rng: Box<dyn rand::RngCore>,
+    ) -> Result<Self, BuildError> {
+        Self::builder()
+            .font(&int[..int.len().min(PROGRAM_START as usize)])
+            .rom(rom)
+            .load_address(load)
+            .start_address(start)
+            .rng(rng)
+            .build()
+    }
+
+    /// Starts building a machine, with nothing below 0x200, an empty
+    /// program at 0x200 and the RNG seeded with 0
+    ///
+    /// ```
+    /// use chip8::emu::{Chip8, Quirks};
+    ///
+    /// let c8 = Chip8::builder()
+    ///     .rom(&[0x12, 0x00])
+    ///     .quirks(Quirks::default())
+    ///     .seed(1)
+    ///     .build()
+    ///     .unwrap();
+    /// assert_eq!(c8.pc(), 0x200);
+    /// ```
+    pub fn builder<'a>() -> Chip8Builder<'a> {
+        Chip8Builder {
+            font: &[],
+            glyphs: &[],
+            font_overlay: true,
+            native_calls: false,
+            rom: &[],
+            quirks: Quirks::default(),
+            rng: None,
+            clock: None,
+            seed: 0,
+            load: PROGRAM_START,
+            start: None,
+            stack_depth: STACK_DEPTH_CLASSIC,
+            ram_size: MIN_RAM,
+            validate: cfg!(debug_assertions),
+            on_unknown: OnUnknown::Halt,
+            on_odd_pc: OnOddPc::Allow,
+            steps_per_frame: 10,
         }
     }
 
+    /// A machine in exactly the state given, with the default RNG. Handy
+    /// for trying single instructions:
+    ///
+    /// ```
+    /// use chip8::emu::{Chip8, Quirks, StepOutcome};
+    /// use chip8::state::SaveState;
+    ///
+    /// let mut v = [0; 16];
+    /// v[1] = 0x20;
+    /// let mut c8 = Chip8::from_state(&SaveState { v, ..SaveState::default() }, Quirks::default());
+    /// // V1 += 0x22
+    /// assert_eq!(c8.execute_one(0x7122), Ok(StepOutcome::Ran));
+    /// assert_eq!(c8.v()[1], 0x42);
+    /// assert_eq!(c8.pc(), 0x202);
+    /// ```
+    pub fn from_state(state: &SaveState, quirks: Quirks) -> Self {
+        let mut c8 = Self::builder().quirks(quirks).build().unwrap();
+        c8.load_state(state);
+        c8.rom_hash = state.rom;
+        c8
+    }
+
+    /// A few lines describing the machine for bug reports. The format is
+    /// kept stable so it can be searched:
+    ///
+    /// ```
+    /// use chip8::emu::{Chip8, Quirks};
+    /// use chip8::state::SaveState;
+    ///
+    /// let mut state = SaveState::default();
+    /// state.ram[0x200..0x202].copy_from_slice(&[0xA2, 0x2A]);
+    /// state.v[0xF] = 1;
+    /// state.i = 0x123;
+    /// state.sp = 1;
+    /// state.stk[0] = 0x206;
+    /// let c8 = Chip8::from_state(&state, Quirks::default());
+    /// assert_eq!(
+    ///     c8.dump_state(),
+    ///     "PC 200  A22A  LD I, 22A\n\
+    ///      I 123  DT 00  ST 00  SP 1  stack 206\n\
+    ///      V0-7  00 00 00 00 00 00 00 00\n\
+    ///      V8-F  00 00 00 00 00 00 00 01\n"
+    /// );
+    /// ```
+    pub fn dump_state(&self) -> String {
+        let op = self.peek_word(self.pc);
+        let stack: Vec<_> = self.stk[..(self.sp as usize).min(self.stk.len())]
+            .iter()
+            .map(|a| format!("{:03X}", a))
+            .collect();
+        let row = |v: &[u8]| -> String {
+            v.iter()
+                .map(|r| format!("{:02X}", r))
+                .collect::<Vec<_>>()
+                .join(" ")
+        };
+        format!(
+            "PC {:03X}  {:04X}  {}\nI {:03X}  DT {:02X}  ST {:02X}  SP {}  stack {}\n\
+             V0-7  {}\nV8-F  {}\n",
+            self.pc,
+            op,
+            disassemble(op),
+            self.i,
+            self.dt,
+            self.st,
+            self.sp,
+            if stack.is_empty() {
+                "empty".to_string()
+            } else {
+                stack.join(" ")
+            },
+            row(&self.v[..8]),
+            row(&self.v[8..])
+        )
+    }
+
+    /// Puts the machine back how it was when the ROM was loaded, apart from
+    /// RAM. The interpreter area is kept and so is the program, including
+    /// any changes it made to itself. Held keys, the RNG and the quirk flags
+    /// carry over, and `frames`, `retired` and `frame_step` count from 0
+    /// again. Nothing from before can be stepped back to.
+    pub fn reset(&mut self) {
+        let st = self.st;
+        self.v = [0; 16];
+        self.i = 0;
+        self.dt = 0;
+        self.st = 0;
+        self.pc = self.start;
+        self.sp = 0;
+        self.stk = [0; STACK_DEPTH_CLASSIC];
+        self.screen = [0; LORES_HEIGHT];
+        self.dirty = u32::MAX;
+        self.waiting_key = None;
+        self.frames = 0;
+        self.retired = 0;
+        self.frame_step = 0;
+        self.last_dec = self.read_clock();
+        self.sound_edge(st);
+        self.clear_journal();
+    }
+
+    /// Replaces the program, clearing RAM from the load address (or 0x200
+    /// if that is lower) before copying it in, then resets
+    pub fn load_rom(&mut self, rom: &[u8]) -> Result<(), RomError> {
+        let at = self.load as usize;
+        if at + rom.len() > self.ram.len() {
+            return Err(RomError::DoesNotFit {
+                load: self.load,
+                len: rom.len(),
+            });
+        }
+        for b in &mut self.ram[at.min(PROGRAM_START as usize)..] {
+            *b = 0;
+        }
+        self.ram[at..at + rom.len()].copy_from_slice(rom);
+        self.rom_hash = state::rom_hash(rom);
+        self.forget_executed();
+        self.reset();
+        Ok(())
+    }
+
+    pub fn save_state(&self) -> SaveState {
+        SaveState {
+            rom: self.rom_hash,
+            v: self.v,
+            i: self.i,
+            dt: self.dt,
+            st: self.st,
+            pc: self.pc,
+            sp: self.sp,
+            stk: self.stk,
+            ram: self.ram.clone(),
+            screen: self.screen,
+            extensions: !self.extensions.is_empty(),
+            frames: self.frames,
+            retired: self.retired,
+            frame_step: self.frame_step,
+        }
+    }
+
+    pub fn load_state(&mut self, state: &SaveState) {
+        let st = self.st;
+        self.v = state.v;
+        self.dt = state.dt;
+        self.st = state.st;
+        self.pc = state.pc;
+        // A state made by hand could point past the stack
+        self.sp = state.sp.min(self.stack_depth);
+        self.stk = state.stk;
+        if is_ram_size(state.ram.len()) {
+            self.ram.clone_from(&state.ram);
+        } else {
+            // Made by hand with some other amount, so keep this machine's
+            self.ram.fill(0);
+            let n = state.ram.len().min(self.ram.len());
+            self.ram[..n].copy_from_slice(&state.ram[..n]);
+        }
+        self.i = state.i & self.addr_mask();
+        self.screen = state.screen;
+        self.frames = state.frames;
+        self.retired = state.retired;
+        self.frame_step = state.frame_step;
+        self.dirty = u32::MAX;
+        self.waiting_key = None;
+        self.sound_edge(st);
+        self.forget_executed();
+    }
+
+    fn forget_executed(&mut self) {
+        let len = self.ram.len();
+        match &mut self.smc {
+            Some(m) if m.executed.len() == len.div_ceil(64) => m.executed.fill(0),
+            // A state with more or less RAM, so the bitmap has to be too
+            Some(m) => **m = SmcMonitor::new(len),
+            None => {}
+        }
+    }
+
+    /// Keeps undo information for the last `cap` instructions so
+    /// `step_back` can reverse them, 0 turns it off (the default). The RNG
+    /// isn't rewound, so RND may give a different number when run again.
+    pub fn set_journal(&mut self, cap: usize) {
+        self.journal = if cap == 0 {
+            None
+        } else {
+            Some(Journal {
+                entries: VecDeque::with_capacity(cap),
+                cap,
+            })
+        };
+    }
+
+    /// Watches for the program writing with Fx33 or Fx55 over bytes it
+    /// has already run as code, off by default. Changes made with `poke`
+    /// aren't counted, nor writes that leave a byte as it was. Loading a
+    /// ROM or a state forgets what has run.
+    ///
+    /// ```
+    /// use chip8::emu::{Chip8, SmcWrite};
+    ///
+    /// let rom = [
+    ///     0x6A, 0x00, // 200: LD VA, 0
+    ///     0x7A, 0x01, // 202: ADD VA, 1
+    ///     0x3A, 0x01, // 204: SE VA, 1
+    ///     0x12, 0x12, // 206: JP 212
+    ///     0x60, 0x7A, // 208: LD V0, 7A
+    ///     0x61, 0x10, // 20A: LD V1, 10
+    ///     0xA2, 0x02, // 20C: LD I, 202
+    ///     0xF1, 0x55, // 20E: LD [I], V1, making 202 ADD VA, 10
+    ///     0x12, 0x02, // 210: JP 202
+    ///     0xA3, 0x00, // 212: LD I, 300
+    ///     0xFA, 0x33, // 214: LD B, VA, which isn't code
+    ///     0x12, 0x16, // 216: JP 216
+    /// ];
+    /// let mut c8 = Chip8::builder().rom(&rom).build().unwrap();
+    /// c8.set_smc_monitor(true);
+    /// for _ in 0..20 {
+    ///     c8.step().unwrap();
+    /// }
+    /// // The patched instruction ran: 1 + 0x10
+    /// assert_eq!(c8.v()[0xA], 0x11);
+    /// // 202 was written too, but kept its 7A
+    /// assert_eq!(
+    ///     c8.smc_writes(),
+    ///     [SmcWrite { pc: 0x20E, addr: 0x203, old: 0x01, new: 0x10 }]
+    /// );
+    /// ```
+    pub fn set_smc_monitor(&mut self, on: bool) {
+        self.smc = if on {
+            Some(Box::new(SmcMonitor::new(self.ram.len())))
+        } else {
+            None
+        };
+    }
+
+    /// Writes over code seen by `set_smc_monitor`, oldest first
+    pub fn smc_writes(&self) -> &[SmcWrite] {
+        self.smc.as_ref().map_or(&[], |m| &m.writes)
+    }
+
+    /// Inclusive runs of RAM fetched as instructions since
+    /// `set_smc_monitor` turned it on, `None` while it's off
+    pub fn coverage(&self) -> Option<Vec<(u16, u16)>> {
+        let m = self.smc.as_ref()?;
+        let mut runs: Vec<(u16, u16)> = Vec::new();
+        for a in (0..m.executed.len() * 64).filter(|&a| m.executed(a)) {
+            match runs.last_mut() {
+                Some((_, end)) if *end as usize + 1 == a => *end = a as u16,
+                _ => runs.push((a as u16, a as u16)),
+            }
+        }
+        Some(runs)
+    }
+
+    /// Undoes the last instruction, returning false if there is nothing
+    /// left in the journal
+    pub fn step_back(&mut self) -> bool {
+        let u = match self.journal.as_mut().and_then(|j| j.entries.pop_back()) {
+            Some(u) => u,
+            None => return false,
+        };
+        let st = self.st;
+        self.pc = u.pc;
+        self.i = u.i;
+        self.sp = u.sp;
+        self.dt = u.dt;
+        self.st = u.st;
+        self.v = u.v;
+        self.frames = u.frames;
+        self.retired = u.retired;
+        self.frame_step = u.frame_step;
+        // Fx0A starts over if it was waiting for a release
+        self.waiting_key = None;
+        for old in u.old.into_iter().rev() {
+            match old {
+                Old::Ram(a, b) => self.ram[a as usize] = b,
+                Old::Stack(n, a) => self.stk[n as usize] = a,
+                Old::Row(y, bits) => {
+                    self.screen[y as usize] = bits;
+                    self.dirty |= 1 << y;
+                }
+            }
+        }
+        self.sound_edge(st);
+        true
+    }
+
+    /// Calls `listener` with true when ST goes from 0 to running and false
+    /// when it gets back to 0, whether that's the timer running out, LD ST,
+    /// a reset or loading a state. Nothing is checked while there isn't one.
+    ///
+    /// ST set to 1 runs out on the next tick, which with this clock comes
+    /// before every instruction:
+    ///
+    /// ```
+    /// use chip8::emu::{self, Chip8};
+    /// use std::cell::RefCell;
+    /// use std::rc::Rc;
+    ///
+    /// // LD V0, 1; LD ST, V0; LD ST, V0; LD V0, 0; LD ST, V0; JP 20A
+    /// let rom = [0x60, 0x01, 0xF0, 0x18, 0xF0, 0x18, 0x60, 0x00, 0xF0, 0x18, 0x12, 0x0A];
+    /// let mut c8 = Chip8::builder().rom(&rom).clock(emu::step_clock(60)).build().unwrap();
+    /// let heard = Rc::new(RefCell::new(Vec::new()));
+    /// let (h, frames) = (heard.clone(), Rc::new(RefCell::new(0)));
+    /// let f = frames.clone();
+    /// c8.set_sound_listener(Box::new(move |on| h.borrow_mut().push((*f.borrow(), on))));
+    /// let f = frames.clone();
+    /// c8.set_vblank_listener(Box::new(move || *f.borrow_mut() += 1));
+    /// for _ in 0..5 {
+    ///     c8.step().unwrap();
+    /// }
+    /// // Started by the first LD ST, stopped by the tick before the second
+    /// // which starts it again, then stopped by the tick before LD V0, 0
+    /// assert_eq!(*heard.borrow(), [(2, true), (3, false), (3, true), (4, false)]);
+    /// assert_eq!(*frames.borrow(), 5);
+    /// ```
+    pub fn set_sound_listener(&mut self, listener: SoundListener) {
+        self.sound_listener = Some(listener);
+    }
+
+    /// Calls `f` every time the timers tick, which is where a frontend
+    /// would draw
+    pub fn set_vblank_listener(&mut self, f: Box<dyn FnMut()>) {
+        self.on_vblank = Some(f);
+    }
+
+    /// Calls `hook` before every step, for tracers and debuggers, and does
+    /// what it says. Replaces any hook set before. The machine isn't
+    /// checked for hooks more than once a step, nor at all in
+    /// `run_steps_fast` while there are none.
+    ///
+    /// ```
+    /// use chip8::emu::{Chip8, HookAction, StepOutcome};
+    /// use std::cell::RefCell;
+    /// use std::rc::Rc;
+    ///
+    /// let rom = [
+    ///     0x60, 0x01, // 200: LD V0, 01
+    ///     0x70, 0x01, // 202: ADD V0, 01
+    ///     0x12, 0x02, // 204: JP 202
+    /// ];
+    /// let mut c8 = Chip8::builder().rom(&rom).build().unwrap();
+    /// // The LD left out, and a breakpoint at 204 once V0 is 3
+    /// c8.set_pre_exec_hook(Box::new(|c8, pc, op| match (pc, op, c8.v()[0]) {
+    ///     (_, 0x6001, _) => HookAction::Skip,
+    ///     (0x204, _, 3) => HookAction::Pause,
+    ///     _ => HookAction::Continue,
+    /// }));
+    /// let seen = Rc::new(RefCell::new(Vec::new()));
+    /// let s = seen.clone();
+    /// c8.set_post_exec_hook(Box::new(move |c8, pc, _, res| {
+    ///     s.borrow_mut().push((pc, c8.v()[0], res.is_ok()));
+    /// }));
+    /// assert_eq!(c8.run_n(100), Ok(StepOutcome::Paused));
+    /// let seen = seen.borrow().clone();
+    /// assert_eq!(seen[..3], [(0x200, 0, true), (0x202, 1, true), (0x204, 1, true)]);
+    /// assert_eq!((seen.len(), c8.pc(), c8.v()[0]), (6, 0x204, 3));
+    ///
+    /// // Without hooks it's back to running everything
+    /// c8.clear_exec_hooks();
+    /// assert_eq!(c8.run_steps_fast(3), Ok(3));
+    /// assert_eq!((c8.pc(), c8.v()[0]), (0x202, 4));
+    /// ```
+    pub fn set_pre_exec_hook(&mut self, hook: PreExecHook) {
+        self.hooks.get_or_insert_with(Default::default).pre = Some(hook);
+    }
+
+    /// Calls `hook` after every step that wasn't paused, faults included.
+    /// Replaces any hook set before.
+    pub fn set_post_exec_hook(&mut self, hook: PostExecHook) {
+        self.hooks.get_or_insert_with(Default::default).post = Some(hook);
+    }
+
+    pub fn clear_exec_hooks(&mut self) {
+        self.hooks = None;
+    }
+
+    // Tells the sound listener if ST started or stopped since it was `st`
+    fn sound_edge(&mut self, st: u8) {
+        if let Some(l) = &mut self.sound_listener {
+            if (st == 0) != (self.st == 0) {
+                l(self.st != 0);
+            }
+        }
+    }
+
+    /// Instructions `step_back` can still undo
+    pub fn journal_len(&self) -> usize {
+        self.journal.as_ref().map_or(0, |j| j.entries.len())
+    }
+
+    /// Forgets every instruction `step_back` could undo, keeping the
+    /// journal on. For after changing the machine some other way, which
+    /// undoing them wouldn't undo.
+    pub fn clear_journal(&mut self) {
+        if let Some(j) = &mut self.journal {
+            j.entries.clear();
+        }
+    }
+
+    // Addresses of the RAM `ins` is about to write
+    fn ram_written(&self, ins: &Instruction) -> Vec<usize> {
+        let n = match ins {
+            Instruction::LDD(_) => 3,
+            Instruction::LDMV(x) => x.0 as usize + 1,
+            _ => 0,
+        };
+        (0..n).map(|k| self.at_i(k)).collect()
+    }
+
+    // What `ins` is about to overwrite
+    fn undo_for(&self, ins: &Instruction) -> Undo {
+        use Instruction::*;
+        let rows = |from: usize, n: usize| -> Vec<Old> {
+            (from..(from + n).min(self.screen.len()))
+                .map(|y| Old::Row(y as u8, self.screen[y]))
+                .collect()
+        };
+        let old = match ins {
+            CLS => rows(0, LORES_HEIGHT),
+            CALL(_) if self.sp < self.stack_depth => {
+                vec![Old::Stack(self.sp, self.stk[self.sp as usize])]
+            }
+            // The return address is cleared once popped
+            RET if self.sp > 0 => {
+                let sp = self.sp - 1;
+                vec![Old::Stack(sp, self.stk[sp as usize])]
+            }
+            LDD(_) | LDMV(_) => self
+                .ram_written(ins)
+                .into_iter()
+                .map(|a| Old::Ram(a as u16, self.ram[a]))
+                .collect(),
+            DRW(_, y, n) => {
+                let py = self.v[y.0 as usize] as usize % LORES_HEIGHT;
+                self.sprite_rows(py, *n as usize)
+                    .map(|(_, y)| Old::Row(y as u8, self.screen[y]))
+                    .collect()
+            }
+            _ => Vec::new(),
+        };
+        Undo {
+            pc: self.pc,
+            i: self.i,
+            sp: self.sp,
+            dt: self.dt,
+            st: self.st,
+            v: self.v,
+            frames: self.frames,
+            retired: self.retired,
+            frame_step: self.frame_step,
+            old,
+        }
+    }
+
+    /// Runs `handler` for words that aren't instructions but match
+    /// `pattern` in the bits set in `mask`. Extensions that could match the
+    /// same word are rejected. Instructions run by extensions can't be
+    /// undone, so running one empties the journal.
+    pub fn register_extension(
+        &mut self,
+        mask: u16,
+        pattern: u16,
+        handler: ExtHandler,
+    ) -> Result<(), ExtensionError> {
+        if pattern & !mask != 0 {
+            return Err(ExtensionError::OutsideMask);
+        }
+        // Two patterns clash unless they differ in a bit both care about
+        if let Some(e) = self
+            .extensions
+            .iter()
+            .find(|e| (e.pattern ^ pattern) & e.mask & mask == 0)
+        {
+            return Err(ExtensionError::Overlaps {
+                mask: e.mask,
+                pattern: e.pattern,
+            });
+        }
+        self.extensions.push(Extension {
+            mask,
+            pattern,
+            handler,
+        });
+        Ok(())
+    }
+
+    // Runs the first extension matching `op`
+    fn run_extension(&mut self, op: u16) -> Result<(), Fault> {
+        let pc = self.pc;
+        let mut extensions = std::mem::take(&mut self.extensions);
+        let res = extensions
+            .iter_mut()
+            .find(|e| op & e.mask == e.pattern)
+            .map(|e| {
+                (e.handler)(
+                    &mut Chip8Context {
+                        v: &mut self.v,
+                        i: &mut self.i,
+                        dt: &mut self.dt,
+                        st: &mut self.st,
+                        ram: &mut self.ram,
+                        screen: &mut self.screen,
+                        keys: self.keys,
+                        frames: self.frames,
+                        retired: self.retired,
+                        frame_step: self.frame_step,
+                    },
+                    op,
+                )
+            });
+        self.extensions = extensions;
+        let res = res.ok_or(Fault::UnknownOpcode { pc, opcode: op })?;
+        if let Some(j) = &mut self.journal {
+            j.entries.clear();
+        }
+        self.dirty = u32::MAX;
+        match res {
+            ExtResult::Done => self.skip(),
+            ExtResult::Skip => {
+                self.skip();
+                self.skip_next();
+            }
+            ExtResult::Fault(msg) => {
+                return Err(Fault::Extension {
+                    pc,
+                    opcode: op,
+                    msg,
+                })
+            }
+        }
+        Ok(())
+    }
+
+    /// Steps until the program halts or faults, and no further
+    pub fn steps(&mut self) -> Steps<'_> {
+        Steps {
+            c8: self,
+            done: false,
+        }
+    }
+
+    /// Steps until `done` says so, checking after each step, or the
+    /// program halts or a hook pauses it. Never returns if none of these
+    /// happens.
+    ///
+    /// ```
+    /// use chip8::emu::{Chip8, StepOutcome};
+    ///
+    /// // Draws one row of a sprite and stops
+    /// let rom = [0xA2, 0x08, 0xD0, 0x11, 0x12, 0x04, 0x00, 0x00, 0xF0];
+    /// let mut c8 = Chip8::builder().rom(&rom).build().unwrap();
+    /// c8.run_until(|c8| c8.screen.iter().any(|&row| row != 0)).unwrap();
+    /// assert_eq!(c8.screen[0], 0xF << 60);
+    /// assert_eq!(c8.run_n(10), Ok(StepOutcome::Halted));
+    /// ```
+    pub fn run_until<F: FnMut(&Chip8) -> bool>(
+        &mut self,
+        mut done: F,
+    ) -> Result<StepOutcome, Fault> {
+        loop {
+            let outcome = self.step()?;
+            if matches!(outcome, StepOutcome::Halted | StepOutcome::Paused) || done(self) {
+                return Ok(outcome);
+            }
+        }
+    }
+
+    /// Runs `n` steps, fewer if the program halts first, and returns
+    /// what the last one did
+    pub fn run_n(&mut self, n: u32) -> Result<StepOutcome, Fault> {
+        let mut last = StepOutcome::Ran;
+        for res in self.steps().take(n as usize) {
+            last = res?;
+        }
+        Ok(last)
+    }
+
+    pub fn quirks(&self) -> Quirks {
+        self.quirks
+    }
+
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.quirks = quirks;
+    }
+
+    pub fn pc(&self) -> u16 {
+        self.pc
+    }
+
+    /// Byte at `addr`, 0 past the end of RAM
+    pub fn peek(&self, addr: u16) -> u8 {
+        self.ram.get(addr as usize).copied().unwrap_or(0)
+    }
+
+    /// Writes a byte of RAM, ignored past the end
+    pub fn poke(&mut self, addr: u16, val: u8) {
+        if let Some(b) = self.ram.get_mut(addr as usize) {
+            *b = val;
+        }
+    }
+
+    pub fn set_v(&mut self, x: u8, val: u8) {
+        *self.get_v(&VReg(x)) = val;
+    }
+
+    /// Word at `addr`, 0 past the end of RAM
+    pub fn peek_word(&self, addr: u16) -> u16 {
+        let a = addr as usize;
+        match self.ram.get(a..a + 2) {
+            Some(w) => u16::from_be_bytes([w[0], w[1]]),
+            None => 0,
+        }
+    }
+
+    /// Rows of the screen changed since the last call, as a bit per row.
+    /// Everything counts as changed after CLS, resets and state loads, and
+    /// the first call reports every row.
+    pub fn take_dirty(&mut self) -> u32 {
+        std::mem::take(&mut self.dirty)
+    }
+
+    /// `state::rom_hash` of the loaded ROM
+    pub fn rom_hash(&self) -> u64 {
+        self.rom_hash
+    }
+
+    /// Keys held down, bit n for key n. SKP and SKNP only look at the low
+    /// nibble of Vx, like most interpreters:
+    ///
+    /// ```
+    /// use chip8::emu::{Chip8, StepOutcome};
+    ///
+    /// // LD V0, 1A then SKP V0
+    /// let rom = [0x60, 0x1A, 0xE0, 0x9E];
+    /// let mut c8 = Chip8::builder().rom(&rom).build().unwrap();
+    /// c8.set_keys(1 << 0xA);
+    /// assert_eq!(c8.keys(), 1 << 0xA);
+    /// c8.run_n(2).unwrap();
+    /// assert_eq!(c8.pc(), 0x206);
+    /// ```
+    ///
+    /// Inside a frame, keys set here aren't seen until it ends, and debug
+    /// builds panic, see `begin_frame`.
+    pub fn set_keys(&mut self, keys: u16) {
+        if self.in_frame {
+            self.next_keys = Some(keys);
+        } else {
+            self.keys = keys;
+        }
+        debug_assert!(
+            !self.in_frame,
+            "set_keys inside a frame, the keys wait for end_frame"
+        );
+    }
+
+    /// Starts a frame with `keys` held for the whole of it. Every
+    /// instruction until `end_frame` sees the same keys, so SKP and SKNP
+    /// give the same answers on every run given the same keys a frame,
+    /// however the host's events arrive. A movie of the keys each frame
+    /// began with is then all it takes to replay it. Keys set with
+    /// `set_keys` in between are kept for when the frame ends, and debug
+    /// builds panic as the host shouldn't be doing it.
+    ///
+    /// ```
+    /// use chip8::emu::Chip8;
+    /// use std::panic::{catch_unwind, AssertUnwindSafe};
+    ///
+    /// let rom = [
+    ///     0x60, 0x05, // 200: LD V0, 5
+    ///     0xE0, 0x9E, // 202: SKP V0
+    ///     0x71, 0x01, // 204: ADD V1, 1
+    ///     0x12, 0x02, // 206: JP 202
+    /// ];
+    /// let mut c8 = Chip8::builder().rom(&rom).build().unwrap();
+    /// c8.begin_frame(0);
+    /// c8.run_n(4).unwrap();
+    /// // An event arriving in the middle of the frame, a panic in debug
+    /// let _ = catch_unwind(AssertUnwindSafe(|| c8.set_keys(1 << 5)));
+    /// c8.run_n(6).unwrap();
+    /// // SKP didn't see 5 go down, ADD ran every time round
+    /// assert_eq!((c8.keys(), c8.v()[1]), (0, 3));
+    /// c8.end_frame();
+    /// assert_eq!(c8.keys(), 1 << 5);
+    ///
+    /// // Next frame it's held from the start
+    /// c8.begin_frame(c8.keys());
+    /// c8.run_n(6).unwrap();
+    /// c8.end_frame();
+    /// assert_eq!(c8.v()[1], 3);
+    /// ```
+    pub fn begin_frame(&mut self, keys: u16) {
+        self.keys = keys;
+        self.next_keys = None;
+        self.in_frame = true;
+        self.frame_step = 0;
+    }
+
+    /// Ends the frame `begin_frame` started, holding any keys set since
+    pub fn end_frame(&mut self) {
+        self.in_frame = false;
+        self.frames += 1;
+        if let Some(keys) = self.next_keys.take() {
+            self.keys = keys;
+        }
+    }
+
+    pub fn keys(&self) -> u16 {
+        self.keys
+    }
+
+    /// Frames `end_frame` has ended. Between `begin_frame` and `end_frame`
+    /// it's the number of the frame running, from 0, so replays and
+    /// netplay can key off it. Like `retired` and `frame_step` it's kept
+    /// in save states, taken back by `step_back` and cleared by `reset`.
+    ///
+    /// ```
+    /// use chip8::emu::{Chip8, HookAction};
+    /// use std::cell::RefCell;
+    /// use std::rc::Rc;
+    ///
+    /// let rom = [
+    ///     0x70, 0x01, // 200: ADD V0, 01
+    ///     0x12, 0x00, // 202: JP 200
+    /// ];
+    /// let mut c8 = Chip8::builder().rom(&rom).build().unwrap();
+    /// c8.set_journal(16);
+    /// // Hooks see the instruction's place before it runs and after
+    /// let seen = Rc::new(RefCell::new(Vec::new()));
+    /// let s = seen.clone();
+    /// c8.set_pre_exec_hook(Box::new(move |c8, _, _| {
+    ///     s.borrow_mut().push((c8.frames(), c8.frame_step(), c8.retired()));
+    ///     HookAction::Continue
+    /// }));
+    /// for _ in 0..2 {
+    ///     c8.begin_frame(0);
+    ///     c8.run_n(3).unwrap();
+    ///     c8.end_frame();
+    /// }
+    /// assert_eq!(*seen.borrow(), [(0, 0, 0), (0, 1, 1), (0, 2, 2), (1, 0, 3), (1, 1, 4), (1, 2, 5)]);
+    /// // The count for the frame is kept until the next one begins
+    /// assert_eq!((c8.frames(), c8.frame_step(), c8.retired()), (2, 3, 6));
+    ///
+    /// // Stepping back over the end of a frame goes back into it
+    /// let state = c8.save_state();
+    /// assert!(c8.step_back());
+    /// assert_eq!((c8.frames(), c8.frame_step(), c8.retired()), (1, 2, 5));
+    ///
+    /// // A state carries on counting from where it was saved
+    /// let mut resumed = Chip8::builder().rom(&rom).build().unwrap();
+    /// resumed.load_state(&state);
+    /// resumed.begin_frame(0);
+    /// resumed.run_steps_fast(4).unwrap();
+    /// resumed.end_frame();
+    /// assert_eq!((resumed.frames(), resumed.frame_step(), resumed.retired()), (3, 4, 10));
+    ///
+    /// // Until it's reset
+    /// resumed.reset();
+    /// assert_eq!((resumed.frames(), resumed.frame_step(), resumed.retired()), (0, 0, 0));
+    /// ```
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+
+    /// Instructions run to the end, including ones skipped as unknown, by
+    /// a hook or while waiting on DT with `set_idle_skip`. Ones that
+    /// fault, and steps a hook paused before, don't count.
+    pub fn retired(&self) -> u64 {
+        self.retired
+    }
+
+    /// Instructions retired since `begin_frame`, the place in the frame of
+    /// the next one
+    pub fn frame_step(&self) -> u32 {
+        self.frame_step
+    }
+
+    /// Key Fx0A would store right now: the one it's waiting to see let go,
+    /// otherwise the lowest key held
+    pub fn first_pressed_key(&self) -> Option<u8> {
+        self.waiting_key
+            .or_else(|| (self.keys != 0).then(|| self.keys.trailing_zeros() as u8))
+    }
+
+    // Only the low nibble picks the key
+    fn key_down(&self, key: u8) -> bool {
+        (self.keys >> (key & 0xF)) & 1 != 0
+    }
+
+    pub fn sound_timer(&self) -> u8 {
+        self.st
+    }
+
+    pub fn delay_timer(&self) -> u8 {
+        self.dt
+    }
+
+    pub fn v(&self) -> [u8; 16] {
+        self.v
+    }
+
+    pub fn i(&self) -> u16 {
+        self.i
+    }
+
+    pub fn sp(&self) -> u8 {
+        self.sp
+    }
+
+    // (sprite row, screen row) for a sprite `n` rows high starting at row
+    // `py`, which is on the screen
+    fn sprite_rows(&self, py: usize, n: usize) -> impl Iterator<Item = (usize, usize)> {
+        let (clip, h) = (self.quirks.clip_y, self.screen.len());
+        (0..n)
+            .map(move |r| (r, py + r))
+            .filter(move |&(_, y)| !clip || y < h)
+            .map(move |(r, y)| (r, y % h))
+    }
+
+    /// Bits of an address that reach RAM, 0xFFF with 4 KB and 0xFFFF
+    /// with 64 KB. Everything that sets I or PC keeps them within it, so
+    /// addresses past the end wrap round to the start, as do runs of
+    /// bytes read or written through I that go over the end. There's no
+    /// other memory to reach, so nothing reads or writes outside RAM.
+    ///
+    /// ```
+    /// use chip8::emu::{Chip8, Quirks};
+    /// use chip8::state::SaveState;
+    ///
+    /// let run = |rom: &[u8], n| {
+    ///     let mut c8 = Chip8::builder().rom(rom).build().unwrap();
+    ///     c8.set_journal(16);
+    ///     c8.run_n(n).unwrap();
+    ///     c8
+    /// };
+    /// assert_eq!(run(&[], 0).addr_mask(), 0xFFF);
+    /// let big = Chip8::builder().ram_size(0x10000).build().unwrap();
+    /// assert_eq!(big.addr_mask(), 0xFFFF);
+    ///
+    /// // ADD I past the end wraps, and sets no flag
+    /// let c8 = run(&[0xAF, 0xFE, 0x60, 0x03, 0xF0, 0x1E], 3);
+    /// assert_eq!((c8.i(), c8.v()[0xF]), (0x001, 0));
+    /// // LD F can't reach past the font, but is kept in RAM all the same
+    /// let c8 = run(&[0x60, 0xFF, 0xF0, 0x29], 2);
+    /// assert_eq!(c8.i(), 0x050 + 0xFF * 5);
+    ///
+    /// // Stores, BCD, loads and sprites all go over the end and carry on at 0
+    /// let rom = [
+    ///     0xAF, 0xFF, // LD I, FFF
+    ///     0x60, 0x12, // LD V0, 12
+    ///     0x61, 0x34, // LD V1, 34
+    ///     0xF1, 0x55, // LD [I], V1, so I is 001
+    ///     0xAF, 0xFE, // LD I, FFE
+    ///     0x62, 0xEA, // LD V2, 234
+    ///     0xF2, 0x33, // LD B, V2
+    ///     0xAF, 0xFF, // LD I, FFF
+    ///     0xF1, 0x65, // LD V1, [I], so I is 001
+    ///     0xAF, 0xFF, // LD I, FFF
+    ///     0xD3, 0x32, // DRW V3, V3, 2
+    /// ];
+    /// let mut c8 = run(&rom, 11);
+    /// assert_eq!((c8.peek(0xFFE), c8.peek(0xFFF), c8.peek(0x000)), (2, 3, 4));
+    /// assert_eq!((c8.v()[0], c8.v()[1]), (3, 4));
+    /// assert_eq!((c8.screen[0] >> 56, c8.screen[1] >> 56), (3, 4));
+    /// // And undoing them puts back the bytes either side of the end
+    /// for _ in 0..5 {
+    ///     assert!(c8.step_back());
+    /// }
+    /// assert_eq!((c8.peek(0xFFE), c8.peek(0xFFF), c8.peek(0x000)), (0, 0x12, 0x34));
+    ///
+    /// // A state from a bigger machine has its I wrapped
+    /// let mut state = SaveState::default();
+    /// state.i = 0x1234;
+    /// let c8 = Chip8::from_state(&state, Quirks::default());
+    /// assert_eq!(c8.i(), 0x234);
+    /// ```
+    pub fn addr_mask(&self) -> u16 {
+        (self.ram.len() - 1) as u16
+    }
+
+    // `n` bytes on from I, wrapped onto RAM
+    fn at_i(&self, n: usize) -> usize {
+        (self.i as usize + n) & self.addr_mask() as usize
+    }
+
+    // Moves PC on an instruction
+    fn skip(&mut self) {
+        self.pc = self.pc.wrapping_add(2) & self.addr_mask();
+    }
+
+    // Steps over the next instruction, for the skip instructions. All of
+    // them go through here so instructions longer than one word only
+    // need handling in one place.
+    fn skip_next(&mut self) {
+        self.skip();
+    }
+
+    // Moves PC back so the instruction runs again
+    fn repeat(&mut self) {
+        self.pc = self.pc.wrapping_sub(2) & self.addr_mask();
+    }
+
+    // Looks for anything wrong after `op` at `pc` ran. `ins` is None for
+    // extensions and `timers` are DT and ST from just before it ran.
+    fn check(
+        &self,
+        pc: u16,
+        opcode: u16,
+        ins: Option<&Instruction>,
+        timers: (u8, u8),
+    ) -> Result<(), Fault> {
+        use Instruction::*;
+        let broken = if self.pc as usize >= self.ram.len() {
+            "PC is past the end of RAM"
+        } else if self.i as usize >= self.ram.len() {
+            "I is past the end of RAM"
+        } else if self.sp > self.stack_depth {
+            "SP is deeper than the stack"
+        } else if !matches!(ins, None | Some(LDDV(_))) && self.dt != timers.0 {
+            "DT changed between ticks"
+        } else if !matches!(ins, None | Some(LDSV(_))) && self.st != timers.1 {
+            "ST changed between ticks"
+        } else {
+            return Ok(());
+        };
+        let fault = Fault::Invariant { pc, opcode, broken };
+        if cfg!(debug_assertions) {
+            panic!("{}\n{}", fault, self.dump_state());
+        }
+        Err(fault)
+    }
+
     // Clamp v to max value so no out of range access
     pub(self) fn get_v(&mut self, n: &VReg) -> &mut u8 {
         let l = n.0 as usize;
@@ -372,23 +2605,616 @@ impl Chip8 {
         }]
     }
 
-    pub fn step(&mut self) {
-        let now = std::time::Instant::now();
-        let steps = (now.duration_since(self.last_dec).as_secs_f64() * 60.0).max(255.0) as u8;
+    // Counts the timers down for the time since they last were, or while
+    // paused once a frame's worth of instructions has been stepped
+    fn read_clock(&mut self) -> f64 {
+        self.now = (self.clock)();
+        self.now
+    }
+
+    fn tick(&mut self) {
+        let steps = if self.paused.is_some() {
+            self.paused_steps += 1;
+            if self.paused_steps < self.steps_per_frame {
+                return;
+            }
+            self.paused_steps = 0;
+            1
+        } else {
+            let now = self.read_clock();
+            let steps = (ticks(now) - ticks(self.last_dec)).min(255.0) as u8;
+            if steps > 0 {
+                self.last_dec = now;
+            }
+            steps
+        };
         if steps > 0 {
-            self.last_dec = now;
-            self.dt = if steps > self.dt { 0 } else { self.dt - steps };
-            self.st = if steps > self.st { 0 } else { self.st - steps }
+            let st = self.st;
+            self.dt = self.dt.saturating_sub(steps);
+            self.st = self.st.saturating_sub(steps);
+            if let Some(f) = &mut self.on_vblank {
+                f();
+            }
+            self.sound_edge(st);
+        }
+    }
+
+    /// Stops the timers following the clock, for a debugger or a lost
+    /// window. Steps taken while paused tick them once every
+    /// `steps_per_frame` instructions, like running would, and however
+    /// long the pause lasts it doesn't count once `resume` is called.
+    ///
+    /// ```
+    /// use chip8::emu::Chip8;
+    /// use std::cell::Cell;
+    /// use std::rc::Rc;
+    ///
+    /// let now = Rc::new(Cell::new(0.0));
+    /// let clock = now.clone();
+    /// // LD V0, 30; LD DT, V0; JP 204
+    /// let rom = [0x60, 30, 0xF0, 0x15, 0x12, 0x04];
+    /// let mut c8 = Chip8::builder()
+    ///     .rom(&rom)
+    ///     .clock(Box::new(move || clock.get()))
+    ///     .steps_per_frame(4)
+    ///     .build()
+    ///     .unwrap();
+    /// c8.step().unwrap();
+    /// c8.step().unwrap();
+    /// now.set(10.0 / 60.0);
+    /// c8.step().unwrap();
+    /// assert_eq!(c8.delay_timer(), 20);
+    ///
+    /// // A minute in the debugger, stepping one frame's worth
+    /// c8.pause();
+    /// now.set(now.get() + 60.0);
+    /// for _ in 0..3 {
+    ///     c8.step().unwrap();
+    /// }
+    /// assert_eq!(c8.delay_timer(), 20);
+    /// c8.step().unwrap();
+    /// assert_eq!(c8.delay_timer(), 19);
+    ///
+    /// c8.resume();
+    /// c8.step().unwrap();
+    /// assert_eq!(c8.delay_timer(), 19);
+    /// now.set(now.get() + 5.0 / 60.0);
+    /// c8.step().unwrap();
+    /// assert_eq!(c8.delay_timer(), 14);
+    /// ```
+    pub fn pause(&mut self) {
+        if self.paused.is_none() {
+            self.paused = Some(self.read_clock());
+            self.paused_steps = 0;
+        }
+    }
+
+    pub fn resume(&mut self) {
+        if let Some(at) = self.paused.take() {
+            self.last_dec += self.read_clock() - at;
+        }
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused.is_some()
+    }
+
+    /// A copy of the machine to run on its own from here, for trying
+    /// things out or checking two runs against each other. Closures can't
+    /// be copied, so:
+    ///
+    /// - `clock` stands in for this machine's clock, read from where it
+    ///   has got to, so a new clock going at the same rate keeps the
+    ///   timers in step
+    /// - the RNG is seeded afresh from the OS, so RND gives different
+    ///   numbers on each
+    /// - extension opcodes and listeners aren't copied, so extensions
+    ///   are unknown opcodes on the fork until registered again
+    ///
+    /// ```
+    /// use chip8::emu::{step_clock, Chip8};
+    ///
+    /// // Counts down DT, drawing V1 = DT as it goes
+    /// let rom = [
+    ///     0x60, 0xC8, // LD V0, 200
+    ///     0xF0, 0x15, // LD DT, V0
+    ///     0xF1, 0x07, // LD V1, DT
+    ///     0xF1, 0x29, // LD F, V1
+    ///     0xD2, 0x25, // DRW V2, V2, 5
+    ///     0x72, 0x01, // ADD V2, 1
+    ///     0x12, 0x04, // JP 204
+    /// ];
+    /// let font: Vec<u8> = (0..0x50u32).map(|n| (n * 7) as u8).collect();
+    /// let build = || Chip8::builder().font(&font).rom(&rom).clock(step_clock(600)).build().unwrap();
+    /// let mut c8 = build();
+    /// for _ in 0..500 {
+    ///     c8.step().unwrap();
+    /// }
+    /// let mut fork = c8.fork(step_clock(600));
+    /// assert_eq!(fork.save_state(), c8.save_state());
+    /// for _ in 0..2000 {
+    ///     c8.step().unwrap();
+    ///     fork.step().unwrap();
+    /// }
+    /// assert_eq!(fork.save_state(), c8.save_state());
+    /// assert_eq!(fork.delay_timer(), c8.delay_timer());
+    ///
+    /// // Changing one leaves the other be
+    /// let before = c8.save_state();
+    /// fork.reset();
+    /// fork.step().unwrap();
+    /// assert_eq!(c8.save_state(), before);
+    /// assert_ne!(fork.save_state(), before);
+    /// ```
+    pub fn fork(&self, mut clock: Clock) -> Chip8 {
+        use rand::SeedableRng;
+        let offset = self.now - clock();
+        Chip8 {
+            quirks: self.quirks,
+            clock: Box::new(move || clock() + offset),
+            last_dec: self.last_dec,
+            now: self.now,
+            paused: self.paused,
+            paused_steps: self.paused_steps,
+            steps_per_frame: self.steps_per_frame,
+            rng: CountingRng {
+                inner: Box::new(rand::rngs::StdRng::from_entropy()),
+                drawn: self.rng.drawn,
+            },
+            v: self.v,
+            i: self.i,
+            dt: self.dt,
+            st: self.st,
+            pc: self.pc,
+            load: self.load,
+            start: self.start,
+            rom_hash: self.rom_hash,
+            sp: self.sp,
+            stk: self.stk,
+            stack_depth: self.stack_depth,
+            ram: self.ram.clone(),
+            keys: self.keys,
+            in_frame: self.in_frame,
+            next_keys: self.next_keys,
+            frames: self.frames,
+            retired: self.retired,
+            frame_step: self.frame_step,
+            waiting_key: self.waiting_key,
+            screen: self.screen,
+            dirty: self.dirty,
+            journal: self.journal.clone(),
+            smc: self.smc.clone(),
+            extensions: Vec::new(),
+            validate: self.validate,
+            idle_skip: self.idle_skip,
+            idle_steps: self.idle_steps,
+            on_unknown: self.on_unknown,
+            on_odd_pc: self.on_odd_pc,
+            native_calls: self.native_calls,
+            sound_listener: None,
+            on_vblank: None,
+            hooks: None,
         }
+    }
+
+    pub fn step(&mut self) -> Result<StepOutcome, Fault> {
+        if self.hooks.is_some() {
+            return self.step_hooked();
+        }
+        self.step_unhooked()
+    }
+
+    fn step_unhooked(&mut self) -> Result<StepOutcome, Fault> {
+        // Timers are saved before they tick, so they go back too
+        let (dt, st) = (self.dt, self.st);
+        self.tick();
+        self.check_pc(self.pc)?;
         let idx = self.pc as usize;
-        if idx + 1 < self.ram.len() {
-            let val: u16 = ((self.ram[idx] as u16) << 8) | self.ram[idx + 1] as u16;
-            let ins = Instruction::decode(&val);
-            println!("{:?}", ins);
-            if let Some(i) = ins {
+        if idx + 1 >= self.ram.len() {
+            return Err(Fault::PcOutOfRange(self.pc));
+        }
+        let val: u16 = ((self.ram[idx] as u16) << 8) | self.ram[idx + 1] as u16;
+        if let Some(m) = &mut self.smc {
+            m.mark(idx);
+            m.mark(idx + 1);
+        }
+        self.run(val, dt, st)
+    }
+
+    // A step with the hooks around it. They're taken out while they run,
+    // so they can be shown the machine.
+    fn step_hooked(&mut self) -> Result<StepOutcome, Fault> {
+        let mut hooks = self.hooks.take().unwrap_or_default();
+        let (pc, op) = (self.pc, self.peek_word(self.pc));
+        let action = match &mut hooks.pre {
+            Some(f) => f(self, pc, op),
+            None => HookAction::Continue,
+        };
+        let res = match action {
+            HookAction::Continue => self.step_unhooked(),
+            HookAction::Pause => Ok(StepOutcome::Paused),
+            HookAction::Skip => self.pass_over(),
+        };
+        if let (Some(f), false) = (&mut hooks.post, action == HookAction::Pause) {
+            f(self, pc, op, &res);
+        }
+        self.hooks = Some(hooks);
+        res
+    }
+
+    // A step that only moves PC on, for `HookAction::Skip`
+    fn pass_over(&mut self) -> Result<StepOutcome, Fault> {
+        let (dt, st) = (self.dt, self.st);
+        self.tick();
+        self.check_pc(self.pc)?;
+        self.push_undo(&Instruction::SYS(Addr(0)), dt, st);
+        self.skip();
+        self.retire(1);
+        Ok(StepOutcome::Ran)
+    }
+
+    fn check_pc(&self, pc: u16) -> Result<(), Fault> {
+        if self.on_odd_pc == OnOddPc::Fault && pc % 2 == 1 {
+            return Err(Fault::MisalignedPc(pc));
+        }
+        Ok(())
+    }
+
+    /// Runs up to `n` instructions and returns how many ran, stopping
+    /// after one that halts, waits for a key or is skipped with a warning,
+    /// or before one a hook paused on. Does exactly what calling `step`
+    /// that many times would, but when the journal, invariant checks,
+    /// hooks and the SMC monitor are off it skips looking at them for
+    /// every instruction.
+    pub fn run_steps_fast(&mut self, n: u32) -> Result<u32, Fault> {
+        if self.journal.is_some() || self.validate || self.hooks.is_some() || self.smc.is_some() {
+            for ran in 1..=n {
+                match self.step()? {
+                    StepOutcome::Ran => {}
+                    StepOutcome::Paused => return Ok(ran - 1),
+                    _ => return Ok(ran),
+                }
+            }
+            return Ok(n);
+        }
+        let mut ran = 0;
+        while ran < n {
+            ran += 1;
+            self.tick();
+            let pc = self.pc;
+            self.check_pc(pc)?;
+            let op = match self.ram.get(pc as usize..pc as usize + 2) {
+                Some(w) => u16::from_be_bytes([w[0], w[1]]),
+                None => return Err(Fault::PcOutOfRange(pc)),
+            };
+            match Instruction::decode(&op) {
+                Some(Instruction::SYS(a)) if self.native_calls => {
+                    return Err(Fault::NativeCall { pc, addr: a.0 })
+                }
+                Some(i) => {
+                    let st = self.st;
+                    self.skip();
+                    i.execute(self);
+                    self.sound_edge(st);
+                    self.retire(1);
+                    if self.pc == pc && matches!(i, Instruction::JP(_) | Instruction::LDK(_)) {
+                        return Ok(ran);
+                    }
+                    if self.idle_skip && matches!(i, Instruction::JP(_)) {
+                        if let Some(wait) = self.dt_wait() {
+                            let idle = self.skip_dt_wait(wait, n - ran);
+                            self.idle_steps += idle as u64;
+                            self.retire(idle);
+                            ran += idle;
+                        }
+                    }
+                }
+                None if self.is_extension(op) => {
+                    let st = self.st;
+                    self.run_extension(op)?;
+                    self.sound_edge(st);
+                    self.retire(1);
+                }
+                None => {
+                    let outcome = self.unknown(op, self.dt, self.st)?;
+                    self.retire(1);
+                    if outcome != StepOutcome::Ran {
+                        return Ok(ran);
+                    }
+                }
+            }
+        }
+        Ok(n)
+    }
+
+    /// Lets `run_steps_fast` go through loops waiting on DT, `LD Vx, DT`
+    /// then `SE Vx, kk` or `SNE Vx, kk` then a jump back, without
+    /// decoding them. The timers still tick once a step, so the program
+    /// leaves the loop on the same step with everything the same as if it
+    /// had run, and `idle_steps` counts the steps gone through this way.
+    /// Like the rest of the fast path it's off with the journal or
+    /// invariant checks on.
+    ///
+    /// ```
+    /// use chip8::emu::{step_clock, Chip8};
+    ///
+    /// let rom = [
+    ///     0x60, 0x1E, // 200: LD V0, 30
+    ///     0xF0, 0x15, // 202: LD DT, V0
+    ///     0xF1, 0x07, // 204: LD V1, DT
+    ///     0x31, 0x00, // 206: SE V1, 0
+    ///     0x12, 0x04, // 208: JP 204
+    ///     0x72, 0x01, // 20A: ADD V2, 1
+    ///     0x12, 0x02, // 20C: JP 202
+    /// ];
+    /// let build = || Chip8::builder().rom(&rom).clock(step_clock(600)).validate(false).build().unwrap();
+    /// let (mut plain, mut idle) = (build(), build());
+    /// idle.set_idle_skip(true);
+    /// // 10 steps a frame for 5 seconds, the same after every one
+    /// for _ in 0..300 {
+    ///     assert_eq!(plain.run_steps_fast(10).unwrap(), idle.run_steps_fast(10).unwrap());
+    ///     assert_eq!(plain.save_state(), idle.save_state());
+    ///     assert_eq!(plain.delay_timer(), idle.delay_timer());
+    /// }
+    /// assert_eq!(idle.v()[2], 9);
+    /// // Most of them waiting, only a loop a frame is run to find it
+    /// assert_eq!(plain.idle_steps(), 0);
+    /// assert!(3000 - idle.idle_steps() < 700, "{}", idle.idle_steps());
+    /// ```
+    pub fn set_idle_skip(&mut self, on: bool) {
+        self.idle_skip = on;
+    }
+
+    /// Steps `set_idle_skip` went through without running them
+    pub fn idle_steps(&self) -> u64 {
+        self.idle_steps
+    }
+
+    /// Random bytes RND has drawn since the machine was built. Loading a
+    /// state or stepping back doesn't take them back.
+    pub fn rng_bytes(&self) -> u64 {
+        self.rng.drawn
+    }
+
+    // A DT wait loop at PC: the register, the value it's compared with and
+    // whether it leaves when they're equal
+    fn dt_wait(&self) -> Option<(usize, u8, bool)> {
+        let pc = self.pc;
+        self.check_pc(pc).ok()?;
+        let mask = self.addr_mask();
+        let word = |n: u16| self.peek_word(pc.wrapping_add(n) & mask);
+        let (ld, cmp) = (word(0), word(2));
+        let x = (ld >> 8 & 0xF) as usize;
+        if ld & 0xF0FF != 0xF007 || (cmp >> 8 & 0xF) as usize != x || word(4) != 0x1000 | pc {
+            return None;
+        }
+        match cmp >> 12 {
+            0x3 => Some((x, cmp as u8, true)),
+            0x4 => Some((x, cmp as u8, false)),
+            _ => None,
+        }
+    }
+
+    // Goes through up to `n` steps of the loop `dt_wait` found as running
+    // it would, stopping once it has left, and returns how many
+    fn skip_dt_wait(&mut self, (x, kk, leave_on_eq): (usize, u8, bool), n: u32) -> u32 {
+        let start = self.pc;
+        let mask = self.addr_mask();
+        let at = |n: u16| start.wrapping_add(n) & mask;
+        for taken in 1..=n {
+            self.tick();
+            match (taken - 1) % 3 {
+                0 => {
+                    self.v[x] = self.dt;
+                    self.pc = at(2);
+                }
+                1 if (self.v[x] == kk) == leave_on_eq => {
+                    self.pc = at(6);
+                    return taken;
+                }
+                1 => self.pc = at(4),
+                _ => self.pc = start,
+            }
+        }
+        n
+    }
+
+    /// Runs `opcode` as if it were the word at PC, without it having to be
+    /// in RAM. Timers don't tick, so DT and ST stay as they were set.
+    ///
+    /// The arithmetic follows Cowgod's reference for every pair of values,
+    /// both shift quirks and with VF as either operand, VF being written
+    /// last:
+    ///
+    /// ```
+    /// use chip8::emu::{Chip8, Quirks};
+    ///
+    /// for hp_shift in [false, true] {
+    ///     let quirks = Quirks { hp_shift, ..Quirks::default() };
+    ///     let mut c8 = Chip8::builder().quirks(quirks).build().unwrap();
+    ///     for (x, y) in [(1u8, 2u8), (0xF, 2), (1, 0xF)] {
+    ///         for a in 0..=255u8 {
+    ///             for b in 0..=255u8 {
+    ///                 // 8xyn with Vx = a and Vy = b, giving Vx and VF
+    ///                 let mut run = |n: u16| {
+    ///                     c8.set_v(x, a);
+    ///                     c8.set_v(y, b);
+    ///                     c8.execute_one(0x8000 | (x as u16) << 8 | (y as u16) << 4 | n).unwrap();
+    ///                     (c8.v()[x as usize], c8.v()[0xF])
+    ///                 };
+    ///                 let expect = |res: u8, flag: bool| (if x == 0xF { flag as u8 } else { res }, flag as u8);
+    ///                 let (sum, carry) = a.overflowing_add(b);
+    ///                 assert_eq!(run(0x4), expect(sum, carry));
+    ///                 assert_eq!(run(0x5), expect(a.wrapping_sub(b), a >= b));
+    ///                 assert_eq!(run(0x7), expect(b.wrapping_sub(a), b >= a));
+    ///                 let src = if hp_shift { a } else { b };
+    ///                 assert_eq!(run(0x6), expect(src >> 1, src & 1 != 0));
+    ///                 assert_eq!(run(0xE), expect(src << 1, src >> 7 != 0));
+    ///             }
+    ///         }
+    ///     }
+    /// }
+    /// ```
+    ///
+    /// LD B writes three digits and Fx55/Fx65 round trip the registers
+    /// through RAM, with any `mem_inc`:
+    ///
+    /// ```
+    /// use chip8::emu::{Chip8, LoadStoreIncrement, Quirks};
+    ///
+    /// for mem_inc in [LoadStoreIncrement::None, LoadStoreIncrement::X, LoadStoreIncrement::XPlusOne] {
+    ///     let quirks = Quirks { mem_inc, ..Quirks::default() };
+    ///     let mut c8 = Chip8::builder().quirks(quirks).build().unwrap();
+    ///     for n in 0..=255u8 {
+    ///         c8.set_v(3, n);
+    ///         c8.execute_one(0xA300).unwrap();
+    ///         c8.execute_one(0xF333).unwrap();
+    ///         let digits = [c8.peek(0x300), c8.peek(0x301), c8.peek(0x302)];
+    ///         assert!(digits.iter().all(|&d| d < 10));
+    ///         assert_eq!(digits[0] as u32 * 100 + digits[1] as u32 * 10 + digits[2] as u32, n as u32);
+    ///
+    ///         for r in 0..16 {
+    ///             c8.set_v(r, n.wrapping_mul(r + 1));
+    ///         }
+    ///         let saved = c8.v();
+    ///         c8.execute_one(0xA400).unwrap();
+    ///         c8.execute_one(0xFF55).unwrap();
+    ///         let after = match mem_inc {
+    ///             LoadStoreIncrement::None => 0x400,
+    ///             LoadStoreIncrement::X => 0x40F,
+    ///             LoadStoreIncrement::XPlusOne => 0x410,
+    ///         };
+    ///         assert_eq!(c8.i(), after);
+    ///         for r in 0..16 {
+    ///             c8.set_v(r, 0);
+    ///         }
+    ///         c8.execute_one(0xA400).unwrap();
+    ///         c8.execute_one(0xFF65).unwrap();
+    ///         assert_eq!((c8.v(), c8.i()), (saved, after));
+    ///     }
+    /// }
+    ///
+    /// // The same Fx55 with V0 to V4 under each
+    /// let i_after = |mem_inc| {
+    ///     let mut c8 = Chip8::builder().quirks(Quirks { mem_inc, ..Quirks::default() }).build().unwrap();
+    ///     c8.execute_one(0xA300).unwrap();
+    ///     c8.execute_one(0xF455).unwrap();
+    ///     c8.i()
+    /// };
+    /// assert_eq!(i_after(LoadStoreIncrement::None), 0x300);
+    /// assert_eq!(i_after(LoadStoreIncrement::X), 0x304);
+    /// assert_eq!(i_after(LoadStoreIncrement::XPlusOne), 0x305);
+    /// ```
+    pub fn execute_one(&mut self, opcode: u16) -> Result<StepOutcome, Fault> {
+        self.run(opcode, self.dt, self.st)
+    }
+
+    // Runs `val` from PC, `dt` and `st` being the timers from before they
+    // ticked this step
+    fn run(&mut self, val: u16, dt: u8, st: u8) -> Result<StepOutcome, Fault> {
+        let pc = self.pc;
+        let outcome = match Instruction::decode(&val) {
+            Some(Instruction::SYS(a)) if self.native_calls => {
+                return Err(Fault::NativeCall { pc, addr: a.0 })
+            }
+            Some(i) => {
+                self.push_undo(&i, dt, st);
+                let before = self.smc_before(&i);
+                // Jumps and calls overwrite this, skips add to it
+                self.skip();
+                let timers = (self.dt, self.st);
                 i.execute(self);
+                if let Some(old) = before {
+                    self.smc_after(pc, &old);
+                }
+                self.sound_edge(timers.1);
+                if self.validate {
+                    self.check(pc, val, Some(&i), timers)?;
+                }
+                match i {
+                    Instruction::LDK(_) if self.pc == pc => StepOutcome::WaitingForKey,
+                    Instruction::JP(_) if self.pc == pc => StepOutcome::Halted,
+                    _ => StepOutcome::Ran,
+                }
+            }
+            None if self.is_extension(val) => {
+                let st = self.st;
+                self.run_extension(val)?;
+                self.sound_edge(st);
+                if self.validate {
+                    self.check(pc, val, None, (self.dt, self.st))?;
+                }
+                StepOutcome::Ran
+            }
+            None => self.unknown(val, dt, st)?,
+        };
+        self.retire(1);
+        Ok(outcome)
+    }
+
+    // Counts `n` instructions as finished
+    fn retire(&mut self, n: u32) {
+        self.retired += n as u64;
+        self.frame_step = self.frame_step.wrapping_add(n);
+    }
+
+    fn is_extension(&self, op: u16) -> bool {
+        self.extensions.iter().any(|e| op & e.mask == e.pattern)
+    }
+
+    // RAM `ins` will write over, if it could write over code
+    fn smc_before(&self, ins: &Instruction) -> Option<Vec<(usize, u8)>> {
+        let m = self.smc.as_ref()?;
+        let addrs = self.ram_written(ins);
+        if addrs.iter().any(|&a| m.executed(a)) {
+            Some(addrs.into_iter().map(|a| (a, self.ram[a])).collect())
+        } else {
+            None
+        }
+    }
+
+    // Notes code that `smc_before` saw get changed
+    fn smc_after(&mut self, pc: u16, old: &[(usize, u8)]) {
+        if let Some(m) = &mut self.smc {
+            for &(a, o) in old {
+                if m.executed(a) && self.ram[a] != o {
+                    m.writes.push(SmcWrite {
+                        pc,
+                        addr: a as u16,
+                        old: o,
+                        new: self.ram[a],
+                    });
+                }
             }
-            self.pc += 2;
         }
     }
+
+    // Journals what `ins` is about to overwrite, `dt` and `st` being the
+    // timers from before they ticked
+    fn push_undo(&mut self, ins: &Instruction, dt: u8, st: u8) {
+        let undo = self.journal.as_ref().map(|_| self.undo_for(ins));
+        if let (Some(mut undo), Some(j)) = (undo, &mut self.journal) {
+            undo.dt = dt;
+            undo.st = st;
+            if j.entries.len() == j.cap {
+                j.entries.pop_front();
+            }
+            j.entries.push_back(undo);
+        }
+    }
+
+    // `op` at PC is neither an instruction nor an extension
+    fn unknown(&mut self, op: u16, dt: u8, st: u8) -> Result<StepOutcome, Fault> {
+        let pc = self.pc;
+        if self.on_unknown == OnUnknown::Halt {
+            return Err(Fault::UnknownOpcode { pc, opcode: op });
+        }
+        // Skipping changes nothing else, just like SYS
+        self.push_undo(&Instruction::SYS(Addr(0)), dt, st);
+        self.skip();
+        Ok(match self.on_unknown {
+            OnUnknown::Warn => StepOutcome::Skipped { pc, opcode: op },
+            _ => StepOutcome::Ran,
+        })
+    }
 }
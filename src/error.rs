@@ -0,0 +1,211 @@
+//! One error type for everything the library can fail with, for programs
+//! that would rather not name each module's own. Every module error
+//! converts into it with `?`, and `source` gives the original back.
+use crate::dump::DumpError;
+use crate::emu::{BuildError, ExtensionError, Fault};
+use crate::font::FontError;
+use crate::lockstep::LockstepError;
+use crate::movie::{MovieError, ReplayError};
+use crate::netplay::NetplayError;
+use crate::prefs::PrefsError;
+use crate::rom::RomError;
+use crate::script::ScriptError;
+use crate::slots::SlotError;
+use crate::state::StateError;
+use crate::theme::PaletteError;
+use crate::timeline::TimelineError;
+use crate::watchdog::KeyDeadlock;
+use std::fmt;
+
+/// Any error from this crate, shown the same as the one it wraps
+///
+/// ```
+/// use chip8::emu::{BuildError, Chip8};
+/// use chip8::rom::RomError;
+/// use chip8::state::SaveState;
+///
+/// fn run(rom: &[u8], state: &[u8]) -> Result<u16, chip8::Error> {
+///     let mut c8 = Chip8::builder().rom(rom).build()?;
+///     c8.load_state(&SaveState::from_bytes(state)?);
+///     c8.step()?;
+///     Ok(c8.pc())
+/// }
+///
+/// let state = |rom: &[u8]| Chip8::builder().rom(rom).build().unwrap().save_state().to_bytes();
+/// assert_eq!(run(&[0x12, 0x00], &state(&[0x12, 0x00])).unwrap(), 0x200);
+///
+/// let err = run(&[0; 0x1000], &[]).unwrap_err();
+/// assert!(matches!(err, chip8::Error::Build(BuildError::Rom(RomError::DoesNotFit { .. }))));
+/// let err = run(&[0x12, 0x00], b"nope").unwrap_err();
+/// assert_eq!(err.to_string(), chip8::state::SaveState::from_bytes(b"nope").unwrap_err().to_string());
+/// assert!(std::error::Error::source(&err).is_some());
+/// let err = run(&[0xF0, 0x75], &state(&[0xF0, 0x75])).unwrap_err();
+/// assert_eq!(err.to_string(), "unknown opcode F075 at 200 (this looks like a SUPER-CHIP instruction, which isn't supported)");
+/// ```
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    Io(std::io::Error),
+    Rom(RomError),
+    Build(BuildError),
+    Fault(Fault),
+    Extension(ExtensionError),
+    State(StateError),
+    Slot(SlotError),
+    Font(FontError),
+    Palette(PaletteError),
+    Movie(MovieError),
+    Replay(ReplayError),
+    Script(ScriptError),
+    Timeline(TimelineError),
+    Prefs(PrefsError),
+    Dump(DumpError),
+    Netplay(NetplayError),
+    Lockstep(LockstepError),
+    KeyDeadlock(KeyDeadlock),
+}
+
+impl Error {
+    fn inner(&self) -> &(dyn std::error::Error + 'static) {
+        match self {
+            Self::Io(e) => e,
+            Self::Rom(e) => e,
+            Self::Build(e) => e,
+            Self::Fault(e) => e,
+            Self::Extension(e) => e,
+            Self::State(e) => e,
+            Self::Slot(e) => e,
+            Self::Font(e) => e,
+            Self::Palette(e) => e,
+            Self::Movie(e) => e,
+            Self::Replay(e) => e,
+            Self::Script(e) => e,
+            Self::Timeline(e) => e,
+            Self::Prefs(e) => e,
+            Self::Dump(e) => e,
+            Self::Netplay(e) => e,
+            Self::Lockstep(e) => e,
+            Self::KeyDeadlock(e) => e,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(self.inner(), f)
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(self.inner())
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+impl From<RomError> for Error {
+    fn from(e: RomError) -> Self {
+        Self::Rom(e)
+    }
+}
+
+impl From<BuildError> for Error {
+    fn from(e: BuildError) -> Self {
+        Self::Build(e)
+    }
+}
+
+impl From<Fault> for Error {
+    fn from(e: Fault) -> Self {
+        Self::Fault(e)
+    }
+}
+
+impl From<ExtensionError> for Error {
+    fn from(e: ExtensionError) -> Self {
+        Self::Extension(e)
+    }
+}
+
+impl From<StateError> for Error {
+    fn from(e: StateError) -> Self {
+        Self::State(e)
+    }
+}
+
+impl From<SlotError> for Error {
+    fn from(e: SlotError) -> Self {
+        Self::Slot(e)
+    }
+}
+
+impl From<FontError> for Error {
+    fn from(e: FontError) -> Self {
+        Self::Font(e)
+    }
+}
+
+impl From<PaletteError> for Error {
+    fn from(e: PaletteError) -> Self {
+        Self::Palette(e)
+    }
+}
+
+impl From<MovieError> for Error {
+    fn from(e: MovieError) -> Self {
+        Self::Movie(e)
+    }
+}
+
+impl From<ReplayError> for Error {
+    fn from(e: ReplayError) -> Self {
+        Self::Replay(e)
+    }
+}
+
+impl From<ScriptError> for Error {
+    fn from(e: ScriptError) -> Self {
+        Self::Script(e)
+    }
+}
+
+impl From<TimelineError> for Error {
+    fn from(e: TimelineError) -> Self {
+        Self::Timeline(e)
+    }
+}
+
+impl From<PrefsError> for Error {
+    fn from(e: PrefsError) -> Self {
+        Self::Prefs(e)
+    }
+}
+
+impl From<DumpError> for Error {
+    fn from(e: DumpError) -> Self {
+        Self::Dump(e)
+    }
+}
+
+impl From<NetplayError> for Error {
+    fn from(e: NetplayError) -> Self {
+        Self::Netplay(e)
+    }
+}
+
+impl From<LockstepError> for Error {
+    fn from(e: LockstepError) -> Self {
+        Self::Lockstep(e)
+    }
+}
+
+impl From<KeyDeadlock> for Error {
+    fn from(e: KeyDeadlock) -> Self {
+        Self::KeyDeadlock(e)
+    }
+}
@@ -0,0 +1,101 @@
+//! What to do while the window is in the background. Terminals that take
+//! `\x1b[?1004h` report focus as `\x1b[I` and `\x1b[O`. While unfocused the
+//! screen is drawn less often, and with `pause_on_loss` the game stops
+//! too, through the same pause as the pause key so the timers stop with
+//! it.
+use std::time::{Duration, Instant};
+
+/// Frames drawn a second while unfocused
+pub const BACKGROUND_FPS: u64 = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FocusEvent {
+    Gained,
+    Lost,
+}
+
+/// The event in an escape sequence without the leading escape, such as
+/// `[I`
+pub fn event(seq: &[u8]) -> Option<FocusEvent> {
+    match seq {
+        b"[I" => Some(FocusEvent::Gained),
+        b"[O" => Some(FocusEvent::Lost),
+        _ => None,
+    }
+}
+
+/// Focus as last reported, and when the screen was last drawn
+///
+/// ```
+/// use chip8::focus::{self, Focus};
+/// use std::time::{Duration, Instant};
+///
+/// // Every combination of pausing on focus loss, the pause key and focus,
+/// // with (runs, dimmed, frames drawn in a second at 20 a second)
+/// let start = Instant::now();
+/// let frames = |f: &mut Focus, second: u64| {
+///     (0..20)
+///         .filter(|n| f.should_draw(start + Duration::from_millis(second * 1000 + n * 50)))
+///         .count()
+/// };
+/// for pause_on_loss in [false, true] {
+///     for paused in [false, true] {
+///         let mut f = Focus::new(pause_on_loss);
+///         assert_eq!((f.runs(paused), f.dimmed(), frames(&mut f, 0)), (!paused, false, 20));
+///         f.update(focus::event(b"[O").unwrap());
+///         let runs = !paused && !pause_on_loss;
+///         assert_eq!((f.runs(paused), f.dimmed(), frames(&mut f, 1)), (runs, pause_on_loss, 10));
+///         // Coming back is straight back to normal, with nothing to catch up
+///         f.update(focus::event(b"[I").unwrap());
+///         assert_eq!((f.runs(paused), f.dimmed(), frames(&mut f, 2)), (!paused, false, 20));
+///     }
+/// }
+/// ```
+pub struct Focus {
+    pause_on_loss: bool,
+    focused: bool,
+    last_draw: Option<Instant>,
+}
+
+impl Focus {
+    /// Starts out focused
+    pub fn new(pause_on_loss: bool) -> Self {
+        Self {
+            pause_on_loss,
+            focused: true,
+            last_draw: None,
+        }
+    }
+
+    pub fn update(&mut self, event: FocusEvent) {
+        self.focused = event == FocusEvent::Gained;
+        // The first frame back is drawn straight away
+        if self.focused {
+            self.last_draw = None;
+        }
+    }
+
+    pub fn focused(&self) -> bool {
+        self.focused
+    }
+
+    /// Whether the game should run, `paused` being the pause key
+    pub fn runs(&self, paused: bool) -> bool {
+        !paused && !self.dimmed()
+    }
+
+    /// Stopped for being in the background, so shown dimmed
+    pub fn dimmed(&self) -> bool {
+        self.pause_on_loss && !self.focused
+    }
+
+    /// Whether to draw a frame at `now`, noting it if so
+    pub fn should_draw(&mut self, now: Instant) -> bool {
+        let gap = Duration::from_millis(1000 / BACKGROUND_FPS);
+        let due = self.focused || self.last_draw.is_none_or(|t| now >= t + gap);
+        if due {
+            self.last_draw = Some(now);
+        }
+        due
+    }
+}
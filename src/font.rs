@@ -0,0 +1,171 @@
+//! Digit glyphs for Fx29. Interpreters drew the hex digits slightly
+//! differently, and some programs' art counts on one of them, so the
+//! common modern set can be swapped for another with `--font`.
+use crate::layout::FONT_START;
+use std::fmt;
+
+/// Bytes in a set of 16 glyphs, 5 rows each
+pub const SMALL_LEN: usize = 80;
+/// Bytes in the 10-row SUPER-CHIP digits that may follow them
+pub const BIG_LEN: usize = 160;
+
+/// The set most emulators use
+pub const DEFAULT: [u8; SMALL_LEN] = [
+    0xF0, 0x90, 0x90, 0x90, 0xF0, 0x20, 0x60, 0x20, 0x20, 0x70, 0xF0, 0x10, 0xF0, 0x80, 0xF0, 0xF0,
+    0x10, 0xF0, 0x10, 0xF0, 0x90, 0x90, 0xF0, 0x10, 0x10, 0xF0, 0x80, 0xF0, 0x10, 0xF0, 0xF0, 0x80,
+    0xF0, 0x90, 0xF0, 0xF0, 0x10, 0x20, 0x40, 0x40, 0xF0, 0x90, 0xF0, 0x90, 0xF0, 0xF0, 0x90, 0xF0,
+    0x10, 0xF0, 0xF0, 0x90, 0xF0, 0x90, 0x90, 0xE0, 0x90, 0xE0, 0x90, 0xE0, 0xF0, 0x80, 0x80, 0x80,
+    0xF0, 0xE0, 0x90, 0x90, 0x90, 0xE0, 0xF0, 0x80, 0xF0, 0x80, 0xF0, 0xF0, 0x80, 0xF0, 0x80, 0x80,
+];
+
+/// COSMAC VIP
+pub const VIP: [u8; SMALL_LEN] = [
+    0xF0, 0x90, 0x90, 0x90, 0xF0, 0x60, 0x20, 0x20, 0x20, 0x70, 0xF0, 0x10, 0xF0, 0x80, 0xF0, 0xF0,
+    0x10, 0xF0, 0x10, 0xF0, 0xA0, 0xA0, 0xF0, 0x20, 0x20, 0xF0, 0x80, 0xF0, 0x10, 0xF0, 0xF0, 0x80,
+    0xF0, 0x90, 0xF0, 0xF0, 0x10, 0x10, 0x10, 0x10, 0xF0, 0x90, 0xF0, 0x90, 0xF0, 0xF0, 0x90, 0xF0,
+    0x10, 0xF0, 0xF0, 0x90, 0xF0, 0x90, 0x90, 0xF0, 0x50, 0x70, 0x50, 0xF0, 0xF0, 0x80, 0x80, 0x80,
+    0xF0, 0xF0, 0x50, 0x50, 0x50, 0xF0, 0xF0, 0x80, 0xF0, 0x80, 0xF0, 0xF0, 0x80, 0xF0, 0x80, 0x80,
+];
+
+/// DREAM 6800, three pixels wide
+pub const DREAM6800: [u8; SMALL_LEN] = [
+    0xE0, 0xA0, 0xA0, 0xA0, 0xE0, 0x40, 0x40, 0x40, 0x40, 0x40, 0xE0, 0x20, 0xE0, 0x80, 0xE0, 0xE0,
+    0x20, 0xE0, 0x20, 0xE0, 0x80, 0xA0, 0xA0, 0xE0, 0x20, 0xE0, 0x80, 0xE0, 0x20, 0xE0, 0xE0, 0x80,
+    0xE0, 0xA0, 0xE0, 0xE0, 0x20, 0x20, 0x20, 0x20, 0xE0, 0xA0, 0xE0, 0xA0, 0xE0, 0xE0, 0xA0, 0xE0,
+    0x20, 0xE0, 0xE0, 0xA0, 0xE0, 0xA0, 0xA0, 0xC0, 0xA0, 0xE0, 0xA0, 0xC0, 0xE0, 0x80, 0x80, 0x80,
+    0xE0, 0xC0, 0xA0, 0xA0, 0xA0, 0xC0, 0xE0, 0x80, 0xE0, 0x80, 0xE0, 0xE0, 0x80, 0xC0, 0x80, 0x80,
+];
+
+/// ETI-660, with lower case b and d
+pub const ETI660: [u8; SMALL_LEN] = [
+    0xE0, 0xA0, 0xA0, 0xA0, 0xE0, 0x20, 0x20, 0x20, 0x20, 0x20, 0xE0, 0x20, 0xE0, 0x80, 0xE0, 0xE0,
+    0x20, 0xE0, 0x20, 0xE0, 0xA0, 0xA0, 0xE0, 0x20, 0x20, 0xE0, 0x80, 0xE0, 0x20, 0xE0, 0xE0, 0x80,
+    0xE0, 0xA0, 0xE0, 0xE0, 0x20, 0x20, 0x20, 0x20, 0xE0, 0xA0, 0xE0, 0xA0, 0xE0, 0xE0, 0xA0, 0xE0,
+    0x20, 0xE0, 0xE0, 0xA0, 0xE0, 0xA0, 0xA0, 0x80, 0x80, 0xE0, 0xA0, 0xE0, 0xE0, 0x80, 0x80, 0x80,
+    0xE0, 0x20, 0x20, 0xE0, 0xA0, 0xE0, 0xE0, 0x80, 0xE0, 0x80, 0xE0, 0xE0, 0x80, 0xC0, 0x80, 0x80,
+];
+
+/// Names `by_name` knows
+pub const NAMES: [&str; 4] = ["default", "vip", "dream6800", "eti660"];
+
+/// A built-in set
+///
+/// ```
+/// use chip8::font;
+///
+/// assert_eq!(font::by_name("VIP"), Some(&font::VIP));
+/// assert_eq!(font::by_name("octo"), None);
+/// ```
+pub fn by_name(name: &str) -> Option<&'static [u8; SMALL_LEN]> {
+    match name.to_ascii_lowercase().as_str() {
+        "default" => Some(&DEFAULT),
+        "vip" => Some(&VIP),
+        "dream6800" => Some(&DREAM6800),
+        "eti660" => Some(&ETI660),
+        _ => None,
+    }
+}
+
+/// Whether `image`, the start of RAM, has glyphs at `FONT_START`: 16 of
+/// them, each drawn only in the left 4 columns and not blank
+///
+/// ```
+/// use chip8::font;
+///
+/// let mut image = [0u8; 0x200];
+/// assert!(!font::present(&image));
+/// image[0x50..0xA0].copy_from_slice(&font::ETI660);
+/// assert!(font::present(&image));
+/// assert!(!font::present(&image[..0x9F]));
+/// // Machine code rather than digits
+/// image[0x60] = 0x3F;
+/// assert!(!font::present(&image));
+/// ```
+pub fn present(image: &[u8]) -> bool {
+    let at = FONT_START as usize;
+    match image.get(at..at + SMALL_LEN) {
+        Some(g) => {
+            g.iter().all(|b| b & 0x0F == 0) && g.chunks(5).all(|c| c.iter().any(|&b| b != 0))
+        }
+        None => false,
+    }
+}
+
+#[derive(Debug)]
+pub enum FontError {
+    Io(std::io::Error),
+    /// Neither the small glyphs nor the small and big ones
+    Length(usize),
+}
+
+impl fmt::Display for FontError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "{}", e),
+            Self::Length(n) => write!(
+                f,
+                "font is {} bytes, not {} or {} with big digits",
+                n,
+                SMALL_LEN,
+                SMALL_LEN + BIG_LEN
+            ),
+        }
+    }
+}
+
+impl std::error::Error for FontError {}
+
+impl From<std::io::Error> for FontError {
+    fn from(e: std::io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+/// Whether `glyphs` is a whole font
+pub fn check(glyphs: &[u8]) -> Result<(), FontError> {
+    match glyphs.len() {
+        SMALL_LEN => Ok(()),
+        n if n == SMALL_LEN + BIG_LEN => Ok(()),
+        n => Err(FontError::Length(n)),
+    }
+}
+
+/// A built-in set by name, otherwise a file holding one
+///
+/// ```
+/// use chip8::emu::Chip8;
+/// use chip8::font::{self, FontError};
+///
+/// // LD V0, 8; LD F, V0; DRW V1, V1, 5; JP 206
+/// let rom = [0x60, 0x08, 0xF0, 0x29, 0xD1, 0x15, 0x12, 0x06];
+/// // LD V0, 1 instead
+/// let one = [0x60, 0x01, 0xF0, 0x29, 0xD1, 0x15, 0x12, 0x06];
+/// let draw = |rom: &[u8], name| {
+///     let glyphs = font::load(name).unwrap();
+///     let mut c8 = Chip8::builder().rom(rom).glyphs(&glyphs).build().unwrap();
+///     c8.run_n(4).unwrap();
+///     c8.screen
+/// };
+/// let rows = |a: [u64; 32], b: [u64; 32]| (0..32).filter(|&y| a[y] != b[y]).collect::<Vec<_>>();
+/// // A 3 pixel wide 8 against the usual 4, every row differs
+/// assert_eq!(draw(&rom, "default")[1], 0x90 << 56);
+/// assert_eq!(draw(&rom, "dream6800")[1], 0xA0 << 56);
+/// assert_eq!(rows(draw(&rom, "default"), draw(&rom, "dream6800")), [0, 1, 2, 3, 4]);
+/// // The VIP's 1 only differs at the top
+/// assert_eq!(rows(draw(&one, "default"), draw(&one, "vip")), [0, 1]);
+///
+/// let path = std::env::temp_dir().join(format!("chip8-font-{}.bin", std::process::id()));
+/// std::fs::write(&path, &font::ETI660).unwrap();
+/// assert_eq!(font::load(path.to_str().unwrap()).unwrap(), font::ETI660);
+/// std::fs::write(&path, [0; 100]).unwrap();
+/// assert!(matches!(font::load(path.to_str().unwrap()), Err(FontError::Length(100))));
+/// std::fs::remove_file(&path).unwrap();
+/// ```
+pub fn load(name_or_path: &str) -> Result<Vec<u8>, FontError> {
+    if let Some(glyphs) = by_name(name_or_path) {
+        return Ok(glyphs.to_vec());
+    }
+    let glyphs = std::fs::read(name_or_path)?;
+    check(&glyphs)?;
+    Ok(glyphs)
+}
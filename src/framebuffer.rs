@@ -0,0 +1,256 @@
+use crate::layout::{HIRES_HEIGHT, HIRES_WIDTH, LORES_HEIGHT, LORES_WIDTH};
+
+/// Screen width in pixels
+pub const WIDTH: usize = LORES_WIDTH;
+/// Screen height in pixels
+pub const HEIGHT: usize = LORES_HEIGHT;
+
+/// First byte hashed by `Framebuffer::hash`, changed whenever what's
+/// hashed is
+pub const HASH_VERSION: u8 = 1;
+
+pub type Rgba = [u8; 4];
+
+/// Pixel access for a screen however it's stored. Colors are palette
+/// indices, 0 for off and 1 for on.
+pub trait Framebuffer {
+    /// Palette index at (x, y), 0 outside the screen
+    fn get(&self, x: usize, y: usize) -> u8;
+
+    fn width(&self) -> usize {
+        WIDTH
+    }
+
+    fn height(&self) -> usize {
+        HEIGHT
+    }
+
+    /// Bits per pixel
+    fn planes(&self) -> usize {
+        1
+    }
+
+    /// FNV-1a hash of what can be seen, the same however it's stored:
+    /// `HASH_VERSION`, the width and height as u16s, the plane count as a
+    /// byte, then each plane's pixels row by row, 8 to a byte with the
+    /// first in the top bit
+    fn hash(&self) -> u64 {
+        let (w, h) = (self.width(), self.height());
+        let mut bytes = vec![HASH_VERSION];
+        bytes.extend_from_slice(&(w as u16).to_be_bytes());
+        bytes.extend_from_slice(&(h as u16).to_be_bytes());
+        bytes.push(self.planes() as u8);
+        for plane in 0..self.planes() {
+            let mut byte = 0u8;
+            for n in 0..w * h {
+                byte = byte << 1 | (self.get(n % w, n / w) >> plane) & 1;
+                if n % 8 == 7 {
+                    bytes.push(byte);
+                    byte = 0;
+                }
+            }
+            if (w * h) % 8 != 0 {
+                bytes.push(byte << (8 - (w * h) % 8));
+            }
+        }
+        crate::state::rom_hash(&bytes)
+    }
+
+    /// Set pixels as (x, y, color), row by row
+    fn pixels(&self) -> impl Iterator<Item = (u16, u16, u8)> + '_;
+
+    /// Fills `out` with WIDTH x HEIGHT RGBA pixels, reusing its allocation
+    fn to_rgba(&self, palette: &[Rgba; 4], out: &mut Vec<u8>);
+
+    /// Palette index of every pixel, row by row
+    fn indices(&self) -> Vec<u8>;
+}
+
+/// The screen, one u64 per row with the leftmost pixel in the top bit
+impl Framebuffer for [u64; HEIGHT] {
+    fn get(&self, x: usize, y: usize) -> u8 {
+        if x < WIDTH && y < HEIGHT {
+            ((self[y] >> (63 - x)) & 1) as u8
+        } else {
+            0
+        }
+    }
+
+    fn pixels(&self) -> impl Iterator<Item = (u16, u16, u8)> + '_ {
+        (0..HEIGHT).flat_map(move |y| {
+            (0..WIDTH).filter_map(move |x| match self.get(x, y) {
+                0 => None,
+                c => Some((x as u16, y as u16, c)),
+            })
+        })
+    }
+
+    fn to_rgba(&self, palette: &[Rgba; 4], out: &mut Vec<u8>) {
+        out.clear();
+        out.reserve(WIDTH * HEIGHT * 4);
+        for y in 0..HEIGHT {
+            for x in 0..WIDTH {
+                out.extend_from_slice(&palette[self.get(x, y) as usize]);
+            }
+        }
+    }
+
+    fn indices(&self) -> Vec<u8> {
+        (0..HEIGHT)
+            .flat_map(|y| (0..WIDTH).map(move |x| self.get(x, y)))
+            .collect()
+    }
+}
+
+/// `Framebuffer::hash` of the screen, for telling screens apart
+pub fn hash(bits: &[u64; 32]) -> u64 {
+    Framebuffer::hash(bits)
+}
+
+/// A 128x64 screen with one u128 per row, the leftmost pixel in the top
+/// bit
+impl Framebuffer for [u128; HIRES_HEIGHT] {
+    fn get(&self, x: usize, y: usize) -> u8 {
+        if x < HIRES_WIDTH && y < HIRES_HEIGHT {
+            ((self[y] >> (HIRES_WIDTH - 1 - x)) & 1) as u8
+        } else {
+            0
+        }
+    }
+
+    fn width(&self) -> usize {
+        HIRES_WIDTH
+    }
+
+    fn height(&self) -> usize {
+        HIRES_HEIGHT
+    }
+
+    fn pixels(&self) -> impl Iterator<Item = (u16, u16, u8)> + '_ {
+        (0..HIRES_HEIGHT).flat_map(move |y| {
+            (0..HIRES_WIDTH).filter_map(move |x| match self.get(x, y) {
+                0 => None,
+                c => Some((x as u16, y as u16, c)),
+            })
+        })
+    }
+
+    fn to_rgba(&self, palette: &[Rgba; 4], out: &mut Vec<u8>) {
+        out.clear();
+        for y in 0..HIRES_HEIGHT {
+            for x in 0..HIRES_WIDTH {
+                out.extend_from_slice(&palette[self.get(x, y) as usize]);
+            }
+        }
+    }
+
+    fn indices(&self) -> Vec<u8> {
+        (0..HIRES_HEIGHT)
+            .flat_map(|y| (0..HIRES_WIDTH).map(move |x| self.get(x, y)))
+            .collect()
+    }
+}
+
+/// A screen of any size with a byte per pixel, row by row, holding a
+/// palette index of `planes` bits
+///
+/// ```
+/// use chip8::framebuffer::{self, Framebuffer, Indexed};
+///
+/// // A diagonal line and a box, stored packed and a byte a pixel
+/// let mut lores = [0u64; 32];
+/// let mut hires = [0u128; 64];
+/// let mut small = Indexed::new(64, 32, 1);
+/// let mut big = Indexed::new(128, 64, 1);
+/// for n in 0..32 {
+///     lores[n] |= 1 << (63 - n);
+///     small.set(n, n, 1);
+/// }
+/// for n in 0..64 {
+///     hires[n] |= 1 << (127 - 2 * n) | 1 << 127 | 1;
+///     big.set(2 * n, n, 1);
+///     big.set(0, n, 1);
+///     big.set(127, n, 1);
+/// }
+/// assert_eq!(lores.hash(), small.hash());
+/// assert_eq!(framebuffer::hash(&lores), small.hash());
+/// assert_eq!(hires.hash(), big.hash());
+///
+/// // Size and plane count are part of it, even with nothing showing
+/// let blank = [Indexed::new(64, 32, 1), Indexed::new(32, 64, 1), Indexed::new(64, 32, 2)];
+/// assert_eq!(framebuffer::hash(&[0; 32]), blank[0].hash());
+/// assert_ne!(blank[0].hash(), blank[1].hash());
+/// assert_ne!(blank[0].hash(), blank[2].hash());
+/// assert_ne!([0u128; 64].hash(), blank[0].hash());
+/// // As is the second plane
+/// let mut two = Indexed::new(64, 32, 2);
+/// two.set(5, 5, 2);
+/// assert_ne!(two.hash(), blank[2].hash());
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Indexed {
+    width: usize,
+    height: usize,
+    planes: usize,
+    pixels: Vec<u8>,
+}
+
+impl Indexed {
+    /// All off
+    pub fn new(width: usize, height: usize, planes: usize) -> Self {
+        Self {
+            width,
+            height,
+            planes,
+            pixels: vec![0; width * height],
+        }
+    }
+
+    /// Ignored outside the screen
+    pub fn set(&mut self, x: usize, y: usize, color: u8) {
+        if x < self.width && y < self.height {
+            self.pixels[y * self.width + x] = color & ((1 << self.planes) - 1);
+        }
+    }
+}
+
+impl Framebuffer for Indexed {
+    fn get(&self, x: usize, y: usize) -> u8 {
+        if x < self.width && y < self.height {
+            self.pixels[y * self.width + x]
+        } else {
+            0
+        }
+    }
+
+    fn width(&self) -> usize {
+        self.width
+    }
+
+    fn height(&self) -> usize {
+        self.height
+    }
+
+    fn planes(&self) -> usize {
+        self.planes
+    }
+
+    fn pixels(&self) -> impl Iterator<Item = (u16, u16, u8)> + '_ {
+        self.pixels
+            .iter()
+            .enumerate()
+            .filter(|(_, &c)| c != 0)
+            .map(move |(n, &c)| ((n % self.width) as u16, (n / self.width) as u16, c))
+    }
+
+    fn to_rgba(&self, palette: &[Rgba; 4], out: &mut Vec<u8>) {
+        out.clear();
+        for &c in &self.pixels {
+            out.extend_from_slice(&palette[c as usize & 3]);
+        }
+    }
+
+    fn indices(&self) -> Vec<u8> {
+        self.pixels.clone()
+    }
+}
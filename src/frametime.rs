@@ -0,0 +1,227 @@
+//! Where the time in each frame goes, for tracking down stutter. Every
+//! frame is split into running the game, drawing it (everything else the
+//! frame does) and sleeping until the next one. The last `WINDOW` frames
+//! are kept for percentiles, and `--frame-log` writes them all as CSV.
+use std::fmt;
+use std::io::{self, Write};
+use std::time::Duration;
+
+/// Frames the percentiles are taken over
+pub const WINDOW: usize = 600;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FrameTime {
+    pub emulate: Duration,
+    pub render: Duration,
+    pub sleep: Duration,
+    /// How far past its budget the frame went
+    pub overrun: Duration,
+}
+
+impl FrameTime {
+    /// Time spent working rather than sleeping
+    pub fn busy(&self) -> Duration {
+        self.emulate + self.render
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Percentiles {
+    pub p50: Duration,
+    pub p95: Duration,
+    pub max: Duration,
+}
+
+/// Nearest-rank percentiles of `samples`, which get sorted
+///
+/// ```
+/// use chip8::frametime::{percentiles, Percentiles};
+/// use std::time::Duration;
+///
+/// let ms = Duration::from_millis;
+/// let mut samples: Vec<Duration> = (1..=100).rev().map(ms).collect();
+/// assert_eq!(percentiles(&mut samples), Some(Percentiles { p50: ms(50), p95: ms(95), max: ms(100) }));
+/// // A single stutter only shows in the max
+/// let mut samples = vec![ms(5); 99];
+/// samples.push(ms(300));
+/// assert_eq!(percentiles(&mut samples), Some(Percentiles { p50: ms(5), p95: ms(5), max: ms(300) }));
+/// assert_eq!(percentiles(&mut [ms(7)]), Some(Percentiles { p50: ms(7), p95: ms(7), max: ms(7) }));
+/// assert_eq!(percentiles(&mut []), None);
+/// ```
+pub fn percentiles(samples: &mut [Duration]) -> Option<Percentiles> {
+    samples.sort_unstable();
+    let max = *samples.last()?;
+    // The smallest sample with at least p% of them at or below it
+    let rank = |p: usize| samples[(p * samples.len()).div_ceil(100) - 1];
+    Some(Percentiles {
+        p50: rank(50),
+        p95: rank(95),
+        max,
+    })
+}
+
+/// A frame that went well over its budget
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SlowFrame {
+    pub frame: u64,
+    /// "emulation" or "rendering", whichever took longer
+    pub phase: &'static str,
+    pub took: Duration,
+    pub overrun: Duration,
+}
+
+impl fmt::Display for SlowFrame {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "frame {} ran {} ms over, {} took {} ms",
+            self.frame,
+            self.overrun.as_millis(),
+            self.phase,
+            self.took.as_millis()
+        )
+    }
+}
+
+/// The last `WINDOW` frames
+///
+/// ```
+/// use chip8::frametime::FrameTimes;
+/// use std::time::Duration;
+///
+/// let ms = Duration::from_millis;
+/// let mut t = FrameTimes::new(ms(50));
+/// // 130 ms of work is 80 ms over, more than a whole frame, so it's warned about
+/// let slow = t.push(0, ms(10), ms(120), ms(0)).unwrap();
+/// assert_eq!(slow.to_string(), "frame 0 ran 80 ms over, rendering took 120 ms");
+/// assert_eq!(t.last().overrun, ms(80));
+/// // Every one is, `warnings::Warnings` keeps it from being said each time
+/// let slow = t.push(1, ms(120), ms(0), ms(0)).unwrap();
+/// assert_eq!((slow.frame, slow.phase), (1, "emulation"));
+/// // Late, but not by a whole frame
+/// assert_eq!(t.push(2, ms(90), ms(0), ms(0)), None);
+/// assert_eq!(t.last().overrun, ms(40));
+/// // Within budget counts nothing over, however it was split
+/// t.push(3, ms(5), ms(5), ms(40));
+/// assert_eq!(t.last().overrun, ms(0));
+///
+/// // Only the last WINDOW frames count
+/// for n in 0..600 {
+///     t.push(4 + n, ms(3), ms(2), ms(45));
+/// }
+/// let p = t.percentiles().unwrap();
+/// assert_eq!((p.p50, p.p95, p.max), (ms(5), ms(5), ms(5)));
+/// ```
+#[derive(Debug, Clone)]
+pub struct FrameTimes {
+    budget: Duration,
+    // A ring once full, `next` is where the oldest is
+    times: Vec<FrameTime>,
+    next: usize,
+}
+
+impl FrameTimes {
+    /// For frames meant to take `budget`
+    pub fn new(budget: Duration) -> Self {
+        Self {
+            budget,
+            times: Vec::with_capacity(WINDOW),
+            next: 0,
+        }
+    }
+
+    /// Records frame number `frame`, returning a warning if it went more
+    /// than a whole budget over
+    pub fn push(
+        &mut self,
+        frame: u64,
+        emulate: Duration,
+        render: Duration,
+        sleep: Duration,
+    ) -> Option<SlowFrame> {
+        let t = FrameTime {
+            emulate,
+            render,
+            sleep,
+            overrun: (emulate + render + sleep).saturating_sub(self.budget),
+        };
+        if self.times.len() < WINDOW {
+            self.times.push(t);
+        } else {
+            self.times[self.next] = t;
+        }
+        self.next = (self.next + 1) % WINDOW;
+        if t.overrun <= self.budget {
+            return None;
+        }
+        let (phase, took) = if emulate >= render {
+            ("emulation", emulate)
+        } else {
+            ("rendering", render)
+        };
+        Some(SlowFrame {
+            frame,
+            phase,
+            took,
+            overrun: t.overrun,
+        })
+    }
+
+    /// The frame pushed last
+    pub fn last(&self) -> FrameTime {
+        let i = (self.next + WINDOW - 1) % WINDOW;
+        self.times.get(i).copied().unwrap_or_default()
+    }
+
+    /// Of the time each frame was busy, `None` before any
+    pub fn percentiles(&self) -> Option<Percentiles> {
+        let mut busy: Vec<Duration> = self.times.iter().map(FrameTime::busy).collect();
+        percentiles(&mut busy)
+    }
+}
+
+/// `--frame-log`, a CSV row of microseconds for every frame
+///
+/// ```
+/// use chip8::frametime::{CsvLog, FrameTime};
+/// use std::time::Duration;
+///
+/// let us = Duration::from_micros;
+/// let mut log = CsvLog::new(Vec::new()).unwrap();
+/// log.row(0, &FrameTime { emulate: us(1200), render: us(300), sleep: us(48500), overrun: us(0) }).unwrap();
+/// log.row(1, &FrameTime { emulate: us(1100), render: us(80000), sleep: us(0), overrun: us(31100) }).unwrap();
+/// assert_eq!(
+///     String::from_utf8(log.into_inner().unwrap()).unwrap(),
+///     "frame,emulate_us,render_us,sleep_us,overrun_us\n0,1200,300,48500,0\n1,1100,80000,0,31100\n"
+/// );
+/// ```
+pub struct CsvLog<W: Write> {
+    out: io::BufWriter<W>,
+}
+
+impl<W: Write> CsvLog<W> {
+    /// Writes the header
+    pub fn new(out: W) -> io::Result<Self> {
+        let mut out = io::BufWriter::new(out);
+        writeln!(out, "frame,emulate_us,render_us,sleep_us,overrun_us")?;
+        Ok(Self { out })
+    }
+
+    /// Written straight into the buffer, nothing is allocated per row
+    pub fn row(&mut self, frame: u64, t: &FrameTime) -> io::Result<()> {
+        writeln!(
+            self.out,
+            "{},{},{},{},{}",
+            frame,
+            t.emulate.as_micros(),
+            t.render.as_micros(),
+            t.sleep.as_micros(),
+            t.overrun.as_micros()
+        )
+    }
+
+    /// Flushes and gives back the writer
+    pub fn into_inner(self) -> io::Result<W> {
+        self.out.into_inner().map_err(|e| e.into_error())
+    }
+}
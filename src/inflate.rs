@@ -0,0 +1,224 @@
+//! DEFLATE decompression (RFC 1951), enough to read zip archives and
+//! check the PNGs written by screenshots
+
+struct Bits<'a> {
+    data: &'a [u8],
+    pos: usize,
+    buf: u32,
+    count: u32,
+}
+
+impl<'a> Bits<'a> {
+    fn bits(&mut self, n: u32) -> Result<u32, &'static str> {
+        while self.count < n {
+            let b = *self
+                .data
+                .get(self.pos)
+                .ok_or("compressed data is truncated")?;
+            self.pos += 1;
+            self.buf |= (b as u32) << self.count;
+            self.count += 8;
+        }
+        let v = self.buf & ((1u32 << n) - 1);
+        self.buf >>= n;
+        self.count -= n;
+        Ok(v)
+    }
+
+    // Drops bits up to the next byte boundary
+    fn align(&mut self) {
+        self.buf = 0;
+        self.count = 0;
+    }
+}
+
+// Canonical Huffman code as symbol counts per length and symbols in order
+struct Huffman {
+    counts: [u16; 16],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Self {
+        let mut counts = [0u16; 16];
+        for &l in lengths {
+            counts[l as usize] += 1;
+        }
+        counts[0] = 0;
+        let mut offs = [0u16; 16];
+        for l in 1..16 {
+            offs[l] = offs[l - 1] + counts[l - 1];
+        }
+        let mut symbols = vec![0u16; lengths.len()];
+        for (sym, &l) in lengths.iter().enumerate() {
+            if l != 0 {
+                symbols[offs[l as usize] as usize] = sym as u16;
+                offs[l as usize] += 1;
+            }
+        }
+        Self { counts, symbols }
+    }
+
+    fn decode(&self, bits: &mut Bits) -> Result<u16, &'static str> {
+        let (mut code, mut first, mut index) = (0i32, 0i32, 0i32);
+        for len in 1..16 {
+            code |= bits.bits(1)? as i32;
+            let count = self.counts[len] as i32;
+            if code - first < count {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err("bad Huffman code")
+    }
+}
+
+const LEN_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LEN_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DIST_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DIST_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+
+// Order code length code lengths are stored in
+const CL_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+fn block(
+    bits: &mut Bits,
+    out: &mut Vec<u8>,
+    lit: &Huffman,
+    dist: &Huffman,
+    limit: usize,
+) -> Result<(), &'static str> {
+    loop {
+        let sym = lit.decode(bits)? as usize;
+        if sym < 256 {
+            out.push(sym as u8);
+        } else if sym == 256 {
+            return Ok(());
+        } else {
+            let sym = sym - 257;
+            if sym >= 29 {
+                return Err("bad length code");
+            }
+            let len = LEN_BASE[sym] as usize + bits.bits(LEN_EXTRA[sym] as u32)? as usize;
+            let d = dist.decode(bits)? as usize;
+            if d >= 30 {
+                return Err("bad distance code");
+            }
+            let d = DIST_BASE[d] as usize + bits.bits(DIST_EXTRA[d] as u32)? as usize;
+            if d > out.len() {
+                return Err("distance is too far back");
+            }
+            let start = out.len() - d;
+            for n in 0..len {
+                out.push(out[start + n]);
+            }
+        }
+        if out.len() > limit {
+            return Err("decompressed data is too large");
+        }
+    }
+}
+
+/// Decompresses a raw DEFLATE stream, failing if the output would exceed
+/// `limit` bytes
+pub fn inflate(data: &[u8], limit: usize) -> Result<Vec<u8>, &'static str> {
+    let mut bits = Bits {
+        data,
+        pos: 0,
+        buf: 0,
+        count: 0,
+    };
+    let mut out = Vec::new();
+    loop {
+        let last = bits.bits(1)? == 1;
+        match bits.bits(2)? {
+            0 => {
+                bits.align();
+                let header = data
+                    .get(bits.pos..bits.pos + 4)
+                    .ok_or("stored block is truncated")?;
+                let len = u16::from_le_bytes([header[0], header[1]]) as usize;
+                let nlen = u16::from_le_bytes([header[2], header[3]]) as usize;
+                if len != !nlen & 0xFFFF {
+                    return Err("stored block length is corrupt");
+                }
+                bits.pos += 4;
+                let body = data
+                    .get(bits.pos..bits.pos + len)
+                    .ok_or("stored block is truncated")?;
+                out.extend_from_slice(body);
+                bits.pos += len;
+                if out.len() > limit {
+                    return Err("decompressed data is too large");
+                }
+            }
+            1 => {
+                let mut lengths = [0u8; 288];
+                for (n, l) in lengths.iter_mut().enumerate() {
+                    *l = match n {
+                        0..=143 => 8,
+                        144..=255 => 9,
+                        256..=279 => 7,
+                        _ => 8,
+                    };
+                }
+                let lit = Huffman::new(&lengths);
+                let dist = Huffman::new(&[5u8; 30]);
+                block(&mut bits, &mut out, &lit, &dist, limit)?;
+            }
+            2 => {
+                let nlen = bits.bits(5)? as usize + 257;
+                let ndist = bits.bits(5)? as usize + 1;
+                let ncode = bits.bits(4)? as usize + 4;
+                let mut cl = [0u8; 19];
+                for &i in CL_ORDER.iter().take(ncode) {
+                    cl[i] = bits.bits(3)? as u8;
+                }
+                let clh = Huffman::new(&cl);
+                let mut lengths = vec![0u8; nlen + ndist];
+                let mut n = 0;
+                while n < nlen + ndist {
+                    let sym = clh.decode(&mut bits)?;
+                    let (value, repeat) = match sym {
+                        0..=15 => (sym as u8, 1),
+                        16 => {
+                            let prev = *lengths[..n].last().ok_or("repeat with no length")?;
+                            (prev, 3 + bits.bits(2)? as usize)
+                        }
+                        17 => (0, 3 + bits.bits(3)? as usize),
+                        _ => (0, 11 + bits.bits(7)? as usize),
+                    };
+                    if n + repeat > lengths.len() {
+                        return Err("too many code lengths");
+                    }
+                    for l in &mut lengths[n..n + repeat] {
+                        *l = value;
+                    }
+                    n += repeat;
+                }
+                let lit = Huffman::new(&lengths[..nlen]);
+                let dist = Huffman::new(&lengths[nlen..]);
+                block(&mut bits, &mut out, &lit, &dist, limit)?;
+            }
+            _ => return Err("bad block type"),
+        }
+        if last {
+            return Ok(out);
+        }
+    }
+}
@@ -0,0 +1,325 @@
+use crate::emu;
+use crate::json;
+use crate::layout::{MemoryMap, Region, PROGRAM_START, RAM_SIZE_CLASSIC};
+use crate::rom::MAX_SIZE;
+use std::fmt;
+
+/// SHA-1 digest
+pub fn sha1(data: &[u8]) -> [u8; 20] {
+    let mut h: [u32; 5] = [
+        0x6745_2301,
+        0xEFCD_AB89,
+        0x98BA_DCFE,
+        0x1032_5476,
+        0xC3D2_E1F0,
+    ];
+    let mut msg = data.to_vec();
+    msg.push(0x80);
+    while msg.len() % 64 != 56 {
+        msg.push(0);
+    }
+    msg.extend_from_slice(&((data.len() as u64) * 8).to_be_bytes());
+    for block in msg.chunks(64) {
+        let mut w = [0u32; 80];
+        for (n, word) in block.chunks(4).enumerate() {
+            w[n] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+        }
+        for n in 16..80 {
+            w[n] = (w[n - 3] ^ w[n - 8] ^ w[n - 14] ^ w[n - 16]).rotate_left(1);
+        }
+        let [mut a, mut b, mut c, mut d, mut e] = h;
+        for (n, &wn) in w.iter().enumerate() {
+            let (f, k) = match n {
+                0..=19 => ((b & c) | (!b & d), 0x5A82_7999),
+                20..=39 => (b ^ c ^ d, 0x6ED9_EBA1),
+                40..=59 => ((b & c) | (b & d) | (c & d), 0x8F1B_BCDC),
+                _ => (b ^ c ^ d, 0xCA62_C1D6),
+            };
+            let t = a
+                .rotate_left(5)
+                .wrapping_add(f)
+                .wrapping_add(e)
+                .wrapping_add(k)
+                .wrapping_add(wn);
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = t;
+        }
+        for (x, v) in h.iter_mut().zip([a, b, c, d, e]) {
+            *x = x.wrapping_add(v);
+        }
+    }
+    let mut out = [0u8; 20];
+    for (chunk, v) in out.chunks_mut(4).zip(h.iter()) {
+        chunk.copy_from_slice(&v.to_be_bytes());
+    }
+    out
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Platform {
+    Chip8,
+    /// CHIP-8 with a 64x64 screen, from a patched VIP interpreter
+    Hires,
+    SuperChip,
+    XoChip,
+}
+
+impl fmt::Display for Platform {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Self::Chip8 => "CHIP-8",
+            Self::Hires => "HI-RES CHIP-8",
+            Self::SuperChip => "SUPER-CHIP",
+            Self::XoChip => "XO-CHIP",
+        })
+    }
+}
+
+// Opcodes only later platforms have
+fn superchip_only(op: u16) -> bool {
+    matches!(op & 0xFFF0, 0x00C0)
+        || matches!(op, 0x00FB..=0x00FF)
+        || (op & 0xF000 == 0xD000 && op & 0xF == 0)
+        || matches!(op & 0xF0FF, 0xF030 | 0xF075 | 0xF085)
+}
+
+fn xochip_only(op: u16) -> bool {
+    op & 0xFFF0 == 0x00D0
+        || matches!(op & 0xF00F, 0x5002 | 0x5003)
+        || op == 0xF000
+        || matches!(op & 0xF0FF, 0xF001 | 0xF002 | 0xF03A)
+}
+
+/// Whether `rom` starts like 64x64 hi-res programs, which jump over the
+/// interpreter patch that was loaded with them
+///
+/// ```
+/// use chip8::info::{is_hires, Info, Platform};
+///
+/// let rom = [0x12, 0x60, 0x00, 0xE0];
+/// assert!(is_hires(&rom));
+/// let info = Info::new(&rom);
+/// assert_eq!(info.platform, Platform::Hires);
+/// assert!(info.warnings[0].starts_with("starts with JP 260"));
+/// assert!(!is_hires(&[0x12, 0x00]));
+/// // Where it goes in memory, labelled as `chip8 hexdump` has it
+/// assert!(info.to_string().contains("\nmemory    FONT 050-09F, BIGF 0A0-13F, PROG 200-203, STCK EA0-EFF\n"));
+/// assert!(info.to_json().contains(r#""memory":[{"region":"FONT","start":"050","end":"09F"},"#));
+/// ```
+pub fn is_hires(rom: &[u8]) -> bool {
+    rom.starts_with(&[0x12, 0x60])
+}
+
+/// The later platform `op` is an instruction of, if only later platforms
+/// have it. Some of these (00FF, Dxy0) are also CHIP-8 instructions that
+/// do nothing useful.
+pub fn platform_of(op: u16) -> Option<Platform> {
+    if xochip_only(op) {
+        Some(Platform::XoChip)
+    } else if superchip_only(op) {
+        Some(Platform::SuperChip)
+    } else {
+        None
+    }
+}
+
+/// Something to add to an error about `op` not being an instruction
+///
+/// ```
+/// use chip8::info::{hint, platform_of, Platform};
+///
+/// for op in [0x00C4, 0x00FB, 0x00FF, 0xD120, 0xF330, 0xF075, 0xF585] {
+///     assert_eq!(platform_of(op), Some(Platform::SuperChip), "{:04X}", op);
+/// }
+/// for op in [0x00D2, 0x5122, 0x5123, 0xF000, 0xF201, 0xF002, 0xF13A] {
+///     assert_eq!(platform_of(op), Some(Platform::XoChip), "{:04X}", op);
+/// }
+/// for op in [0x00E0, 0x1200, 0x5120, 0xF065, 0xF129, 0xD125] {
+///     assert_eq!(platform_of(op), None, "{:04X}", op);
+/// }
+/// assert_eq!(
+///     hint(0xF075).as_deref(),
+///     Some("this looks like a SUPER-CHIP instruction, which isn't supported")
+/// );
+/// assert_eq!(hint(0x5124), None);
+/// ```
+pub fn hint(op: u16) -> Option<String> {
+    platform_of(op).map(|p| format!("this looks like a {} instruction, which isn't supported", p))
+}
+
+/// What a scan of one alignment of the ROM found
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Scan {
+    /// Words this emulator can run
+    pub chip8: usize,
+    pub superchip: usize,
+    pub xochip: usize,
+    /// Words no platform has as an instruction
+    pub invalid: usize,
+}
+
+// 0NNN calls into the host are so rare that they mostly mean data
+fn is_sys(op: u16) -> bool {
+    op & 0xF000 == 0 && op != 0x00E0 && op != 0x00EE
+}
+
+/// Classifies every word starting at `offset` (0 or 1) and every second
+/// byte after it. Sprite data is full of words like 00FF, so an opcode
+/// only later platforms have is only counted after a word that looks
+/// like code, otherwise it counts as whatever this emulator makes of it.
+pub fn scan(rom: &[u8], offset: usize) -> Scan {
+    let mut s = Scan::default();
+    let mut after_code = true;
+    for w in rom.get(offset..).unwrap_or(&[]).chunks_exact(2) {
+        let op = u16::from_be_bytes([w[0], w[1]]);
+        let later = xochip_only(op) || superchip_only(op);
+        if later && after_code {
+            if xochip_only(op) {
+                s.xochip += 1;
+            } else {
+                s.superchip += 1;
+            }
+        } else if emu::is_instruction(op) {
+            s.chip8 += 1;
+        } else {
+            s.invalid += 1;
+        }
+        after_code = (later && after_code) || (emu::is_instruction(op) && !is_sys(op));
+    }
+    s
+}
+
+/// Facts about a ROM file
+#[derive(Debug, Clone, PartialEq)]
+pub struct Info {
+    pub size: usize,
+    pub crc32: u32,
+    pub sha1: [u8; 20],
+    pub platform: Platform,
+    /// Scan of whichever alignment looks more like code
+    pub scan: Scan,
+    pub warnings: Vec<String>,
+    /// Regions of classic RAM with the ROM at 0x200, leaving out free
+    /// ones, as the region and first and last address
+    pub memory: Vec<(Region, u16, u16)>,
+}
+
+impl Info {
+    pub fn new(rom: &[u8]) -> Self {
+        let (even, odd) = (scan(rom, 0), scan(rom, 1));
+        // Code is normally aligned, only trust odd offsets if they decode
+        // much better
+        let s = if odd.invalid * 2 < even.invalid {
+            odd
+        } else {
+            even
+        };
+        let platform = if s.xochip > 0 {
+            Platform::XoChip
+        } else if s.superchip > 0 {
+            Platform::SuperChip
+        } else if is_hires(rom) {
+            Platform::Hires
+        } else {
+            Platform::Chip8
+        };
+        let mut warnings = Vec::new();
+        if platform == Platform::Hires {
+            warnings.push(
+                "starts with JP 260 like 64x64 hi-res programs, which aren't supported".to_string(),
+            );
+        }
+        if !rom.len().is_multiple_of(2) {
+            warnings.push("odd length, the last byte can't be an instruction".to_string());
+        }
+        if rom.len() > MAX_SIZE {
+            warnings.push(format!(
+                "{} bytes is more than the {} that fit at 0x200 in 4 KB",
+                rom.len(),
+                MAX_SIZE
+            ));
+        }
+        if rom.len() >= 2 && scan(&rom[..2], 0).invalid > 0 {
+            warnings.push("the first word isn't an instruction on any platform".to_string());
+        }
+        if s.invalid > 0 {
+            warnings.push(format!(
+                "{} words aren't instructions on any platform (data, or not a ROM)",
+                s.invalid
+            ));
+        }
+        Self {
+            size: rom.len(),
+            crc32: crate::screenshot::crc32(rom),
+            sha1: sha1(rom),
+            platform,
+            scan: s,
+            warnings,
+            memory: MemoryMap::new(PROGRAM_START, rom.len())
+                .regions(RAM_SIZE_CLASSIC)
+                .into_iter()
+                .filter(|&(r, _, _)| r != Region::Free)
+                .collect(),
+        }
+    }
+
+    pub fn sha1_hex(&self) -> String {
+        self.sha1.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    /// The same facts as one JSON object
+    pub fn to_json(&self) -> String {
+        let opcodes = json::Object::new()
+            .num("chip8", self.scan.chip8)
+            .num("superchip", self.scan.superchip)
+            .num("xochip", self.scan.xochip)
+            .num("invalid", self.scan.invalid);
+        json::Object::new()
+            .num("size", self.size)
+            .str("crc32", &format!("{:08x}", self.crc32))
+            .str("sha1", &self.sha1_hex())
+            .str("platform", &self.platform.to_string())
+            .raw("opcodes", opcodes.to_string())
+            .raw(
+                "warnings",
+                json::array(self.warnings.iter().map(|w| json::string(w))),
+            )
+            .raw(
+                "memory",
+                json::array(self.memory.iter().map(|&(r, a, b)| {
+                    json::Object::new()
+                        .str("region", r.label())
+                        .str("start", &format!("{:03X}", a))
+                        .str("end", &format!("{:03X}", b))
+                        .to_string()
+                })),
+            )
+            .to_string()
+    }
+}
+
+impl fmt::Display for Info {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "size      {} bytes", self.size)?;
+        writeln!(f, "crc32     {:08x}", self.crc32)?;
+        writeln!(f, "sha1      {}", self.sha1_hex())?;
+        writeln!(
+            f,
+            "platform  {} ({} CHIP-8, {} SUPER-CHIP, {} XO-CHIP opcodes)",
+            self.platform, self.scan.chip8, self.scan.superchip, self.scan.xochip
+        )?;
+        let memory: Vec<String> = self
+            .memory
+            .iter()
+            .map(|(r, a, b)| format!("{} {:03X}-{:03X}", r, a, b))
+            .collect();
+        writeln!(f, "memory    {}", memory.join(", "))?;
+        for w in &self.warnings {
+            writeln!(f, "warning   {}", w)?;
+        }
+        Ok(())
+    }
+}
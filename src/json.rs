@@ -0,0 +1,354 @@
+//! Just enough JSON writing for `--json` output, and reading for files
+//! this writes to be read back. Field names are part of the output
+//! format, so don't rename them. Types saved as JSON have a `to_json`,
+//! and a `from_json` that takes a parsed `Value`.
+use std::fmt;
+
+/// `s` as a quoted JSON string
+pub fn string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// Array of values that are already JSON
+pub fn array<I: IntoIterator<Item = String>>(items: I) -> String {
+    format!("[{}]", items.into_iter().collect::<Vec<_>>().join(","))
+}
+
+/// Object with its fields in the order they were added
+#[derive(Debug, Clone, Default)]
+pub struct Object {
+    fields: Vec<(String, String)>,
+}
+
+impl Object {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a field whose value is already JSON
+    pub fn raw(mut self, key: &str, value: String) -> Self {
+        self.fields.push((string(key), value));
+        self
+    }
+
+    pub fn num<T: fmt::Display>(self, key: &str, value: T) -> Self {
+        self.raw(key, value.to_string())
+    }
+
+    pub fn str(self, key: &str, value: &str) -> Self {
+        self.raw(key, string(value))
+    }
+
+    /// String field, or null if `value` is `None`
+    pub fn opt_str(self, key: &str, value: Option<&str>) -> Self {
+        match value {
+            Some(v) => self.str(key, v),
+            None => self.raw(key, "null".to_string()),
+        }
+    }
+}
+
+impl fmt::Display for Object {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("{")?;
+        for (n, (k, v)) in self.fields.iter().enumerate() {
+            if n > 0 {
+                f.write_str(",")?;
+            }
+            write!(f, "{}:{}", k, v)?;
+        }
+        f.write_str("}")
+    }
+}
+
+/// A JSON value as read by `parse`
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Num(f64),
+    Str(String),
+    Array(Vec<Value>),
+    /// Fields in the order they were written
+    Object(Vec<(String, Value)>),
+}
+
+impl Value {
+    /// Field `key` of an object
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Self::Object(fields) => fields.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Self::Str(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Self::Bool(b) => Some(*b),
+            _ => None,
+        }
+    }
+
+    /// A whole number that fits
+    pub fn as_u64(&self) -> Option<u64> {
+        match self {
+            Self::Num(n) if n.fract() == 0.0 && *n >= 0.0 && *n <= u64::MAX as f64 => {
+                Some(*n as u64)
+            }
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Value]> {
+        match self {
+            Self::Array(items) => Some(items),
+            _ => None,
+        }
+    }
+}
+
+/// Where `parse` gave up, a byte offset
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParseError(pub usize);
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "bad JSON at byte {}", self.0)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// Why a value read by `parse` couldn't be turned back into what wrote it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReadError {
+    Parse(ParseError),
+    /// A field that's missing or isn't what it should be
+    Field(&'static str),
+}
+
+impl fmt::Display for ReadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Parse(e) => write!(f, "{}", e),
+            Self::Field(name) => write!(f, "missing or bad {}", name),
+        }
+    }
+}
+
+impl std::error::Error for ReadError {}
+
+impl From<ParseError> for ReadError {
+    fn from(e: ParseError) -> Self {
+        Self::Parse(e)
+    }
+}
+
+/// Reads one value, with nothing but whitespace after it
+///
+/// ```
+/// use chip8::json::{self, Value};
+///
+/// let v = json::parse(r#" {"a": [1, -2.5e1, true, null], "b": "x\"\u00e9\n"} "#).unwrap();
+/// assert_eq!(
+///     v.get("a").unwrap().as_array().unwrap(),
+///     [Value::Num(1.0), Value::Num(-25.0), Value::Bool(true), Value::Null]
+/// );
+/// assert_eq!(v.get("b").unwrap().as_str(), Some("x\"\u{e9}\n"));
+/// assert_eq!(v.get("c"), None);
+///
+/// // What's written reads back
+/// let out = json::Object::new().str("s", "tab\there").num("n", 12).to_string();
+/// let v = json::parse(&out).unwrap();
+/// assert_eq!((v.get("s").unwrap().as_str(), v.get("n").unwrap().as_u64()), (Some("tab\there"), Some(12)));
+///
+/// assert_eq!(json::parse("[1,]").unwrap_err().to_string(), "bad JSON at byte 3");
+/// assert_eq!(json::parse("{} x").unwrap_err(), json::ParseError(3));
+/// assert!(json::parse("\"open").is_err());
+/// ```
+pub fn parse(src: &str) -> Result<Value, ParseError> {
+    let mut p = Parser {
+        s: src.as_bytes(),
+        at: 0,
+    };
+    let v = p.value()?;
+    p.space();
+    if p.at < p.s.len() {
+        return Err(ParseError(p.at));
+    }
+    Ok(v)
+}
+
+struct Parser<'a> {
+    s: &'a [u8],
+    at: usize,
+}
+
+impl Parser<'_> {
+    fn space(&mut self) {
+        while self.s.get(self.at).is_some_and(u8::is_ascii_whitespace) {
+            self.at += 1;
+        }
+    }
+
+    // Skips whitespace and then `b`, if it's there
+    fn eat(&mut self, b: u8) -> bool {
+        self.space();
+        if self.s.get(self.at) == Some(&b) {
+            self.at += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, b: u8) -> Result<(), ParseError> {
+        if self.eat(b) {
+            Ok(())
+        } else {
+            Err(ParseError(self.at))
+        }
+    }
+
+    fn word(&mut self, w: &str, v: Value) -> Result<Value, ParseError> {
+        if self.s[self.at..].starts_with(w.as_bytes()) {
+            self.at += w.len();
+            Ok(v)
+        } else {
+            Err(ParseError(self.at))
+        }
+    }
+
+    fn value(&mut self) -> Result<Value, ParseError> {
+        self.space();
+        match self.s.get(self.at) {
+            Some(b'{') => {
+                self.at += 1;
+                let mut fields = Vec::new();
+                if !self.eat(b'}') {
+                    loop {
+                        self.space();
+                        let k = self.string()?;
+                        self.expect(b':')?;
+                        fields.push((k, self.value()?));
+                        if self.eat(b'}') {
+                            break;
+                        }
+                        self.expect(b',')?;
+                    }
+                }
+                Ok(Value::Object(fields))
+            }
+            Some(b'[') => {
+                self.at += 1;
+                let mut items = Vec::new();
+                if !self.eat(b']') {
+                    loop {
+                        items.push(self.value()?);
+                        if self.eat(b']') {
+                            break;
+                        }
+                        self.expect(b',')?;
+                    }
+                }
+                Ok(Value::Array(items))
+            }
+            Some(b'"') => self.string().map(Value::Str),
+            Some(b't') => self.word("true", Value::Bool(true)),
+            Some(b'f') => self.word("false", Value::Bool(false)),
+            Some(b'n') => self.word("null", Value::Null),
+            _ => self.number(),
+        }
+    }
+
+    fn number(&mut self) -> Result<Value, ParseError> {
+        let start = self.at;
+        while self
+            .s
+            .get(self.at)
+            .is_some_and(|b| b.is_ascii_digit() || b"+-.eE".contains(b))
+        {
+            self.at += 1;
+        }
+        std::str::from_utf8(&self.s[start..self.at])
+            .ok()
+            .and_then(|n| n.parse().ok())
+            .map(Value::Num)
+            .ok_or(ParseError(start))
+    }
+
+    fn string(&mut self) -> Result<String, ParseError> {
+        if self.s.get(self.at) != Some(&b'"') {
+            return Err(ParseError(self.at));
+        }
+        self.at += 1;
+        let mut out = Vec::new();
+        loop {
+            let b = *self.s.get(self.at).ok_or(ParseError(self.at))?;
+            self.at += 1;
+            match b {
+                b'"' => break,
+                b'\\' => {
+                    let e = *self.s.get(self.at).ok_or(ParseError(self.at))?;
+                    self.at += 1;
+                    let c = match e {
+                        b'"' | b'\\' | b'/' => e as char,
+                        b'n' => '\n',
+                        b'r' => '\r',
+                        b't' => '\t',
+                        b'b' => '\u{8}',
+                        b'f' => '\u{c}',
+                        b'u' => self.escape()?,
+                        _ => return Err(ParseError(self.at - 1)),
+                    };
+                    out.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+                }
+                b => out.push(b),
+            }
+        }
+        // The input was a str and escapes only add whole chars
+        Ok(String::from_utf8(out).unwrap_or_default())
+    }
+
+    // The XXXX of \uXXXX, and a second one if that's half a surrogate pair
+    fn escape(&mut self) -> Result<char, ParseError> {
+        let hex = |p: &mut Self| -> Result<u32, ParseError> {
+            let h =
+                p.s.get(p.at..p.at + 4)
+                    .and_then(|h| std::str::from_utf8(h).ok())
+                    .and_then(|h| u32::from_str_radix(h, 16).ok())
+                    .ok_or(ParseError(p.at))?;
+            p.at += 4;
+            Ok(h)
+        };
+        let at = self.at;
+        let mut c = hex(self)?;
+        if (0xD800..0xDC00).contains(&c) && self.s[self.at..].starts_with(b"\\u") {
+            self.at += 2;
+            let low = hex(self)?;
+            c = 0x10000 + ((c - 0xD800) << 10) + (low.wrapping_sub(0xDC00) & 0x3FF);
+        }
+        char::from_u32(c).ok_or(ParseError(at))
+    }
+}
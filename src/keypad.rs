@@ -0,0 +1,373 @@
+use crate::json;
+use std::time::{Duration, Instant};
+
+/// Host key for each CHIP-8 key, laid out as
+/// ```text
+/// 1 2 3 4    1 2 3 C
+/// q w e r    4 5 6 D
+/// a s d f    7 8 9 E
+/// z x c v    A 0 B F
+/// ```
+pub const KEYMAP: [u8; 16] = *b"x123qweasdzc4rfv";
+
+/// Order the keys are laid out on the keypad
+pub const GRID: [u8; 16] = [
+    0x1, 0x2, 0x3, 0xC, 0x4, 0x5, 0x6, 0xD, 0x7, 0x8, 0x9, 0xE, 0xA, 0x0, 0xB, 0xF,
+];
+
+// Terminals only report presses (and repeats), so keys are let go after this
+pub const HOLD: Duration = Duration::from_millis(200);
+
+/// Keys games mostly move with, 2 4 6 and 8 in a cross
+pub const MOVEMENT: u16 = 1 << 0x2 | 1 << 0x4 | 1 << 0x6 | 1 << 0x8;
+
+/// When keys come back up, since terminals never say
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Release {
+    /// This long after the last press or repeat. Movement keys can be
+    /// given longer, to bridge the gap before the terminal starts
+    /// repeating.
+    After {
+        movement: Duration,
+        action: Duration,
+    },
+    /// Not until another key is pressed or `Keypad::release_all`
+    Latched,
+}
+
+impl Default for Release {
+    fn default() -> Self {
+        Self::After {
+            movement: HOLD,
+            action: HOLD,
+        }
+    }
+}
+
+/// CHIP-8 key for a host key
+pub fn map_key(c: u8) -> Option<u8> {
+    let c = c.to_ascii_lowercase();
+    KEYMAP.iter().position(|&k| k == c).map(|k| k as u8)
+}
+
+/// What each layout types on the keys where 1234/QWER/ASDF/ZXCV are on a
+/// US keyboard, in `GRID` order, so the keypad stays in the same place
+pub const LAYOUTS: [(&str, [char; 16]); 5] = [
+    (
+        "qwerty",
+        [
+            '1', '2', '3', '4', 'q', 'w', 'e', 'r', 'a', 's', 'd', 'f', 'z', 'x', 'c', 'v',
+        ],
+    ),
+    (
+        "azerty",
+        [
+            '&', 'é', '"', '\'', 'a', 'z', 'e', 'r', 'q', 's', 'd', 'f', 'w', 'x', 'c', 'v',
+        ],
+    ),
+    (
+        "qwertz",
+        [
+            '1', '2', '3', '4', 'q', 'w', 'e', 'r', 'a', 's', 'd', 'f', 'y', 'x', 'c', 'v',
+        ],
+    ),
+    (
+        "dvorak",
+        [
+            '1', '2', '3', '4', '\'', ',', '.', 'p', 'a', 'o', 'e', 'u', ';', 'q', 'j', 'k',
+        ],
+    ),
+    (
+        "colemak",
+        [
+            '1', '2', '3', '4', 'q', 'w', 'f', 'p', 'a', 'r', 's', 't', 'z', 'x', 'c', 'd',
+        ],
+    ),
+];
+
+/// Host key for each CHIP-8 key, a layout with any keys moved
+///
+/// ```
+/// use chip8::keypad::{Keymap, GRID, KEYMAP};
+///
+/// // QWERTY is the same as the fixed map
+/// let qwerty = Keymap::default();
+/// assert!((0..16).all(|k| qwerty.host_key(k) == KEYMAP[k as usize] as char));
+/// let row = |m: &Keymap, r: usize| GRID[r * 4..r * 4 + 4].iter().map(|&k| m.host_key(k)).collect::<String>();
+///
+/// // Each layout puts the keypad on the same physical keys
+/// let azerty = Keymap::preset("azerty").unwrap();
+/// assert_eq!([row(&azerty, 0), row(&azerty, 1), row(&azerty, 3)], ["&é\"'", "azer", "wxcv"]);
+/// assert_eq!((azerty.map('a'), azerty.map('Q'), azerty.map('é')), (Some(0x4), Some(0x7), Some(0x2)));
+/// assert_eq!(row(&Keymap::preset("qwertz").unwrap(), 3), "yxcv");
+/// let dvorak = Keymap::preset("dvorak").unwrap();
+/// assert_eq!([row(&dvorak, 1), row(&dvorak, 2), row(&dvorak, 3)], ["',.p", "aoeu", ";qjk"]);
+/// assert_eq!(dvorak.map('w'), None);
+/// let colemak = Keymap::preset("colemak").unwrap();
+/// assert_eq!([row(&colemak, 1), row(&colemak, 2), row(&colemak, 3)], ["qwfp", "arst", "zxcd"]);
+/// assert_eq!(Keymap::preset("bepo"), None);
+///
+/// // Moving a key onto one in use swaps them, so it wins whatever the
+/// // layout and every key can still be reached
+/// let mut m = Keymap::preset("dvorak").unwrap();
+/// m.set(0x5, 'W');
+/// m.set(0x6, 'a');
+/// assert_eq!((m.map('w'), m.map('a'), m.map('.')), (Some(0x5), Some(0x6), Some(0x7)));
+/// assert_eq!((m.host_key(0x6), m.host_key(0x7)), ('a', '.'));
+/// assert_eq!(m.map(','), None);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Keymap {
+    keys: [char; 16],
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        Self::preset("qwerty").unwrap()
+    }
+}
+
+impl Keymap {
+    /// One of `LAYOUTS` by name
+    pub fn preset(name: &str) -> Option<Self> {
+        let (_, chars) = LAYOUTS.iter().find(|(n, _)| *n == name)?;
+        let mut keys = ['\0'; 16];
+        for (&k, &c) in GRID.iter().zip(chars) {
+            keys[k as usize] = c;
+        }
+        Some(Self { keys })
+    }
+
+    /// Puts `key` on host key `c`, giving the key that had `c` the one
+    /// `key` had
+    pub fn set(&mut self, key: u8, c: char) {
+        let key = (key & 0xF) as usize;
+        let c = lower(c);
+        if let Some(other) = self.keys.iter().position(|&k| k == c) {
+            self.keys[other] = self.keys[key];
+        }
+        self.keys[key] = c;
+    }
+
+    /// CHIP-8 key for a host key, ignoring case
+    pub fn map(&self, c: char) -> Option<u8> {
+        let c = lower(c);
+        self.keys.iter().position(|&k| k == c).map(|k| k as u8)
+    }
+
+    pub fn host_key(&self, key: u8) -> char {
+        self.keys[(key & 0xF) as usize]
+    }
+
+    /// Each key's host key, under the key as a hex digit
+    ///
+    /// ```
+    /// use chip8::json;
+    /// use chip8::keypad::Keymap;
+    ///
+    /// let m = Keymap::default();
+    /// assert!(m.to_json().starts_with(r#"{"0":"x","1":"1","2":"2","3":"3","4":"q","#));
+    /// let azerty = Keymap::preset("azerty").unwrap();
+    /// assert_eq!(Keymap::from_json(&json::parse(&azerty.to_json()).unwrap()), Ok(azerty));
+    ///
+    /// // Keys left out stay where they were, like a key moved with `set`
+    /// let mut moved = Keymap::default();
+    /// moved.set(0x5, 'j');
+    /// assert_eq!(Keymap::from_json(&json::parse(r#"{"5":"J"}"#).unwrap()), Ok(moved));
+    /// assert!(Keymap::from_json(&json::parse(r#"{"G":"a"}"#).unwrap()).is_err());
+    /// assert!(Keymap::from_json(&json::parse(r#"{"5":"ab"}"#).unwrap()).is_err());
+    /// ```
+    pub fn to_json(&self) -> String {
+        self.keys
+            .iter()
+            .enumerate()
+            .fold(json::Object::new(), |o, (k, c)| {
+                o.str(&format!("{:X}", k), &c.to_string())
+            })
+            .to_string()
+    }
+
+    pub fn from_json(v: &json::Value) -> Result<Self, json::ReadError> {
+        let bad = json::ReadError::Field("keymap");
+        let fields = match v {
+            json::Value::Object(fields) => fields,
+            _ => return Err(bad),
+        };
+        let mut map = Self::default();
+        for (key, c) in fields {
+            let key = match u8::from_str_radix(key, 16) {
+                Ok(k) if k < 16 && key.len() == 1 => k,
+                _ => return Err(bad),
+            };
+            let mut chars = c.as_str().unwrap_or("").chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) => map.set(key, c),
+                _ => return Err(bad),
+            }
+        }
+        Ok(map)
+    }
+}
+
+fn lower(c: char) -> char {
+    c.to_lowercase().next().unwrap_or(c)
+}
+
+/// The character starting with byte `first`, taking the rest of it from
+/// `rest`, for layouts with keys outside ASCII
+///
+/// ```
+/// let mut rest = "\u{e9}x".bytes().skip(1);
+/// assert_eq!(chip8::keypad::read_char(0xC3, &mut rest), Some('é'));
+/// assert_eq!(rest.next(), Some(b'x'));
+/// assert_eq!(chip8::keypad::read_char(b'q', &mut rest), Some('q'));
+/// assert_eq!(chip8::keypad::read_char(0xC3, &mut "".bytes()), None);
+/// ```
+pub fn read_char(first: u8, rest: &mut impl Iterator<Item = u8>) -> Option<char> {
+    let len = match first {
+        0xC0..=0xDF => 2,
+        0xE0..=0xEF => 3,
+        0xF0..=0xF7 => 4,
+        _ => 1,
+    };
+    let mut buf = vec![first];
+    buf.extend(rest.take(len - 1));
+    std::str::from_utf8(&buf).ok()?.chars().next()
+}
+
+/// Function key number (0 for F1) and whether Shift was held, given an
+/// escape sequence without the leading escape such as `OP` or `[15;2~`
+pub fn function_key(seq: &[u8]) -> Option<(u8, bool)> {
+    let (&last, body) = seq.split_last()?;
+    let (&intro, params) = body.split_first()?;
+    let params = std::str::from_utf8(params).ok()?;
+    let mut params = params.split(';');
+    let first = params.next().unwrap_or("");
+    // xterm modifiers are 1 plus a bit mask with Shift as bit 0
+    let shift = params
+        .next()
+        .and_then(|m| m.parse::<u8>().ok())
+        .and_then(|m| m.checked_sub(1))
+        .is_some_and(|m| m & 1 != 0);
+    let n = match (intro, last) {
+        (b'O', b'P'..=b'S') | (b'[', b'P'..=b'S') => last - b'P',
+        (b'[', b'~') => match first.parse::<u8>().ok()? {
+            n @ 11..=15 => n - 11,
+            n @ 17..=21 => n - 12,
+            _ => return None,
+        },
+        _ => return None,
+    };
+    Some((n, shift))
+}
+
+/// Keys held down, released a while after their last press
+#[derive(Default)]
+pub struct Keypad {
+    mode: Release,
+    release: [Option<Instant>; 16],
+    latched: Option<u8>,
+}
+
+impl Keypad {
+    pub fn new(mode: Release) -> Self {
+        Self {
+            mode,
+            ..Self::default()
+        }
+    }
+
+    pub fn press(&mut self, key: u8, now: Instant) {
+        let key = key & 0xF;
+        match self.mode {
+            Release::After { movement, action } => {
+                let hold = if MOVEMENT & 1 << key != 0 {
+                    movement
+                } else {
+                    action
+                };
+                self.release[key as usize] = Some(now + hold);
+            }
+            Release::Latched => self.latched = Some(key),
+        }
+    }
+
+    /// Lets go of every key
+    pub fn release_all(&mut self) {
+        self.release = [None; 16];
+        self.latched = None;
+    }
+}
+
+/// Anything that can hold CHIP-8 keys down
+pub trait KeySource {
+    /// Bit n is set while key n is held
+    fn mask(&mut self, now: Instant) -> u16;
+}
+
+impl KeySource for Keypad {
+    fn mask(&mut self, now: Instant) -> u16 {
+        let mut mask = self.latched.map_or(0, |k| 1 << k);
+        for (n, r) in self.release.iter_mut().enumerate() {
+            match r {
+                Some(t) if *t > now => mask |= 1 << n,
+                _ => *r = None,
+            }
+        }
+        mask
+    }
+}
+
+/// Keys held for as long as something says so, like a pointer on an
+/// on-screen keypad
+#[derive(Default)]
+pub struct Held(pub u16);
+
+impl KeySource for Held {
+    fn mask(&mut self, _: Instant) -> u16 {
+        self.0
+    }
+}
+
+/// Keys held by any of several sources
+#[derive(Default)]
+pub struct Sources(pub Vec<Box<dyn KeySource>>);
+
+impl KeySource for Sources {
+    fn mask(&mut self, now: Instant) -> u16 {
+        self.0.iter_mut().fold(0, |m, s| m | s.mask(now))
+    }
+}
+
+/// Keys that press themselves over and over while held, for games that
+/// want a key mashed
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Turbo {
+    // Presses per second for each key, 0 for none
+    rates: [u32; 16],
+}
+
+impl Turbo {
+    pub fn set(&mut self, key: u8, rate: u32) {
+        self.rates[(key & 0xF) as usize] = rate;
+    }
+
+    /// Whether any of the keys in `mask` are turbo keys
+    pub fn active(&self, mask: u16) -> bool {
+        (0..16).any(|k| mask & 1 << k != 0 && self.rates[k] > 0)
+    }
+
+    /// `mask` for the given frame, with turbo keys let go for the second
+    /// half of each press. Going by frames rather than the clock means
+    /// recordings and netplay see the same presses.
+    pub fn apply(&self, mask: u16, frame: u64, fps: u32) -> u16 {
+        let mut out = mask;
+        for (k, &rate) in self.rates.iter().enumerate() {
+            // Half presses so far, odd ones are the released halves
+            if rate > 0 && (frame * rate as u64 * 2 / fps as u64) % 2 == 1 {
+                out &= !(1 << k);
+            }
+        }
+        out
+    }
+}
@@ -0,0 +1,191 @@
+//! Sizes of the screens and where things are in memory, for everything
+//! that would otherwise write 64, 32, 0x050 or 0x200 out.
+//!
+//! They have to agree with each other, which is checked here so a change
+//! to one that leaves another behind is caught:
+//!
+//! ```
+//! use chip8::layout::*;
+//! use chip8::font::{BIG_LEN, SMALL_LEN};
+//!
+//! // Both fonts fit between the start of RAM and the program
+//! assert!(FONT_START as usize + SMALL_LEN <= BIG_FONT_START as usize);
+//! assert!(BIG_FONT_START as usize + BIG_LEN <= PROGRAM_START as usize);
+//! assert_eq!(font_sprite_addr(0xF) as usize + 5, FONT_START as usize + SMALL_LEN);
+//! // With room for a program, and the screen and stack as the machine has them
+//! assert!((PROGRAM_START as usize) < RAM_SIZE_CLASSIC);
+//! assert_eq!(RAM_SIZE_CLASSIC, chip8::emu::MIN_RAM);
+//! assert!(RAM_SIZE_CLASSIC.is_power_of_two());
+//! assert_eq!((HIRES_WIDTH, HIRES_HEIGHT), (LORES_WIDTH * 2, LORES_HEIGHT * 2));
+//! assert_eq!(LORES_WIDTH, u64::BITS as usize);
+//! assert_eq!(HIRES_WIDTH, u128::BITS as usize);
+//! let c8 = chip8::emu::Chip8::builder().build().unwrap();
+//! assert_eq!((c8.screen.len(), c8.pc()), (LORES_HEIGHT, PROGRAM_START));
+//! assert_eq!(chip8::state::SaveState::default().stk.len(), STACK_DEPTH_CLASSIC);
+//!
+//! assert!(is_program_area(PROGRAM_START) && is_program_area(0xFFF));
+//! assert!(!is_program_area(font_sprite_addr(0)) && !is_program_area(0x1000));
+//! assert_eq!((font_sprite_addr(0xA), font_sprite_addr(0x10)), (0x082, BIG_FONT_START));
+//! // The VIP's stack sits above anything a 4 KB ROM at 0x200 leaves free
+//! assert!(STACK_SHADOW_START < STACK_SHADOW_END && (STACK_SHADOW_END as usize) < RAM_SIZE_CLASSIC);
+//! ```
+//!
+//! `MemoryMap` says which of these an address is in, so everything that
+//! lists memory labels it the same way.
+use crate::font::{BIG_LEN, SMALL_LEN};
+use std::fmt;
+use std::ops::RangeInclusive;
+
+/// The 64x32 screen every CHIP-8 has
+pub const LORES_WIDTH: usize = 64;
+pub const LORES_HEIGHT: usize = 32;
+/// SUPER-CHIP's 128x64 screen
+pub const HIRES_WIDTH: usize = 128;
+pub const HIRES_HEIGHT: usize = 64;
+
+/// Where programs are loaded and start, below it was the interpreter
+pub const PROGRAM_START: u16 = 0x200;
+/// Where Fx29 finds the 5-row digit glyphs
+pub const FONT_START: u16 = 0x050;
+/// Where the 10-row SUPER-CHIP digits go, straight after the small ones
+pub const BIG_FONT_START: u16 = 0x0A0;
+/// RAM on the COSMAC VIP and most machines since
+pub const RAM_SIZE_CLASSIC: usize = 0x1000;
+/// Levels of subroutine calls
+pub const STACK_DEPTH_CLASSIC: usize = 16;
+/// Where the COSMAC VIP interpreter kept its stack, work area and V
+/// registers, just below the screen. Some programs read them there.
+pub const STACK_SHADOW_START: u16 = 0xEA0;
+pub const STACK_SHADOW_END: u16 = 0xEFF;
+
+/// Rows in a small digit glyph
+const GLYPH_ROWS: u16 = 5;
+
+/// The glyph Fx29 points I at for `digit`. Past F it's past the glyphs,
+/// as Fx29 has it.
+pub const fn font_sprite_addr(digit: u8) -> u16 {
+    FONT_START + digit as u16 * GLYPH_ROWS
+}
+
+/// Whether `addr` is in classic RAM past the interpreter, where a program
+/// is
+pub const fn is_program_area(addr: u16) -> bool {
+    addr >= PROGRAM_START && (addr as usize) < RAM_SIZE_CLASSIC
+}
+
+/// What an address is for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Region {
+    Font,
+    BigFont,
+    /// In the ROM as loaded
+    Program,
+    /// In the ROM and marked as data
+    Data,
+    StackShadow,
+    /// The rest of the interpreter area, and RAM the ROM doesn't reach
+    Free,
+}
+
+impl Region {
+    /// Four letters, so listings line up
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Font => "FONT",
+            Self::BigFont => "BIGF",
+            Self::Program => "PROG",
+            Self::Data => "DATA",
+            Self::StackShadow => "STCK",
+            Self::Free => "FREE",
+        }
+    }
+}
+
+impl fmt::Display for Region {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.label())
+    }
+}
+
+/// The regions of memory with a ROM loaded, and any of it marked as data
+///
+/// ```
+/// use chip8::layout::{MemoryMap, Region::*};
+///
+/// // 256 bytes at 0x200 with a table at 0x280
+/// let map = MemoryMap::new(0x200, 0x100).data(0x280..=0x28F);
+/// let at = |addrs: &[u16]| addrs.iter().map(|&a| map.annotate(a)).collect::<Vec<_>>();
+/// assert_eq!(at(&[0x000, 0x04F, 0x050, 0x09F, 0x0A0, 0x13F, 0x140, 0x1FF]), [Free, Free, Font, Font, BigFont, BigFont, Free, Free]);
+/// assert_eq!(at(&[0x200, 0x27F, 0x280, 0x28F, 0x290, 0x2FF, 0x300]), [Program, Program, Data, Data, Program, Program, Free]);
+/// assert_eq!(at(&[0xE9F, 0xEA0, 0xEFF, 0xF00, 0xFFF]), [Free, StackShadow, StackShadow, Free, Free]);
+///
+/// // ETI-660 programs load at 0x600, leaving what's below it free
+/// let eti = MemoryMap::new(0x600, 0x100);
+/// let at = |addrs: &[u16]| addrs.iter().map(|&a| eti.annotate(a)).collect::<Vec<_>>();
+/// assert_eq!(at(&[0x13F, 0x140, 0x200, 0x5FF, 0x600, 0x6FF, 0x700]), [BigFont, Free, Free, Free, Program, Program, Free]);
+/// // A ROM long enough to reach the VIP's stack is program there
+/// assert_eq!(MemoryMap::new(0x200, 0xE00).annotate(0xEA0), Program);
+///
+/// // As runs, for a summary
+/// assert_eq!(
+///     map.regions(0x1000)[..5],
+///     [(Free, 0x000, 0x04F), (Font, 0x050, 0x09F), (BigFont, 0x0A0, 0x13F), (Free, 0x140, 0x1FF), (Program, 0x200, 0x27F)]
+/// );
+/// assert_eq!(map.regions(0x1000).len(), 10);
+/// assert_eq!(format!("{} {}", Data, StackShadow), "DATA STCK");
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MemoryMap {
+    load: u16,
+    rom_len: usize,
+    data: Vec<RangeInclusive<u16>>,
+}
+
+impl MemoryMap {
+    /// A ROM of `rom_len` bytes loaded at `load`
+    pub fn new(load: u16, rom_len: usize) -> Self {
+        Self {
+            load,
+            rom_len,
+            data: Vec::new(),
+        }
+    }
+
+    /// Marks the ROM's bytes in `range` as data
+    pub fn data(mut self, range: RangeInclusive<u16>) -> Self {
+        self.data.push(range);
+        self
+    }
+
+    pub fn annotate(&self, addr: u16) -> Region {
+        let in_rom = addr >= self.load && ((addr - self.load) as usize) < self.rom_len;
+        let within = |start: u16, len: usize| addr >= start && ((addr - start) as usize) < len;
+        if in_rom && self.data.iter().any(|d| d.contains(&addr)) {
+            Region::Data
+        } else if in_rom {
+            Region::Program
+        } else if within(FONT_START, SMALL_LEN) {
+            Region::Font
+        } else if within(BIG_FONT_START, BIG_LEN) {
+            Region::BigFont
+        } else if (STACK_SHADOW_START..=STACK_SHADOW_END).contains(&addr) {
+            Region::StackShadow
+        } else {
+            Region::Free
+        }
+    }
+
+    /// Runs of addresses below `end` in the same region, as the region
+    /// and the first and last address
+    pub fn regions(&self, end: usize) -> Vec<(Region, u16, u16)> {
+        let mut runs: Vec<(Region, u16, u16)> = Vec::new();
+        for addr in 0..end.min(u16::MAX as usize + 1) {
+            let (addr, region) = (addr as u16, self.annotate(addr as u16));
+            match runs.last_mut() {
+                Some((r, _, last)) if *r == region => *last = addr,
+                _ => runs.push((region, addr, addr)),
+            }
+        }
+        runs
+    }
+}
@@ -0,0 +1,57 @@
+pub mod asm;
+pub mod breakpoints;
+pub mod browser;
+pub mod compare;
+pub mod disasm;
+pub mod dropped;
+pub mod dump;
+pub mod emu;
+mod error;
+pub mod focus;
+pub mod font;
+pub mod framebuffer;
+pub mod frametime;
+pub mod inflate;
+pub mod info;
+pub mod json;
+pub mod keypad;
+pub mod layout;
+pub mod lockstep;
+pub mod mouse;
+pub mod movie;
+pub mod netplay;
+pub mod octo;
+pub mod opstats;
+pub mod pacer;
+pub mod phosphor;
+pub mod prefs;
+pub mod recorder;
+pub mod renderer;
+pub mod rom;
+pub mod scale;
+#[cfg(feature = "terminal")]
+pub mod screen;
+pub mod screenshot;
+pub mod script;
+pub mod selftest;
+pub mod serve;
+pub mod slots;
+pub mod sound;
+pub mod sprites;
+pub mod state;
+pub mod summary;
+#[cfg(feature = "terminal")]
+pub mod term;
+#[cfg(feature = "testing")]
+pub mod testing;
+pub mod text;
+pub mod theme;
+pub mod timeline;
+pub mod timetravel;
+pub mod title;
+pub mod trace;
+pub mod warnings;
+pub mod watch;
+pub mod watchdog;
+
+pub use error::Error;
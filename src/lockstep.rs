@@ -0,0 +1,265 @@
+use crate::emu::{self, BuildError, Chip8, Fault, Quirks, StepOutcome};
+use crate::layout::PROGRAM_START;
+use crate::state::{SaveState, StateDiff};
+use rand::SeedableRng;
+use std::collections::VecDeque;
+use std::fmt;
+
+// Instructions of history kept for the report
+const HISTORY: usize = 16;
+
+/// How one of the two instances is set up
+#[derive(Clone)]
+pub struct Config {
+    pub interpreter: Vec<u8>,
+    pub rom: Vec<u8>,
+    pub seed: u64,
+    pub quirks: Quirks,
+}
+
+impl Config {
+    pub fn new(interpreter: &[u8], rom: &[u8]) -> Self {
+        Self {
+            interpreter: interpreter.to_vec(),
+            rom: rom.to_vec(),
+            seed: 0,
+            quirks: Quirks::default(),
+        }
+    }
+
+    fn build(&self) -> Result<Chip8, BuildError> {
+        self.build_with(cfg!(debug_assertions))
+    }
+
+    // With the invariant checks on or off
+    fn build_with(&self, validate: bool) -> Result<Chip8, BuildError> {
+        let rng = rand::rngs::StdRng::seed_from_u64(self.seed);
+        Chip8::builder()
+            .font(&self.interpreter[..self.interpreter.len().min(PROGRAM_START as usize)])
+            .rom(&self.rom)
+            .quirks(self.quirks)
+            .rng(Box::new(rng))
+            // The wall clock would tick the two apart
+            .clock(emu::step_clock(600))
+            .validate(validate)
+            .build()
+    }
+}
+
+/// Keys to hold from a given step on, sorted by step
+pub type Script = [(u64, u16)];
+
+/// Where the two instances first disagreed
+pub struct Divergence {
+    /// Steps both had run when the difference was seen
+    pub step: u64,
+    pub diff: StateDiff,
+    /// What each step returned, if that is what differed
+    pub faults: Option<(Option<Fault>, Option<Fault>)>,
+    /// Recent (PC, opcode) pairs of each, oldest first
+    pub history_a: Vec<(u16, u16)>,
+    pub history_b: Vec<(u16, u16)>,
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "diverged after {} steps", self.step)?;
+        if let Some((a, b)) = &self.faults {
+            let show = |r: &Option<Fault>| r.map_or("ok".to_string(), |f| f.to_string());
+            writeln!(f, "step results: a {}, b {}", show(a), show(b))?;
+        }
+        write!(f, "{}", self.diff)?;
+        for (label, h) in [("a", &self.history_a), ("b", &self.history_b)] {
+            write!(f, "recent {}:", label)?;
+            for (pc, op) in h {
+                write!(f, " {:03X}:{:04X}", pc, op)?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+impl std::error::Error for Divergence {}
+
+#[derive(Debug)]
+pub enum LockstepError {
+    /// A configuration that can't be run, such as a ROM too big for RAM
+    Build(BuildError),
+    Diverged(Box<Divergence>),
+}
+
+impl fmt::Display for LockstepError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Build(e) => write!(f, "{}", e),
+            Self::Diverged(d) => write!(f, "{}", d),
+        }
+    }
+}
+
+impl std::error::Error for LockstepError {}
+
+impl From<BuildError> for LockstepError {
+    fn from(e: BuildError) -> Self {
+        Self::Build(e)
+    }
+}
+
+impl From<Divergence> for LockstepError {
+    fn from(d: Divergence) -> Self {
+        Self::Diverged(Box::new(d))
+    }
+}
+
+/// Runs both configurations for `steps` instructions with the same input,
+/// comparing full state after every step
+///
+/// ```
+/// use chip8::lockstep::{self, Config, LockstepError};
+///
+/// // ADD V0, 1; JP 200, the second with the last increment dropped
+/// let a = Config::new(&[], &[0x70, 0x01, 0x12, 0x00]);
+/// let b = Config::new(&[], &[0x70, 0x00, 0x12, 0x00]);
+/// assert!(lockstep::compare(&a, &a, &[], 100).is_ok());
+/// match lockstep::compare(&a, &b, &[], 100) {
+///     Err(LockstepError::Diverged(d)) => assert_eq!(d.step, 1),
+///     _ => panic!("should diverge"),
+/// }
+/// // Too big to load is reported, not a panic
+/// let big = Config::new(&[], &[0; 0x1000]);
+/// assert!(matches!(lockstep::compare(&a, &big, &[], 100), Err(LockstepError::Build(_))));
+/// ```
+pub fn compare(a: &Config, b: &Config, script: &Script, steps: u64) -> Result<(), LockstepError> {
+    compare_every(a, b, script, steps, 1)
+}
+
+/// Like `compare`, but only compares state every `every` steps. Both
+/// stopping with the same fault ends the run early without a divergence.
+pub fn compare_every(
+    a: &Config,
+    b: &Config,
+    script: &Script,
+    steps: u64,
+    every: u64,
+) -> Result<(), LockstepError> {
+    let (mut ca, mut cb) = (a.build()?, b.build()?);
+    let (mut ha, mut hb) = (VecDeque::new(), VecDeque::new());
+    let mut keys = script.iter().peekable();
+    let record = |c8: &Chip8, h: &mut VecDeque<(u16, u16)>| {
+        if h.len() == HISTORY {
+            h.pop_front();
+        }
+        h.push_back((c8.pc(), c8.peek_word(c8.pc())));
+    };
+    let diverged =
+        |step, sa: SaveState, sb: SaveState, faults, ha: &VecDeque<_>, hb: &VecDeque<_>| {
+            Divergence {
+                step,
+                diff: sa.diff(&sb),
+                faults,
+                history_a: ha.iter().copied().collect(),
+                history_b: hb.iter().copied().collect(),
+            }
+        };
+    let every = every.max(1);
+    for step in 0..steps {
+        while let Some(&&(_, mask)) = keys.peek().filter(|(at, _)| *at <= step) {
+            ca.set_keys(mask);
+            cb.set_keys(mask);
+            keys.next();
+        }
+        record(&ca, &mut ha);
+        record(&cb, &mut hb);
+        let (ra, rb) = (ca.step().err(), cb.step().err());
+        if ra != rb {
+            let (sa, sb) = (ca.save_state(), cb.save_state());
+            return Err(diverged(step + 1, sa, sb, Some((ra, rb)), &ha, &hb).into());
+        }
+        if (step + 1) % every == 0 || ra.is_some() || step + 1 == steps {
+            let (sa, sb) = (ca.save_state(), cb.save_state());
+            if sa != sb {
+                return Err(diverged(step + 1, sa, sb, None, &ha, &hb).into());
+            }
+        }
+        if ra.is_some() {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// Runs `config` twice with the same input, once a `step` at a time and
+/// once through `Chip8::run_steps_fast` in batches of up to `batch`,
+/// comparing state after every batch. The batched one has its invariant
+/// checks off so the fast path is what gets tested, and its history only
+/// has where each batch started.
+pub fn compare_fast(
+    config: &Config,
+    script: &Script,
+    steps: u64,
+    batch: u32,
+) -> Result<(), LockstepError> {
+    let (mut ca, mut cb) = (config.build()?, config.build_with(false)?);
+    let (mut ha, mut hb) = (VecDeque::new(), VecDeque::new());
+    let mut keys = script.iter().peekable();
+    let record = |c8: &Chip8, h: &mut VecDeque<(u16, u16)>| {
+        if h.len() == HISTORY {
+            h.pop_front();
+        }
+        h.push_back((c8.pc(), c8.peek_word(c8.pc())));
+    };
+    let mut step = 0;
+    while step < steps {
+        while let Some(&&(_, mask)) = keys.peek().filter(|(at, _)| *at <= step) {
+            ca.set_keys(mask);
+            cb.set_keys(mask);
+            keys.next();
+        }
+        // Batches stop short of the next change of keys
+        let end = keys.peek().map_or(steps, |(at, _)| (*at).min(steps));
+        let len = (end - step).min(batch.max(1) as u64) as u32;
+        record(&cb, &mut hb);
+        let rb = cb.run_steps_fast(len);
+        let mut ra = Ok(0);
+        for n in 1..=len {
+            record(&ca, &mut ha);
+            match ca.step() {
+                Ok(StepOutcome::Ran) => ra = Ok(n),
+                Ok(_) => {
+                    ra = Ok(n);
+                    break;
+                }
+                Err(f) => {
+                    ra = Err(f);
+                    break;
+                }
+            }
+        }
+        step += match ra {
+            Ok(n) => n as u64,
+            Err(_) => len as u64,
+        };
+        let (sa, sb) = (ca.save_state(), cb.save_state());
+        if ra != rb || sa != sb {
+            return Err(Divergence {
+                step,
+                diff: sa.diff(&sb),
+                faults: (ra != rb).then(|| (ra.err(), rb.err())),
+                history_a: ha.iter().copied().collect(),
+                history_b: hb.iter().copied().collect(),
+            }
+            .into());
+        }
+        if ra.is_err() {
+            break;
+        }
+    }
+    Ok(())
+}
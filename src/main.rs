@@ -1,29 +1,2079 @@
+use chip8::{
+    breakpoints, browser, compare, disasm, dropped, dump, emu, focus, font, framebuffer, frametime,
+    info, json, keypad, layout, lockstep, mouse, movie, netplay, opstats, pacer, phosphor, prefs,
+    recorder, renderer, rom, screen, screenshot, script, selftest, serve, slots, sound, sprites,
+    state, summary, term, text, theme, timeline, timetravel, title, trace, warnings, watch,
+    watchdog,
+};
+use keypad::KeySource;
 use rand::SeedableRng;
-mod emu;
-mod screen;
 
+use rom::RomSource;
+use slots::Slot;
+use std::cell::RefCell;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
+const FRAME_MS: u64 = 50;
+
+struct Options {
+    rom: String,
+    headless: bool,
+    scale: usize,
+    screenshot: PathBuf,
+    screenshot_at: Option<u64>,
+    record: Option<PathBuf>,
+    record_input: Option<PathBuf>,
+    rng_trail: bool,
+    play_input: Option<PathBuf>,
+    max_frames: Option<u64>,
+    max_frameskip: u32,
+    frame_log: Option<PathBuf>,
+    // Where to write a `dump::FaultDump` if the program faults
+    fault_dump: Option<PathBuf>,
+    load_dump: Option<dump::FaultDump>,
+    phosphor: u8,
+    status: bool,
+    load_addr: u16,
+    ram_size: usize,
+    start_addr: Option<u16>,
+    reset_key: u8,
+    hard_reset_key: u8,
+    hold_ms: u64,
+    move_hold_ms: Option<u64>,
+    latch: bool,
+    release_key: u8,
+    turbo: keypad::Turbo,
+    // CHIP-8 keys moved to other host keys, on top of the layout
+    key_overrides: Vec<(u8, char)>,
+    state_dir: PathBuf,
+    autosave: Option<u64>,
+    autoload: bool,
+    force: bool,
+    journal: usize,
+    trace: Option<PathBuf>,
+    trace_format: Option<String>,
+    dump_screen: Option<PathBuf>,
+    dump_frames: Option<PathBuf>,
+    dump_every: u64,
+    sprites: bool,
+    bell: bool,
+    pause_on_focus_loss: bool,
+    confirm_drop: bool,
+    // Clicks on the status line's keypad press keys
+    mouse: bool,
+    palette: theme::Theme,
+    // Settings given here win over ones remembered for the ROM
+    prefs: prefs::Prefs,
+    // An original interpreter for the first 512 bytes of RAM
+    interpreter: Option<Vec<u8>>,
+    font_overlay: bool,
+    // Digit glyphs from --font, the interpreter's own or the built-in
+    // ones if not given
+    font: Option<Vec<u8>>,
+    volume: f32,
+    mute: bool,
+    mute_key: u8,
+    opcode_stats: bool,
+    smc: bool,
+    idle_skip: bool,
+    // Instructions run each frame, 20 frames a second
+    steps_per_frame: u32,
+    on_unknown: emu::OnUnknown,
+    on_odd_pc: emu::OnOddPc,
+    json: bool,
+    serve: Option<String>,
+    allow_control: bool,
+    netplay: Option<String>,
+    netplay_listen: Option<u16>,
+    netplay_delay: u8,
+    netplay_check: u32,
+    script: Option<PathBuf>,
+    input_script: Option<PathBuf>,
+    // Instructions > runs before giving up on reaching the next one
+    until_limit: u32,
+    watches: watch::Watches,
+}
+
+fn num<T: std::str::FromStr>(arg: &str, value: &str) -> Result<T, String>
+where
+    T::Err: std::fmt::Display,
+{
+    value.parse().map_err(|e| format!("{}: {}", arg, e))
+}
+
+// A built-in font's name or a file
+fn font_arg(arg: &str, value: &str) -> Result<Vec<u8>, String> {
+    font::load(value).map_err(|e| format!("{}: {} (built in: {})", arg, e, font::NAMES.join(", ")))
+}
+
+// Addresses can be given in hex with a 0x prefix. Whether they fit in
+// RAM is up to the builder, as that depends on --ram-size.
+fn addr(arg: &str, value: &str) -> Result<u16, String> {
+    match value
+        .strip_prefix("0x")
+        .or_else(|| value.strip_prefix("0X"))
+    {
+        Some(hex) => u16::from_str_radix(hex, 16).map_err(|e| format!("{}: {}", arg, e)),
+        None => num(arg, value),
+    }
+}
+
+const fn ctrl(c: u8) -> u8 {
+    c & 0x1F
+}
+
+// A key is a single character, or ^ and a letter for a control key
+fn key(arg: &str, value: &str) -> Result<u8, String> {
+    match value.as_bytes() {
+        [c] if c.is_ascii_graphic() => Ok(*c),
+        [b'^', c] if c.is_ascii_alphabetic() => Ok(ctrl(c.to_ascii_uppercase())),
+        _ => Err(format!("{} must be one character or ^ and a letter", arg)),
+    }
+}
+
+// KEY:RATE, a CHIP-8 key in hex and presses per second
+fn turbo(arg: &str, value: &str) -> Result<(u8, u32), String> {
+    let (k, rate) = value
+        .split_once(':')
+        .ok_or(format!("{} must be KEY:RATE", arg))?;
+    let k = match u8::from_str_radix(k, 16) {
+        Ok(k) if k < 16 => k,
+        _ => return Err(format!("{}: {} isn't a CHIP-8 key", arg, k)),
+    };
+    match num(arg, rate)? {
+        0 => Err(format!("{} rate must be at least 1", arg)),
+        rate => Ok((k, rate)),
+    }
+}
+
+// A CHIP-8 key and the host key it goes on, 5=k
+fn key_override(arg: &str, value: &str) -> Result<(u8, char), String> {
+    let (k, c) = value
+        .split_once('=')
+        .ok_or(format!("{} must be KEY=CHAR", arg))?;
+    let k = match u8::from_str_radix(k, 16) {
+        Ok(k) if k < 16 => k,
+        _ => return Err(format!("{}: {} isn't a CHIP-8 key", arg, k)),
+    };
+    let mut chars = c.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) if !c.is_control() && c != ' ' => Ok((k, c)),
+        _ => Err(format!("{}: the host key must be one character", arg)),
+    }
+}
+
+/// How long a status line message stays up
+const FLASH: std::time::Duration = std::time::Duration::from_secs(1);
+
+fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut opts = Options {
+        rom: "ibm.ch8".to_string(),
+        headless: false,
+        scale: 8,
+        screenshot: PathBuf::from("."),
+        screenshot_at: None,
+        record: None,
+        record_input: None,
+        rng_trail: false,
+        play_input: None,
+        max_frames: None,
+        max_frameskip: pacer::DEFAULT_MAX_SKIP,
+        frame_log: None,
+        interpreter: None,
+        font_overlay: true,
+        fault_dump: None,
+        load_dump: None,
+        phosphor: 0,
+        status: true,
+        load_addr: layout::PROGRAM_START,
+        ram_size: emu::MIN_RAM,
+        start_addr: None,
+        reset_key: ctrl(b'R'),
+        hard_reset_key: ctrl(b'X'),
+        hold_ms: 200,
+        move_hold_ms: None,
+        latch: false,
+        release_key: b'/',
+        turbo: keypad::Turbo::default(),
+        key_overrides: Vec::new(),
+        state_dir: PathBuf::from("states"),
+        autosave: None,
+        autoload: false,
+        force: false,
+        journal: 0,
+        trace: None,
+        trace_format: None,
+        dump_screen: None,
+        dump_frames: None,
+        dump_every: 1,
+        sprites: false,
+        bell: true,
+        pause_on_focus_loss: false,
+        confirm_drop: false,
+        mouse: false,
+        palette: theme::Theme::default(),
+        prefs: prefs::Prefs::default(),
+        font: None,
+        volume: 1.0,
+        mute: false,
+        mute_key: b'm',
+        opcode_stats: false,
+        smc: false,
+        idle_skip: false,
+        steps_per_frame: 1,
+        on_unknown: emu::OnUnknown::Halt,
+        on_odd_pc: emu::OnOddPc::Allow,
+        json: false,
+        serve: None,
+        allow_control: false,
+        netplay: None,
+        netplay_listen: None,
+        netplay_delay: 2,
+        netplay_check: 60,
+        script: None,
+        input_script: None,
+        until_limit: breakpoints::DEFAULT_LIMIT,
+        watches: watch::Watches::new(),
+    };
+    let mut rom_given = false;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or(format!("{} needs a value", arg));
+        match arg.as_str() {
+            "--headless" => opts.headless = true,
+            "--no-status" => opts.status = false,
+            "--scale" => opts.scale = num(arg, value()?)?,
+            "--screenshot" => opts.screenshot = PathBuf::from(value()?),
+            "--screenshot-at" => opts.screenshot_at = Some(num(arg, value()?)?),
+            "--record" => opts.record = Some(PathBuf::from(value()?)),
+            "--record-input" => opts.record_input = Some(PathBuf::from(value()?)),
+            "--rng-trail" => opts.rng_trail = true,
+            "--play-input" => opts.play_input = Some(PathBuf::from(value()?)),
+            "--max-frames" => opts.max_frames = Some(num(arg, value()?)?),
+            "--watch" => opts
+                .watches
+                .add(value()?)
+                .map_err(|e| format!("{}: {}", arg, e))?,
+            "--until-limit" => opts.until_limit = num(arg, value()?)?,
+            "--max-frameskip" => opts.max_frameskip = num(arg, value()?)?,
+            "--frame-log" => opts.frame_log = Some(PathBuf::from(value()?)),
+            "--fault-dump" => opts.fault_dump = Some(PathBuf::from(value()?)),
+            "--load-dump" => {
+                let d = dump::FaultDump::load(Path::new(value()?))
+                    .map_err(|e| format!("{}: {}", arg, e))?;
+                opts.load_dump = Some(d);
+            }
+            "--load-addr" => opts.load_addr = addr(arg, value()?)?,
+            "--ram-size" => opts.ram_size = num(arg, value()?)?,
+            "--start-addr" => opts.start_addr = Some(addr(arg, value()?)?),
+            "--reset-key" => opts.reset_key = key(arg, value()?)?,
+            "--hard-reset-key" => opts.hard_reset_key = key(arg, value()?)?,
+            "--hold-ms" => opts.hold_ms = num(arg, value()?)?,
+            "--move-hold-ms" => opts.move_hold_ms = Some(num(arg, value()?)?),
+            "--latch" => opts.latch = true,
+            "--turbo" => {
+                let (k, rate) = turbo(arg, value()?)?;
+                opts.turbo.set(k, rate);
+            }
+            "--layout" => {
+                let v = value()?;
+                if keypad::Keymap::preset(v).is_none() {
+                    let names: Vec<&str> = keypad::LAYOUTS.iter().map(|(n, _)| *n).collect();
+                    return Err(format!(
+                        "unknown layout {} (one of {})",
+                        v,
+                        names.join(", ")
+                    ));
+                }
+                opts.prefs.layout = Some(v.to_string());
+            }
+            "--key" => opts.key_overrides.push(key_override(arg, value()?)?),
+            "--release-key" => opts.release_key = key(arg, value()?)?,
+            "--state-dir" => opts.state_dir = PathBuf::from(value()?),
+            "--autosave" => opts.autosave = Some(num(arg, value()?)?),
+            "--autoload" => opts.autoload = true,
+            "--force" => opts.force = true,
+            "--trace" => opts.trace = Some(PathBuf::from(value()?)),
+            "--trace-format" => match value()?.as_str() {
+                f @ ("compact" | "counters") => opts.trace_format = Some(f.to_string()),
+                f => return Err(format!("unknown trace format {}", f)),
+            },
+            "--dump-screen" => opts.dump_screen = Some(PathBuf::from(value()?)),
+            "--dump-frames" => opts.dump_frames = Some(PathBuf::from(value()?)),
+            "--dump-every" => opts.dump_every = num(arg, value()?)?,
+            "--sprites" => opts.sprites = true,
+            "--no-bell" => opts.bell = false,
+            "--pause-on-focus-loss" => opts.pause_on_focus_loss = true,
+            "--confirm-drop" => opts.confirm_drop = true,
+            "--mouse" => opts.mouse = true,
+            "--font" => opts.font = Some(font_arg(arg, value()?)?),
+            "--interpreter" => {
+                let path = value()?;
+                let image = std::fs::read(path).map_err(|e| format!("{}: {}", arg, e))?;
+                if image.len() > layout::PROGRAM_START as usize {
+                    return Err(format!(
+                        "{}: {} is {} bytes, more than the 512 below 0x200",
+                        arg,
+                        path,
+                        image.len()
+                    ));
+                }
+                opts.interpreter = Some(image);
+            }
+            "--no-font-overlay" => opts.font_overlay = false,
+            "--palette" => {
+                let v = value()?;
+                opts.palette = theme::Theme::parse(v).map_err(|e| format!("{}: {}", arg, e))?;
+                opts.prefs.palette = Some(v.to_string());
+            }
+            "--volume" => match num(arg, value()?)? {
+                v if (0.0..=1.0).contains(&v) => {
+                    opts.volume = v;
+                    opts.prefs.volume = Some(v);
+                }
+                _ => return Err(format!("{} must be from 0 to 1", arg)),
+            },
+            "--mute" => {
+                opts.mute = true;
+                opts.prefs.muted = Some(true);
+            }
+            "--mute-key" => opts.mute_key = key(arg, value()?)?,
+            "--opcode-stats" => opts.opcode_stats = true,
+            "--smc" => opts.smc = true,
+            "--idle-skip" => opts.idle_skip = true,
+            "--steps-per-frame" => match num(arg, value()?)? {
+                n @ 1..=1000 => opts.steps_per_frame = n,
+                _ => return Err(format!("{} must be from 1 to 1000", arg)),
+            },
+            "--on-unknown" => {
+                opts.on_unknown = match value()?.as_str() {
+                    "skip" => emu::OnUnknown::Skip,
+                    "halt" => emu::OnUnknown::Halt,
+                    "warn" => emu::OnUnknown::Warn,
+                    v => return Err(format!("{} must be skip, halt or warn, not {}", arg, v)),
+                }
+            }
+            "--odd-pc" => {
+                opts.on_odd_pc = match value()?.as_str() {
+                    "allow" => emu::OnOddPc::Allow,
+                    "fault" => emu::OnOddPc::Fault,
+                    v => return Err(format!("{} must be allow or fault, not {}", arg, v)),
+                }
+            }
+            "--json" => opts.json = true,
+            "--script" => opts.script = Some(PathBuf::from(value()?)),
+            "--input-script" => opts.input_script = Some(PathBuf::from(value()?)),
+            "--serve" => opts.serve = Some(value()?.to_string()),
+            "--allow-control" => opts.allow_control = true,
+            "--netplay" => opts.netplay = Some(value()?.to_string()),
+            "--netplay-listen" => opts.netplay_listen = Some(num(arg, value()?)?),
+            "--netplay-delay" => match num(arg, value()?)? {
+                n @ 1..=3 => opts.netplay_delay = n,
+                _ => return Err("--netplay-delay must be 1 to 3".to_string()),
+            },
+            "--netplay-check" => match num(arg, value()?)? {
+                0 => return Err("--netplay-check must be at least 1".to_string()),
+                n => opts.netplay_check = n,
+            },
+            "--journal" => opts.journal = num(arg, value()?)?,
+            "--phosphor" => match num(arg, value()?)? {
+                n @ 0..=5 => opts.phosphor = n,
+                _ => return Err("--phosphor must be 0 to 5".to_string()),
+            },
+            a if a.starts_with("--") => return Err(format!("unknown option {}", a)),
+            a => {
+                opts.rom = a.to_string();
+                rom_given = true;
+            }
+        }
+    }
+    // The ROM the dump came from, unless another is given
+    if let (Some(d), false) = (&opts.load_dump, rom_given) {
+        opts.rom = d.config.rom.clone();
+    }
+    Ok(opts)
+}
+
+fn diff(a: &str, b: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let a = state::SaveState::from_bytes(&std::fs::read(a)?)?;
+    let b = state::SaveState::from_bytes(&std::fs::read(b)?)?;
+    print!("{}", a.diff(&b));
+    Ok(())
+}
+
+// Prints where two traces first differ, with the line before as context
+fn trace_diff(args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    let (ours, theirs, max) = match args {
+        [a, b] => (a, b, 5),
+        [a, b, n] => (a, b, num("N", n)?),
+        _ => return Err("usage: chip8 trace-diff OURS THEIRS [N]".into()),
+    };
+    let (ours, theirs) = (
+        std::fs::read_to_string(ours)?,
+        std::fs::read_to_string(theirs)?,
+    );
+    let lines = |t: &str| -> Vec<String> {
+        t.lines()
+            .filter(|l| !l.trim().is_empty())
+            .map(str::to_string)
+            .collect()
+    };
+    let (a, b) = (lines(&ours), lines(&theirs));
+    let mismatches = trace::diff(&ours, &theirs, max);
+    if mismatches.is_empty() {
+        println!("Traces match over {} instructions", a.len());
+    }
+    for m in mismatches {
+        match m {
+            trace::Mismatch::Fields { line, fields } => {
+                println!("instruction {}, differs in {}", line, fields.join(", "));
+                if line > 1 {
+                    println!("  before: {}", a[line - 2]);
+                }
+                println!("  ours:   {}", a[line - 1]);
+                println!("  theirs: {}", b[line - 1]);
+            }
+            trace::Mismatch::Ended { line, ours } => {
+                let (short, long) = if ours {
+                    ("ours", "theirs")
+                } else {
+                    ("theirs", "ours")
+                };
+                println!("instruction {}: {} ends, {} carries on", line, short, long);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn print_atlas(ram: &[u8], uses: &sprites::Uses) {
+    for s in sprites::atlas(ram, uses) {
+        let addrs: Vec<_> = s.addrs.iter().map(|a| format!("{:03X}", a)).collect();
+        println!(
+            "{} ({} bytes, {} uses)",
+            addrs.join(" "),
+            s.data.len(),
+            s.uses
+        );
+        println!("{}", s.render());
+    }
+}
+
+// Sprites the program can be seen drawing without running it
+fn sprites_cmd(args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    let rom = match args {
+        [rom] => rom,
+        _ => return Err("usage: chip8 sprites ROM".into()),
+    };
+    let rng = rand::rngs::StdRng::seed_from_u64(0);
+    let c8 = emu::Chip8::new(&[], &RomSource::parse(rom).resolve()?, Box::new(rng))?;
+    let ram = c8.save_state().ram;
+    print_atlas(&ram, &sprites::find_static(&ram, c8.pc()));
+    Ok(())
+}
+
+fn range_arg(arg: &str, value: &str) -> Result<disasm::Range, String> {
+    value.parse().map_err(|e| format!("{}: {}", arg, e))
+}
+
+// The ROM as instructions, with ranges marked as data shown as bytes,
+// or with `hex` all of memory as it is once the ROM's loaded
+fn disasm_cmd(args: &[String], hex: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let usage = if hex {
+        "usage: chip8 hexdump ROM [--load-addr ADDR] [--data START-END]..."
+    } else {
+        "usage: chip8 disasm ROM [--load-addr ADDR] [--range START-END] [--data START-END]..."
+    };
+    let (mut path, mut range, mut data) = (None, None, Vec::new());
+    let mut load = layout::PROGRAM_START;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or(usage);
+        match arg.as_str() {
+            "--range" if !hex => range = Some(range_arg(arg, value()?)?),
+            "--data" => data.push(range_arg(arg, value()?)?),
+            "--load-addr" => load = addr(arg, value()?)?,
+            a if path.is_none() && !a.starts_with("--") => path = Some(a),
+            _ => return Err(usage.into()),
+        }
+    }
+    let rom = RomSource::parse(path.ok_or(usage)?).resolve()?;
+    let lines = if hex {
+        let c8 = emu::Chip8::builder()
+            .rom(&rom)
+            .load_address(load)
+            .build()
+            .map_err(|e| e.to_string())?;
+        let map = disasm::memory_map(load, rom.len(), &data);
+        disasm::hexdump(&c8.save_state().ram, &map)
+    } else {
+        disasm::listing(&rom, load, range, &data)
+    };
+    for line in lines {
+        println!("{}", line);
+    }
+    Ok(())
+}
+
+// Size, hashes, a platform guess and anything odd about a ROM file
+fn info_cmd(args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    let (rom, json) = match args {
+        [rom] => (rom, false),
+        [flag, rom] | [rom, flag] if flag == "--json" => (rom, true),
+        _ => return Err("usage: chip8 info [--json] ROM".into()),
+    };
+    let info = info::Info::new(&std::fs::read(rom)?);
+    if json {
+        println!("{}", info.to_json());
+    } else {
+        print!("{}", info);
+    }
+    Ok(())
+}
+
+// Plays a movie back headless as fast as it goes, into a GIF or a
+// directory of numbered PNGs
+// Exits with 1 if any case failed
+// A preset with the quirks after each + flipped, such as schip+clip_x
+fn quirks_arg(arg: &str, value: &str) -> Result<emu::Quirks, String> {
+    let mut parts = value.split('+');
+    let name = parts.next().unwrap_or("");
+    let mut quirks = emu::Quirks::preset(name).ok_or_else(|| {
+        format!(
+            "{}: unknown preset {}, expected {}",
+            arg,
+            name,
+            emu::Quirks::PRESETS.join(", ")
+        )
+    })?;
+    for q in parts {
+        if !quirks.toggle(q) {
+            return Err(format!("{}: unknown quirk {}", arg, q));
+        }
+    }
+    Ok(quirks)
+}
+
+// Runs a ROM twice side by side with different quirks, both getting the
+// same keys and seed. `h` highlights where the screens differ.
+fn compare_cmd(args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    let usage = "usage: chip8 compare ROM [--left QUIRKS] [--right QUIRKS] [--seed N]";
+    let (mut path, mut seed) = (None, 0u64);
+    let mut sides = [("default", emu::Quirks::default()); 2];
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or(usage);
+        match arg.as_str() {
+            "--left" => {
+                let v = value()?;
+                sides[0] = (v, quirks_arg(arg, v)?);
+            }
+            "--right" => {
+                let v = value()?;
+                sides[1] = (v, quirks_arg(arg, v)?);
+            }
+            "--seed" => seed = num(arg, value()?)?,
+            _ if path.is_none() => path = Some(arg),
+            _ => return Err(usage.into()),
+        }
+    }
+    let game = RomSource::parse(path.ok_or(usage)?).resolve()?;
+    let mut machines = Vec::new();
+    for (_, quirks) in &sides {
+        machines.push(
+            emu::Chip8::builder()
+                .rom(&game)
+                .quirks(*quirks)
+                .seed(seed)
+                .clock(emu::step_clock((1000 / FRAME_MS) as u32))
+                .build()?,
+        );
+    }
+    let running = Arc::new(AtomicBool::new(true));
+    let r = running.clone();
+    ctrlc::set_handler(move || r.store(false, Ordering::SeqCst))?;
+    term::install_panic_hook();
+    term::enable_raw();
+    if !term::enable_ansi() {
+        term::restore();
+        return Err("chip8 compare needs a terminal that takes escape sequences".into());
+    }
+    term::hide_cursor();
+    term::watch_resize();
+    let res = compare_view(&mut machines, [sides[0].0, sides[1].0], &running);
+    term::restore();
+    println!();
+    Ok(res?)
+}
+
+fn compare_view(
+    machines: &mut [emu::Chip8],
+    names: [&str; 2],
+    running: &AtomicBool,
+) -> std::io::Result<()> {
+    let hold = std::time::Duration::from_millis(200);
+    let mut keys = keypad::Keypad::new(keypad::Release::After {
+        movement: hold,
+        action: hold,
+    });
+    let mut faults: [Option<emu::Fault>; 2] = [None, None];
+    let (mut highlight, mut style) = (false, None);
+    let mut out = std::io::BufWriter::new(std::io::stdout().lock());
+    let mut placed = false;
+    while running.load(Ordering::SeqCst) {
+        let now = std::time::Instant::now();
+        let input = term::read_input();
+        let mut bytes = input.iter().copied().peekable();
+        while let Some(b) = bytes.next() {
+            match b {
+                // Escape on its own quits, escape sequences are skipped
+                0x1B if matches!(bytes.peek(), Some(b'[') | Some(b'O')) => {
+                    bytes.next();
+                    for c in bytes.by_ref() {
+                        if (0x40..=0x7E).contains(&c) {
+                            break;
+                        }
+                    }
+                }
+                0x1B => return Ok(()),
+                b'h' | b'H' => highlight = !highlight,
+                b => {
+                    if let Some(k) = keypad::map_key(b) {
+                        keys.press(k, now);
+                    }
+                }
+            }
+        }
+        // Both get the same keys, and one that faults stops there
+        let held = keys.mask(now);
+        for (c8, fault) in machines.iter_mut().zip(&mut faults) {
+            if fault.is_none() {
+                c8.set_keys(held);
+                *fault = c8.step().err();
+            }
+        }
+        if !placed || term::take_resized() {
+            style = term::size().and_then(|(c, r)| compare::pick_style(c, r, 1));
+            write!(out, "\x1b[2J")?;
+            placed = true;
+        }
+        write!(out, "\x1b[H")?;
+        let (left, right) = (&machines[0].screen, &machines[1].screen);
+        match style {
+            Some(style) => {
+                for line in compare::lines(style, left, right, highlight) {
+                    writeln!(out, "{}", line)?;
+                }
+                let side = |n: usize| match &faults[n] {
+                    Some(f) => format!("{} stopped by {}", names[n], f),
+                    None => names[n].to_string(),
+                };
+                write!(
+                    out,
+                    "\x1b[K{} | {} | {} pixels differ, h to {}",
+                    side(0),
+                    side(1),
+                    compare::count_differing(left, right),
+                    if highlight {
+                        "stop highlighting"
+                    } else {
+                        "highlight"
+                    }
+                )?;
+            }
+            None => {
+                let (c, r) = compare::cells(text::RenderStyle::Braille);
+                write!(
+                    out,
+                    "Terminal too small, at least {}x{} is needed",
+                    c,
+                    r + 1
+                )?;
+            }
+        }
+        out.flush()?;
+        std::thread::sleep(std::time::Duration::from_millis(FRAME_MS));
+    }
+    Ok(())
+}
+
+fn selftest_cmd() -> Result<(), Box<dyn std::error::Error>> {
+    let results = selftest::run_all();
+    println!("{}", selftest::Report(&results));
+    if !results.iter().all(|r| r.passed()) {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+fn render_cmd(args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    let usage =
+        "usage: chip8 render MOVIE --rom ROM --out OUT.gif|DIR [--scale N] [--font F] [--force]";
+    let (mut path, mut rom, mut out, mut scale, mut force) = (None, None, None, 4, false);
+    let mut glyphs = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or(usage);
+        match arg.as_str() {
+            "--rom" => rom = Some(value()?),
+            "--out" => out = Some(PathBuf::from(value()?)),
+            "--scale" => scale = num(arg, value()?)?,
+            "--font" => glyphs = font_arg(arg, value()?)?,
+            "--force" => force = true,
+            _ if path.is_none() => path = Some(arg),
+            _ => return Err(usage.into()),
+        }
+    }
+    let (path, rom, out) = match (path, rom, out) {
+        (Some(p), Some(r), Some(o)) => (p, r, o),
+        _ => return Err(usage.into()),
+    };
+    let movie = movie::Movie::from_bytes(&std::fs::read(path)?)?;
+    let game = RomSource::parse(rom).resolve()?;
+    match movie.check_rom(&game) {
+        Err(e) if force => eprintln!("Rendering anyway: {}", e),
+        r => r?,
+    }
+    let mut c8 = movie
+        .header()
+        .builder(&game)
+        .glyphs(&glyphs)
+        .clock(emu::step_clock((1000 / FRAME_MS) as u32))
+        .build()?;
+    let theme = theme::Theme::default();
+    let total = movie.len() as u64;
+    let progress = |n: u64| {
+        if n.is_multiple_of(1000) || n == total {
+            eprint!("\rRendered {}/{} frames", n, total);
+        }
+    };
+    // Frames go straight to disk, so long movies don't build up in memory
+    let gif = out
+        .extension()
+        .is_some_and(|e| e.eq_ignore_ascii_case("gif"));
+    let res = if gif {
+        let fps = (1000 / FRAME_MS) as u32;
+        let mut rec = recorder::Recorder::create(&out, scale, fps, &theme)?;
+        let res = movie::replay(&mut c8, &movie, &mut rec, progress);
+        rec.finish()?;
+        res
+    } else {
+        let mut pngs = renderer::PngSequence::create(&out, scale, &theme)?;
+        movie::replay(&mut c8, &movie, &mut pngs, progress)
+    };
+    eprintln!();
+    res?;
+    eprintln!("Replay matched, wrote {}", out.display());
+    Ok(())
+}
+
+// Hidden: runs a ROM twice in lockstep, with one quirk flipped on the
+// second run if named, and reports where they part ways. `fast` instead
+// runs the second through `Chip8::run_steps_fast`.
+fn lockstep(args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    let (rom, steps, flip) = match args {
+        [rom, steps] => (rom, steps, None),
+        [rom, steps, quirk] => (rom, steps, Some(quirk.as_str())),
+        _ => return Err("usage: chip8 lockstep ROM STEPS [QUIRK|fast]".into()),
+    };
+    let a = lockstep::Config::new(&[], &RomSource::parse(rom).resolve()?);
+    let mut b = a.clone();
+    match flip {
+        None => {}
+        Some("fast") => {
+            lockstep::compare_fast(&a, &[], num("STEPS", steps)?, 64)?;
+            eprintln!("No divergence");
+            return Ok(());
+        }
+        Some(q) if b.quirks.toggle(q) => {}
+        Some(q) => return Err(format!("unknown quirk {}", q).into()),
+    }
+    lockstep::compare(&a, &b, &[], num("STEPS", steps)?)?;
+    eprintln!("No divergence");
+    Ok(())
+}
+
+// Hidden: times a ROM run with `step` against `run_steps_fast`, and
+// `step` with hooks that do nothing
+fn bench(args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    let (rom, steps) = match args {
+        [drw, steps] if drw == "drw" => return bench_drw(num("STEPS", steps)?),
+        [rom, steps] => (
+            RomSource::parse(rom).resolve()?,
+            num::<u32>("STEPS", steps)?,
+        ),
+        _ => return Err("usage: chip8 bench ROM STEPS, or chip8 bench drw STEPS".into()),
+    };
+    let build = || emu::Chip8::builder().rom(&rom).validate(false).build();
+    let mut c8 = build()?;
+    let start = std::time::Instant::now();
+    let mut ran = 0;
+    while ran < steps {
+        ran += 1;
+        if c8.step()? != emu::StepOutcome::Ran {
+            break;
+        }
+    }
+    let slow = (ran, start.elapsed());
+    let mut c8 = build()?;
+    let start = std::time::Instant::now();
+    let fast = (c8.run_steps_fast(steps)?, start.elapsed());
+    let mut c8 = build()?;
+    c8.set_pre_exec_hook(Box::new(|_, _, _| emu::HookAction::Continue));
+    c8.set_post_exec_hook(Box::new(|_, _, _, _| {}));
+    let start = std::time::Instant::now();
+    let hooked = (c8.run_steps_fast(steps)?, start.elapsed());
+    for (name, (ran, took)) in [("step", slow), ("run_steps_fast", fast), ("hooked", hooked)] {
+        eprintln!(
+            "{:15} {} instructions in {:?}, {:.1} ns each",
+            name,
+            ran,
+            took,
+            took.as_nanos() as f64 / ran.max(1) as f64
+        );
+    }
+    Ok(())
+}
+
+// Hidden: times DRW drawing a sprite over and over, next to the same
+// loop with LD V0, V0 where the DRW was
+fn bench_drw(steps: u32) -> Result<(), Box<dyn std::error::Error>> {
+    let cases = [
+        ("8x1", 8, 4, 1, true),
+        ("8x8 aligned", 8, 4, 8, true),
+        ("8x15 unaligned", 13, 4, 15, true),
+        ("8x15 wrapped", 60, 28, 15, false),
+        ("8x15 clipped", 60, 28, 15, true),
+    ];
+    for (name, x, y, n, clip) in cases {
+        let time = |op: [u8; 2]| -> Result<std::time::Duration, emu::Fault> {
+            let mut rom = vec![
+                0x60, x, // 200: LD V0, x
+                0x61, y, // 202: LD V1, y
+                0xA2, 0x0A, // 204: LD I, 20A
+                op[0], op[1], // 206
+                0x12, 0x06, // 208: JP 206
+            ];
+            rom.extend_from_slice(&[0xFF, 0x81, 0xBD, 0xA5, 0xA5, 0xBD, 0x81, 0xFF]);
+            rom.extend_from_slice(&[0x3C, 0x42, 0x99, 0xA5, 0x99, 0x42, 0x3C]);
+            let quirks = emu::Quirks {
+                clip_x: clip,
+                clip_y: clip,
+                ..emu::Quirks::default()
+            };
+            let mut c8 = emu::Chip8::builder()
+                .rom(&rom)
+                .quirks(quirks)
+                .build()
+                .expect("the bench ROM loads");
+            let start = std::time::Instant::now();
+            c8.run_steps_fast(steps)?;
+            Ok(start.elapsed())
+        };
+        let drawn = time([0xD0, 0x10 | n])?;
+        let base = time([0x80, 0x00])?;
+        // Each time round the loop is the DRW and the JP
+        let loops = (steps / 2).max(1) as f64;
+        eprintln!(
+            "{:15} {:.1} ns a loop, {:.1} ns with LD V0, V0 instead",
+            name,
+            drawn.as_nanos() as f64 / loops,
+            base.as_nanos() as f64 / loops
+        );
+    }
+    Ok(())
+}
+
+fn main() {
+    let argv: Vec<String> = std::env::args().collect();
+    if let Err(e) = run_cmd(&argv) {
+        // Scripts asking for JSON get errors as JSON too
+        if argv.iter().any(|a| a == "--json") {
+            eprintln!("{}", json::Object::new().str("error", &e.to_string()));
+        } else {
+            eprintln!("Error: {}", e);
+        }
+        std::process::exit(1);
+    }
+}
+
+fn run_cmd(argv: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    if argv.len() == 4 && argv[1] == "diff" {
+        return diff(&argv[2], &argv[3]);
+    }
+    match argv.get(1).map(String::as_str) {
+        Some("lockstep") => return lockstep(&argv[2..]),
+        Some("bench") => return bench(&argv[2..]),
+        Some("trace-diff") => return trace_diff(&argv[2..]),
+        Some("sprites") => return sprites_cmd(&argv[2..]),
+        Some("info") => return info_cmd(&argv[2..]),
+        Some("disasm") => return disasm_cmd(&argv[2..], false),
+        Some("hexdump") => return disasm_cmd(&argv[2..], true),
+        Some("render") => return render_cmd(&argv[2..]),
+        Some("selftest") => return selftest_cmd(),
+        Some("compare") => return compare_cmd(&argv[2..]),
+        _ => {}
+    }
+    let opts = parse_args(&argv[1..])?;
     let running = Arc::new(AtomicBool::new(true));
     let r = running.clone();
     ctrlc::set_handler(move || {
         r.store(false, Ordering::SeqCst);
     })?;
-    let argv: Vec<String> = std::env::args().collect();
-    let interpreter = std::fs::read("interpreter.bin")?;
-    let game = std::fs::read::<&str>(if argv.len() == 2 { &argv[1] } else { "ibm.ch8" })?;
-    let rng = rand::rngs::StdRng::seed_from_u64(0);
-    let mut c8 = emu::Chip8::new(&interpreter, &game, Box::new(rng) as _);
-    drop(interpreter);
-    drop(game);
-    let mut s = screen::Screen::new();
+    term::install_panic_hook();
+    let res = run(&opts, &running);
+    term::restore();
+    match res? {
+        Outcome::Fault(fault, dump) => {
+            if opts.json {
+                let err = json::Object::new()
+                    .str("error", "emulator fault")
+                    .str("fault", &fault.to_string())
+                    .str("state", &dump);
+                eprintln!("{}", err);
+            } else {
+                eprint!("Emulator fault: {}\n{}", fault, dump);
+            }
+            std::process::exit(2);
+        }
+        Outcome::KeyDeadlock(e) => {
+            if opts.json {
+                let err = json::Object::new()
+                    .str("error", &e.to_string())
+                    .str("pc", &format!("{:03x}", e.pc));
+                eprintln!("{}", err);
+            } else {
+                eprintln!("{}", e);
+            }
+            std::process::exit(3);
+        }
+        Outcome::Quit | Outcome::Menu => Ok(()),
+    }
+}
 
-    while running.load(Ordering::SeqCst) {
-        c8.step();
-        s.draw(&c8.screen);
-        std::thread::sleep(std::time::Duration::from_millis(50));
+enum Outcome {
+    Quit,
+    // Back to the ROM browser
+    Menu,
+    // With `Chip8::dump_state` from when it happened
+    Fault(emu::Fault, String),
+    // Fx0A waiting with nothing left to press a key
+    KeyDeadlock(watchdog::KeyDeadlock),
+}
+
+// Runs until quit, returning what stopped the program
+// Saves a setting changed while playing for the next time the ROM runs,
+// giving what to show instead if it can't be
+fn remember(slots: &slots::Slots, change: impl FnOnce(&mut prefs::Prefs)) -> Option<String> {
+    prefs::update(&slots.dir().join(prefs::FILE), change)
+        .err()
+        .map(|e| format!("CAN'T SAVE SETTINGS: {}", e))
+}
+
+fn run(opts: &Options, running: &AtomicBool) -> Result<Outcome, Box<dyn std::error::Error>> {
+    let source = RomSource::parse(&opts.rom);
+    let path = Path::new(&opts.rom);
+    let roms = match &source {
+        _ if path.is_dir() => browser::list_roms(path)?,
+        RomSource::Zip(zip, None) => browser::zip_roms(zip)?,
+        _ => Vec::new(),
+    };
+    if roms.len() <= 1 && !path.is_dir() {
+        let source = roms.into_iter().next().map_or(source, |r| r.source);
+        return play(opts, &source, false, running);
     }
-    Ok(())
+    if opts.headless {
+        return Err(format!(
+            "{} has several ROMs, which can't be browsed headless",
+            opts.rom
+        )
+        .into());
+    }
+    if roms.is_empty() {
+        return Err(format!("no ROMs in {}", path.display()).into());
+    }
+    let mut selected = 0;
+    while let Some(n) = browser::choose(&roms, selected, &|| running.load(Ordering::SeqCst)) {
+        selected = n;
+        match play(opts, &roms[n].source, true, running)? {
+            Outcome::Menu => {}
+            Outcome::Quit => break,
+            o => return Ok(o),
+        }
+    }
+    Ok(Outcome::Quit)
+}
+
+// A soft reset, noted in the movie being recorded. Rewinding can't go
+// back past it.
+fn soft_reset(c8: &mut emu::Chip8, movie: Option<&mut movie::Movie>) {
+    c8.reset();
+    if let Some(m) = movie {
+        m.push_reset();
+    }
+}
+
+// Resets before frame `n` if the movie being played back did, giving
+// whether it did
+fn replay_reset(
+    c8: &mut emu::Chip8,
+    playback: Option<&movie::Movie>,
+    movie: Option<&mut movie::Movie>,
+    n: u64,
+) -> bool {
+    let reset = playback.is_some_and(|p| p.reset_before(n));
+    if reset {
+        soft_reset(c8, movie);
+    }
+    reset
+}
+
+// How a frame of `budget` steps from `pc` went once run through
+// `run_steps_fast`, which doesn't say, as far as the key watchdog needs to
+// know. A waiting Fx0A stops it early, or with one step a frame leaves PC
+// where it was.
+fn fast_outcome(c8: &emu::Chip8, pc: u16, budget: u32, ran: u32) -> emu::StepOutcome {
+    let early = ran < budget || c8.pc() == pc;
+    if early && c8.peek_word(c8.pc()) & 0xF0FF == 0xF00A {
+        emu::StepOutcome::WaitingForKey
+    } else {
+        emu::StepOutcome::Ran
+    }
+}
+
+// Plays one ROM, Backspace goes back to the browser if `menu` is set
+fn play(
+    opts: &Options,
+    rom: &RomSource,
+    menu: bool,
+    running: &AtomicBool,
+) -> Result<Outcome, Box<dyn std::error::Error>> {
+    let loaded = rom.load()?;
+    let mut game = loaded.bytes;
+    // An OctoCart's options, which the command line and saved settings
+    // win over
+    let cart = loaded.options;
+    let quirks = cart.map_or_else(emu::Quirks::default, |o| o.quirks(emu::Quirks::default()));
+    if *rom == RomSource::Stdin {
+        term::reattach_tty();
+    }
+    // Dropping a file on the window swaps this for another
+    let mut rom = rom.clone();
+    // Both sides of a netplay game need the same seed and quirks, which
+    // the host picks
+    let mut net = match (&opts.netplay, opts.netplay_listen) {
+        (Some(addr), _) => {
+            eprintln!("Joining {}", addr);
+            Some(netplay::Netplay::join(addr, state::rom_hash(&game))?)
+        }
+        (None, Some(port)) => {
+            eprintln!("Waiting for the other player on port {}", port);
+            let settings = netplay::Settings {
+                seed: rand::random(),
+                quirks,
+                delay: opts.netplay_delay,
+                check_every: opts.netplay_check,
+            };
+            let addr = format!("0.0.0.0:{}", port);
+            Some(netplay::Netplay::host(
+                &addr,
+                state::rom_hash(&game),
+                settings,
+            )?)
+        }
+        (None, None) => None,
+    };
+    // A movie plays back on the machine it was recorded on
+    let mut playback = match &opts.play_input {
+        Some(_) if net.is_some() => return Err("--play-input can't be used over netplay".into()),
+        Some(path) => {
+            let m = movie::Movie::from_bytes(&std::fs::read(path)?)?;
+            match m.check_rom(&game) {
+                Err(e) if opts.force => eprintln!("Playing anyway: {}", e),
+                r => r?,
+            }
+            Some(m)
+        }
+        None => None,
+    };
+    let seed = match (&net, &playback) {
+        (Some(n), _) => n.settings().seed,
+        (None, Some(m)) => m.header().seed,
+        (None, None) => 0,
+    };
+    let rng = rand::rngs::StdRng::seed_from_u64(seed);
+    let start = match &playback {
+        Some(m) => m.header().start,
+        None => opts.start_addr.unwrap_or(opts.load_addr),
+    };
+    // Netplay and movies run every step the same on both ends, one a
+    // frame
+    let lockstep = net.is_some() || playback.is_some() || opts.record_input.is_some();
+    let budget = if lockstep { 1 } else { opts.steps_per_frame };
+    // A fault dump runs as it did
+    let (on_unknown, on_odd_pc) = match &opts.load_dump {
+        Some(d) => (d.config.on_unknown, d.config.on_odd_pc),
+        None => (opts.on_unknown, opts.on_odd_pc),
+    };
+    let mut c8 = emu::Chip8::builder()
+        .interpreter(opts.interpreter.as_deref().unwrap_or(&[]))
+        .font_overlay(opts.font_overlay)
+        .glyphs(opts.font.as_deref().unwrap_or(&[]))
+        .rom(&game)
+        .quirks(quirks)
+        .load_address(opts.load_addr)
+        .start_address(start)
+        .ram_size(opts.ram_size)
+        .rng(Box::new(rng))
+        // Timers go by frames run, so they stop while paused, speed up
+        // with fast forward and stay in step over netplay
+        .clock(emu::step_clock((1000 / FRAME_MS) as u32 * budget))
+        // Checking every step would keep --idle-skip off the fast path
+        .validate(cfg!(debug_assertions) && !opts.idle_skip)
+        .on_unknown_opcode(on_unknown)
+        .on_odd_pc(on_odd_pc)
+        .build()?;
+    if let Some(net) = &net {
+        c8.set_quirks(net.settings().quirks);
+    }
+    if let Some(m) = &playback {
+        c8.set_quirks(m.header().quirks);
+    }
+    if let Some(d) = &opts.load_dump {
+        c8.set_quirks(d.config.quirks);
+    }
+    let mut slots = slots::Slots::new(&opts.state_dir, c8.rom_hash());
+    let saved = prefs::Prefs::load(&slots.dir().join(prefs::FILE)).unwrap_or_else(|e| {
+        eprintln!("Ignoring saved settings: {}", e);
+        prefs::Prefs::default()
+    });
+    let prefs = opts.prefs.clone().or(saved);
+    let theme = match &prefs.palette {
+        Some(p) => theme::Theme::parse(p)?,
+        None => cart.map_or(opts.palette, |o| o.theme),
+    };
+    // Overrides win over the layout, from the command line or saved
+    let mut keymap = prefs
+        .layout
+        .as_deref()
+        .and_then(keypad::Keymap::preset)
+        .unwrap_or_default();
+    for &(k, c) in &opts.key_overrides {
+        keymap.set(k, c);
+    }
+    let mut name = rom.name();
+    let mut s = if opts.headless {
+        None
+    } else {
+        term::enable_raw();
+        let s = screen::Screen::new();
+        if opts.mouse {
+            term::report_mouse();
+        }
+        let s = s.with_watches(opts.watches.len());
+        Some(if opts.status { s.with_status() } else { s })
+    };
+    // Movement keys are held as long as the rest unless told otherwise
+    let mut keys = keypad::Keypad::new(if opts.latch {
+        keypad::Release::Latched
+    } else {
+        let ms = std::time::Duration::from_millis;
+        keypad::Release::After {
+            movement: ms(opts.move_hold_ms.unwrap_or(opts.hold_ms)),
+            action: ms(opts.hold_ms),
+        }
+    });
+    let (mut paused, mut fast) = (false, false);
+    let mut focus = focus::Focus::new(opts.pause_on_focus_loss);
+    let mut undrawn = 0u32;
+    let mut pacer = pacer::Pacer::new(
+        std::time::Duration::from_millis(FRAME_MS),
+        opts.max_frameskip,
+        std::time::Instant::now(),
+    );
+    let platform = info::Info::new(&game).platform;
+    let mut title = title::TitleState::new(&name, platform);
+    let mut flash: Option<(String, std::time::Instant)> = None;
+    if platform == info::Platform::Hires {
+        let msg = "64x64 hi-res ROMs aren't supported, the screen will be wrong";
+        if opts.headless {
+            eprintln!("Warning: {}", msg);
+        } else {
+            flash = Some((msg.to_uppercase(), std::time::Instant::now()));
+        }
+    }
+    c8.set_journal(opts.journal);
+    c8.set_smc_monitor(opts.smc);
+    let turned_off = match (opts.idle_skip, opts.steps_per_frame > 1) {
+        _ if !lockstep => None,
+        (true, true) => Some("--idle-skip and --steps-per-frame are off"),
+        (true, false) => Some("--idle-skip is off"),
+        (false, true) => Some("--steps-per-frame is off"),
+        (false, false) => None,
+    };
+    if let Some(what) = turned_off {
+        let msg = format!("{} with netplay and movies", what);
+        if opts.headless {
+            eprintln!("Warning: {}", msg);
+        } else {
+            flash = Some((msg.to_uppercase(), std::time::Instant::now()));
+        }
+    }
+    c8.set_idle_skip(opts.idle_skip && !lockstep);
+    // Traces go to stderr unless a file is given
+    let trace: Option<Box<dyn Write>> = match (&opts.trace, &opts.trace_format) {
+        (Some(path), _) => Some(Box::new(std::io::BufWriter::new(std::fs::File::create(
+            path,
+        )?))),
+        (None, Some(_)) => Some(Box::new(std::io::stderr())),
+        (None, None) => None,
+    };
+    let counters = opts.trace_format.as_deref() == Some("counters");
+    let trace = trace.map(|out| Rc::new(RefCell::new(trace::Logger::new(out).counters(counters))));
+
+    let mut last_save = std::time::Instant::now();
+    if opts.autoload && opts.record_input.is_some() {
+        return Err(
+            "--record-input starts from power on, so it can't be used with --autoload".into(),
+        );
+    }
+    if opts.autoload {
+        match slots.load(Slot::Auto) {
+            Ok(state) => {
+                c8.load_state(&state);
+                flash = Some(("RESUMED".to_string(), last_save));
+            }
+            // Otherwise the slot last used
+            Err(slots::SlotError::Empty) => {
+                if let Some(slot) = prefs.slot.map(Slot::Numbered) {
+                    let msg = match slots.load(slot) {
+                        Ok(state) => {
+                            c8.load_state(&state);
+                            format!("RESUMED {}", slot)
+                        }
+                        Err(e) => format!("{}: {}", slot, e),
+                    };
+                    flash = Some((msg.to_uppercase(), last_save));
+                }
+            }
+            Err(e) => flash = Some((format!("AUTOSAVE: {}", e), last_save)),
+        }
+    }
+    if let Some(d) = &opts.load_dump {
+        if opts.record_input.is_some() || playback.is_some() || net.is_some() {
+            return Err("--load-dump can't be used with movies or netplay".into());
+        }
+        c8.load_state(&d.state);
+        // Headless it runs straight into the fault again
+        if !opts.headless {
+            paused = true;
+            flash = Some(("PAUSED BEFORE THE FAULT, . TO STEP".to_string(), last_save));
+        }
+    }
+    // (PC, opcode) of the last instructions run, for a fault dump
+    let mut recent = std::collections::VecDeque::with_capacity(dump::RECENT);
+
+    // Keys for every frame run, frame by frame from the ROM being loaded
+    let header = movie::Header::new(&game, c8.quirks(), seed, start);
+    let mut movie = opts
+        .record_input
+        .as_ref()
+        .map(|_| movie::Movie::new(header));
+    if opts.rng_trail {
+        movie = movie.map(movie::Movie::with_rng_trail);
+    }
+    // Frames into the movies, rewinding goes back
+    let mut movie_frame = 0u64;
+    // Keys for the next frame, set while paused
+    let mut advance = movie::FrameAdvance::new();
+    let mut breakpoints = breakpoints::Breakpoints::new();
+    // How far , has gone back from the furthest frame
+    let mut travel = timetravel::TimeTravel::new();
+    // Worked out after every step, shown under the status line
+    let mut watches = opts.watches.clone();
+
+    let mut rec = match &opts.record {
+        Some(path) => Some(recorder::Recorder::create(
+            path,
+            opts.scale,
+            (1000 / FRAME_MS) as u32,
+            &theme,
+        )?),
+        None => None,
+    };
+
+    // Frames are also sent here, for saving them as they play
+    let mut batch: Box<dyn renderer::Renderer> = match &opts.dump_frames {
+        Some(path) => Box::new(renderer::FileRenderer::create(path, opts.dump_every)?),
+        None => Box::new(renderer::NullRenderer),
+    };
+
+    // (I, n) of every sprite drawn, for --sprites
+    let mut drawn = sprites::Uses::new();
+    let op_stats = Rc::new(RefCell::new(opstats::OpcodeStats::new()));
+    // The tracer and --opcode-stats see every step, frames run one at a
+    // time included
+    if opts.opcode_stats || trace.is_some() {
+        let stats = opts.opcode_stats.then(|| op_stats.clone());
+        let trace = trace.clone();
+        c8.set_post_exec_hook(Box::new(move |c8, pc, op, res| {
+            if let Some(s) = &stats {
+                s.borrow_mut().record_step(pc, op, res);
+            }
+            if let Some(t) = &trace {
+                t.borrow_mut().log_step(c8, pc, op, res);
+            }
+        }));
+    }
+    // Kept outside the machine so resets and save states leave it be
+    let volume = std::sync::Arc::new(sound::Volume::new(prefs.volume.unwrap_or(opts.volume)));
+    volume.set_muted(prefs.muted.unwrap_or(opts.mute));
+    // Headless there's no terminal to ring
+    let mut sound: Box<dyn sound::SoundSink> = if opts.bell && !opts.headless {
+        Box::new(sound::BellSink::new(std::io::stdout()))
+    } else {
+        Box::new(sound::NullSink)
+    };
+    // Things that happen over and over, said once and then counted
+    let mut warnings = warnings::Warnings::new();
+    // What the server last had of them
+    let mut warnings_served = 0;
+    // A dropped file waiting for Enter, with --confirm-drop
+    let mut drop_waiting: Option<RomSource> = None;
+
+    // Keys held down by network clients
+    let mut remote_keys = 0u16;
+    // And by the mouse on the status line, until the button comes up
+    let mut mouse_keys = 0u16;
+    let mut server = match &opts.serve {
+        Some(addr) => {
+            let server = serve::Server::bind(addr, opts.allow_control)?;
+            eprintln!("Serving on http://{}", server.local_addr());
+            Some(server)
+        }
+        None => None,
+    };
+
+    let mut script = match &opts.script {
+        Some(path) => Some(script::Script::load(path)?),
+        None => None,
+    };
+    let mut script_error = None;
+    // Keys worked out in advance, by frame
+    let inputs = match &opts.input_script {
+        Some(path) => Some(timeline::Timeline::load(path)?),
+        None => None,
+    };
+
+    let mut phos = if opts.phosphor > 0 {
+        Some(phosphor::Phosphor::new(opts.phosphor))
+    } else {
+        None
+    };
+
+    let (mut frame, mut steps) = (0u64, 0u64);
+    let mut outcome = None;
+    let mut key_watchdog = watchdog::KeyWatchdog::new(watchdog::KEY_WAIT_FRAMES);
+    let mut frame_times = frametime::FrameTimes::new(std::time::Duration::from_millis(FRAME_MS));
+    let mut frame_log = match &opts.frame_log {
+        Some(path) => Some(frametime::CsvLog::new(std::fs::File::create(path)?)?),
+        None => None,
+    };
+    while outcome.is_none() && running.load(Ordering::SeqCst) && opts.max_frames != Some(frame) {
+        let frame_start = std::time::Instant::now();
+        let mut slept = std::time::Duration::ZERO;
+        // A dropped file to load in place of this one
+        let mut dropped_rom = None;
+        // Set when the machine jumps somewhere the movies can't follow
+        let mut end_movie = false;
+        if s.is_some() {
+            let now = std::time::Instant::now();
+            let input = term::read_input();
+            let mut bytes = input.iter().copied().peekable();
+            while let Some(b) = bytes.next() {
+                match b {
+                    // Escape on its own quits, escape sequences are skipped
+                    0x1B => match bytes.peek() {
+                        Some(b'[') | Some(b'O') => {
+                            let mut seq = vec![0];
+                            seq.extend(bytes.next());
+                            for c in bytes.by_ref() {
+                                seq.push(c);
+                                if (0x40..=0x7E).contains(&c) {
+                                    break;
+                                }
+                            }
+                            if let Some(e) = focus::event(&seq[1..]) {
+                                focus.update(e);
+                            }
+                            // Paused, a click sets the key for the next frame
+                            // as typing it would
+                            match mouse::event(&seq[1..]) {
+                                Some(mouse::MouseEvent::Press {
+                                    button: mouse::Button::Left,
+                                    col,
+                                    row,
+                                }) => match s.as_ref().and_then(|s| s.key_at(row, col)) {
+                                    Some(k) if paused => advance.toggle(k),
+                                    Some(k) => mouse_keys |= 1 << k,
+                                    None => {}
+                                },
+                                Some(mouse::MouseEvent::Release { .. }) => mouse_keys = 0,
+                                _ => {}
+                            }
+                            // Pasted text, which is what dropping a file gives
+                            if seq[1..] == *b"[200~" {
+                                let mut text = Vec::new();
+                                for c in bytes.by_ref() {
+                                    text.push(c);
+                                    if text.ends_with(b"\x1b[201~") {
+                                        text.truncate(text.len() - 6);
+                                        break;
+                                    }
+                                }
+                                let text = String::from_utf8_lossy(&text);
+                                let msg = match dropped::paths(&text).first() {
+                                    None => None,
+                                    Some(_) if net.is_some() => {
+                                        Some("CAN'T SWAP ROMS OVER NETPLAY".to_string())
+                                    }
+                                    Some(path) => match dropped::source(path) {
+                                        Ok(src) if opts.confirm_drop => {
+                                            let msg = format!("LOAD {}? ENTER", src.name());
+                                            drop_waiting = Some(src);
+                                            Some(msg)
+                                        }
+                                        Ok(src) => {
+                                            dropped_rom = Some(src);
+                                            None
+                                        }
+                                        Err(e) => Some(format!("CAN'T LOAD: {}", e)),
+                                    },
+                                };
+                                if let Some(msg) = msg {
+                                    flash = Some((msg.to_uppercase(), now));
+                                }
+                            }
+                            // F1 to F10 load a slot, with Shift they save to it
+                            if let Some((n, shift)) = keypad::function_key(&seq[1..]) {
+                                if n < slots::SLOTS {
+                                    let slot = Slot::Numbered(n);
+                                    let msg = if shift {
+                                        match slots.save(slot, &c8.save_state()) {
+                                            Ok(()) => remember(&slots, |p| p.slot = Some(n))
+                                                .unwrap_or(format!("SAVED {}", slot)),
+                                            Err(e) => format!("SAVE FAILED: {}", e),
+                                        }
+                                    } else {
+                                        match slots.load(slot) {
+                                            Ok(state) => {
+                                                c8.load_state(&state);
+                                                end_movie = true;
+                                                remember(&slots, |p| p.slot = Some(n))
+                                                    .unwrap_or(format!("LOADED {}", slot))
+                                            }
+                                            Err(e) => format!("{}: {}", slot, e),
+                                        }
+                                    };
+                                    flash = Some((msg.to_uppercase(), now));
+                                }
+                            }
+                        }
+                        _ => running.store(false, Ordering::SeqCst),
+                    },
+                    0x7F | 0x08 if menu => outcome = Some(Outcome::Menu),
+                    b if b == opts.reset_key => {
+                        soft_reset(&mut c8, movie.as_mut());
+                        travel.commit();
+                        frame = 0;
+                        // Not one the movie being played back has
+                        let msg = match playback.take() {
+                            Some(_) => "INPUT MOVIE ENDED",
+                            None => "RESET",
+                        };
+                        flash = Some((msg.to_string(), now));
+                    }
+                    b'\r' | b'\n' if drop_waiting.is_some() => dropped_rom = drop_waiting.take(),
+                    // Reloaded from the source, undoing any changes to RAM.
+                    // Stdin can't be read twice so the first copy is used.
+                    b if b == opts.hard_reset_key => {
+                        match rom {
+                            RomSource::Stdin => c8.load_rom(&game)?,
+                            _ => c8.load_rom(&rom.resolve()?)?,
+                        }
+                        // Noted in the movie being recorded like a soft
+                        // one, and the one being played back starts over
+                        if let Some(m) = &mut movie {
+                            m.push_reset();
+                        }
+                        movie_frame = 0;
+                        frame = 0;
+                        travel.commit();
+                        flash = Some(("HARD RESET".to_string(), now));
+                    }
+                    b if opts.latch && b == opts.release_key => keys.release_all(),
+                    // Carrying on from a frame stepped back to leaves the
+                    // ones after it behind
+                    b' ' => {
+                        paused = !paused;
+                        travel.commit();
+                    }
+                    b if b == opts.mute_key => {
+                        let muted = volume.toggle_mute();
+                        let msg = if muted { "MUTED" } else { "UNMUTED" };
+                        let msg =
+                            remember(&slots, |p| p.muted = Some(muted)).unwrap_or(msg.to_string());
+                        flash = Some((msg, now));
+                    }
+                    // While paused , and . go back and forward one frame, with
+                    // the keypad setting the keys for the next one
+                    b',' if paused => {
+                        if travel.back(&mut c8, 1, |c8| advance.rewind(c8, movie.as_mut())) == 1 {
+                            movie_frame = movie_frame.saturating_sub(1);
+                        } else {
+                            flash = Some(("NO HISTORY".to_string(), now));
+                        }
+                    }
+                    b'.' if paused => {
+                        if replay_reset(&mut c8, playback.as_ref(), movie.as_mut(), movie_frame) {
+                            frame = 0;
+                        }
+                        if let Some(k) = playback.as_ref().and_then(|p| p.get(movie_frame)) {
+                            advance.set_pending(k);
+                        }
+                        match travel.forward(&mut c8, |c8| advance.advance(c8, movie.as_mut())) {
+                            Ok(_) => {
+                                steps += 1;
+                                watches.update(&c8, frame);
+                                movie_frame += 1;
+                            }
+                            Err(f) => outcome = Some(Outcome::Fault(f, c8.dump_state())),
+                        }
+                    }
+                    // Runs frames until PC reaches the instruction after this
+                    // one, to get past a CALL or a loop
+                    b'>' if paused => {
+                        let target = c8.pc().wrapping_add(2);
+                        let mut ran = 0;
+                        let res = breakpoints.run_to(&mut c8, target, opts.until_limit, |c8| {
+                            let n = movie_frame + ran;
+                            if replay_reset(c8, playback.as_ref(), movie.as_mut(), n) {
+                                frame = 0;
+                            }
+                            if let Some(k) = playback.as_ref().and_then(|p| p.get(n)) {
+                                advance.set_pending(k);
+                            }
+                            let res = travel.forward(c8, |c8| advance.advance(c8, movie.as_mut()));
+                            ran += res.is_ok() as u64;
+                            res
+                        });
+                        steps += ran;
+                        watches.update(&c8, frame);
+                        movie_frame += ran;
+                        let msg = match res {
+                            Ok(breakpoints::Stop::Breakpoint { addr, steps }) => {
+                                format!("AT {:03X} AFTER {}", addr, steps)
+                            }
+                            Ok(breakpoints::Stop::Limit { steps }) => {
+                                format!("{:03X} NOT REACHED IN {}", target, steps)
+                            }
+                            Ok(stop) => format!("STOPPED AFTER {}", stop.steps()),
+                            Err(f) => {
+                                outcome = Some(Outcome::Fault(f, c8.dump_state()));
+                                continue;
+                            }
+                        };
+                        flash = Some((msg, now));
+                    }
+                    b'\t' => fast = !fast,
+                    b => match keypad::read_char(b, &mut bytes).and_then(|c| keymap.map(c)) {
+                        Some(k) if paused => advance.toggle(k),
+                        Some(k) => keys.press(k, now),
+                        None => {}
+                    },
+                }
+            }
+        }
+        // Like a hard reset with a different ROM. One that can't be read
+        // leaves the game running.
+        if let Some(src) = dropped_rom {
+            let msg = match src.resolve().and_then(|g| c8.load_rom(&g).map(|()| g)) {
+                Ok(g) => {
+                    end_movie = true;
+                    name = src.name();
+                    title = title::TitleState::new(&name, info::Info::new(&g).platform);
+                    slots = slots::Slots::new(&opts.state_dir, c8.rom_hash());
+                    frame = 0;
+                    game = g;
+                    rom = src;
+                    format!("LOADED {}", name)
+                }
+                Err(e) => format!("CAN'T LOAD: {}", e),
+            };
+            flash = Some((msg.to_uppercase(), std::time::Instant::now()));
+        }
+        if let Some(server) = &server {
+            let now = std::time::Instant::now();
+            for cmd in server.commands() {
+                match cmd {
+                    serve::Command::KeyDown(k) => remote_keys |= 1 << k,
+                    serve::Command::KeyUp(k) => remote_keys &= !(1 << k),
+                    serve::Command::Pause => paused = !paused,
+                    serve::Command::Reset => {
+                        soft_reset(&mut c8, movie.as_mut());
+                        travel.commit();
+                        frame = 0;
+                        let msg = match playback.take() {
+                            Some(_) => "INPUT MOVIE ENDED",
+                            None => "RESET",
+                        };
+                        flash = Some((msg.to_string(), now));
+                    }
+                    serve::Command::Load(n) => {
+                        let slot = Slot::Numbered(n);
+                        let msg = match slots.load(slot) {
+                            Ok(state) => {
+                                c8.load_state(&state);
+                                end_movie = true;
+                                remember(&slots, |p| p.slot = Some(n))
+                                    .unwrap_or(format!("LOADED {}", slot))
+                            }
+                            Err(e) => format!("{}: {}", slot, e),
+                        };
+                        flash = Some((msg.to_uppercase(), now));
+                    }
+                }
+            }
+        }
+        // Frames stepped back from aren't there to go forward to now
+        if end_movie {
+            travel.commit();
+        }
+        // A movie only plays back from power on, so one going on from
+        // anywhere else is saved as it is and stopped
+        if end_movie && (movie.is_some() || playback.is_some()) {
+            if let (Some(m), Some(path)) = (movie.take(), &opts.record_input) {
+                std::fs::write(path, m.to_bytes())?;
+            }
+            playback = None;
+            flash = Some(("INPUT MOVIE ENDED".to_string(), std::time::Instant::now()));
+        }
+        // The pause key, or the window being in the background
+        let stopped = !focus.runs(paused);
+        if let (Some(sc), false) = (&mut script, stopped) {
+            let now = std::time::Instant::now();
+            match sc.before_frame(&mut c8, frame) {
+                Ok(actions) => {
+                    for a in actions {
+                        match a {
+                            script::Action::Log(msg) => {
+                                if s.is_none() {
+                                    eprintln!("{}", msg);
+                                }
+                                flash = Some((msg, now));
+                            }
+                            script::Action::Pause => paused = true,
+                            script::Action::Screenshot => {
+                                let path = screenshot::save(
+                                    &opts.screenshot,
+                                    &c8.screen,
+                                    opts.scale,
+                                    &theme,
+                                )?;
+                                flash = Some(("SCREENSHOT SAVED".to_string(), now));
+                                if s.is_none() {
+                                    eprintln!("Saved screenshot to {}", path.display());
+                                }
+                            }
+                            script::Action::Quit => outcome = Some(Outcome::Quit),
+                        }
+                    }
+                }
+                // Reported once, then the game carries on without it
+                Err(e) => {
+                    if s.is_none() {
+                        eprintln!("{}", e);
+                    }
+                    flash = Some((e.to_string().to_uppercase(), now));
+                    script_error = Some(e.to_string());
+                }
+            }
+            if script_error.is_some() {
+                script = None;
+            }
+            if outcome.is_some() {
+                break;
+            }
+        }
+        // Everything that can hold keys down
+        let held = keys.mask(std::time::Instant::now())
+            | remote_keys
+            | mouse_keys
+            | script.as_ref().map_or(0, |sc| sc.keys())
+            | inputs.as_ref().map_or(0, |t| t.keys(frame));
+        let turbo = opts.turbo.active(held);
+        let held = opts.turbo.apply(held, frame, (1000 / FRAME_MS) as u32);
+        let held = playback
+            .as_ref()
+            .and_then(|p| p.get(movie_frame))
+            .unwrap_or(held);
+        c8.set_keys(held);
+        if !stopped && replay_reset(&mut c8, playback.as_ref(), movie.as_mut(), movie_frame) {
+            frame = 0;
+        }
+        if !stopped {
+            if let Some(net) = &mut net {
+                match net.exchange(held) {
+                    Ok(k) => c8.set_keys(k),
+                    Err(netplay::NetplayError::Disconnected) => {
+                        eprintln!("The other player left");
+                        outcome = Some(Outcome::Quit);
+                        break;
+                    }
+                    Err(e) => return Err(e.into()),
+                }
+            }
+            // Latched for the frame, which is what the movie records
+            let pressed = c8.keys();
+            let rng = c8.rng_bytes();
+            c8.begin_frame(pressed);
+            // A movie being recorded, breakpoints, unknown opcode warnings,
+            // the SMC monitor, --sprites and fault dumps want every step as
+            // it went, otherwise the frame's steps take the fast path all
+            // at once. Hooks are left to it to see to.
+            let careful = movie.is_some()
+                || !breakpoints.is_empty()
+                || on_unknown == emu::OnUnknown::Warn
+                || opts.smc
+                || opts.sprites
+                || opts.fault_dump.is_some();
+            let res = if careful {
+                let mut res = Ok(emu::StepOutcome::Ran);
+                for _ in 0..budget {
+                    let (pc, op) = (c8.pc(), c8.peek_word(c8.pc()));
+                    if opts.sprites && op & 0xF000 == 0xD000 {
+                        *drawn.entry((c8.i(), (op & 0xF) as u8)).or_insert(0) += 1;
+                    }
+                    res = c8.step();
+                    if res.is_err() {
+                        break;
+                    }
+                    steps += 1;
+                    if opts.fault_dump.is_some() {
+                        if recent.len() == dump::RECENT {
+                            recent.pop_front();
+                        }
+                        recent.push_back((pc, op));
+                    }
+                    match res {
+                        Ok(emu::StepOutcome::Ran) => {}
+                        // Once for each opcode, loops would repeat it every
+                        // frame
+                        Ok(emu::StepOutcome::Skipped { pc, opcode }) => {
+                            let first = warnings.warn("unknown opcode", opcode as u64, || {
+                                let hint = info::hint(opcode)
+                                    .map_or(String::new(), |h| format!(", {}", h));
+                                format!(
+                                    "Skipped unknown opcode {:04X} at {:03X}{}",
+                                    opcode, pc, hint
+                                )
+                            });
+                            if let Some(msg) = first {
+                                if s.is_none() {
+                                    eprintln!("{}", msg);
+                                }
+                                flash = Some((
+                                    format!("UNKNOWN OPCODE {:04X}", opcode),
+                                    std::time::Instant::now(),
+                                ));
+                            }
+                        }
+                        // Waiting, halted or paused, the rest of the frame
+                        // would only do the same
+                        _ => break,
+                    }
+                }
+                res
+            } else {
+                let pc = c8.pc();
+                c8.run_steps_fast(budget).map(|ran| {
+                    steps += ran as u64;
+                    fast_outcome(&c8, pc, budget, ran)
+                })
+            };
+            c8.end_frame();
+            if res.is_ok() {
+                let drawn = c8.rng_bytes() - rng;
+                if let Some(m) = &mut movie {
+                    m.push(pressed);
+                    m.push_rng(drawn);
+                    m.set_screen(&c8.screen);
+                }
+                // Found as it happens rather than at the end, and the
+                // keyboard takes over from there
+                if let Some(Err(e)) = playback.as_ref().map(|p| p.check_rng(movie_frame, drawn)) {
+                    playback = None;
+                    if s.is_none() {
+                        eprintln!("{}", e);
+                    }
+                    flash = Some((e.to_string().to_uppercase(), std::time::Instant::now()));
+                }
+                movie_frame += 1;
+            }
+            // Anything that could still press a key
+            let live = s.is_some()
+                || server.is_some()
+                || net.is_some()
+                || playback.is_some()
+                || script.as_ref().is_some_and(|sc| !sc.is_done())
+                || inputs.as_ref().is_some_and(|t| !t.is_done(frame));
+            // Waiting for a key leaves PC on the Fx0A
+            if let Ok(o) = &res {
+                if let Err(e) = key_watchdog.update(o, c8.pc(), live) {
+                    outcome = Some(Outcome::KeyDeadlock(e));
+                    break;
+                }
+            }
+            if let Err(f) = res {
+                outcome = Some(Outcome::Fault(f, c8.dump_state()));
+                break;
+            }
+            watches.update(&c8, frame);
+            if let Some(net) = &mut net {
+                // Leaving is noticed when the next frame's keys don't come
+                match net.end_frame(framebuffer::hash(&c8.screen)) {
+                    Ok(()) | Err(netplay::NetplayError::Disconnected) => {}
+                    Err(e) => return Err(e.into()),
+                }
+            }
+            if let Some(e) = trace.as_ref().and_then(|t| t.borrow_mut().take_error()) {
+                return Err(e.into());
+            }
+        }
+        // Once the movie runs out the keyboard takes over, after checking
+        // the game got where it did when recorded
+        if let Some(m) = playback.take_if(|m| movie_frame >= m.len() as u64) {
+            let msg = match m.verify(&c8.screen) {
+                Ok(()) => "REPLAY MATCHED".to_string(),
+                Err(e) => e.to_string(),
+            };
+            if s.is_none() {
+                eprintln!("{}", msg);
+            }
+            flash = Some((msg.to_uppercase(), std::time::Instant::now()));
+        }
+        // The bell has no volume of its own, only on or off
+        sound.update(frame, c8.sound_timer() > 0 && volume.gain() > 0.0);
+        let dirty = c8.take_dirty();
+        if let Some(server) = &mut server {
+            server.push(frame, &c8.screen);
+            server.publish(serve::Snapshot {
+                frame,
+                instructions: steps,
+                pc: c8.pc(),
+                paused,
+                screen: c8.screen,
+            });
+            // Headless there's no screen to pace the frames
+            if s.is_none() && !fast {
+                let at = std::time::Instant::now();
+                std::thread::sleep(std::time::Duration::from_millis(FRAME_MS));
+                slept += at.elapsed();
+            }
+        }
+        // Rows changed since the screen was last drawn, which in the
+        // background isn't every frame
+        undrawn |= dirty;
+        if let Some(p) = &mut phos {
+            p.update(&c8.screen);
+        }
+        // Everything from here to the end of the frame counts as drawing
+        let emulated = frame_start.elapsed().saturating_sub(slept);
+        if let Some(s) = &mut s {
+            title.paused = stopped;
+            title.fast = fast;
+            title.recording = rec.is_some();
+            if let Some(t) = title.changed() {
+                term::set_title(&t);
+            }
+            // Skipped frames still ran, only drawing them is left out
+            let now = std::time::Instant::now();
+            if pacer.frame_done(now, fast) && focus.should_draw(now) {
+                s.set_dim(focus.dimmed());
+                let drawn = match &phos {
+                    Some(p) => s.draw_levels(p, &theme),
+                    None => s.draw_rows(&c8.screen, undrawn),
+                };
+                undrawn = 0;
+                let drawn = drawn.and_then(|()| {
+                    if !opts.status {
+                        return Ok(());
+                    }
+                    s.draw_status(&screen::Status {
+                        sound: c8.sound_timer() > 0,
+                        paused: stopped,
+                        rewound: travel.behind(),
+                        fast,
+                        keys: c8.keys(),
+                        next_keys: if paused {
+                            Some(advance.pending())
+                        } else {
+                            None
+                        },
+                        turbo,
+                        keymap: &keymap,
+                        muted: volume.muted(),
+                        name: &name,
+                        flash: flash
+                            .as_ref()
+                            .filter(|(_, at)| at.elapsed() < FLASH)
+                            .map(|(f, _)| f.as_str()),
+                    })
+                });
+                let drawn = drawn.and_then(|()| {
+                    if watches.is_empty() {
+                        return Ok(());
+                    }
+                    s.draw_watches(&watches.lines())
+                });
+                match drawn {
+                    // Whatever was reading the output has gone, so stop quietly
+                    Err(e) if e.kind() == std::io::ErrorKind::BrokenPipe => {
+                        outcome = Some(Outcome::Quit);
+                        break;
+                    }
+                    r => r?,
+                }
+            }
+            let at = std::time::Instant::now();
+            std::thread::sleep(pacer.next(at));
+            slept += at.elapsed();
+        }
+        if stopped {
+            continue;
+        }
+        if let Some(secs) = opts.autosave {
+            if last_save.elapsed().as_secs() >= secs {
+                last_save = std::time::Instant::now();
+                if let Err(e) = slots.save(Slot::Auto, &c8.save_state()) {
+                    flash = Some((format!("AUTOSAVE FAILED: {}", e).to_uppercase(), last_save));
+                }
+            }
+        }
+        if let Some(rec) = &mut rec {
+            rec.screen(&c8.screen)?;
+        }
+        frame += 1;
+        batch.render(frame, &c8.screen, dirty)?;
+        if opts.screenshot_at == Some(frame) {
+            let path = screenshot::save(&opts.screenshot, &c8.screen, opts.scale, &theme)?;
+            eprintln!("Saved screenshot to {}", path.display());
+            if opts.headless && opts.max_frames.is_none() {
+                break;
+            }
+        }
+        let rendered = frame_start.elapsed().saturating_sub(emulated + slept);
+        if let Some(slow) = frame_times.push(frame - 1, emulated, rendered, slept) {
+            let phase = (slow.phase == "rendering") as u64;
+            if let Some(msg) =
+                warnings.warn("slow frame", phase, || format!("Slow frame: {}", slow))
+            {
+                if s.is_none() {
+                    eprintln!("{}", msg);
+                }
+                flash = Some((
+                    format!("SLOW: {}", slow.phase).to_uppercase(),
+                    std::time::Instant::now(),
+                ));
+            }
+        }
+        if let (Some(server), true) = (&server, warnings.total() != warnings_served) {
+            server.publish_warnings(&warnings);
+            warnings_served = warnings.total();
+        }
+        if let Some(log) = &mut frame_log {
+            log.row(frame - 1, &frame_times.last())?;
+        }
+    }
+    if let Some(log) = frame_log {
+        log.into_inner()?;
+    }
+    let op_stats = op_stats.take();
+    // Headless JSON runs have them in the summary instead
+    let print_stats = opts.opcode_stats && !(opts.headless && opts.json);
+    let print_smc = opts.smc && !(opts.headless && opts.json);
+    let print_warnings = !(warnings.is_empty() || opts.headless && opts.json);
+    if opts.sprites || print_stats || print_smc || print_warnings {
+        drop(s);
+        term::restore();
+    }
+    if opts.sprites {
+        print_atlas(&c8.save_state().ram, &drawn);
+    }
+    if print_stats {
+        eprint!("{}", op_stats);
+    }
+    if print_smc {
+        eprint!("{}", summary::SmcReport(c8.smc_writes()));
+    }
+    if print_warnings {
+        eprint!("{}", warnings);
+    }
+    if let Some(path) = &opts.dump_screen {
+        std::fs::write(path, text::render(&c8.screen, text::RenderStyle::Full))?;
+    }
+    if let (Some(dir), Some(Outcome::Fault(f, _))) = (&opts.fault_dump, &outcome) {
+        let config = dump::Config {
+            rom: opts.rom.clone(),
+            quirks: c8.quirks(),
+            on_unknown,
+            on_odd_pc,
+        };
+        let path = dump::FaultDump::new(&mut c8, f, recent.into(), config).write(dir)?;
+        if !opts.json {
+            eprintln!("Fault dump written to {}", path.display());
+        }
+    }
+    // One last autosave on the way out, unless the program crashed
+    if opts.autosave.is_some() && !matches!(outcome, Some(Outcome::Fault(..))) {
+        slots.save(Slot::Auto, &c8.save_state())?;
+    }
+    if let Some(rec) = rec {
+        let frames = rec.count;
+        rec.finish()?;
+        eprintln!("Recorded {} frames", frames);
+    }
+    if let (Some(m), Some(path)) = (&movie, &opts.record_input) {
+        std::fs::write(path, m.to_bytes())?;
+        eprintln!("Recorded the keys for {} frames", m.len());
+    }
+    if opts.headless {
+        let summary = summary::Summary {
+            frame_count: frame,
+            instruction_count: steps,
+            machine_frames: c8.frames(),
+            retired: c8.retired(),
+            idle_steps: c8.idle_steps(),
+            screen_hash: framebuffer::hash(&c8.screen),
+            fault: match &outcome {
+                Some(Outcome::Fault(f, _)) => Some(f.to_string()),
+                _ => None,
+            },
+            script_error,
+            opcode_stats: opts.opcode_stats.then_some(op_stats),
+            smc_writes: opts.smc.then(|| c8.smc_writes().to_vec()),
+            frame_times: frame_times.percentiles(),
+            warnings: warnings.clone(),
+        };
+        if opts.json {
+            println!("{}", summary.to_json());
+        } else {
+            eprintln!("{}", summary);
+        }
+    }
+    Ok(outcome.unwrap_or(Outcome::Quit))
 }
@@ -0,0 +1,83 @@
+//! Clicks from terminals that take `\x1b[?1000h` to report buttons and
+//! `\x1b[?1006h` to give them as SGR sequences, `\x1b[<B;X;YM` for a press
+//! and the same ending in `m` for a release. B is the button, plus 4, 8
+//! and 16 for Shift, Alt and Ctrl, 32 for a drag and 64 for the wheel. X
+//! and Y are the column and row, from 1.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Button {
+    Left,
+    Middle,
+    Right,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MouseEvent {
+    Press {
+        button: Button,
+        col: usize,
+        row: usize,
+    },
+    Release {
+        button: Button,
+        col: usize,
+        row: usize,
+    },
+    /// The wheel, which has no release
+    Scroll { up: bool, col: usize, row: usize },
+}
+
+/// The event in an escape sequence without the leading escape, such as
+/// `[<0;12;40M`. Drags aren't asked for, so they're None like anything
+/// else that isn't a click.
+///
+/// ```
+/// use chip8::mouse::{self, Button, MouseEvent};
+///
+/// assert_eq!(mouse::event(b"[<0;12;40M"), Some(MouseEvent::Press { button: Button::Left, col: 12, row: 40 }));
+/// assert_eq!(mouse::event(b"[<2;1;1m"), Some(MouseEvent::Release { button: Button::Right, col: 1, row: 1 }));
+/// // Held modifiers don't change the button
+/// assert_eq!(mouse::event(b"[<17;300;2M"), Some(MouseEvent::Press { button: Button::Middle, col: 300, row: 2 }));
+/// assert_eq!(mouse::event(b"[<64;5;6M"), Some(MouseEvent::Scroll { up: true, col: 5, row: 6 }));
+/// assert_eq!(mouse::event(b"[<65;5;6M"), Some(MouseEvent::Scroll { up: false, col: 5, row: 6 }));
+///
+/// for seq in [&b"[<32;5;6M"[..], b"[<3;5;6M", b"[<0;5M", b"[<0;5;6;7M", b"[<0;0;6M", b"[<0;a;6M", b"[<0;5;6~", b"[I", b"[<66;5;6M"] {
+///     assert_eq!(mouse::event(seq), None, "{}", String::from_utf8_lossy(seq));
+/// }
+/// ```
+pub fn event(seq: &[u8]) -> Option<MouseEvent> {
+    let body = seq.strip_prefix(b"[<")?;
+    let (&last, params) = body.split_last()?;
+    let params = std::str::from_utf8(params).ok()?;
+    let nums: Vec<usize> = params
+        .split(';')
+        .map(|p| p.parse().ok())
+        .collect::<Option<_>>()?;
+    let (b, col, row) = match nums[..] {
+        [b, col, row] if col > 0 && row > 0 => (b, col, row),
+        _ => return None,
+    };
+    // Without the modifiers
+    let b = b & !0b11100;
+    if b & 64 != 0 {
+        return match (b & 3, last) {
+            (0 | 1, b'M') => Some(MouseEvent::Scroll {
+                up: b & 3 == 0,
+                col,
+                row,
+            }),
+            _ => None,
+        };
+    }
+    let button = match b {
+        0 => Button::Left,
+        1 => Button::Middle,
+        2 => Button::Right,
+        _ => return None,
+    };
+    match last {
+        b'M' => Some(MouseEvent::Press { button, col, row }),
+        b'm' => Some(MouseEvent::Release { button, col, row }),
+        _ => None,
+    }
+}
@@ -0,0 +1,721 @@
+//! Input recordings for tool-assisted play. A movie is the keypad mask for
+//! every frame from the ROM being loaded, as `Chip8::begin_frame` latched
+//! it, so playing it back from there does exactly what was recorded. Frames can be played one at a time
+//! with the keys for the next one set while paused, and rewinding takes
+//! frames off the end of the recording. Resets are recorded too, by the
+//! frame they came before, with a hard reset kept as a soft one.
+//!
+//! On disk a movie is, with numbers big-endian:
+//!
+//! - `C8MV`, then the major and minor format version as a byte each
+//! - the length of the header fields as a u16, then the fields: the ROM's
+//!   SHA-1, the quirk bits, the RNG seed as a u64, the start address as a
+//!   u16, the number of frames as a u64 and, since 1.1, a byte of flags:
+//!   1 if there's an RNG trail and, since 1.2, 2 if there are resets
+//! - runs of frames with the same keys, as a u32 count then the mask
+//! - with an RNG trail, runs of frames that drew the same number of random
+//!   bytes, as a u32 count then the number as a u16
+//! - with resets, how many as a u32, then the frames they came before in
+//!   order as u64s
+//! - the screen hash after the last frame and a hash of everything before
+//!   it, as u64s
+//!
+//! A newer minor version may add header fields after these, which older
+//! readers skip. A newer major version can't be read at all. A 1.0 reader
+//! can read a later movie without a trail or resets, and a 1.1 reader one
+//! without resets, but not ones with.
+//!
+//! The RNG trail is for finding where a replay went wrong: a game that
+//! drew a different number of random bytes on some frame has gone
+//! somewhere else, usually well before the screen shows it.
+use crate::emu::{Chip8, Chip8Builder, Fault, Quirks, StepOutcome};
+use crate::framebuffer;
+use crate::info::sha1;
+use crate::renderer::Renderer;
+use crate::state::rom_hash;
+use std::{fmt, io};
+
+const MAGIC: &[u8; 4] = b"C8MV";
+const MAJOR: u8 = 1;
+const MINOR: u8 = 2;
+// Bytes of header fields 1.0 wrote, and this version writes
+const HEADER_LEN_1_0: u16 = 20 + 1 + 8 + 2 + 8;
+const HEADER_LEN: u16 = HEADER_LEN_1_0 + 1;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MovieError {
+    BadMagic,
+    /// Major version too new to read
+    Version(u8),
+    Truncated,
+    /// The checksum or frame count doesn't add up
+    Corrupt,
+    /// Recorded with a different ROM
+    WrongRom,
+    /// Playback ended on a different screen to the recording
+    Desync {
+        recorded: u64,
+        played: u64,
+    },
+    /// A frame, counting from 1, drew a different number of random bytes
+    /// to the recording
+    RngDesync {
+        frame: u64,
+        recorded: u16,
+        played: u64,
+    },
+}
+
+impl fmt::Display for MovieError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::BadMagic => write!(f, "not a movie"),
+            Self::Version(v) => write!(f, "unsupported movie version {}", v),
+            Self::Truncated => write!(f, "movie is truncated"),
+            Self::Corrupt => write!(f, "movie is corrupt"),
+            Self::WrongRom => write!(f, "movie was recorded with a different ROM"),
+            Self::Desync { recorded, played } => write!(
+                f,
+                "replay desynced, screen {:016x} where the recording had {:016x}",
+                played, recorded
+            ),
+            Self::RngDesync {
+                frame,
+                recorded,
+                played,
+            } => write!(
+                f,
+                "replay desynced at frame {}, which drew {} random bytes where the recording drew {}",
+                frame, played, recorded
+            ),
+        }
+    }
+}
+
+impl std::error::Error for MovieError {}
+
+/// How the machine was set up when recording started
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Header {
+    pub rom_sha1: [u8; 20],
+    pub quirks: Quirks,
+    pub seed: u64,
+    pub start: u16,
+}
+
+impl Header {
+    pub fn new(rom: &[u8], quirks: Quirks, seed: u64, start: u16) -> Self {
+        Self {
+            rom_sha1: sha1(rom),
+            quirks,
+            seed,
+            start,
+        }
+    }
+
+    /// A machine set up like the recorded one, given the ROM. The font
+    /// and clock are up to the caller.
+    pub fn builder<'a>(&self, rom: &'a [u8]) -> Chip8Builder<'a> {
+        Chip8::builder()
+            .rom(rom)
+            .start_address(self.start)
+            .quirks(self.quirks)
+            .seed(self.seed)
+    }
+}
+
+// Reads fixed size fields off the front of a slice
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], MovieError> {
+        if self.0.len() < n {
+            return Err(MovieError::Truncated);
+        }
+        let (a, b) = self.0.split_at(n);
+        self.0 = b;
+        Ok(a)
+    }
+
+    fn u8(&mut self) -> Result<u8, MovieError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, MovieError> {
+        let b = self.take(2)?;
+        Ok(u16::from_be_bytes([b[0], b[1]]))
+    }
+
+    fn u32(&mut self) -> Result<u32, MovieError> {
+        let b = self.take(4)?;
+        Ok(u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    fn u64(&mut self) -> Result<u64, MovieError> {
+        let mut b = [0u8; 8];
+        b.copy_from_slice(self.take(8)?);
+        Ok(u64::from_be_bytes(b))
+    }
+}
+
+/// Keys held each frame, frame 0 first
+///
+/// ```
+/// use chip8::emu::Quirks;
+/// use chip8::movie::{Header, Movie, MovieError};
+///
+/// let rom = [0x12, 0x00];
+/// let mut movie = Movie::new(Header::new(&rom, Quirks::default(), 42, 0x200));
+/// for n in 0..1000u16 {
+///     movie.push(if n % 100 < 3 { 1 << 5 } else { 0 });
+/// }
+/// movie.set_screen(&[1; 32]);
+/// let bytes = movie.to_bytes();
+/// // Runs of the same keys take one entry
+/// assert!(bytes.len() < 200);
+/// let read = Movie::from_bytes(&bytes).unwrap();
+/// assert_eq!(read, movie);
+/// assert_eq!(read.header().seed, 42);
+/// assert_eq!(read.check_rom(&rom), Ok(()));
+/// assert_eq!(read.check_rom(&[0x12, 0x02]), Err(MovieError::WrongRom));
+/// assert_eq!(read.verify(&[1; 32]), Ok(()));
+/// assert!(matches!(read.verify(&[0; 32]), Err(MovieError::Desync { .. })));
+///
+/// // Anything cut off, changed or not a movie is turned down
+/// for len in 0..bytes.len() {
+///     assert!(Movie::from_bytes(&bytes[..len]).is_err());
+/// }
+/// assert_eq!(Movie::from_bytes(&bytes[..bytes.len() - 1]), Err(MovieError::Truncated));
+/// let mut bad = bytes.clone();
+/// bad[60] ^= 1;
+/// assert_eq!(Movie::from_bytes(&bad), Err(MovieError::Corrupt));
+/// assert_eq!(Movie::from_bytes(b"C8ST\x03"), Err(MovieError::BadMagic));
+///
+/// // A newer minor version with an extra header field reads, a newer
+/// // major version doesn't
+/// let mut newer = bytes[..6].to_vec();
+/// newer[5] += 1;
+/// let len = u16::from_be_bytes([bytes[6], bytes[7]]);
+/// newer.extend_from_slice(&(len + 3).to_be_bytes());
+/// newer.extend_from_slice(&bytes[8..8 + len as usize]);
+/// newer.extend_from_slice(b"new");
+/// newer.extend_from_slice(&bytes[8 + len as usize..bytes.len() - 8]);
+/// let sum = chip8::state::rom_hash(&newer);
+/// newer.extend_from_slice(&sum.to_be_bytes());
+/// assert_eq!(Movie::from_bytes(&newer).unwrap(), movie);
+/// let mut major = bytes.clone();
+/// major[4] += 1;
+/// assert_eq!(Movie::from_bytes(&major), Err(MovieError::Version(2)));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Movie {
+    header: Header,
+    masks: Vec<u16>,
+    // Random bytes drawn each frame, if they're being kept
+    rng: Option<Vec<u16>>,
+    // Frames the machine was reset before, in order
+    resets: Vec<u64>,
+    // framebuffer::hash of the screen after the last frame
+    screen: u64,
+}
+
+impl Movie {
+    pub fn new(header: Header) -> Self {
+        Self {
+            header,
+            masks: Vec::new(),
+            rng: None,
+            resets: Vec::new(),
+            screen: 0,
+        }
+    }
+
+    /// Keeps an RNG trail, `push_rng` after every `push`
+    pub fn with_rng_trail(mut self) -> Self {
+        self.rng = Some(Vec::new());
+        self
+    }
+
+    /// Random bytes drawn each frame, if there's a trail
+    pub fn rng_trail(&self) -> Option<&[u16]> {
+        self.rng.as_deref()
+    }
+
+    pub fn header(&self) -> &Header {
+        &self.header
+    }
+
+    pub fn push(&mut self, keys: u16) {
+        self.masks.push(keys);
+    }
+
+    /// Notes how many random bytes the last frame pushed drew, if there's
+    /// a trail
+    pub fn push_rng(&mut self, bytes: u64) {
+        if let Some(rng) = &mut self.rng {
+            rng.push(bytes.min(u16::MAX as u64) as u16);
+        }
+    }
+
+    /// Notes a soft reset before the next frame pushed
+    pub fn push_reset(&mut self) {
+        let at = self.masks.len() as u64;
+        if self.resets.last() != Some(&at) {
+            self.resets.push(at);
+        }
+    }
+
+    /// Whether the machine was reset before frame `n`, counting from 0
+    pub fn reset_before(&self, n: u64) -> bool {
+        self.resets.binary_search(&n).is_ok()
+    }
+
+    /// Keys for frame `n`, if the movie gets that far
+    pub fn get(&self, n: u64) -> Option<u16> {
+        self.masks.get(n as usize).copied()
+    }
+
+    pub fn len(&self) -> usize {
+        self.masks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.masks.is_empty()
+    }
+
+    /// Forgets everything from frame `len` on
+    pub fn truncate(&mut self, len: usize) {
+        self.masks.truncate(len);
+        if let Some(rng) = &mut self.rng {
+            rng.truncate(len);
+        }
+        // One before frame `len` stays, it comes after the frames kept
+        self.resets.retain(|&n| n <= len as u64);
+    }
+
+    /// Notes the screen after the last frame, for checking playback against
+    pub fn set_screen(&mut self, screen: &[u64; 32]) {
+        self.screen = framebuffer::hash(screen);
+    }
+
+    /// Whether the movie was recorded with `rom`
+    pub fn check_rom(&self, rom: &[u8]) -> Result<(), MovieError> {
+        if sha1(rom) == self.header.rom_sha1 {
+            Ok(())
+        } else {
+            Err(MovieError::WrongRom)
+        }
+    }
+
+    /// Whether playback ended on the screen the recording did
+    pub fn verify(&self, screen: &[u64; 32]) -> Result<(), MovieError> {
+        let played = framebuffer::hash(screen);
+        if played == self.screen {
+            Ok(())
+        } else {
+            Err(MovieError::Desync {
+                recorded: self.screen,
+                played,
+            })
+        }
+    }
+
+    /// Whether frame `n`, counting from 0, drew the random bytes it did
+    /// when recorded. Without a trail, or past its end, anything matches.
+    pub fn check_rng(&self, n: u64, bytes: u64) -> Result<(), MovieError> {
+        match self.rng.as_ref().and_then(|rng| rng.get(n as usize)) {
+            Some(&recorded) if recorded as u64 != bytes.min(u16::MAX as u64) => {
+                Err(MovieError::RngDesync {
+                    frame: n + 1,
+                    recorded,
+                    played: bytes,
+                })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        out.extend_from_slice(MAGIC);
+        out.push(MAJOR);
+        out.push(MINOR);
+        out.extend_from_slice(&HEADER_LEN.to_be_bytes());
+        out.extend_from_slice(&self.header.rom_sha1);
+        out.push(self.header.quirks.to_bits());
+        out.extend_from_slice(&self.header.seed.to_be_bytes());
+        out.extend_from_slice(&self.header.start.to_be_bytes());
+        out.extend_from_slice(&(self.masks.len() as u64).to_be_bytes());
+        out.push(self.rng.is_some() as u8 | (!self.resets.is_empty() as u8) << 1);
+        write_runs(&mut out, &self.masks);
+        if let Some(rng) = &self.rng {
+            write_runs(&mut out, rng);
+        }
+        if !self.resets.is_empty() {
+            out.extend_from_slice(&(self.resets.len() as u32).to_be_bytes());
+            for n in &self.resets {
+                out.extend_from_slice(&n.to_be_bytes());
+            }
+        }
+        out.extend_from_slice(&self.screen.to_be_bytes());
+        let sum = rom_hash(&out);
+        out.extend_from_slice(&sum.to_be_bytes());
+        out
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Result<Self, MovieError> {
+        let mut r = Reader(data);
+        if r.take(4)? != MAGIC {
+            return Err(MovieError::BadMagic);
+        }
+        let major = r.u8()?;
+        if major != MAJOR {
+            return Err(MovieError::Version(major));
+        }
+        r.u8()?;
+        let len = r.u16()?;
+        if len < HEADER_LEN_1_0 {
+            return Err(MovieError::Corrupt);
+        }
+        let mut h = Reader(r.take(len as usize)?);
+        let mut rom_sha1 = [0; 20];
+        rom_sha1.copy_from_slice(h.take(20)?);
+        let header = Header {
+            rom_sha1,
+            quirks: Quirks::from_bits(h.u8()?),
+            seed: h.u64()?,
+            start: h.u16()?,
+        };
+        let frames = h.u64()?;
+        let flags = if h.0.is_empty() { 0 } else { h.u8()? };
+        let masks = read_runs(&mut r, frames)?;
+        let rng = if flags & 1 != 0 {
+            Some(read_runs(&mut r, frames)?)
+        } else {
+            None
+        };
+        let mut resets = Vec::new();
+        if flags & 2 != 0 {
+            for _ in 0..r.u32()? {
+                let n = r.u64()?;
+                // In order, and none after the end
+                if n > frames || resets.last().is_some_and(|&last| n <= last) {
+                    return Err(MovieError::Corrupt);
+                }
+                resets.push(n);
+            }
+        }
+        let screen = r.u64()?;
+        let body = data.len() - r.0.len();
+        if r.u64()? != rom_hash(&data[..body]) || !r.0.is_empty() {
+            return Err(MovieError::Corrupt);
+        }
+        Ok(Self {
+            header,
+            masks,
+            rng,
+            resets,
+            screen,
+        })
+    }
+}
+
+// Runs of the same value, as a u32 count then the value
+fn write_runs(out: &mut Vec<u8>, values: &[u16]) {
+    let mut values = values.iter().peekable();
+    while let Some(&v) = values.next() {
+        let mut run = 1u32;
+        while run < u32::MAX && values.next_if_eq(&&v).is_some() {
+            run += 1;
+        }
+        out.extend_from_slice(&run.to_be_bytes());
+        out.extend_from_slice(&v.to_be_bytes());
+    }
+}
+
+fn read_runs(r: &mut Reader, len: u64) -> Result<Vec<u16>, MovieError> {
+    let mut values = Vec::new();
+    while (values.len() as u64) < len {
+        let run = r.u32()? as u64;
+        let v = r.u16()?;
+        if run == 0 || values.len() as u64 + run > len {
+            return Err(MovieError::Corrupt);
+        }
+        values.extend(std::iter::repeat_n(v, run as usize));
+    }
+    Ok(values)
+}
+
+/// Keys for the next frame, set while paused, and playing frames one at
+/// a time into a movie
+///
+/// ```
+/// use chip8::emu::{Chip8, Quirks};
+/// use chip8::movie::{FrameAdvance, Header, Movie};
+///
+/// // Draws a mark at the x of every key held, over and over:
+/// // LD V0, K is avoided so frames never wait
+/// let rom = [
+///     0x00, 0xE0, // CLS
+///     0x61, 0x00, // LD V1, 0
+///     0xE1, 0x9E, // SKP V1
+///     0x12, 0x0C, // JP 20C
+///     0xA2, 0x00, // LD I, 200
+///     0xD1, 0x25, // DRW V1, V2, 5
+///     0x71, 0x01, // ADD V1, 1
+///     0x31, 0x10, // SE V1, 16
+///     0x12, 0x04, // JP 204
+///     0x12, 0x00, // JP 200
+/// ];
+/// let mut c8 = Chip8::builder().rom(&rom).build().unwrap();
+/// c8.set_journal(64);
+/// let mut movie = Movie::new(Header::new(&rom, Quirks::default(), 0, 0x200));
+/// let mut fa = FrameAdvance::new();
+///
+/// fa.toggle(3);
+/// fa.toggle(0xA);
+/// assert_eq!(fa.pending(), 1 << 3 | 1 << 0xA);
+/// for _ in 0..20 {
+///     fa.advance(&mut c8, Some(&mut movie)).unwrap();
+/// }
+/// fa.toggle(3);
+/// for _ in 0..20 {
+///     fa.advance(&mut c8, Some(&mut movie)).unwrap();
+/// }
+/// // Taking back five frames takes them off the movie too
+/// for _ in 0..5 {
+///     assert!(fa.rewind(&mut c8, Some(&mut movie)));
+/// }
+/// assert_eq!(movie.len(), 35);
+/// fa.toggle(5);
+/// for _ in 0..10 {
+///     fa.advance(&mut c8, Some(&mut movie)).unwrap();
+/// }
+///
+/// // Played back through a file from the start, it ends up the same
+/// let movie = Movie::from_bytes(&movie.to_bytes()).unwrap();
+/// let mut again = Chip8::builder().rom(&rom).build().unwrap();
+/// for n in 0..movie.len() as u64 {
+///     again.begin_frame(movie.get(n).unwrap());
+///     again.step().unwrap();
+///     again.end_frame();
+/// }
+/// assert_eq!(again.save_state(), c8.save_state());
+/// assert_eq!(movie.verify(&again.screen), Ok(()));
+/// assert_eq!(movie.get(0), Some(1 << 3 | 1 << 0xA));
+/// assert_eq!(movie.get(44), Some(1 << 5 | 1 << 0xA));
+/// ```
+#[derive(Debug, Default)]
+pub struct FrameAdvance {
+    pending: u16,
+}
+
+impl FrameAdvance {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Keys the next frame will have held
+    pub fn pending(&self) -> u16 {
+        self.pending
+    }
+
+    pub fn set_pending(&mut self, keys: u16) {
+        self.pending = keys;
+    }
+
+    /// Presses or lets go of `key` for the next frame
+    pub fn toggle(&mut self, key: u8) {
+        self.pending ^= 1 << (key & 0xF);
+    }
+
+    /// Runs one frame with the pending keys held, adding it to `movie`
+    pub fn advance(
+        &mut self,
+        c8: &mut Chip8,
+        movie: Option<&mut Movie>,
+    ) -> Result<StepOutcome, Fault> {
+        let drawn = c8.rng_bytes();
+        c8.begin_frame(self.pending);
+        let res = c8.step();
+        c8.end_frame();
+        let res = res?;
+        if let Some(m) = movie {
+            m.push(self.pending);
+            m.push_rng(c8.rng_bytes() - drawn);
+            m.set_screen(&c8.screen);
+        }
+        Ok(res)
+    }
+
+    /// Undoes the last frame and takes it off the end of `movie`,
+    /// returning false if the journal has nothing left
+    pub fn rewind(&mut self, c8: &mut Chip8, movie: Option<&mut Movie>) -> bool {
+        if !c8.step_back() {
+            return false;
+        }
+        if let Some(m) = movie {
+            m.truncate(m.len().saturating_sub(1));
+            m.set_screen(&c8.screen);
+        }
+        true
+    }
+}
+
+#[derive(Debug)]
+pub enum ReplayError {
+    /// The frame, counting from 1, and what went wrong
+    Fault(u64, Fault),
+    Io(io::Error),
+    Movie(MovieError),
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Fault(frame, e) => write!(f, "frame {}: {}", frame, e),
+            Self::Io(e) => write!(f, "{}", e),
+            Self::Movie(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for ReplayError {}
+
+impl From<io::Error> for ReplayError {
+    fn from(e: io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+impl From<MovieError> for ReplayError {
+    fn from(e: MovieError) -> Self {
+        Self::Movie(e)
+    }
+}
+
+/// Plays all of `movie` on `c8` as fast as it goes, sending every frame
+/// to `out` as it's made and telling `progress` how many are done. Stops
+/// at the first frame that drew other random bytes than the RNG trail
+/// has, if there is one, and ends by checking the screen against the
+/// recording's. Resets happen where they were recorded.
+///
+/// ```
+/// use chip8::emu::Quirks;
+/// use chip8::movie::{replay, FrameAdvance, Header, Movie, MovieError, ReplayError};
+/// use chip8::recorder::Recorder;
+/// use chip8::renderer::{PngSequence, Renderer};
+/// use chip8::theme::Theme;
+///
+/// // Draws a mark at the x of every key held
+/// let rom = [
+///     0x00, 0xE0, // CLS
+///     0x61, 0x00, // LD V1, 0
+///     0xE1, 0x9E, // SKP V1
+///     0x12, 0x0C, // JP 20C
+///     0xA2, 0x00, // LD I, 200
+///     0xD1, 0x25, // DRW V1, V2, 5
+///     0x71, 0x01, // ADD V1, 1
+///     0x31, 0x10, // SE V1, 16
+///     0x12, 0x04, // JP 204
+///     0x12, 0x00, // JP 200
+/// ];
+/// let header = Header::new(&rom, Quirks::default(), 7, 0x200);
+/// let mut c8 = header.builder(&rom).build().unwrap();
+/// let mut movie = Movie::new(header);
+/// let mut fa = FrameAdvance::new();
+/// for n in 0..80 {
+///     if n < 30 && n % 10 == 0 {
+///         fa.toggle(n / 10 + 4);
+///     }
+///     fa.advance(&mut c8, Some(&mut movie)).unwrap();
+/// }
+/// let movie = Movie::from_bytes(&movie.to_bytes()).unwrap();
+///
+/// // Frames as they're rendered, the last one to compare
+/// struct Frames(u64, [u64; 32]);
+/// impl Renderer for Frames {
+///     fn render(&mut self, frame: u64, bits: &[u64; 32], _: u32) -> std::io::Result<()> {
+///         assert_eq!(frame, self.0 + 1);
+///         *self = Frames(frame, *bits);
+///         Ok(())
+///     }
+/// }
+/// let mut frames = Frames(0, [0; 32]);
+/// let mut again = header.builder(&rom).build().unwrap();
+/// let mut done = 0;
+/// replay(&mut again, &movie, &mut frames, |n| done = n).unwrap();
+/// assert_eq!((frames.0, done), (80, 80));
+/// assert_ne!(frames.1, [0; 32]);
+/// assert_eq!(frames.1, c8.screen);
+///
+/// // The same into a GIF and into PNGs
+/// let theme = Theme::default();
+/// let mut gif = Recorder::new(Vec::new(), 64, 32, 2, 20, &theme).unwrap();
+/// replay(&mut header.builder(&rom).build().unwrap(), &movie, &mut gif, |_| {}).unwrap();
+/// assert_eq!(gif.count, 80);
+/// assert!(gif.finish().unwrap().starts_with(b"GIF89a"));
+/// let dir = std::env::temp_dir().join(format!("chip8-replay-{}", std::process::id()));
+/// let mut pngs = PngSequence::create(&dir, 1, &theme).unwrap();
+/// replay(&mut header.builder(&rom).build().unwrap(), &movie, &mut pngs, |_| {}).unwrap();
+/// assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 80);
+/// assert!(dir.join("000080.png").exists());
+/// std::fs::remove_dir_all(&dir).unwrap();
+///
+/// // Other keys with the same screen claimed at the end don't match
+/// let mut wrong = Movie::new(header);
+/// for _ in 0..80 {
+///     wrong.push(0);
+/// }
+/// wrong.set_screen(&c8.screen);
+/// let mut c8 = header.builder(&rom).build().unwrap();
+/// let res = replay(&mut c8, &wrong, &mut Frames(0, [0; 32]), |_| {});
+/// assert!(matches!(res, Err(ReplayError::Movie(MovieError::Desync { .. }))));
+///
+/// // With an RNG trail, a quirk that changes whether RND runs is caught
+/// // on the frame it first does, long before the end
+/// let rom = [
+///     0x61, 0x02, // LD V1, 02
+///     0x80, 0x16, // SHR V0, V1, leaving 0 or 1 in V0 by the shift quirk
+///     0x30, 0x00, // SE V0, 00
+///     0xC0, 0xFF, // RND V0, FF
+///     0x12, 0x08, // JP 208
+/// ];
+/// let header = Header::new(&rom, Quirks::default(), 7, 0x200);
+/// let mut c8 = header.builder(&rom).build().unwrap();
+/// let mut movie = Movie::new(header).with_rng_trail();
+/// for _ in 0..60 {
+///     fa.advance(&mut c8, Some(&mut movie)).unwrap();
+/// }
+/// let movie = Movie::from_bytes(&movie.to_bytes()).unwrap();
+/// assert_eq!(movie.rng_trail().unwrap().iter().map(|&n| n as u64).sum::<u64>(), c8.rng_bytes());
+/// let mut flipped = header.quirks;
+/// flipped.toggle("hp_shift");
+/// let mut c8 = header.builder(&rom).quirks(flipped).build().unwrap();
+/// let err = replay(&mut c8, &movie, &mut Frames(0, [0; 32]), |_| {}).unwrap_err();
+/// assert!(matches!(err, ReplayError::Movie(MovieError::RngDesync { frame: 4, .. })));
+/// assert!(err.to_string().starts_with("replay desynced at frame 4, which drew "));
+/// ```
+pub fn replay(
+    c8: &mut Chip8,
+    movie: &Movie,
+    out: &mut dyn Renderer,
+    mut progress: impl FnMut(u64),
+) -> Result<(), ReplayError> {
+    for n in 0..movie.len() as u64 {
+        if movie.reset_before(n) {
+            c8.reset();
+        }
+        let drawn = c8.rng_bytes();
+        c8.begin_frame(movie.get(n).unwrap_or(0));
+        let res = c8.step();
+        c8.end_frame();
+        res.map_err(|e| ReplayError::Fault(n + 1, e))?;
+        movie.check_rng(n, c8.rng_bytes() - drawn)?;
+        let dirty = c8.take_dirty();
+        out.render(n + 1, &c8.screen, dirty)?;
+        progress(n + 1);
+    }
+    movie.verify(&c8.screen)?;
+    Ok(())
+}
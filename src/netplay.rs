@@ -0,0 +1,233 @@
+//! Two instances sharing one keypad. Each frame both sides send the keys
+//! they hold and only run the frame once the other side's keys for it
+//! have arrived. Local keys take effect `delay` frames later so they have
+//! time to cross the network. Every so often both sides send a hash of
+//! the screen, and a mismatch stops the game.
+use crate::emu::Quirks;
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::io::{self, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::time::Duration;
+
+const VERSION: u8 = 1;
+
+const HELLO: u8 = 0x00;
+const INPUT: u8 = 0x01;
+const HASH: u8 = 0x02;
+
+// Long enough for the other side to sit paused for a bit
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Debug)]
+pub enum NetplayError {
+    Io(io::Error),
+    /// Something that isn't this protocol, or a different version of it
+    Protocol(String),
+    /// The two sides loaded different ROMs
+    RomMismatch,
+    /// The screens differed after this frame
+    Desync(u64),
+    /// The other side quit
+    Disconnected,
+}
+
+impl fmt::Display for NetplayError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "netplay: {}", e),
+            Self::Protocol(e) => write!(f, "netplay: {}", e),
+            Self::RomMismatch => write!(f, "netplay: the other side is running a different ROM"),
+            Self::Desync(frame) => write!(f, "netplay: out of sync after frame {}", frame),
+            Self::Disconnected => write!(f, "netplay: the other side left"),
+        }
+    }
+}
+
+impl std::error::Error for NetplayError {}
+
+impl From<io::Error> for NetplayError {
+    fn from(e: io::Error) -> Self {
+        match e.kind() {
+            io::ErrorKind::UnexpectedEof
+            | io::ErrorKind::BrokenPipe
+            | io::ErrorKind::ConnectionReset
+            | io::ErrorKind::ConnectionAborted => Self::Disconnected,
+            _ => Self::Io(e),
+        }
+    }
+}
+
+/// What the host decides and the other side goes along with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Settings {
+    pub seed: u64,
+    pub quirks: Quirks,
+    /// Frames between pressing a key and it taking effect, 1 to 3
+    pub delay: u8,
+    /// Frames between screen hash checks
+    pub check_every: u32,
+}
+
+pub struct Netplay {
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+    settings: Settings,
+    // Next frame to run
+    frame: u64,
+    // Our keys for this frame and the `delay` after it
+    ours: VecDeque<u16>,
+    // The other side's keys by frame
+    theirs: HashMap<u64, u16>,
+    // Screen hashes by frame, waiting for the other side's to compare
+    our_hashes: HashMap<u64, u64>,
+    their_hashes: HashMap<u64, u64>,
+}
+
+impl Netplay {
+    /// Waits on `addr` for the other side to join and tells it `settings`
+    pub fn host(addr: &str, rom: u64, settings: Settings) -> Result<Self, NetplayError> {
+        let (stream, _) = TcpListener::bind(addr)?.accept()?;
+        let mut np = Self::new(stream, settings)?;
+        let mut hello = vec![HELLO, VERSION];
+        hello.extend_from_slice(&rom.to_be_bytes());
+        hello.extend_from_slice(&settings.seed.to_be_bytes());
+        hello.push(settings.quirks.to_bits());
+        hello.push(settings.delay);
+        hello.extend_from_slice(&settings.check_every.to_be_bytes());
+        np.writer.write_all(&hello)?;
+        // Confirms the ROM before anything runs
+        let mut reply = [0u8; 10];
+        np.reader.read_exact(&mut reply)?;
+        np.check_hello(&reply, rom)?;
+        Ok(np)
+    }
+
+    /// Connects to a host and takes on its settings
+    pub fn join(addr: &str, rom: u64) -> Result<Self, NetplayError> {
+        let stream = TcpStream::connect(addr)?;
+        stream.set_read_timeout(Some(TIMEOUT))?;
+        let mut hello = [0u8; 24];
+        stream.try_clone()?.read_exact(&mut hello)?;
+        let mut word = [0u8; 8];
+        word.copy_from_slice(&hello[10..18]);
+        let settings = Settings {
+            seed: u64::from_be_bytes(word),
+            quirks: Quirks::from_bits(hello[18]),
+            delay: hello[19],
+            check_every: u32::from_be_bytes([hello[20], hello[21], hello[22], hello[23]]),
+        };
+        let mut np = Self::new(stream, settings)?;
+        let mut reply = vec![HELLO, VERSION];
+        reply.extend_from_slice(&rom.to_be_bytes());
+        np.writer.write_all(&reply)?;
+        np.check_hello(&hello[..10], rom)?;
+        if !(1..=3).contains(&settings.delay) || settings.check_every == 0 {
+            return Err(NetplayError::Protocol("bad settings from host".to_string()));
+        }
+        Ok(np)
+    }
+
+    fn new(stream: TcpStream, settings: Settings) -> io::Result<Self> {
+        stream.set_read_timeout(Some(TIMEOUT))?;
+        stream.set_nodelay(true)?;
+        let delay = settings.delay as u64;
+        Ok(Self {
+            writer: stream.try_clone()?,
+            reader: BufReader::new(stream),
+            settings,
+            frame: 0,
+            ours: (0..delay).map(|_| 0).collect(),
+            // Nobody can have pressed anything before the first frame
+            theirs: (0..delay).map(|f| (f, 0)).collect(),
+            our_hashes: HashMap::new(),
+            their_hashes: HashMap::new(),
+        })
+    }
+
+    // Tag, version and ROM hash
+    fn check_hello(&self, hello: &[u8], rom: u64) -> Result<(), NetplayError> {
+        if hello[0] != HELLO || hello[1] != VERSION {
+            return Err(NetplayError::Protocol(
+                "the other side speaks a different protocol".to_string(),
+            ));
+        }
+        let mut word = [0u8; 8];
+        word.copy_from_slice(&hello[2..10]);
+        if u64::from_be_bytes(word) != rom {
+            return Err(NetplayError::RomMismatch);
+        }
+        Ok(())
+    }
+
+    pub fn settings(&self) -> Settings {
+        self.settings
+    }
+
+    /// Sends the keys held now and returns the keys held by both sides
+    /// for the frame about to run, waiting for the other side if need be
+    pub fn exchange(&mut self, held: u16) -> Result<u16, NetplayError> {
+        let at = self.frame + self.settings.delay as u64;
+        let mut msg = vec![INPUT];
+        msg.extend_from_slice(&at.to_be_bytes());
+        msg.extend_from_slice(&held.to_be_bytes());
+        self.writer.write_all(&msg)?;
+        self.ours.push_back(held);
+        let ours = self.ours.pop_front().unwrap_or(0);
+        while !self.theirs.contains_key(&self.frame) {
+            self.receive()?;
+        }
+        let theirs = self.theirs.remove(&self.frame).unwrap_or(0);
+        Ok(ours | theirs)
+    }
+
+    /// Finishes the frame, sending the screen hash if it's time to check
+    pub fn end_frame(&mut self, screen_hash: u64) -> Result<(), NetplayError> {
+        let frame = self.frame;
+        self.frame += 1;
+        if !frame.is_multiple_of(self.settings.check_every as u64) {
+            return Ok(());
+        }
+        let mut msg = vec![HASH];
+        msg.extend_from_slice(&frame.to_be_bytes());
+        msg.extend_from_slice(&screen_hash.to_be_bytes());
+        self.writer.write_all(&msg)?;
+        self.our_hashes.insert(frame, screen_hash);
+        self.compare(frame)
+    }
+
+    fn compare(&mut self, frame: u64) -> Result<(), NetplayError> {
+        if let (Some(a), Some(b)) = (self.our_hashes.get(&frame), self.their_hashes.get(&frame)) {
+            if a != b {
+                return Err(NetplayError::Desync(frame));
+            }
+            self.our_hashes.remove(&frame);
+            self.their_hashes.remove(&frame);
+        }
+        Ok(())
+    }
+
+    // Reads one message
+    fn receive(&mut self) -> Result<(), NetplayError> {
+        let mut tag = [0u8];
+        self.reader.read_exact(&mut tag)?;
+        let mut frame = [0u8; 8];
+        self.reader.read_exact(&mut frame)?;
+        let frame = u64::from_be_bytes(frame);
+        match tag[0] {
+            INPUT => {
+                let mut keys = [0u8; 2];
+                self.reader.read_exact(&mut keys)?;
+                self.theirs.insert(frame, u16::from_be_bytes(keys));
+                Ok(())
+            }
+            HASH => {
+                let mut hash = [0u8; 8];
+                self.reader.read_exact(&mut hash)?;
+                self.their_hashes.insert(frame, u64::from_be_bytes(hash));
+                self.compare(frame)
+            }
+            t => Err(NetplayError::Protocol(format!("unknown message {:02X}", t))),
+        }
+    }
+}
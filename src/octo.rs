@@ -0,0 +1,754 @@
+//! Octo cartridges, GIFs holding a program's Octo source and the options
+//! it runs with. The data is in the low two bits of each pixel of the
+//! first image, four pixels to a byte with the high bits first: a 32-bit
+//! big endian length, then that much JSON with `program` and `options`.
+//!
+//! `assemble` turns the source into a ROM. It knows the core of Octo's
+//! language: labels, `:const`, `:alias`, `:byte`, `:org`, `:call`, every
+//! CHIP-8 instruction, `if ... then`, `if ... begin ... else ... end` and
+//! `loop ... while ... again`. Macros, `:calc`, `:next`, `:unpack`, the
+//! comparison pseudo-ops and the SUPER-CHIP and XO-CHIP instructions are
+//! errors naming what wasn't understood.
+use crate::emu::{LoadStoreIncrement, Quirks};
+use crate::json;
+use crate::layout::PROGRAM_START;
+use crate::theme::Theme;
+use std::collections::HashMap;
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OctoError {
+    /// The image couldn't be read
+    Gif(&'static str),
+    /// The image has no cartridge in it, or a broken one
+    Payload(String),
+    /// Line number and what was wrong with it
+    Asm(usize, String),
+}
+
+impl fmt::Display for OctoError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Gif(e) => write!(f, "bad GIF: {}", e),
+            Self::Payload(e) => write!(f, "not an OctoCart: {}", e),
+            Self::Asm(line, e) => write!(f, "Octo source line {}: {}", line, e),
+        }
+    }
+}
+
+impl std::error::Error for OctoError {}
+
+/// The settings Octo saves with a cartridge that this emulator has a use
+/// for. The vBlank, jump, logic and VF order quirks have no counterpart
+/// here and are ignored.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Options {
+    /// Instructions per 60 Hz frame
+    pub tickrate: u32,
+    /// `shiftQuirks`, SHR and SHL shift Vx in place
+    pub shift: bool,
+    /// `loadStoreQuirks`, Fx55 and Fx65 leave I alone
+    pub load_store: bool,
+    /// `clipQuirks`, sprites are cut off at the edges instead of wrapping
+    pub clip: bool,
+    /// From `backgroundColor`, `fillColor`, `fillColor2` and `blendColor`
+    pub theme: Theme,
+}
+
+impl Default for Options {
+    /// What Octo uses for options a cartridge leaves out
+    fn default() -> Self {
+        Self {
+            tickrate: 20,
+            shift: false,
+            load_store: false,
+            clip: false,
+            theme: Theme {
+                colors: [
+                    [0x99, 0x66, 0x00],
+                    [0xFF, 0xCC, 0x00],
+                    [0xFF, 0x66, 0x00],
+                    [0x66, 0x22, 0x00],
+                ],
+            },
+        }
+    }
+}
+
+impl Options {
+    fn from_json(v: &json::Value) -> Self {
+        let mut o = Self::default();
+        let flag =
+            |key: &str, default: bool| v.get(key).and_then(json::Value::as_bool).unwrap_or(default);
+        o.shift = flag("shiftQuirks", o.shift);
+        o.load_store = flag("loadStoreQuirks", o.load_store);
+        o.clip = flag("clipQuirks", o.clip);
+        if let Some(n) = v.get("tickrate").and_then(json::Value::as_u64) {
+            o.tickrate = n.clamp(1, 100_000) as u32;
+        }
+        let keys = ["backgroundColor", "fillColor", "fillColor2", "blendColor"];
+        for (color, key) in o.theme.colors.iter_mut().zip(keys) {
+            let hex = v.get(key).and_then(json::Value::as_str).unwrap_or("");
+            // One color at a time, so a bad one keeps Octo's
+            if let Ok(t) = Theme::parse(&[hex; 4].join(",")) {
+                *color = t.colors[0];
+            }
+        }
+        o
+    }
+
+    /// `quirks` with the ones Octo has options for set from them
+    pub fn quirks(&self, quirks: Quirks) -> Quirks {
+        Quirks {
+            clip_x: self.clip,
+            clip_y: self.clip,
+            hp_shift: self.shift,
+            mem_inc: if self.load_store {
+                LoadStoreIncrement::None
+            } else {
+                LoadStoreIncrement::XPlusOne
+            },
+            ..quirks
+        }
+    }
+}
+
+/// What a cartridge holds
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cart {
+    /// Octo source, for `assemble`
+    pub program: String,
+    pub options: Options,
+}
+
+impl Cart {
+    /// Reads the cartridge hidden in a GIF
+    pub fn read(gif: &[u8]) -> Result<Self, OctoError> {
+        let pixels = first_image(gif)?;
+        let bytes: Vec<u8> = pixels
+            .chunks_exact(4)
+            .map(|p| p.iter().fold(0, |b, &p| b << 2 | (p & 3)))
+            .collect();
+        let bad = |e: &str| OctoError::Payload(e.to_string());
+        if bytes.len() < 4 {
+            return Err(bad("image is too small"));
+        }
+        let len = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as usize;
+        let body = bytes
+            .get(4..4 + len)
+            .ok_or_else(|| bad("length is past the end of the image"))?;
+        let text = std::str::from_utf8(body).map_err(|_| bad("not UTF-8"))?;
+        let v = json::parse(text).map_err(|e| OctoError::Payload(e.to_string()))?;
+        let program = v
+            .get("program")
+            .and_then(json::Value::as_str)
+            .ok_or_else(|| bad("no program"))?;
+        Ok(Self {
+            program: program.to_string(),
+            options: v.get("options").map(Options::from_json).unwrap_or_default(),
+        })
+    }
+}
+
+// Reads variable width codes LSB first
+struct Codes<'a> {
+    data: &'a [u8],
+    pos: usize,
+    acc: u32,
+    bits: u32,
+}
+
+impl Codes<'_> {
+    fn next(&mut self, size: u32) -> Option<u16> {
+        while self.bits < size {
+            self.acc |= (*self.data.get(self.pos)? as u32) << self.bits;
+            self.pos += 1;
+            self.bits += 8;
+        }
+        let code = self.acc & ((1 << size) - 1);
+        self.acc >>= size;
+        self.bits -= size;
+        Some(code as u16)
+    }
+}
+
+fn lzw_decode(min_size: u32, data: &[u8], limit: usize) -> Result<Vec<u8>, OctoError> {
+    if !(1..=11).contains(&min_size) {
+        return Err(OctoError::Gif("bad LZW code size"));
+    }
+    let clear = 1u16 << min_size;
+    let end = clear + 1;
+    // Each code as the code before it and the byte it adds
+    let mut table: Vec<(u16, u8)> = (0..clear).map(|b| (u16::MAX, b as u8)).collect();
+    table.extend([(u16::MAX, 0), (u16::MAX, 0)]);
+    let mut size = min_size + 1;
+    let mut prev: Option<u16> = None;
+    let mut codes = Codes {
+        data,
+        pos: 0,
+        acc: 0,
+        bits: 0,
+    };
+    let mut out = Vec::new();
+    let mut run = Vec::new();
+    // Streams that stop without an end code still decode
+    while let Some(code) = codes.next(size) {
+        if code == clear {
+            table.truncate(end as usize + 1);
+            size = min_size + 1;
+            prev = None;
+            continue;
+        }
+        if code == end {
+            break;
+        }
+        let known = (code as usize) < table.len() && code != clear && code != end;
+        let start = match (known, prev) {
+            (true, _) => code,
+            // The code about to be added, the previous run and its first byte
+            (false, Some(p)) if code as usize == table.len() => p,
+            _ => return Err(OctoError::Gif("corrupt image data")),
+        };
+        run.clear();
+        let mut c = start;
+        while c != u16::MAX {
+            let (prefix, byte) = table[c as usize];
+            run.push(byte);
+            c = prefix;
+        }
+        run.reverse();
+        if !known {
+            run.push(run[0]);
+        }
+        if let Some(p) = prev {
+            if table.len() < 4096 {
+                table.push((p, run[0]));
+                if table.len() == 1 << size && size < 12 {
+                    size += 1;
+                }
+            }
+        }
+        out.extend_from_slice(&run);
+        if out.len() >= limit {
+            out.truncate(limit);
+            break;
+        }
+        prev = Some(code);
+    }
+    Ok(out)
+}
+
+// Color indices of the first image, in the order they're stored
+fn first_image(gif: &[u8]) -> Result<Vec<u8>, OctoError> {
+    let short = OctoError::Gif("truncated");
+    if !gif.starts_with(b"GIF87a") && !gif.starts_with(b"GIF89a") {
+        return Err(OctoError::Gif("no GIF header"));
+    }
+    let table = |packed: u8| {
+        if packed & 0x80 != 0 {
+            3 << ((packed & 7) + 1)
+        } else {
+            0
+        }
+    };
+    let mut at = 13 + table(*gif.get(10).ok_or(short.clone())?);
+    // Sub-blocks from `at`, joined, and where they end
+    let blocks = |mut at: usize| -> Result<(Vec<u8>, usize), OctoError> {
+        let mut data = Vec::new();
+        loop {
+            let n = *gif.get(at).ok_or(short.clone())? as usize;
+            at += 1;
+            if n == 0 {
+                return Ok((data, at));
+            }
+            data.extend_from_slice(gif.get(at..at + n).ok_or(short.clone())?);
+            at += n;
+        }
+    };
+    loop {
+        match gif.get(at) {
+            Some(0x21) => at = blocks(at + 2)?.1,
+            Some(0x2C) => {
+                let d = gif.get(at + 1..at + 10).ok_or(short.clone())?;
+                let w = u16::from_le_bytes([d[4], d[5]]) as usize;
+                let h = u16::from_le_bytes([d[6], d[7]]) as usize;
+                at += 10 + table(d[8]);
+                let min_size = *gif.get(at).ok_or(short.clone())? as u32;
+                let (data, _) = blocks(at + 1)?;
+                return lzw_decode(min_size, &data, w * h);
+            }
+            Some(0x3B) => return Err(OctoError::Gif("no image")),
+            Some(_) => return Err(OctoError::Gif("unknown block")),
+            None => return Err(short),
+        }
+    }
+}
+
+// A byte, or a negative number that can be one
+fn fits_byte(n: u16) -> bool {
+    !(0x100..0xFF80).contains(&n)
+}
+
+// Words that are instructions of the machines this doesn't emulate
+const UNSUPPORTED: [&str; 14] = [
+    "hires",
+    "lores",
+    "scroll-down",
+    "scroll-up",
+    "scroll-left",
+    "scroll-right",
+    "exit",
+    "bighex",
+    "saveflags",
+    "loadflags",
+    "plane",
+    "audio",
+    "pitch",
+    ":unpack",
+];
+
+// A loop or if waiting for its end
+enum Open {
+    // Start, and the jumps out of it from `while`
+    Loop(u16, Vec<usize>),
+    // The jump over the body or the else part, to fill in at the end
+    If(usize),
+    Else(usize),
+}
+
+struct Asm<'a> {
+    tokens: Vec<(usize, &'a str)>,
+    pos: usize,
+    line: usize,
+    rom: Vec<u8>,
+    labels: HashMap<&'a str, u16>,
+    consts: HashMap<&'a str, u16>,
+    aliases: HashMap<&'a str, u8>,
+    // Addresses to fill into instructions once every label is placed
+    fixups: Vec<(usize, &'a str, usize)>,
+    open: Vec<Open>,
+}
+
+/// Assembles Octo source into a ROM loaded at 0x200, starting with a
+/// jump to the `main` label
+///
+/// ```
+/// use chip8::emu::disassemble;
+/// use chip8::octo::{assemble, OctoError};
+///
+/// let src = "
+///     :alias x v1
+///     : main
+///         x := 3
+///         i := dot
+///         loop
+///             sprite x x 1
+///             x += 2
+///             if x == 9 then return
+///         again
+///     : dot 0x80
+/// ";
+/// let rom = assemble(src).unwrap();
+/// let ops: Vec<String> = rom[..16]
+///     .chunks(2)
+///     .map(|w| disassemble(u16::from_be_bytes([w[0], w[1]])))
+///     .collect();
+/// assert_eq!(
+///     ops,
+///     [
+///         "JP 202", "LD V1, 03", "LD I, 210", "DRW V1, V1, 1", "ADD V1, 02", "SNE V1, 09",
+///         "RET", "JP 206",
+///     ]
+/// );
+/// assert_eq!(rom[16..], [0x80]);
+///
+/// let err = |src| assemble(src).unwrap_err().to_string();
+/// assert_eq!(err(": main\n  hires"), "Octo source line 2: hires isn't supported");
+/// assert_eq!(err(": main\n  jump nowhere"), "Octo source line 2: nowhere isn't defined");
+/// assert_eq!(err("v0 := 1"), "Octo source line 1: there's no main label");
+/// assert!(matches!(assemble(": main loop"), Err(OctoError::Asm(1, _))));
+/// ```
+pub fn assemble(src: &str) -> Result<Vec<u8>, OctoError> {
+    let tokens = src
+        .lines()
+        .enumerate()
+        .flat_map(|(n, line)| {
+            let code = line.split('#').next().unwrap_or("");
+            code.split_whitespace().map(move |t| (n + 1, t))
+        })
+        .collect();
+    let mut asm = Asm {
+        tokens,
+        pos: 0,
+        line: 1,
+        rom: Vec::new(),
+        labels: HashMap::new(),
+        consts: HashMap::new(),
+        aliases: HashMap::new(),
+        fixups: Vec::new(),
+        open: Vec::new(),
+    };
+    asm.addr_op(0x1000, "main")?;
+    while asm.pos < asm.tokens.len() {
+        asm.statement()?;
+    }
+    if let Some(open) = asm.open.last() {
+        let what = match open {
+            Open::Loop(..) => "loop without again",
+            _ => "if without end",
+        };
+        return Err(asm.err(what));
+    }
+    if !asm.labels.contains_key("main") {
+        asm.line = 1;
+        return Err(asm.err("there's no main label"));
+    }
+    for (at, name, line) in std::mem::take(&mut asm.fixups) {
+        asm.line = line;
+        let addr = *asm
+            .labels
+            .get(name)
+            .ok_or_else(|| asm.err(&format!("{} isn't defined", name)))?;
+        asm.rom[at] |= (addr >> 8) as u8;
+        asm.rom[at + 1] = addr as u8;
+    }
+    Ok(asm.rom)
+}
+
+impl<'a> Asm<'a> {
+    fn err(&self, e: &str) -> OctoError {
+        OctoError::Asm(self.line, e.to_string())
+    }
+
+    fn next(&mut self) -> Result<&'a str, OctoError> {
+        let &(line, t) = self
+            .tokens
+            .get(self.pos)
+            .ok_or_else(|| self.err("source ends in the middle of a statement"))?;
+        self.pos += 1;
+        self.line = line;
+        Ok(t)
+    }
+
+    fn peek(&self) -> Option<&'a str> {
+        self.tokens.get(self.pos).map(|&(_, t)| t)
+    }
+
+    fn expect(&mut self, word: &str) -> Result<(), OctoError> {
+        match self.next()? {
+            t if t == word => Ok(()),
+            t => Err(self.err(&format!("expected {}, found {}", word, t))),
+        }
+    }
+
+    fn here(&self) -> u16 {
+        PROGRAM_START + self.rom.len() as u16
+    }
+
+    fn emit(&mut self, op: u16) -> Result<usize, OctoError> {
+        if self.here() as usize + 2 > 0x1000 {
+            return Err(self.err("program is past the end of 4 KB"));
+        }
+        self.rom.extend_from_slice(&op.to_be_bytes());
+        Ok(self.rom.len() - 2)
+    }
+
+    fn reg(&mut self) -> Result<u8, OctoError> {
+        let t = self.next()?;
+        self.as_reg(t)
+            .ok_or_else(|| self.err(&format!("{} isn't a register", t)))
+    }
+
+    fn as_reg(&self, t: &str) -> Option<u8> {
+        if let Some(&r) = self.aliases.get(t) {
+            return Some(r);
+        }
+        let digit = t.strip_prefix(['v', 'V'])?;
+        (digit.len() == 1).then(|| u8::from_str_radix(digit, 16).ok())?
+    }
+
+    fn as_number(&self, t: &str) -> Option<u16> {
+        if let Some(&v) = self.consts.get(t).or_else(|| self.labels.get(t)) {
+            return Some(v);
+        }
+        let (neg, t) = match t.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, t),
+        };
+        let n = if let Some(hex) = t.strip_prefix("0x") {
+            u16::from_str_radix(hex, 16).ok()?
+        } else if let Some(bin) = t.strip_prefix("0b") {
+            u16::from_str_radix(bin, 2).ok()?
+        } else {
+            t.parse().ok()?
+        };
+        Some(if neg { n.wrapping_neg() } else { n })
+    }
+
+    // A byte, negative numbers being two's complement
+    fn byte(&mut self) -> Result<u8, OctoError> {
+        let t = self.next()?;
+        match self.as_number(t) {
+            Some(n) if fits_byte(n) => Ok(n as u8),
+            Some(_) => Err(self.err(&format!("{} doesn't fit in a byte", t))),
+            None => Err(self.err(&format!("{} isn't a number", t))),
+        }
+    }
+
+    // An instruction taking an address, which can be a label placed later
+    fn addr_op(&mut self, op: u16, t: &'a str) -> Result<(), OctoError> {
+        match self.as_number(t) {
+            Some(n) if n < 0x1000 => {
+                self.emit(op | n)?;
+            }
+            Some(_) => return Err(self.err(&format!("{} is past 0xFFF", t))),
+            None => {
+                let at = self.emit(op)?;
+                self.fixups.push((at, t, self.line));
+            }
+        }
+        Ok(())
+    }
+
+    // The skip for a condition, one that skips when it's true if `when`
+    // is set, otherwise one that skips when it's false
+    fn condition(&mut self, when: bool) -> Result<(), OctoError> {
+        let x = self.reg()? as u16;
+        let op = self.next()?;
+        let (eq, ne) = match op {
+            "key" => (0xE09E, 0xE0A1),
+            "-key" => (0xE0A1, 0xE09E),
+            "==" | "!=" => {
+                let rhs = self.next()?;
+                let (eq, ne) = match self.as_reg(rhs) {
+                    Some(y) => (0x5000 | (y as u16) << 4, 0x9000 | (y as u16) << 4),
+                    None => {
+                        self.pos -= 1;
+                        let b = self.byte()? as u16;
+                        (0x3000 | b, 0x4000 | b)
+                    }
+                };
+                if op == "==" {
+                    (eq, ne)
+                } else {
+                    (ne, eq)
+                }
+            }
+            _ => return Err(self.err(&format!("{} isn't supported in a condition", op))),
+        };
+        self.emit(if when { eq } else { ne } | x << 8)?;
+        Ok(())
+    }
+
+    fn patch(&mut self, at: usize) {
+        let to = self.here();
+        self.rom[at] |= (to >> 8) as u8;
+        self.rom[at + 1] = to as u8;
+    }
+
+    fn statement(&mut self) -> Result<(), OctoError> {
+        let t = self.next()?;
+        match t {
+            ":" => {
+                let name = self.next()?;
+                if self.labels.insert(name, self.here()).is_some() {
+                    return Err(self.err(&format!("{} is defined twice", name)));
+                }
+            }
+            ":const" => {
+                let name = self.next()?;
+                let v = self.next()?;
+                let n = self
+                    .as_number(v)
+                    .ok_or_else(|| self.err(&format!("{} isn't a number", v)))?;
+                self.consts.insert(name, n);
+            }
+            ":alias" => {
+                let name = self.next()?;
+                let r = self.reg()?;
+                self.aliases.insert(name, r);
+            }
+            ":byte" => {
+                let b = self.byte()?;
+                self.rom.push(b);
+            }
+            ":org" => {
+                let v = self.next()?;
+                match self.as_number(v) {
+                    Some(a) if a >= self.here() && a < 0x1000 => {
+                        self.rom.resize((a - PROGRAM_START) as usize, 0)
+                    }
+                    _ => return Err(self.err(&format!("can't move to {}", v))),
+                }
+            }
+            ":call" => {
+                let to = self.next()?;
+                self.addr_op(0x2000, to)?;
+            }
+            ":breakpoint" => {
+                self.next()?;
+            }
+            ":monitor" => {
+                self.next()?;
+                self.next()?;
+            }
+            "clear" => {
+                self.emit(0x00E0)?;
+            }
+            "return" | ";" => {
+                self.emit(0x00EE)?;
+            }
+            "jump" | "jump0" | "native" => {
+                let op = match t {
+                    "jump" => 0x1000,
+                    "jump0" => 0xB000,
+                    _ => 0x0000,
+                };
+                let to = self.next()?;
+                self.addr_op(op, to)?;
+            }
+            "sprite" => {
+                let (x, y) = (self.reg()? as u16, self.reg()? as u16);
+                let n = self.byte()? as u16;
+                if n > 15 {
+                    return Err(self.err("sprites are at most 15 rows here"));
+                }
+                self.emit(0xD000 | x << 8 | y << 4 | n)?;
+            }
+            "bcd" | "save" | "load" => {
+                let x = self.reg()? as u16;
+                let low = match t {
+                    "bcd" => 0x33,
+                    "save" => 0x55,
+                    _ => 0x65,
+                };
+                self.emit(0xF000 | x << 8 | low)?;
+            }
+            "delay" | "buzzer" => {
+                self.expect(":=")?;
+                let x = self.reg()? as u16;
+                self.emit(0xF000 | x << 8 | if t == "delay" { 0x15 } else { 0x18 })?;
+            }
+            "i" => match self.next()? {
+                ":=" if self.peek() == Some("hex") => {
+                    self.next()?;
+                    let x = self.reg()? as u16;
+                    self.emit(0xF029 | x << 8)?;
+                }
+                ":=" => {
+                    let to = self.next()?;
+                    self.addr_op(0xA000, to)?;
+                }
+                "+=" => {
+                    let x = self.reg()? as u16;
+                    self.emit(0xF01E | x << 8)?;
+                }
+                op => return Err(self.err(&format!("i {} isn't supported", op))),
+            },
+            "if" => {
+                let at = self.pos;
+                // Find out which kind of if before emitting anything
+                let mut end = at;
+                while !matches!(
+                    self.tokens.get(end).map(|&(_, t)| t),
+                    Some("then" | "begin") | None
+                ) {
+                    end += 1;
+                }
+                let begin = self.tokens.get(end).map(|&(_, t)| t) == Some("begin");
+                self.condition(begin)?;
+                if self.pos != end {
+                    return Err(self.err("expected then or begin after the condition"));
+                }
+                self.pos += 1;
+                if begin {
+                    let at = self.emit(0x1000)?;
+                    self.open.push(Open::If(at));
+                }
+            }
+            "else" => match self.open.pop() {
+                Some(Open::If(at)) => {
+                    let jump = self.emit(0x1000)?;
+                    self.patch(at);
+                    self.open.push(Open::Else(jump));
+                }
+                _ => return Err(self.err("else without if ... begin")),
+            },
+            "end" => match self.open.pop() {
+                Some(Open::If(at)) | Some(Open::Else(at)) => self.patch(at),
+                _ => return Err(self.err("end without if ... begin")),
+            },
+            "loop" => self.open.push(Open::Loop(self.here(), Vec::new())),
+            "while" => {
+                self.condition(true)?;
+                let at = self.emit(0x1000)?;
+                match self.open.iter_mut().rev().find_map(|o| match o {
+                    Open::Loop(_, whiles) => Some(whiles),
+                    _ => None,
+                }) {
+                    Some(whiles) => whiles.push(at),
+                    None => return Err(self.err("while outside a loop")),
+                }
+            }
+            "again" => match self.open.pop() {
+                Some(Open::Loop(start, whiles)) => {
+                    self.emit(0x1000 | start)?;
+                    for at in whiles {
+                        self.patch(at);
+                    }
+                }
+                _ => return Err(self.err("again without loop")),
+            },
+            _ if self.as_reg(t).is_some() => self.register(t)?,
+            _ if UNSUPPORTED.contains(&t) || t.starts_with(':') => {
+                return Err(self.err(&format!("{} isn't supported", t)))
+            }
+            _ if t == "then" || t == "begin" || t == "key" => {
+                return Err(self.err(&format!("{} out of place", t)))
+            }
+            _ => match self.as_number(t) {
+                Some(n) if !self.labels.contains_key(t) && fits_byte(n) => self.rom.push(n as u8),
+                Some(_) if !self.labels.contains_key(t) => {
+                    return Err(self.err(&format!("{} doesn't fit in a byte", t)))
+                }
+                // Naming a label calls it
+                _ => self.addr_op(0x2000, t)?,
+            },
+        }
+        Ok(())
+    }
+
+    // Statements starting with a register
+    fn register(&mut self, t: &str) -> Result<(), OctoError> {
+        let x = self.as_reg(t).unwrap_or(0) as u16;
+        let op = self.next()?;
+        let rhs = self.next()?;
+        let xy = |low: u16, y: u8| 0x8000 | x << 8 | (y as u16) << 4 | low;
+        let ins = match (op, self.as_reg(rhs)) {
+            (":=", Some(y)) => xy(0, y),
+            ("|=", Some(y)) => xy(1, y),
+            ("&=", Some(y)) => xy(2, y),
+            ("^=", Some(y)) => xy(3, y),
+            ("+=", Some(y)) => xy(4, y),
+            ("-=", Some(y)) => xy(5, y),
+            (">>=", Some(y)) => xy(6, y),
+            ("=-", Some(y)) => xy(7, y),
+            ("<<=", Some(y)) => xy(0xE, y),
+            (":=", None) if rhs == "delay" => 0xF007 | x << 8,
+            (":=", None) if rhs == "key" => 0xF00A | x << 8,
+            (":=", None) if rhs == "random" => 0xC000 | x << 8 | self.byte()? as u16,
+            (":=", None) | ("+=", None) | ("-=", None) => {
+                self.pos -= 1;
+                let b = self.byte()?;
+                match op {
+                    ":=" => 0x6000 | x << 8 | b as u16,
+                    "+=" => 0x7000 | x << 8 | b as u16,
+                    _ => 0x7000 | x << 8 | b.wrapping_neg() as u16,
+                }
+            }
+            _ => return Err(self.err(&format!("{} {} {} isn't supported", t, op, rhs))),
+        };
+        self.emit(ins)?;
+        Ok(())
+    }
+}
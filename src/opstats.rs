@@ -0,0 +1,108 @@
+//! Which instructions a ROM ran, for `--opcode-stats`. Words that aren't
+//! instructions usually mean the ROM was written for SUPER-CHIP or
+//! XO-CHIP.
+//!
+//! ```
+//! use chip8::emu::Chip8;
+//! use chip8::opstats::OpcodeStats;
+//! use std::cell::RefCell;
+//! use std::rc::Rc;
+//!
+//! // LD V0, 03 then ADD V0, FF until V0 is 0, then a word that isn't
+//! // an instruction
+//! let rom = [0x60, 0x03, 0x70, 0xFF, 0x30, 0x00, 0x12, 0x02, 0x50, 0x01];
+//! let mut c8 = Chip8::builder().rom(&rom).build().unwrap();
+//! let stats = Rc::new(RefCell::new(OpcodeStats::new()));
+//! let s = stats.clone();
+//! c8.set_post_exec_hook(Box::new(move |_, pc, op, res| s.borrow_mut().record_step(pc, op, res)));
+//! while c8.step().is_ok() {}
+//! let stats = stats.take();
+//! let counts: Vec<_> = stats.counts().collect();
+//! assert_eq!(
+//!     counts,
+//!     [("ADD Vx, byte", 3), ("SE Vx, byte", 3), ("JP addr", 2), ("LD Vx, byte", 1)]
+//! );
+//! assert_eq!(stats.unknown(), [(0x208, 0x5001)]);
+//! ```
+use crate::emu::{self, Fault, StepOutcome};
+use crate::json;
+use std::collections::BTreeMap;
+use std::fmt;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct OpcodeStats {
+    counts: BTreeMap<&'static str, u64>,
+    // (PC, word) in the order they were first seen
+    unknown: Vec<(u16, u16)>,
+}
+
+impl OpcodeStats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Counts `op`, about to be run from `pc`
+    pub fn record(&mut self, pc: u16, op: u16) {
+        match emu::mnemonic(op) {
+            Some(m) => *self.counts.entry(m).or_insert(0) += 1,
+            None => {
+                if !self.unknown.contains(&(pc, op)) {
+                    self.unknown.push((pc, op));
+                }
+            }
+        }
+    }
+
+    /// Counts a step as a post-exec hook is shown it: whatever ran, and
+    /// words that faulted for not being instructions
+    pub fn record_step(&mut self, pc: u16, op: u16, res: &Result<StepOutcome, Fault>) {
+        if matches!(res, Ok(_) | Err(Fault::UnknownOpcode { .. })) {
+            self.record(pc, op);
+        }
+    }
+
+    /// Instructions and how often they ran, most used first
+    pub fn counts(&self) -> impl Iterator<Item = (&'static str, u64)> {
+        let mut counts: Vec<_> = self.counts.iter().map(|(&m, &n)| (m, n)).collect();
+        counts.sort_by_key(|&(_, n)| std::cmp::Reverse(n));
+        counts.into_iter()
+    }
+
+    /// (PC, word) of every word run that isn't an instruction
+    pub fn unknown(&self) -> &[(u16, u16)] {
+        &self.unknown
+    }
+
+    pub fn to_json(&self) -> String {
+        let mut counts = json::Object::new();
+        for (m, n) in self.counts() {
+            counts = counts.num(m, n);
+        }
+        let unknown = self.unknown.iter().map(|(pc, op)| {
+            json::Object::new()
+                .str("pc", &format!("{:03X}", pc))
+                .str("opcode", &format!("{:04X}", op))
+                .to_string()
+        });
+        json::Object::new()
+            .raw("counts", counts.to_string())
+            .raw("unknown", json::array(unknown))
+            .to_string()
+    }
+}
+
+impl fmt::Display for OpcodeStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{:>10}  instruction", "count")?;
+        for (m, n) in self.counts() {
+            writeln!(f, "{:>10}  {}", n, m)?;
+        }
+        if !self.unknown.is_empty() {
+            writeln!(f, "Not instructions:")?;
+            for (pc, op) in &self.unknown {
+                writeln!(f, "{:>10}  {:04X} at {:03X}", "", op, pc)?;
+            }
+        }
+        Ok(())
+    }
+}
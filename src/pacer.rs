@@ -0,0 +1,143 @@
+//! Keeps the terminal to one frame every `FRAME_MS`, and when it falls
+//! behind skips drawing frames rather than running them late. Skipped
+//! frames are still run, and the timers tick off the step clock, so they
+//! go down once per frame whether it's drawn or not.
+use std::time::{Duration, Instant};
+
+/// Frames in a row left undrawn unless `--max-frameskip` says otherwise
+pub const DEFAULT_MAX_SKIP: u32 = 5;
+
+/// Whether to draw a frame that took `ran` to run and finished `behind`
+/// when it was due, after `skipped` frames in a row weren't drawn
+///
+/// ```
+/// use chip8::pacer::should_draw;
+/// use std::time::Duration;
+///
+/// let ms = Duration::from_millis;
+/// let frame = ms(50);
+/// // On time, or less than a frame late
+/// assert!(should_draw(ms(5), ms(0), frame, 0, 5));
+/// assert!(should_draw(ms(5), ms(50), frame, 0, 5));
+/// // More than a frame late is skipped, up to the limit
+/// assert!(!should_draw(ms(5), ms(51), frame, 0, 5));
+/// assert!(!should_draw(ms(5), ms(500), frame, 4, 5));
+/// assert!(should_draw(ms(5), ms(500), frame, 5, 5));
+/// assert!(should_draw(ms(5), ms(500), frame, 0, 0));
+/// // Running alone is slower than real time, skipping won't catch up
+/// assert!(should_draw(ms(60), ms(500), frame, 0, 5));
+/// ```
+pub fn should_draw(
+    ran: Duration,
+    behind: Duration,
+    frame: Duration,
+    skipped: u32,
+    max_skip: u32,
+) -> bool {
+    ran > frame || behind <= frame || skipped >= max_skip
+}
+
+/// When frames are due, and which get drawn. Each frame calls `frame_done`
+/// once it has run and `next` once it's drawn or skipped.
+///
+/// ```
+/// use chip8::pacer::Pacer;
+/// use std::time::{Duration, Instant};
+///
+/// let ms = Duration::from_millis;
+/// // Which of 12 frames are drawn, each taking `run` to run and `draw` to
+/// // draw, and the time it took
+/// let frames = |run: u64, draw: u64, fast: bool| {
+///     let start = Instant::now();
+///     let mut p = Pacer::new(ms(50), 5, start);
+///     let mut t = start;
+///     let mut drawn = String::new();
+///     for _ in 0..12 {
+///         t += ms(run);
+///         let d = p.frame_done(t, fast);
+///         drawn.push(if d { '#' } else { '.' });
+///         if d {
+///             t += ms(draw);
+///         }
+///         t += p.next(t);
+///     }
+///     (drawn, (t - start).as_millis())
+/// };
+/// // Plenty of time, everything is drawn in real time
+/// assert_eq!(frames(5, 5, false), ("############".to_string(), 600));
+/// // Drawing takes 3 frames, so 2 frames are skipped after each one and
+/// // the game keeps up, finishing only the last draw late
+/// assert_eq!(frames(0, 150, false), ("##..#..#..#.".to_string(), 750));
+/// // Running is the slow part, skipping won't help so every frame is drawn
+/// assert_eq!(frames(100, 0, false), ("############".to_string(), 1200));
+/// // So slow to draw the limit is hit, which drops what's owed
+/// assert_eq!(frames(0, 400, false), ("#.....#.....".to_string(), 800));
+/// // Fast forward draws a frame's worth of time apart, however many run
+/// assert_eq!(frames(10, 0, true), ("#....#....#.".to_string(), 120));
+/// ```
+#[derive(Debug, Clone)]
+pub struct Pacer {
+    frame: Duration,
+    max_skip: u32,
+    // When the frame being run started, and should be done by
+    started: Instant,
+    due: Instant,
+    last_draw: Option<Instant>,
+    skipped: u32,
+    fast: bool,
+}
+
+impl Pacer {
+    pub fn new(frame: Duration, max_skip: u32, now: Instant) -> Self {
+        Self {
+            frame,
+            max_skip,
+            started: now,
+            due: now + frame,
+            last_draw: None,
+            skipped: 0,
+            fast: false,
+        }
+    }
+
+    /// Whether to draw the frame that just ran. With `fast` nothing is
+    /// due, frames are drawn a frame's worth of time apart instead.
+    pub fn frame_done(&mut self, now: Instant, fast: bool) -> bool {
+        self.fast = fast;
+        let behind = now.saturating_duration_since(self.due);
+        let draw = if fast {
+            self.last_draw
+                .is_none_or(|at| now.saturating_duration_since(at) >= self.frame)
+        } else {
+            should_draw(
+                now.saturating_duration_since(self.started),
+                behind,
+                self.frame,
+                self.skipped,
+                self.max_skip,
+            )
+        };
+        if draw {
+            self.skipped = 0;
+            self.last_draw = Some(now);
+            // Drawn while behind, what's owed can't be caught up on
+            if behind > self.frame {
+                self.due = now;
+            }
+        } else {
+            self.skipped += 1;
+        }
+        draw
+    }
+
+    /// How long to wait before running the next frame
+    pub fn next(&mut self, now: Instant) -> Duration {
+        if self.fast {
+            self.due = now;
+        }
+        let wait = self.due.saturating_duration_since(now);
+        self.started = now + wait;
+        self.due += self.frame;
+        wait
+    }
+}
@@ -0,0 +1,41 @@
+use crate::framebuffer::Framebuffer;
+use crate::layout::{HIRES_HEIGHT, HIRES_WIDTH, LORES_WIDTH};
+
+pub const WIDTH: usize = HIRES_WIDTH;
+pub const HEIGHT: usize = HIRES_HEIGHT;
+
+/// Per-pixel brightness that fades out over a few frames after a pixel is
+/// turned off, hiding the flicker of sprites being erased and redrawn
+pub struct Phosphor {
+    // 0 is dark, 255 is lit
+    pub levels: [u8; WIDTH * HEIGHT],
+    step: u8,
+}
+
+impl Phosphor {
+    /// Strength 0 disables decay, otherwise unlit pixels stay visible
+    /// (dimming) for `strength` frames
+    pub fn new(strength: u8) -> Self {
+        Self {
+            levels: [0; WIDTH * HEIGHT],
+            step: 255 / (strength.min(5) + 1),
+        }
+    }
+
+    pub fn update(&mut self, bits: &[u64; 32]) {
+        for y in 0..bits.len() {
+            for x in 0..LORES_WIDTH {
+                let l = &mut self.levels[y * WIDTH + x];
+                *l = if bits.get(x, y) != 0 {
+                    255
+                } else {
+                    l.saturating_sub(self.step)
+                };
+            }
+        }
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> u8 {
+        self.levels[y * WIDTH + x]
+    }
+}
@@ -0,0 +1,237 @@
+//! Settings remembered for each ROM, in `prefs.toml` next to its save
+//! states. They're saved when changed while playing and read back the
+//! next time the ROM runs, where the command line doesn't give them. The
+//! file is a few lines of TOML, and keys it doesn't know are left alone:
+//!
+//! ```text
+//! palette = "#000,#0f0,#f00,#ff0"   # as --palette
+//! volume = 0.5                     # as --volume
+//! muted = true                     # toggled with the mute key
+//! slot = 3                         # last slot saved to or loaded
+//! layout = "azerty"                # as --layout
+//! ```
+use crate::keypad::{Keymap, LAYOUTS};
+use crate::slots::SLOTS;
+use crate::theme::Theme;
+use std::fmt;
+use std::io::{self, Write};
+use std::path::Path;
+
+/// Name of the file in a ROM's state directory
+pub const FILE: &str = "prefs.toml";
+
+#[derive(Debug)]
+pub enum PrefsError {
+    Io(io::Error),
+    /// 1-based line, and what's wrong with it
+    Line(usize, String),
+}
+
+impl fmt::Display for PrefsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "{}", e),
+            Self::Line(n, msg) => write!(f, "line {}: {}", n, msg),
+        }
+    }
+}
+
+impl std::error::Error for PrefsError {}
+
+impl From<io::Error> for PrefsError {
+    fn from(e: io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+/// Settings for one ROM, `None` where not set
+///
+/// ```
+/// use chip8::prefs::Prefs;
+///
+/// let saved = Prefs::parse(
+///     "# saved\npalette = \"#000,#0f0,#f00,#ff0\" # green\nmuted = true\nslot = 3 # F3\nzoom = 2\n",
+/// )
+/// .unwrap();
+/// assert_eq!(saved.slot, Some(2));
+/// // The command line wins, then what was saved, then the defaults
+/// let given = Prefs { muted: Some(false), volume: Some(0.25), ..Prefs::default() };
+/// let prefs = given.or(saved.clone());
+/// assert_eq!(prefs.palette.as_deref(), Some("#000,#0f0,#f00,#ff0"));
+/// assert_eq!((prefs.volume, prefs.muted, prefs.slot), (Some(0.25), Some(false), Some(2)));
+/// assert_eq!(Prefs::default().or(Prefs::default()).volume.unwrap_or(1.0), 1.0);
+///
+/// // Written out it reads back the same
+/// assert_eq!(Prefs::parse(&prefs.to_string()).unwrap(), prefs);
+///
+/// let err = |src| Prefs::parse(src).unwrap_err().to_string();
+/// assert_eq!(err("volume = 2"), "line 1: volume must be from 0 to 1");
+/// assert_eq!(err("\nslot = 11"), "line 2: slot must be from 1 to 10");
+/// assert_eq!(err("muted"), "line 1: expected key = value");
+/// assert_eq!(err("palette = \"#000\" 1"), "line 1: expected a comment after the string");
+/// assert_eq!(err("palette = \"#000\""), "line 1: a palette is 4 colors, not 1");
+/// assert_eq!(err("layout = \"bepo\""), "line 1: layout must be one of qwerty, azerty, qwertz, dvorak, colemak");
+/// assert_eq!(Prefs::parse("layout = \"dvorak\"").unwrap().layout.as_deref(), Some("dvorak"));
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Prefs {
+    pub palette: Option<String>,
+    pub volume: Option<f32>,
+    pub muted: Option<bool>,
+    /// 0-based, like `Slot::Numbered`
+    pub slot: Option<u8>,
+    /// One of `keypad::LAYOUTS`
+    pub layout: Option<String>,
+}
+
+impl Prefs {
+    pub fn parse(src: &str) -> Result<Self, PrefsError> {
+        let mut p = Self::default();
+        for (n, line) in src.lines().enumerate() {
+            let err = |msg: String| PrefsError::Line(n + 1, msg);
+            if comment(line) {
+                continue;
+            }
+            let (key, value) = match line.split_once('=') {
+                Some((k, v)) => (k.trim(), v.trim()),
+                None => return Err(err("expected key = value".to_string())),
+            };
+            // A # starts a comment, except inside a string
+            let value = match value.strip_prefix('"').and_then(|v| v.split_once('"')) {
+                Some((s, rest)) if comment(rest) => &value[..s.len() + 2],
+                Some(_) => return Err(err("expected a comment after the string".to_string())),
+                None => value.split('#').next().unwrap_or("").trim(),
+            };
+            match key {
+                "palette" => {
+                    let s =
+                        string(value).ok_or_else(|| err("palette must be a string".to_string()))?;
+                    Theme::parse(s).map_err(|e| err(e.to_string()))?;
+                    p.palette = Some(s.to_string());
+                }
+                "volume" => match value.parse::<f32>() {
+                    Ok(v) if (0.0..=1.0).contains(&v) => p.volume = Some(v),
+                    _ => return Err(err("volume must be from 0 to 1".to_string())),
+                },
+                "muted" => match value {
+                    "true" => p.muted = Some(true),
+                    "false" => p.muted = Some(false),
+                    _ => return Err(err("muted must be true or false".to_string())),
+                },
+                "slot" => match value.parse::<u8>() {
+                    Ok(s) if (1..=SLOTS).contains(&s) => p.slot = Some(s - 1),
+                    _ => return Err(err(format!("slot must be from 1 to {}", SLOTS))),
+                },
+                "layout" => match string(value).filter(|s| Keymap::preset(s).is_some()) {
+                    Some(s) => p.layout = Some(s.to_string()),
+                    None => {
+                        let names: Vec<&str> = LAYOUTS.iter().map(|(n, _)| *n).collect();
+                        return Err(err(format!("layout must be one of {}", names.join(", "))));
+                    }
+                },
+                _ => {}
+            }
+        }
+        Ok(p)
+    }
+
+    /// Settings in the file at `path`, none if there isn't one
+    pub fn load(path: &Path) -> Result<Self, PrefsError> {
+        match std::fs::read_to_string(path) {
+            Ok(src) => Self::parse(&src),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// These, with any not set taken from `other`
+    pub fn or(self, other: Self) -> Self {
+        Self {
+            palette: self.palette.or(other.palette),
+            volume: self.volume.or(other.volume),
+            muted: self.muted.or(other.muted),
+            slot: self.slot.or(other.slot),
+            layout: self.layout.or(other.layout),
+        }
+    }
+}
+
+impl fmt::Display for Prefs {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(p) = &self.palette {
+            writeln!(f, "palette = \"{}\"", p)?;
+        }
+        if let Some(v) = self.volume {
+            writeln!(f, "volume = {}", v)?;
+        }
+        if let Some(m) = self.muted {
+            writeln!(f, "muted = {}", m)?;
+        }
+        if let Some(s) = self.slot {
+            writeln!(f, "slot = {}", s + 1)?;
+        }
+        if let Some(l) = &self.layout {
+            writeln!(f, "layout = \"{}\"", l)?;
+        }
+        Ok(())
+    }
+}
+
+// Whether there's nothing but maybe a comment
+fn comment(s: &str) -> bool {
+    let s = s.trim();
+    s.is_empty() || s.starts_with('#')
+}
+
+// The inside of a "quoted" string without escapes
+fn string(value: &str) -> Option<&str> {
+    let s = value.strip_prefix('"')?.strip_suffix('"')?;
+    if s.contains('"') || s.contains('\\') {
+        None
+    } else {
+        Some(s)
+    }
+}
+
+/// Changes the settings in the file at `path`. It's read again first, so
+/// other settings changed by another copy of the emulator are kept, and
+/// written to a temporary file named after this process before being
+/// renamed over it, so a reader only ever sees a whole file. One that
+/// can't be read is started over.
+///
+/// ```
+/// use chip8::prefs::{self, Prefs};
+///
+/// let dir = std::env::temp_dir().join(format!("chip8-prefs-{}", std::process::id()));
+/// let path = dir.join(prefs::FILE);
+/// assert_eq!(Prefs::load(&path).unwrap(), Prefs::default());
+///
+/// // Two copies each change their own setting
+/// prefs::update(&path, |p| p.muted = Some(true)).unwrap();
+/// prefs::update(&path, |p| p.slot = Some(4)).unwrap();
+/// let p = Prefs::load(&path).unwrap();
+/// assert_eq!((p.muted, p.slot), (Some(true), Some(4)));
+///
+/// // Nothing left behind, and a broken file is replaced
+/// assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+/// std::fs::write(&path, "slot = 99").unwrap();
+/// prefs::update(&path, |p| p.volume = Some(0.5)).unwrap();
+/// assert_eq!(std::fs::read_to_string(&path).unwrap(), "volume = 0.5\n");
+/// std::fs::remove_dir_all(&dir).unwrap();
+/// ```
+pub fn update(path: &Path, change: impl FnOnce(&mut Prefs)) -> io::Result<()> {
+    let mut p = match Prefs::load(path) {
+        Err(PrefsError::Io(e)) => return Err(e),
+        r => r.unwrap_or_default(),
+    };
+    change(&mut p);
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let tmp = path.with_extension(format!("{}.tmp", std::process::id()));
+    let mut f = std::fs::File::create(&tmp)?;
+    f.write_all(p.to_string().as_bytes())?;
+    f.sync_all()?;
+    drop(f);
+    std::fs::rename(&tmp, path)
+}
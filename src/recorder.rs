@@ -0,0 +1,202 @@
+use crate::layout::{LORES_HEIGHT, LORES_WIDTH};
+use crate::screenshot;
+use crate::theme::Theme;
+use std::collections::HashMap;
+use std::io::{self, BufWriter, Write};
+
+// Packs variable width codes LSB first into 255 byte sub-blocks
+struct BitWriter {
+    out: Vec<u8>,
+    acc: u32,
+    bits: u32,
+}
+
+impl BitWriter {
+    fn push(&mut self, code: u16, size: u32) {
+        self.acc |= (code as u32) << self.bits;
+        self.bits += size;
+        while self.bits >= 8 {
+            self.out.push(self.acc as u8);
+            self.acc >>= 8;
+            self.bits -= 8;
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.bits > 0 {
+            self.out.push(self.acc as u8);
+        }
+        self.out
+    }
+}
+
+fn lzw(min_size: u32, data: &[u8]) -> Vec<u8> {
+    let clear = 1u16 << min_size;
+    let end = clear + 1;
+    let mut w = BitWriter {
+        out: Vec::new(),
+        acc: 0,
+        bits: 0,
+    };
+    let mut dict: HashMap<(u16, u8), u16> = HashMap::new();
+    let mut size = min_size + 1;
+    let mut next = end + 1;
+    w.push(clear, size);
+    let mut prefix: Option<u16> = None;
+    for &b in data {
+        let p = match prefix {
+            None => {
+                prefix = Some(b as u16);
+                continue;
+            }
+            Some(p) => p,
+        };
+        if let Some(&code) = dict.get(&(p, b)) {
+            prefix = Some(code);
+            continue;
+        }
+        w.push(p, size);
+        if next == 4096 {
+            w.push(clear, size);
+            dict.clear();
+            size = min_size + 1;
+            next = end + 1;
+        } else {
+            dict.insert((p, b), next);
+            if next == 1 << size {
+                size += 1;
+            }
+            next += 1;
+        }
+        prefix = Some(b as u16);
+    }
+    if let Some(p) = prefix {
+        w.push(p, size);
+    }
+    w.push(end, size);
+    w.finish()
+}
+
+/// Streams frames into an animated GIF, merging identical consecutive
+/// frames into one with a longer delay
+pub struct Recorder<W: Write> {
+    out: W,
+    width: usize,
+    height: usize,
+    scale: usize,
+    fps: u32,
+    // Frame waiting for its delay to be known, and how many frames it covers
+    pending: Option<(Vec<u8>, u64)>,
+    // Frames and centiseconds written so far, so rounding never drifts
+    frames: u64,
+    centis: u64,
+    pub count: u64,
+}
+
+impl Recorder<BufWriter<std::fs::File>> {
+    pub fn create(
+        path: &std::path::Path,
+        scale: usize,
+        fps: u32,
+        theme: &Theme,
+    ) -> io::Result<Self> {
+        Self::new(
+            BufWriter::new(std::fs::File::create(path)?),
+            LORES_WIDTH,
+            LORES_HEIGHT,
+            scale,
+            fps,
+            theme,
+        )
+    }
+}
+
+impl<W: Write> Recorder<W> {
+    pub fn new(
+        mut out: W,
+        width: usize,
+        height: usize,
+        scale: usize,
+        fps: u32,
+        theme: &Theme,
+    ) -> io::Result<Self> {
+        let scale = scale.max(1);
+        out.write_all(b"GIF89a")?;
+        out.write_all(&((width * scale) as u16).to_le_bytes())?;
+        out.write_all(&((height * scale) as u16).to_le_bytes())?;
+        // Global 4 color table
+        out.write_all(&[0xF1, 0, 0])?;
+        out.write_all(&theme.colors.concat())?;
+        // Loop forever
+        out.write_all(b"\x21\xFF\x0BNETSCAPE2.0\x03\x01\x00\x00\x00")?;
+        Ok(Self {
+            out,
+            width,
+            height,
+            scale,
+            fps: fps.max(1),
+            pending: None,
+            frames: 0,
+            centis: 0,
+            count: 0,
+        })
+    }
+
+    /// Adds a frame of color indices (`width` * `height`, row-major)
+    pub fn frame(&mut self, pixels: &[u8]) -> io::Result<()> {
+        self.count += 1;
+        match &mut self.pending {
+            Some((last, n)) if last.as_slice() == pixels => {
+                *n += 1;
+                Ok(())
+            }
+            _ => {
+                let prev = self.pending.replace((pixels.to_vec(), 1));
+                match prev {
+                    Some((p, n)) => self.write(&p, n),
+                    None => Ok(()),
+                }
+            }
+        }
+    }
+
+    pub fn screen(&mut self, bits: &[u64; 32]) -> io::Result<()> {
+        self.frame(&screenshot::pixels(bits))
+    }
+
+    fn write(&mut self, pixels: &[u8], n: u64) -> io::Result<()> {
+        self.frames += n;
+        let delay = self.frames * 100 / self.fps as u64 - self.centis;
+        self.centis += delay;
+
+        let (w, h) = (self.width * self.scale, self.height * self.scale);
+        let mut data = Vec::with_capacity(w * h);
+        for y in 0..h {
+            let row = &pixels[(y / self.scale) * self.width..];
+            data.extend((0..w).map(|x| row[x / self.scale] & 3));
+        }
+
+        let delay = delay.min(u16::MAX as u64) as u16;
+        self.out.write_all(&[0x21, 0xF9, 4, 0])?;
+        self.out.write_all(&delay.to_le_bytes())?;
+        self.out.write_all(&[0, 0, 0x2C, 0, 0, 0, 0])?;
+        self.out.write_all(&(w as u16).to_le_bytes())?;
+        self.out.write_all(&(h as u16).to_le_bytes())?;
+        self.out.write_all(&[0, 2])?;
+        for block in lzw(2, &data).chunks(255) {
+            self.out.write_all(&[block.len() as u8])?;
+            self.out.write_all(block)?;
+        }
+        self.out.write_all(&[0])
+    }
+
+    /// Flushes the last frame and the trailer
+    pub fn finish(mut self) -> io::Result<W> {
+        if let Some((p, n)) = self.pending.take() {
+            self.write(&p, n)?;
+        }
+        self.out.write_all(&[0x3B])?;
+        self.out.flush()?;
+        Ok(self.out)
+    }
+}
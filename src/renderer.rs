@@ -0,0 +1,118 @@
+use crate::layout::{LORES_HEIGHT, LORES_WIDTH};
+use crate::recorder::Recorder;
+#[cfg(feature = "terminal")]
+use crate::screen::Screen;
+use crate::screenshot;
+use crate::text::{self, RenderStyle};
+use crate::theme::Theme;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// Something frames can be shown on or saved to
+pub trait Renderer {
+    /// Frame number `frame`, counting from 1. Bit n of `dirty` is set if
+    /// row n changed since the last frame.
+    fn render(&mut self, frame: u64, bits: &[u64; 32], dirty: u32) -> io::Result<()>;
+}
+
+#[cfg(feature = "terminal")]
+impl Renderer for Screen {
+    fn render(&mut self, _: u64, bits: &[u64; 32], dirty: u32) -> io::Result<()> {
+        self.draw_rows(bits, dirty)
+    }
+}
+
+impl<W: Write> Renderer for Recorder<W> {
+    fn render(&mut self, _: u64, bits: &[u64; 32], _: u32) -> io::Result<()> {
+        self.screen(bits)
+    }
+}
+
+/// Writes every frame to a directory as a numbered PNG, `000001.png` on
+pub struct PngSequence {
+    dir: PathBuf,
+    scale: usize,
+    theme: Theme,
+    pub count: u64,
+}
+
+impl PngSequence {
+    /// Makes `dir` if it isn't there
+    pub fn create(dir: &Path, scale: usize, theme: &Theme) -> io::Result<Self> {
+        std::fs::create_dir_all(dir)?;
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            scale,
+            theme: *theme,
+            count: 0,
+        })
+    }
+}
+
+impl Renderer for PngSequence {
+    fn render(&mut self, frame: u64, bits: &[u64; 32], _: u32) -> io::Result<()> {
+        let png = screenshot::encode_png(
+            LORES_WIDTH,
+            LORES_HEIGHT,
+            self.scale,
+            &screenshot::pixels(bits),
+            &self.theme,
+        );
+        std::fs::write(self.dir.join(format!("{:06}.png", frame)), png)?;
+        self.count += 1;
+        Ok(())
+    }
+}
+
+/// Throws frames away
+pub struct NullRenderer;
+
+impl Renderer for NullRenderer {
+    fn render(&mut self, _: u64, _: &[u64; 32], _: u32) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Appends every `every`th frame to a file as text art under a
+/// `frame N` header. A write error is reported once and then no more
+/// frames are written, so a full disk doesn't stop the run.
+pub struct FileRenderer {
+    out: Option<BufWriter<File>>,
+    every: u64,
+}
+
+impl FileRenderer {
+    pub fn create(path: &Path, every: u64) -> io::Result<Self> {
+        Ok(Self {
+            out: Some(BufWriter::new(File::create(path)?)),
+            every: every.max(1),
+        })
+    }
+}
+
+impl Renderer for FileRenderer {
+    fn render(&mut self, frame: u64, bits: &[u64; 32], _: u32) -> io::Result<()> {
+        if !frame.is_multiple_of(self.every) {
+            return Ok(());
+        }
+        if let Some(out) = &mut self.out {
+            let text = text::render(bits, RenderStyle::Full);
+            if let Err(e) = write!(out, "frame {}\n{}", frame, text) {
+                eprintln!("Stopped writing frames: {}", e);
+                self.out = None;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Drop for FileRenderer {
+    fn drop(&mut self) {
+        if let Some(out) = &mut self.out {
+            if let Err(e) = out.flush() {
+                eprintln!("Stopped writing frames: {}", e);
+            }
+        }
+    }
+}
@@ -0,0 +1,369 @@
+use crate::layout::PROGRAM_START;
+use crate::octo::{self, Cart, OctoError};
+use crate::{emu, inflate};
+use std::fmt;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+/// Largest program that fits between the usual 0x200 and the end of 4 KB
+/// of RAM
+pub const MAX_SIZE: usize = emu::MIN_RAM - PROGRAM_START as usize;
+/// Largest program read at all, which only fits with XO-CHIP's 64 KB
+pub const MAX_LOAD: usize = emu::MAX_RAM - PROGRAM_START as usize;
+
+#[derive(Debug)]
+pub enum RomError {
+    Io(io::Error),
+    Empty,
+    TooLarge(usize),
+    Http(String),
+    Zip(String),
+    /// Line number and what was wrong with it
+    Hex(usize, String),
+    OctoCart(OctoError),
+    DoesNotFit {
+        load: u16,
+        len: usize,
+    },
+}
+
+impl fmt::Display for RomError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "{}", e),
+            Self::Empty => write!(f, "ROM is empty"),
+            Self::TooLarge(n) => write!(f, "ROM is {} bytes, at most {} fit", n, MAX_LOAD),
+            Self::Http(e) => write!(f, "download failed: {}", e),
+            Self::Zip(e) => write!(f, "bad zip archive: {}", e),
+            Self::Hex(line, e) => write!(f, "line {}: {}", line, e),
+            Self::DoesNotFit { load, len } => write!(
+                f,
+                "{} byte ROM doesn't fit in RAM when loaded at {:#05X}",
+                len, load
+            ),
+            Self::OctoCart(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for RomError {}
+
+impl From<OctoError> for RomError {
+    fn from(e: OctoError) -> Self {
+        Self::OctoCart(e)
+    }
+}
+
+impl From<io::Error> for RomError {
+    fn from(e: io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+/// Where a ROM's bytes come from
+#[derive(Debug, Clone, PartialEq)]
+pub enum RomSource {
+    File(PathBuf),
+    Stdin,
+    Url(String),
+    /// An archive, and the entry in it if one has been picked
+    Zip(PathBuf, Option<String>),
+}
+
+impl RomSource {
+    /// `-` is stdin, `http://` and `https://` are URLs, `.zip` files are
+    /// archives and anything else is a file
+    pub fn parse(arg: &str) -> Self {
+        let path = Path::new(arg);
+        if arg == "-" {
+            Self::Stdin
+        } else if arg.starts_with("http://") || arg.starts_with("https://") {
+            Self::Url(arg.to_string())
+        } else if path
+            .extension()
+            .is_some_and(|e| e.eq_ignore_ascii_case("zip"))
+        {
+            Self::Zip(path.to_path_buf(), None)
+        } else {
+            Self::File(path.to_path_buf())
+        }
+    }
+
+    /// Short name for display
+    pub fn name(&self) -> String {
+        let stem = |p: &Path| p.file_stem().map(|s| s.to_string_lossy().into_owned());
+        match self {
+            Self::File(p) | Self::Zip(p, None) => stem(p).unwrap_or_default(),
+            Self::Zip(_, Some(e)) => stem(Path::new(e)).unwrap_or_default(),
+            Self::Stdin => "stdin".to_string(),
+            Self::Url(u) => stem(Path::new(u.rsplit('/').next().unwrap_or(u))).unwrap_or_default(),
+        }
+    }
+
+    /// Reads the ROM, an archive without a picked entry must contain
+    /// exactly one ROM
+    pub fn resolve(&self) -> Result<Vec<u8>, RomError> {
+        self.load().map(|rom| rom.bytes)
+    }
+
+    /// Reads the ROM along with the options it came with, which only
+    /// OctoCarts have
+    pub fn load(&self) -> Result<Rom, RomError> {
+        let rom = match self {
+            Self::File(p) => read_limited(std::fs::File::open(p)?)?,
+            Self::Stdin => read_limited(io::stdin().lock())?,
+            Self::Url(u) => download(u)?,
+            Self::Zip(p, entry) => {
+                let zip = Zip::open(p)?;
+                let name = match entry {
+                    Some(e) => e.clone(),
+                    None => {
+                        let roms = zip.roms();
+                        match roms.as_slice() {
+                            [one] => one.name.clone(),
+                            [] => return Err(RomError::Zip("no ROMs inside".to_string())),
+                            _ => return Err(RomError::Zip("more than one ROM inside".to_string())),
+                        }
+                    }
+                };
+                zip.read(&name)?
+            }
+        };
+        let hex = match self {
+            Self::File(p) => is_hex(p),
+            Self::Zip(_, Some(e)) => is_hex(Path::new(e)),
+            Self::Url(u) => is_hex(Path::new(u)),
+            _ => false,
+        };
+        let (bytes, options) = decode(rom, hex)?;
+        Ok(Rom {
+            bytes: check(bytes)?,
+            options,
+        })
+    }
+}
+
+/// A program and the options an OctoCart gave it
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rom {
+    pub bytes: Vec<u8>,
+    pub options: Option<octo::Options>,
+}
+
+fn is_hex(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|e| e.eq_ignore_ascii_case("hex"))
+}
+
+// Turns hex text into bytes, and assembles OctoCarts, which are GIFs
+fn decode(rom: Vec<u8>, hex: bool) -> Result<(Vec<u8>, Option<octo::Options>), RomError> {
+    if rom.starts_with(b"GIF8") {
+        let cart = Cart::read(&rom)?;
+        Ok((octo::assemble(&cart.program)?, Some(cart.options)))
+    } else if hex {
+        Ok((parse_hex(&String::from_utf8_lossy(&rom))?, None))
+    } else {
+        Ok((rom, None))
+    }
+}
+
+/// Bytes written as whitespace separated hex, either as byte pairs or
+/// longer runs like `00E0`, with `#` starting a comment
+pub fn parse_hex(text: &str) -> Result<Vec<u8>, RomError> {
+    let mut out = Vec::new();
+    for (n, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or("");
+        for word in line.split_whitespace() {
+            let digits = word.trim_start_matches("0x");
+            if digits.len() % 2 != 0 {
+                return Err(RomError::Hex(
+                    n + 1,
+                    format!("{} has an odd number of digits", word),
+                ));
+            }
+            for i in (0..digits.len()).step_by(2) {
+                let b = digits
+                    .get(i..i + 2)
+                    .and_then(|d| u8::from_str_radix(d, 16).ok())
+                    .ok_or_else(|| RomError::Hex(n + 1, format!("{} isn't hex", word)))?;
+                out.push(b);
+            }
+        }
+    }
+    Ok(out)
+}
+
+fn check(rom: Vec<u8>) -> Result<Vec<u8>, RomError> {
+    match rom.len() {
+        0 => Err(RomError::Empty),
+        n if n > MAX_LOAD => Err(RomError::TooLarge(n)),
+        _ => Ok(rom),
+    }
+}
+
+// Reads one byte past the limit so oversized input is still noticed
+fn read_limited(r: impl Read) -> io::Result<Vec<u8>> {
+    let mut out = Vec::new();
+    r.take(MAX_LOAD as u64 + 1).read_to_end(&mut out)?;
+    Ok(out)
+}
+
+#[cfg(feature = "http")]
+fn download(url: &str) -> Result<Vec<u8>, RomError> {
+    use std::io::Write;
+    let err = |e: String| RomError::Http(e);
+    let rest = url
+        .strip_prefix("http://")
+        .ok_or_else(|| err("only plain http:// is supported".to_string()))?;
+    let (host, path) = match rest.find('/') {
+        Some(n) => (&rest[..n], &rest[n..]),
+        None => (rest, "/"),
+    };
+    let addr = if host.contains(':') {
+        host.to_string()
+    } else {
+        format!("{}:80", host)
+    };
+    let mut stream = std::net::TcpStream::connect(&addr)?;
+    stream.set_read_timeout(Some(std::time::Duration::from_secs(10)))?;
+    write!(
+        stream,
+        "GET {} HTTP/1.0\r\nHost: {}\r\nConnection: close\r\n\r\n",
+        path, host
+    )?;
+    let mut resp = Vec::new();
+    stream
+        .take(MAX_LOAD as u64 + 16384)
+        .read_to_end(&mut resp)?;
+    let end = resp
+        .windows(4)
+        .position(|w| w == b"\r\n\r\n")
+        .ok_or_else(|| err("malformed response".to_string()))?;
+    let head = String::from_utf8_lossy(&resp[..end]);
+    let status = head.lines().next().unwrap_or("");
+    match status.split_whitespace().nth(1) {
+        Some("200") => Ok(resp[end + 4..].to_vec()),
+        Some(_) => Err(err(status.to_string())),
+        None => Err(err("malformed response".to_string())),
+    }
+}
+
+#[cfg(not(feature = "http"))]
+fn download(_: &str) -> Result<Vec<u8>, RomError> {
+    Err(RomError::Http("built without the http feature".to_string()))
+}
+
+/// A file inside a zip archive
+pub struct ZipEntry {
+    pub name: String,
+    pub size: u64,
+    method: u16,
+    compressed: usize,
+    offset: usize,
+}
+
+/// A zip archive held in memory, only stored and deflated entries can be
+/// read
+pub struct Zip {
+    data: Vec<u8>,
+    pub entries: Vec<ZipEntry>,
+}
+
+fn u16_at(d: &[u8], at: usize) -> Option<u16> {
+    Some(u16::from_le_bytes([*d.get(at)?, *d.get(at + 1)?]))
+}
+
+fn u32_at(d: &[u8], at: usize) -> Option<u32> {
+    Some(u32::from_le_bytes([
+        *d.get(at)?,
+        *d.get(at + 1)?,
+        *d.get(at + 2)?,
+        *d.get(at + 3)?,
+    ]))
+}
+
+impl Zip {
+    // Archives bigger than this can't sensibly be ROM packs
+    const MAX_ARCHIVE: u64 = 64 << 20;
+
+    pub fn open(path: &Path) -> Result<Self, RomError> {
+        let mut data = Vec::new();
+        std::fs::File::open(path)?
+            .take(Self::MAX_ARCHIVE)
+            .read_to_end(&mut data)?;
+        Self::parse(data)
+    }
+
+    pub fn parse(data: Vec<u8>) -> Result<Self, RomError> {
+        let bad = |e: &str| RomError::Zip(e.to_string());
+        // End of central directory record, possibly followed by a comment
+        let eocd = (0..data.len().saturating_sub(21))
+            .rev()
+            .take(65536)
+            .find(|&n| u32_at(&data, n) == Some(0x0605_4B50))
+            .ok_or_else(|| bad("no central directory"))?;
+        let count = u16_at(&data, eocd + 10).ok_or_else(|| bad("truncated"))?;
+        let mut at = u32_at(&data, eocd + 16).ok_or_else(|| bad("truncated"))? as usize;
+
+        let mut entries = Vec::new();
+        for _ in 0..count {
+            let field = |off: usize| u32_at(&data, at + off).ok_or_else(|| bad("truncated"));
+            let short = |off: usize| u16_at(&data, at + off).ok_or_else(|| bad("truncated"));
+            if field(0)? != 0x0201_4B50 {
+                return Err(bad("corrupt central directory"));
+            }
+            let name_len = short(28)? as usize;
+            let skip = name_len + short(30)? as usize + short(32)? as usize;
+            let name = data
+                .get(at + 46..at + 46 + name_len)
+                .ok_or_else(|| bad("truncated"))?;
+            entries.push(ZipEntry {
+                name: String::from_utf8_lossy(name).into_owned(),
+                size: field(24)? as u64,
+                method: short(10)?,
+                compressed: field(20)? as usize,
+                offset: field(42)? as usize,
+            });
+            at += 46 + skip;
+        }
+        Ok(Self { data, entries })
+    }
+
+    /// Entries that look like ROMs
+    pub fn roms(&self) -> Vec<&ZipEntry> {
+        self.entries
+            .iter()
+            .filter(|e| crate::browser::is_rom(Path::new(&e.name)))
+            .collect()
+    }
+
+    pub fn read(&self, name: &str) -> Result<Vec<u8>, RomError> {
+        let bad = |e: &str| RomError::Zip(e.to_string());
+        let e = self
+            .entries
+            .iter()
+            .find(|e| e.name == name)
+            .ok_or_else(|| bad("entry not found"))?;
+        let d = &self.data;
+        if u32_at(d, e.offset) != Some(0x0403_4B50) {
+            return Err(bad("corrupt local header"));
+        }
+        let name_len = u16_at(d, e.offset + 26).ok_or_else(|| bad("truncated"))? as usize;
+        let extra = u16_at(d, e.offset + 28).ok_or_else(|| bad("truncated"))? as usize;
+        let start = e.offset + 30 + name_len + extra;
+        let body = d
+            .get(start..start + e.compressed)
+            .ok_or_else(|| bad("truncated"))?;
+        match e.method {
+            0 => Ok(body.iter().take(MAX_LOAD + 1).copied().collect()),
+            8 => match inflate::inflate(body, MAX_LOAD) {
+                Err("decompressed data is too large") => Err(RomError::TooLarge(e.size as usize)),
+                r => r.map_err(bad),
+            },
+            m => Err(RomError::Zip(format!(
+                "unsupported compression method {}",
+                m
+            ))),
+        }
+    }
+}
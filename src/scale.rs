@@ -0,0 +1,113 @@
+//! Where the picture goes in a window of any size. Pixels stay square and
+//! the picture is centered, with bars round it where it doesn't reach.
+
+/// Pixels from the top left of the window
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Rect {
+    pub x: u32,
+    pub y: u32,
+    pub w: u32,
+    pub h: u32,
+}
+
+impl Rect {
+    pub fn is_empty(&self) -> bool {
+        self.w == 0 || self.h == 0
+    }
+}
+
+/// The biggest whole-number scale of a `fb_w` by `fb_h` screen that fits
+/// the window, or the biggest scale of any size with `allow_fractional`.
+/// A window smaller than the screen shrinks it rather than cutting it off.
+/// Run again when the screen changes size, 64x32 and 128x64 fill the
+/// same space.
+///
+/// ```
+/// use chip8::scale::{fit, Rect};
+///
+/// let rect = |x, y, w, h| Rect { x, y, w, h };
+/// assert_eq!(fit(640, 320, 64, 32, false), rect(0, 0, 640, 320));
+/// // One short of the next size up, the spare pixel goes to the right
+/// assert_eq!(fit(703, 351, 64, 32, false), rect(31, 15, 640, 320));
+/// assert_eq!(fit(1920, 1080, 64, 32, false), rect(0, 60, 1920, 960));
+/// assert_eq!(fit(1920, 1080, 128, 64, false), rect(0, 60, 1920, 960));
+/// assert_eq!(fit(300, 1000, 64, 32, false), rect(22, 436, 256, 128));
+/// assert_eq!(fit(1000, 1000, 64, 32, false), rect(20, 260, 960, 480));
+/// assert_eq!(fit(1000, 1000, 64, 32, true), rect(0, 250, 1000, 500));
+/// assert_eq!(fit(50, 20, 64, 32, false), rect(5, 0, 40, 20));
+/// assert_eq!(fit(0, 480, 64, 32, false), Rect::default());
+/// assert_eq!(fit(640, 480, 0, 32, false), Rect::default());
+/// ```
+pub fn fit(window_w: u32, window_h: u32, fb_w: u32, fb_h: u32, allow_fractional: bool) -> Rect {
+    if window_w == 0 || window_h == 0 || fb_w == 0 || fb_h == 0 {
+        return Rect::default();
+    }
+    let whole = (window_w / fb_w).min(window_h / fb_h);
+    let (w, h) = if whole >= 1 && !allow_fractional {
+        (fb_w * whole, fb_h * whole)
+    } else {
+        // Whichever side runs out first sets the scale
+        let scale = (window_w as f64 / fb_w as f64).min(window_h as f64 / fb_h as f64);
+        let w = ((fb_w as f64 * scale).round() as u32).min(window_w);
+        let h = ((fb_h as f64 * scale).round() as u32).min(window_h);
+        (w, h)
+    };
+    Rect {
+        x: (window_w - w) / 2,
+        y: (window_h - h) / 2,
+        w,
+        h,
+    }
+}
+
+/// The bars round `picture` to clear, so nothing is left there when the
+/// picture shrinks or changes shape
+///
+/// ```
+/// use chip8::scale::{fit, letterbox, Rect};
+///
+/// let picture = fit(1920, 1080, 64, 32, false);
+/// assert_eq!(
+///     letterbox(1920, 1080, picture),
+///     [Rect { x: 0, y: 0, w: 1920, h: 60 }, Rect { x: 0, y: 1020, w: 1920, h: 60 }]
+/// );
+/// let picture = fit(300, 1000, 64, 32, false);
+/// let bars = letterbox(300, 1000, picture);
+/// let area = |r: &Rect| r.w * r.h;
+/// assert_eq!(bars.iter().map(area).sum::<u32>() + area(&picture), 300 * 1000);
+/// assert!(letterbox(640, 320, fit(640, 320, 64, 32, false)).is_empty());
+/// ```
+pub fn letterbox(window_w: u32, window_h: u32, picture: Rect) -> Vec<Rect> {
+    let bottom = picture.y + picture.h;
+    let right = picture.x + picture.w;
+    [
+        Rect {
+            x: 0,
+            y: 0,
+            w: window_w,
+            h: picture.y,
+        },
+        Rect {
+            x: 0,
+            y: bottom,
+            w: window_w,
+            h: window_h.saturating_sub(bottom),
+        },
+        Rect {
+            x: 0,
+            y: picture.y,
+            w: picture.x,
+            h: picture.h,
+        },
+        Rect {
+            x: right,
+            y: picture.y,
+            w: window_w.saturating_sub(right),
+            h: picture.h,
+        },
+    ]
+    .iter()
+    .filter(|r| !r.is_empty())
+    .copied()
+    .collect()
+}
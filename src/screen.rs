@@ -1,44 +1,362 @@
-use std::io::Write;
+use crate::framebuffer::Framebuffer;
+use crate::keypad;
+use crate::layout::{LORES_HEIGHT, LORES_WIDTH};
+use crate::phosphor::Phosphor;
+use crate::term;
+use crate::text::{level_lines, lines, RenderStyle};
+use crate::theme::{ColorDepth, Theme};
+use std::io::{self, Write};
 
-pub struct Screen {}
+// Layout of the image for the current terminal size
+#[derive(Clone, Copy, PartialEq)]
+enum Layout {
+    // Terminal size unknown, print at the cursor like a plain text stream
+    Stream,
+    // Style plus 1-based position of the top left border corner
+    Placed(RenderStyle, usize, usize),
+    TooSmall(usize, usize),
+}
+
+/// What the status line under the image shows
+pub struct Status<'a> {
+    pub sound: bool,
+    pub paused: bool,
+    /// Instructions stepped back from the furthest the program got
+    pub rewound: u64,
+    pub fast: bool,
+    pub keys: u16,
+    /// Keys set for the next frame while frame advancing, shown in place
+    /// of the held keys
+    pub next_keys: Option<u16>,
+    /// A turbo key is held
+    pub turbo: bool,
+    /// Shown as the keypad, so it's clear which keys to press
+    pub keymap: &'a keypad::Keymap,
+    pub muted: bool,
+    pub name: &'a str,
+    /// Shown in place of the name for a moment after something happens
+    pub flash: Option<&'a str>,
+}
+
+/// Speaker while sound plays, run state, how far it's been stepped back,
+/// the host keys for the keypad in its rows with held ones in reverse
+/// video, then the ROM name or a flashed message
+pub fn status_line(s: &Status) -> String {
+    let mut out = String::new();
+    out.push_str(if s.sound { "\u{266A} " } else { "  " });
+    out.push_str(match (s.paused, s.fast) {
+        (true, _) => "PAUSED ",
+        (false, true) => ">> FF  ",
+        (false, false) => "       ",
+    });
+    if s.rewound > 0 {
+        out.push_str(&format!("\x1b[7m-{}\x1b[27m ", s.rewound));
+    }
+    let keys = s.next_keys.unwrap_or(s.keys);
+    for (n, k) in keypad::GRID.iter().enumerate() {
+        if n > 0 && n % 4 == 0 {
+            out.push(' ');
+        }
+        let c: String = s.keymap.host_key(*k).to_uppercase().collect();
+        if (keys >> k) & 1 != 0 {
+            out.push_str(&format!("\x1b[7m{}\x1b[27m", c));
+        } else {
+            out.push_str(&c);
+        }
+    }
+    out.push(' ');
+    if s.next_keys.is_some() {
+        out.push_str("NEXT ");
+    }
+    if s.turbo {
+        out.push_str("TURBO ");
+    }
+    if s.muted {
+        out.push_str("MUTE ");
+    }
+    match s.flash {
+        Some(f) => out.push_str(&format!("\x1b[1m{}\x1b[22m", f)),
+        None => out.push_str(s.name),
+    }
+    out
+}
+
+// Columns before the keypad in `status_line(s)`
+fn keypad_offset(s: &Status) -> usize {
+    let rewound = match s.rewound {
+        0 => 0,
+        n => format!("-{} ", n).len(),
+    };
+    2 + 7 + rewound
+}
+
+/// The key `col` columns into the keypad of a status line, from 0, going
+/// across the rows of `keypad::GRID` with a space between them
+///
+/// ```
+/// use chip8::screen::keypad_key;
+///
+/// // 123C 456D 789E A0BF
+/// let keys: Vec<_> = (0..20).map(keypad_key).collect();
+/// assert_eq!(keys[..5], [Some(0x1), Some(0x2), Some(0x3), Some(0xC), None]);
+/// assert_eq!((keys[5], keys[15], keys[18], keys[19]), (Some(0x4), Some(0xA), Some(0xF), None));
+/// ```
+pub fn keypad_key(col: usize) -> Option<u8> {
+    match (col / 5, col % 5) {
+        (row @ 0..=3, n @ 0..=3) => Some(keypad::GRID[row * 4 + n]),
+        _ => None,
+    }
+}
+
+pub struct Screen {
+    out: Box<dyn Write>,
+    layout: Layout,
+    // Rows kept free under the image for the status line
+    status_rows: usize,
+    // And under that for watches
+    watch_rows: usize,
+    // Where the status line's keypad was last drawn, 1-based row and
+    // column, for clicks
+    keypad_at: Option<(usize, usize)>,
+    // Whether escape sequences work, otherwise every frame is reprinted
+    ansi: bool,
+    depth: ColorDepth,
+    // Set when the whole image has to be drawn, whatever is dirty
+    stale: bool,
+    dim: bool,
+}
 
 impl Screen {
+    /// Draws to the terminal, through a buffer flushed once per frame
     pub fn new() -> Self {
-        // Hide cursor
-        print!("\x1b[?25l");
-        Self::flush();
-        Self {}
+        let ansi = term::enable_ansi();
+        if ansi {
+            term::hide_cursor();
+            term::report_focus();
+            term::bracket_paste();
+            term::watch_resize();
+        }
+        Self {
+            out: Box::new(io::BufWriter::new(io::stdout().lock())),
+            layout: Layout::Stream,
+            status_rows: 0,
+            watch_rows: 0,
+            keypad_at: None,
+            ansi,
+            depth: term::color_depth(),
+            stale: true,
+            dim: false,
+        }
+    }
+
+    /// Writes frames one after another to `out`, without placing them
+    pub fn with_writer(out: Box<dyn Write>) -> Self {
+        Self {
+            out,
+            layout: Layout::Stream,
+            status_rows: 0,
+            watch_rows: 0,
+            keypad_at: None,
+            ansi: false,
+            depth: ColorDepth::Ansi256,
+            stale: true,
+            dim: false,
+        }
+    }
+
+    /// Draws the image faint, for while the game is stopped in the
+    /// background
+    pub fn set_dim(&mut self, dim: bool) {
+        if dim != self.dim {
+            self.dim = dim;
+            self.stale = true;
+        }
+    }
+
+    /// Leaves a row under the image for `draw_status`
+    pub fn with_status(mut self) -> Self {
+        self.status_rows = 1;
+        self
+    }
+
+    /// Leaves `n` rows under the status line for `draw_watches`
+    pub fn with_watches(mut self, n: usize) -> Self {
+        self.watch_rows = n;
+        self
+    }
+
+    fn clear(&mut self) -> io::Result<()> {
+        write!(self.out, "\x1b[2J\x1b[3J\x1b[1;1H")
     }
 
-    fn flush() {
-        let _ = std::io::stdout().flush();
+    // Recomputes the layout on resize, clearing and redrawing the border
+    fn relayout(&mut self) -> io::Result<()> {
+        // Stream is also the starting layout, so keep trying until placed
+        if !self.ansi || !(term::take_resized() || self.layout == Layout::Stream) {
+            return Ok(());
+        }
+        self.layout = match term::size() {
+            None => Layout::Stream,
+            Some((cols, rows)) => {
+                let reserved = self.status_rows + self.watch_rows;
+                match term::place(cols, rows, reserved, LORES_WIDTH, LORES_HEIGHT) {
+                    Some((style, top, left)) => Layout::Placed(style, top, left),
+                    None => Layout::TooSmall(cols, rows),
+                }
+            }
+        };
+        // Until the status line is drawn again in its new place
+        self.keypad_at = None;
+        if self.layout == Layout::Stream {
+            return Ok(());
+        }
+        self.clear()?;
+        self.stale = true;
+        let out = &mut self.out;
+        match self.layout {
+            Layout::Placed(style, top, left) => {
+                let (c, r) = style.cells(LORES_WIDTH, LORES_HEIGHT);
+                let bar = "\u{2500}".repeat(c);
+                write!(out, "\x1b[{};{}H\u{250C}{}\u{2510}", top, left, bar)?;
+                for y in 1..=r {
+                    write!(out, "\x1b[{};{}H\u{2502}", top + y, left)?;
+                    write!(out, "\x1b[{};{}H\u{2502}", top + y, left + c + 1)?;
+                }
+                write!(out, "\x1b[{};{}H\u{2514}{}\u{2518}", top + r + 1, left, bar)?;
+            }
+            Layout::TooSmall(cols, rows) => {
+                let (c, r) = RenderStyle::Braille.cells(LORES_WIDTH, LORES_HEIGHT);
+                write!(
+                    out,
+                    "Terminal is {}x{}, at least {}x{} is needed",
+                    cols,
+                    rows,
+                    c + 2,
+                    r + 2 + self.status_rows + self.watch_rows
+                )?;
+            }
+            Layout::Stream => {}
+        }
+        Ok(())
+    }
+
+    // Placed images only redraw lines covering a row set in `dirty`
+    fn show(&mut self, lines: &[String], dirty: u32) -> io::Result<()> {
+        match self.layout {
+            Layout::Stream => {
+                for line in lines {
+                    writeln!(self.out, "{}", line)?;
+                }
+            }
+            Layout::Placed(style, top, left) => {
+                // Pixel rows per line
+                let per = LORES_HEIGHT / style.cells(LORES_WIDTH, LORES_HEIGHT).1;
+                let mask = (1u64 << per) as u32 - 1;
+                for (y, line) in lines.iter().enumerate() {
+                    if self.stale || dirty & (mask << (y * per)) != 0 {
+                        write!(self.out, "\x1b[{};{}H", top + 1 + y, left + 1)?;
+                        if self.dim {
+                            write!(self.out, "\x1b[2m{}\x1b[22m", line)?;
+                        } else {
+                            write!(self.out, "{}", line)?;
+                        }
+                    }
+                }
+                self.stale = false;
+            }
+            Layout::TooSmall(..) => {}
+        }
+        self.out.flush()
+    }
+
+    pub fn draw_status(&mut self, status: &Status) -> io::Result<()> {
+        let line = status_line(status);
+        match self.layout {
+            Layout::Stream => writeln!(self.out, "{}", line)?,
+            Layout::Placed(style, top, left) => {
+                let row = top + style.cells(LORES_WIDTH, LORES_HEIGHT).1 + 2;
+                write!(self.out, "\x1b[{};{}H\x1b[K{}", row, left, line)?;
+                self.keypad_at = Some((row, left + keypad_offset(status)));
+            }
+            Layout::TooSmall(..) => return Ok(()),
+        }
+        self.out.flush()
     }
 
-    fn clear() {
-        //print!("\x1b[2J\x1b[3J\x1b[1;1H");
-        Self::flush();
+    /// The key drawn at a 1-based row and column of the terminal, as
+    /// `mouse::event` gives them, if the status line was drawn there
+    pub fn key_at(&self, row: usize, col: usize) -> Option<u8> {
+        let (r, c) = self.keypad_at?;
+        match col.checked_sub(c) {
+            Some(col) if row == r => keypad_key(col),
+            _ => None,
+        }
     }
 
-    pub fn draw(&mut self, bits: &[u64; 32]) {
-        Self::clear();
-        for row in bits {
-            for x in 0..64 {
-                if ((row >> (63 - x)) & 1) != 0 {
-                    print!("\u{2588}")
-                } else {
-                    print!(" ")
+    /// `watch::Watches::lines`, one to a row under the status line
+    pub fn draw_watches(&mut self, lines: &[String]) -> io::Result<()> {
+        match self.layout {
+            Layout::Stream => {
+                for line in lines {
+                    writeln!(self.out, "{}", line)?;
+                }
+            }
+            Layout::Placed(style, top, left) => {
+                let row = top + style.cells(LORES_WIDTH, LORES_HEIGHT).1 + 2 + self.status_rows;
+                for (n, line) in lines.iter().take(self.watch_rows).enumerate() {
+                    write!(self.out, "\x1b[{};{}H\x1b[K{}", row + n, left, line)?;
                 }
             }
-            println!();
-            Self::flush();
+            Layout::TooSmall(..) => return Ok(()),
         }
+        self.out.flush()
+    }
+
+    fn style(&self) -> RenderStyle {
+        match self.layout {
+            Layout::Placed(style, ..) => style,
+            _ => RenderStyle::Full,
+        }
+    }
+
+    pub fn draw(&mut self, bits: &[u64; 32]) -> io::Result<()> {
+        self.draw_rows(bits, u32::MAX)
+    }
+
+    /// Like `draw`, but only rows set in `dirty` (bit n for row n) are
+    /// redrawn if the rest is already on screen
+    pub fn draw_rows(&mut self, bits: &[u64; 32], dirty: u32) -> io::Result<()> {
+        self.relayout()?;
+        let lines = lines(self.style(), |x, y| bits.get(x, y) != 0);
+        self.show(&lines, dirty)
+    }
+
+    pub fn draw_levels(&mut self, p: &Phosphor, theme: &Theme) -> io::Result<()> {
+        self.relayout()?;
+        let lines = level_lines(self.style(), p, theme, self.depth);
+        self.show(&lines, u32::MAX)
+    }
+}
+
+impl Default for Screen {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
 impl Drop for Screen {
     fn drop(&mut self) {
-        // Show cursor
-        print!("\x1b[?25h");
-        Self::clear();
+        // Leave the cursor below the image and show it
+        let _ = match self.layout {
+            Layout::Placed(style, top, _) => write!(
+                self.out,
+                "\x1b[{};1H",
+                top + style.cells(LORES_WIDTH, LORES_HEIGHT).1 + 2 + self.status_rows
+            ),
+            Layout::TooSmall(..) => writeln!(self.out),
+            Layout::Stream => Ok(()),
+        };
+        let _ = self.out.flush();
+        term::restore();
     }
 }
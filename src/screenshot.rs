@@ -0,0 +1,129 @@
+use crate::framebuffer::Framebuffer;
+use crate::layout::{LORES_HEIGHT, LORES_WIDTH};
+use crate::text;
+use crate::theme::Theme;
+use std::path::{Path, PathBuf};
+
+pub(crate) fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &b in data {
+        crc ^= b as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for &d in data {
+        a = (a + d as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    (b << 16) | a
+}
+
+fn chunk(out: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = out.len();
+    out.extend_from_slice(kind);
+    out.extend_from_slice(data);
+    let crc = crc32(&out[start..]);
+    out.extend_from_slice(&crc.to_be_bytes());
+}
+
+// zlib stream made of uncompressed deflate blocks
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let mut out = vec![0x78, 0x01];
+    let mut blocks = data.chunks(0xFFFF).peekable();
+    if blocks.peek().is_none() {
+        out.extend_from_slice(&[1, 0, 0, 0xFF, 0xFF]);
+    }
+    while let Some(block) = blocks.next() {
+        out.push(if blocks.peek().is_none() { 1 } else { 0 });
+        let len = block.len() as u16;
+        out.extend_from_slice(&len.to_le_bytes());
+        out.extend_from_slice(&(!len).to_le_bytes());
+        out.extend_from_slice(block);
+    }
+    out.extend_from_slice(&adler32(data).to_be_bytes());
+    out
+}
+
+/// Encodes `pixels` (color indices, row-major, `width` * `height`) as an
+/// indexed PNG with each pixel scaled to a `scale` * `scale` square
+pub fn encode_png(
+    width: usize,
+    height: usize,
+    scale: usize,
+    pixels: &[u8],
+    theme: &Theme,
+) -> Vec<u8> {
+    let scale = scale.max(1);
+    let (w, h) = (width * scale, height * scale);
+    let mut raw = Vec::with_capacity((w + 1) * h);
+    for y in 0..h {
+        // Filter type none
+        raw.push(0);
+        let row = &pixels[(y / scale) * width..(y / scale + 1) * width];
+        for x in 0..w {
+            raw.push(row[x / scale] & 3);
+        }
+    }
+
+    let mut out = vec![0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1A, b'\n'];
+    let mut ihdr = Vec::with_capacity(13);
+    ihdr.extend_from_slice(&(w as u32).to_be_bytes());
+    ihdr.extend_from_slice(&(h as u32).to_be_bytes());
+    // 8 bit depth, indexed color, default compression/filter/interlace
+    ihdr.extend_from_slice(&[8, 3, 0, 0, 0]);
+    chunk(&mut out, b"IHDR", &ihdr);
+    chunk(&mut out, b"PLTE", &theme.colors.concat());
+    chunk(&mut out, b"IDAT", &zlib_stored(&raw));
+    chunk(&mut out, b"IEND", &[]);
+    out
+}
+
+/// Color index of every pixel of a 64x32 screen
+pub fn pixels(bits: &[u64; 32]) -> Vec<u8> {
+    bits.indices()
+}
+
+// Directories get the first free shot-NNNN.png inside them
+fn resolve(path: &Path) -> PathBuf {
+    if !path.is_dir() {
+        return path.to_path_buf();
+    }
+    (1..)
+        .map(|n| path.join(format!("shot-{:04}.png", n)))
+        .find(|p| !p.exists())
+        .unwrap()
+}
+
+/// Writes the screen to `path` as text art if it ends in `.txt`, otherwise
+/// as a PNG, returning the path actually written
+pub fn save(
+    path: &Path,
+    bits: &[u64; 32],
+    scale: usize,
+    theme: &Theme,
+) -> std::io::Result<PathBuf> {
+    let path = resolve(path);
+    let txt = path
+        .extension()
+        .is_some_and(|e| e.eq_ignore_ascii_case("txt"));
+    if txt {
+        std::fs::write(&path, text::to_text(bits))?;
+    } else {
+        std::fs::write(
+            &path,
+            encode_png(LORES_WIDTH, LORES_HEIGHT, scale, &pixels(bits), theme),
+        )?;
+    }
+    Ok(path)
+}
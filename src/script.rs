@@ -0,0 +1,614 @@
+//! Scripts that run before every frame with `--script`, for automated
+//! tests and bots. One command per line, `#` starts a comment:
+//!
+//! ```text
+//! wait N              let N frames run
+//! until A OP B        let frames run until the condition holds
+//! press K N           hold key K for the next N frames
+//! hold K / release K  hold key K until released
+//! poke ADDR VAL       write a byte of RAM
+//! set vX VAL          write a register
+//! assert A OP B       stop the script with an error unless it holds
+//! label NAME          somewhere to goto
+//! goto NAME
+//! if A OP B goto NAME
+//! log TEXT            show a message
+//! pause / screenshot / quit
+//! ```
+//!
+//! Values are `Expr`s, written without spaces. OP is one of `==`, `!=`,
+//! `<`, `<=`, `>` and `>=`.
+use crate::emu::Chip8;
+use crate::layout::RAM_SIZE_CLASSIC;
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+// Lines run in one frame without waiting before it's treated as stuck
+const MAX_LINES: usize = 10_000;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScriptError {
+    /// 1-based, 0 for errors not tied to a line
+    pub line: usize,
+    pub msg: String,
+}
+
+impl fmt::Display for ScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.line == 0 {
+            write!(f, "script: {}", self.msg)
+        } else {
+            write!(f, "script line {}: {}", self.line, self.msg)
+        }
+    }
+}
+
+impl std::error::Error for ScriptError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExprError {
+    /// Not an expression, and why
+    Syntax(String),
+    /// Read past the end of RAM
+    Address(u64),
+    DivideByZero,
+}
+
+impl fmt::Display for ExprError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Syntax(msg) => f.write_str(msg),
+            Self::Address(a) => write!(f, "address {:X} is out of range", a),
+            Self::DivideByZero => f.write_str("division by zero"),
+        }
+    }
+}
+
+impl std::error::Error for ExprError {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Value {
+    Num(u64),
+    V(u8),
+    I,
+    Pc,
+    Dt,
+    St,
+    Frame,
+    Frames,
+    Retired,
+    FrameStep,
+    Screen,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Node {
+    Value(Value),
+    /// The byte of RAM at the address
+    Ram(Box<Node>),
+    /// One of `+-*/%` and both sides
+    Binary(u8, Box<Node>, Box<Node>),
+}
+
+/// A number worked out from the machine: numbers (`0x` for hex), `v0` to
+/// `vf`, `i`, `pc`, `dt`, `st`, `frame`, `frames`, `retired` and
+/// `frame_step` (`Chip8::frames` and the rest, which unlike `frame` carry
+/// on from a loaded state), `screen` (`framebuffer::hash` of the display)
+/// and `[ADDR]` for the byte of RAM at any expression, put together with
+/// `+`, `-`, `*`, `/`, `%` and brackets. Sums wrap.
+///
+/// ```
+/// use chip8::emu::Chip8;
+/// use chip8::script::{Expr, ExprError};
+///
+/// let mut c8 = Chip8::builder().build().unwrap();
+/// c8.set_v(0, 4);
+/// c8.set_v(1, 2);
+/// c8.execute_one(0xA3A0).unwrap();
+/// c8.poke(0x3A0, 0x12);
+/// c8.poke(0x3A3, 0x34);
+/// let eval = |src: &str| src.parse::<Expr>().and_then(|e| e.eval(&c8, 0));
+/// assert_eq!(eval("v0*10+v1"), Ok(42));
+/// assert_eq!(eval("v0 * (10 + v1)"), Ok(48));
+/// assert_eq!(eval("[i]"), Ok(0x12));
+/// assert_eq!(eval("[0x3a0]"), Ok(0x12));
+/// assert_eq!(eval("[i+v0-1]"), Ok(0x34));
+/// assert_eq!(eval("[[i]+0x391]"), Ok(0x34));
+/// assert_eq!(eval("10-v0-v1%3"), Ok(4));
+/// assert_eq!(eval("0-1"), Ok(u64::MAX));
+/// // The LD I above, outside any frame
+/// assert_eq!(eval("retired*100+frames*10+frame_step"), Ok(101));
+///
+/// // Mistakes in the machine's state only show when it's read
+/// assert_eq!(eval("[i*16]"), Err(ExprError::Address(0x3A00)));
+/// assert_eq!(eval("v0/(v1-2)"), Err(ExprError::DivideByZero));
+/// let err = |src: &str| src.parse::<Expr>().unwrap_err().to_string();
+/// assert_eq!(err("v0*"), "expected a value at the end of v0*");
+/// assert_eq!(err("[i"), "expected ] at the end of [i");
+/// assert_eq!(err("vg"), "no register vg");
+/// assert_eq!(err("v0 v1"), "unexpected v1 in v0 v1");
+/// assert_eq!(err("[0x1000]"), "bad address 0x1000");
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Expr(Node);
+
+// Tokens are brackets, operators and words of anything else
+fn tokens(s: &str) -> Vec<&str> {
+    let mut out = Vec::new();
+    let mut start = None;
+    for (n, c) in s.char_indices() {
+        let punct = "[]()+-*/%".contains(c);
+        if punct || c.is_whitespace() {
+            if let Some(st) = start.take() {
+                out.push(&s[st..n]);
+            }
+            if punct {
+                out.push(&s[n..n + 1]);
+            }
+        } else if start.is_none() {
+            start = Some(n);
+        }
+    }
+    if let Some(st) = start {
+        out.push(&s[st..]);
+    }
+    out
+}
+
+struct Parser<'a> {
+    src: &'a str,
+    tokens: Vec<&'a str>,
+    at: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<&'a str> {
+        self.tokens.get(self.at).copied()
+    }
+
+    fn end(&self, expected: &str) -> ExprError {
+        ExprError::Syntax(match self.peek() {
+            Some(t) => format!("expected {}, not {} in {}", expected, t, self.src),
+            None => format!("expected {} at the end of {}", expected, self.src),
+        })
+    }
+
+    // Operators in `ops` between what `next` parses, left to right
+    fn binary(
+        &mut self,
+        ops: &str,
+        next: fn(&mut Self) -> Result<Node, ExprError>,
+    ) -> Result<Node, ExprError> {
+        let mut left = next(self)?;
+        while let Some(op) = self.peek().filter(|t| t.len() == 1 && ops.contains(*t)) {
+            self.at += 1;
+            left = Node::Binary(op.as_bytes()[0], Box::new(left), Box::new(next(self)?));
+        }
+        Ok(left)
+    }
+
+    fn sum(&mut self) -> Result<Node, ExprError> {
+        self.binary("+-", Self::product)
+    }
+
+    fn product(&mut self) -> Result<Node, ExprError> {
+        self.binary("*/%", Self::atom)
+    }
+
+    fn atom(&mut self) -> Result<Node, ExprError> {
+        let t = self.peek().ok_or_else(|| self.end("a value"))?;
+        let close = match t {
+            "[" => "]",
+            "(" => ")",
+            _ if t.len() == 1 && "])+-*/%".contains(t) => return Err(self.end("a value")),
+            _ => {
+                self.at += 1;
+                return value(t).map(Node::Value).map_err(ExprError::Syntax);
+            }
+        };
+        self.at += 1;
+        let inner = self.sum()?;
+        if self.peek() != Some(close) {
+            return Err(self.end(close));
+        }
+        self.at += 1;
+        Ok(match (close, inner) {
+            ("]", Node::Value(Value::Num(a))) if a >= RAM_SIZE_CLASSIC as u64 => {
+                return Err(ExprError::Syntax(format!("bad address {:#x}", a)))
+            }
+            ("]", inner) => Node::Ram(Box::new(inner)),
+            (_, inner) => inner,
+        })
+    }
+}
+
+impl FromStr for Expr {
+    type Err = ExprError;
+
+    fn from_str(src: &str) -> Result<Self, ExprError> {
+        let mut p = Parser {
+            src,
+            tokens: tokens(src),
+            at: 0,
+        };
+        let node = p.sum()?;
+        match p.peek() {
+            None => Ok(Self(node)),
+            Some(t) => Err(ExprError::Syntax(format!("unexpected {} in {}", t, src))),
+        }
+    }
+}
+
+impl Expr {
+    /// The value on `c8`, `frame` being what `frame` reads
+    pub fn eval(&self, c8: &Chip8, frame: u64) -> Result<u64, ExprError> {
+        eval(&self.0, c8, frame)
+    }
+}
+
+fn eval(node: &Node, c8: &Chip8, frame: u64) -> Result<u64, ExprError> {
+    Ok(match node {
+        Node::Value(v) => match *v {
+            Value::Num(n) => n,
+            Value::V(x) => c8.v()[x as usize] as u64,
+            Value::I => c8.i() as u64,
+            Value::Pc => c8.pc() as u64,
+            Value::Dt => c8.delay_timer() as u64,
+            Value::St => c8.sound_timer() as u64,
+            Value::Frame => frame,
+            Value::Frames => c8.frames(),
+            Value::Retired => c8.retired(),
+            Value::FrameStep => c8.frame_step() as u64,
+            Value::Screen => crate::framebuffer::hash(&c8.screen),
+        },
+        Node::Ram(a) => match eval(a, c8, frame)? {
+            a if a <= c8.addr_mask() as u64 => c8.peek(a as u16) as u64,
+            a => return Err(ExprError::Address(a)),
+        },
+        Node::Binary(op, a, b) => {
+            let (a, b) = (eval(a, c8, frame)?, eval(b, c8, frame)?);
+            match op {
+                b'+' => a.wrapping_add(b),
+                b'-' => a.wrapping_sub(b),
+                b'*' => a.wrapping_mul(b),
+                b'/' => a.checked_div(b).ok_or(ExprError::DivideByZero)?,
+                _ => a.checked_rem(b).ok_or(ExprError::DivideByZero)?,
+            }
+        }
+    })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Cond(Expr, Op, Expr);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Command {
+    Wait(u64),
+    Until(Cond),
+    Press(u8, u64),
+    Hold(u8),
+    Release(u8),
+    Poke(u16, u8),
+    Set(u8, u8),
+    Assert(Cond),
+    Goto(usize),
+    If(Cond, usize),
+    Log(String),
+    Pause,
+    Screenshot,
+    Quit,
+}
+
+/// What the script wants from whoever is running the emulator
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Action {
+    Log(String),
+    Pause,
+    Screenshot,
+    Quit,
+}
+
+fn num(s: &str) -> Option<u64> {
+    match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => u64::from_str_radix(hex, 16).ok(),
+        None => s.parse().ok(),
+    }
+}
+
+fn value(s: &str) -> Result<Value, String> {
+    let s = s.to_ascii_lowercase();
+    Ok(match s.as_str() {
+        "i" => Value::I,
+        "pc" => Value::Pc,
+        "dt" => Value::Dt,
+        "st" => Value::St,
+        "frame" => Value::Frame,
+        "frames" => Value::Frames,
+        "retired" => Value::Retired,
+        "frame_step" => Value::FrameStep,
+        "screen" => Value::Screen,
+        _ => {
+            if let Some(x) = s.strip_prefix('v').filter(|x| x.len() == 1) {
+                Value::V(u8::from_str_radix(x, 16).map_err(|_| format!("no register {}", s))?)
+            } else {
+                Value::Num(num(&s).ok_or_else(|| format!("bad value {}", s))?)
+            }
+        }
+    })
+}
+
+fn cond(words: &[&str]) -> Result<Cond, String> {
+    let (a, op, b) = match words {
+        [a, op, b] => (a, op, b),
+        _ => return Err("a condition is VALUE OP VALUE".to_string()),
+    };
+    let op = match *op {
+        "==" => Op::Eq,
+        "!=" => Op::Ne,
+        "<" => Op::Lt,
+        "<=" => Op::Le,
+        ">" => Op::Gt,
+        ">=" => Op::Ge,
+        _ => return Err(format!("unknown comparison {}", op)),
+    };
+    let expr = |s: &str| s.parse::<Expr>().map_err(|e| e.to_string());
+    Ok(Cond(expr(a)?, op, expr(b)?))
+}
+
+fn key(s: &str) -> Result<u8, String> {
+    match num(s) {
+        Some(k) if k < 16 => Ok(k as u8),
+        _ => Err(format!("bad key {}", s)),
+    }
+}
+
+fn byte(s: &str) -> Result<u8, String> {
+    match num(s) {
+        Some(b) if b < 256 => Ok(b as u8),
+        _ => Err(format!("{} isn't a byte", s)),
+    }
+}
+
+fn count(s: &str) -> Result<u64, String> {
+    num(s).ok_or_else(|| format!("bad count {}", s))
+}
+
+pub struct Script {
+    commands: Vec<(usize, Command)>,
+    // Next command to run
+    next: usize,
+    // Frames left of a `wait`
+    waiting: u64,
+    // Keys held, with the frame they're let go at or None for `hold`
+    held: [Option<Option<u64>>; 16],
+    frame: u64,
+    done: bool,
+}
+
+impl Script {
+    pub fn parse(src: &str) -> Result<Self, ScriptError> {
+        let err = |line: usize| move |msg: String| ScriptError { line, msg };
+        // Labels first, so gotos can go forwards
+        let mut labels = HashMap::new();
+        let mut n = 0;
+        for line in src.lines() {
+            let words: Vec<_> = line
+                .split('#')
+                .next()
+                .unwrap_or("")
+                .split_whitespace()
+                .collect();
+            match words.as_slice() {
+                [] => {}
+                ["label", name] => {
+                    labels.insert(name.to_string(), n);
+                }
+                _ => n += 1,
+            }
+        }
+        let label = |name: &str| {
+            labels
+                .get(name)
+                .copied()
+                .ok_or_else(|| format!("no label {}", name))
+        };
+        let mut commands = Vec::new();
+        for (n, line) in src.lines().enumerate() {
+            let line_no = n + 1;
+            let text = line.split('#').next().unwrap_or("");
+            let words: Vec<_> = text.split_whitespace().collect();
+            let cmd = match words.as_slice() {
+                [] | ["label", _] => continue,
+                ["wait", n] => count(n).map(Command::Wait),
+                ["until", c @ ..] => cond(c).map(Command::Until),
+                ["press", k, n] => key(k).and_then(|k| Ok(Command::Press(k, count(n)?))),
+                ["hold", k] => key(k).map(Command::Hold),
+                ["release", k] => key(k).map(Command::Release),
+                ["poke", a, b] => match num(a) {
+                    Some(a) if a < RAM_SIZE_CLASSIC as u64 => {
+                        byte(b).map(|b| Command::Poke(a as u16, b))
+                    }
+                    _ => Err(format!("bad address {}", a)),
+                },
+                ["set", r, b] => match value(r) {
+                    Ok(Value::V(x)) => byte(b).map(|b| Command::Set(x, b)),
+                    _ => Err(format!("{} isn't a register", r)),
+                },
+                ["assert", c @ ..] => cond(c).map(Command::Assert),
+                ["goto", name] => label(name).map(Command::Goto),
+                ["if", c @ .., "goto", name] => {
+                    cond(c).and_then(|c| Ok(Command::If(c, label(name)?)))
+                }
+                ["log", ..] => Ok(Command::Log(
+                    text.trim_start()["log".len()..].trim().to_string(),
+                )),
+                ["pause"] => Ok(Command::Pause),
+                ["screenshot"] => Ok(Command::Screenshot),
+                ["quit"] => Ok(Command::Quit),
+                [cmd, ..] => Err(format!("unknown command {}", cmd)),
+            }
+            .map_err(err(line_no))?;
+            commands.push((line_no, cmd));
+        }
+        Ok(Self {
+            commands,
+            next: 0,
+            waiting: 0,
+            held: [None; 16],
+            frame: 0,
+            done: false,
+        })
+    }
+
+    #[cfg(feature = "scripting")]
+    pub fn load(path: &std::path::Path) -> Result<Self, ScriptError> {
+        let src = std::fs::read_to_string(path).map_err(|e| ScriptError {
+            line: 0,
+            msg: format!("{}: {}", path.display(), e),
+        })?;
+        Self::parse(&src)
+    }
+
+    #[cfg(not(feature = "scripting"))]
+    pub fn load(_: &std::path::Path) -> Result<Self, ScriptError> {
+        Err(ScriptError {
+            line: 0,
+            msg: "built without the scripting feature".to_string(),
+        })
+    }
+
+    /// Finished, failed or quit, so the keys it holds won't change
+    pub fn is_done(&self) -> bool {
+        self.done
+    }
+
+    /// Keys the script is holding down
+    pub fn keys(&self) -> u16 {
+        self.held
+            .iter()
+            .enumerate()
+            .filter(|(_, h)| matches!(h, Some(until) if until.is_none_or(|f| f > self.frame)))
+            .fold(0, |m, (k, _)| m | 1 << k)
+    }
+
+    fn holds(&self, Cond(a, op, b): &Cond, c8: &Chip8) -> Result<bool, ExprError> {
+        let (a, b) = (a.eval(c8, self.frame)?, b.eval(c8, self.frame)?);
+        Ok(match op {
+            Op::Eq => a == b,
+            Op::Ne => a != b,
+            Op::Lt => a < b,
+            Op::Le => a <= b,
+            Op::Gt => a > b,
+            Op::Ge => a >= b,
+        })
+    }
+
+    // `holds`, stopping the script if the condition can't be worked out
+    fn check(&mut self, c: &Cond, c8: &Chip8, line: usize) -> Result<bool, ScriptError> {
+        self.holds(c, c8).map_err(|e| {
+            self.done = true;
+            ScriptError {
+                line,
+                msg: format!("{} at frame {}", e, self.frame),
+            }
+        })
+    }
+
+    /// Runs the script up to the next wait. `frame` is the frame about to
+    /// run. Once this returns an error the script stops and every later
+    /// call does nothing.
+    pub fn before_frame(&mut self, c8: &mut Chip8, frame: u64) -> Result<Vec<Action>, ScriptError> {
+        self.frame = frame;
+        let mut actions = Vec::new();
+        if self.done {
+            return Ok(actions);
+        }
+        if self.waiting > 0 {
+            self.waiting -= 1;
+            return Ok(actions);
+        }
+        for _ in 0..MAX_LINES {
+            let (line, cmd) = match self.commands.get(self.next) {
+                Some((line, cmd)) => (*line, cmd.clone()),
+                None => {
+                    self.done = true;
+                    return Ok(actions);
+                }
+            };
+            self.next += 1;
+            match cmd {
+                Command::Wait(n) => {
+                    if n > 0 {
+                        self.waiting = n - 1;
+                        return Ok(actions);
+                    }
+                }
+                Command::Until(c) => {
+                    if !self.check(&c, c8, line)? {
+                        self.next -= 1;
+                        return Ok(actions);
+                    }
+                }
+                Command::Press(k, n) => self.held[k as usize] = Some(Some(frame + n)),
+                Command::Hold(k) => self.held[k as usize] = Some(None),
+                Command::Release(k) => self.held[k as usize] = None,
+                // Stepping back past these wouldn't undo them
+                Command::Poke(a, b) => {
+                    c8.poke(a, b);
+                    c8.clear_journal();
+                }
+                Command::Set(x, b) => {
+                    c8.set_v(x, b);
+                    c8.clear_journal();
+                }
+                Command::Assert(c) => {
+                    if !self.check(&c, c8, line)? {
+                        self.done = true;
+                        let Cond(a, _, b) = &c;
+                        // Both worked out already in the check
+                        let get = |e: &Expr| e.eval(c8, frame).unwrap_or_default();
+                        return Err(ScriptError {
+                            line,
+                            msg: format!(
+                                "assertion failed ({:#x} and {:#x}) at frame {}",
+                                get(a),
+                                get(b),
+                                frame
+                            ),
+                        });
+                    }
+                }
+                Command::Goto(n) => self.next = n,
+                Command::If(c, n) => {
+                    if self.check(&c, c8, line)? {
+                        self.next = n;
+                    }
+                }
+                Command::Log(s) => actions.push(Action::Log(s)),
+                Command::Pause => actions.push(Action::Pause),
+                Command::Screenshot => actions.push(Action::Screenshot),
+                Command::Quit => {
+                    self.done = true;
+                    actions.push(Action::Quit);
+                    return Ok(actions);
+                }
+            }
+        }
+        self.done = true;
+        Err(ScriptError {
+            line: 0,
+            msg: format!("ran {} lines in one frame without waiting", MAX_LINES),
+        })
+    }
+}
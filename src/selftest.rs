@@ -0,0 +1,626 @@
+//! Small ROMs checking the emulator on the machine it runs on, for
+//! `chip8 selftest`. Each covers a family of instructions, or a quirk run
+//! both ways, and runs headless until it jumps to itself. Nothing here
+//! needs files or a terminal, so ports without `cargo test` can call
+//! `run_all` too.
+use crate::asm::{AsmError, RomBuilder};
+use crate::emu::{Chip8, LoadStoreIncrement, Quirks, StepOutcome};
+use crate::{framebuffer, layout, trace};
+use std::collections::VecDeque;
+use std::fmt;
+
+// Steps before a ROM that hasn't halted counts as stuck
+const STEP_LIMIT: u32 = 1000;
+// Instructions shown when a case fails
+const TRACE_LEN: usize = 8;
+
+/// Quirks a case can flip
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Quirk {
+    ClipX,
+    ClipY,
+    HpShift,
+    MemInc,
+    KeyRelease,
+}
+
+impl Quirk {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::ClipX => "clip_x",
+            Self::ClipY => "clip_y",
+            Self::HpShift => "hp_shift",
+            Self::MemInc => "mem_inc",
+            Self::KeyRelease => "key_release",
+        }
+    }
+
+    fn set(self, q: &mut Quirks, on: bool) {
+        match self {
+            Self::ClipX => q.clip_x = on,
+            Self::ClipY => q.clip_y = on,
+            Self::HpShift => q.hp_shift = on,
+            Self::MemInc => {
+                q.mem_inc = if on {
+                    LoadStoreIncrement::XPlusOne
+                } else {
+                    LoadStoreIncrement::None
+                }
+            }
+            Self::KeyRelease => q.key_release = on,
+        }
+    }
+}
+
+/// How a case should leave the machine
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Expect {
+    /// Registers to check, as (Vx, value)
+    pub v: &'static [(u8, u8)],
+    pub i: Option<u16>,
+    /// `framebuffer::hash` of the screen
+    pub screen: Option<u64>,
+}
+
+/// A case's ROM
+#[derive(Debug, Clone, Copy)]
+pub enum Program {
+    Bytes(&'static [u8]),
+    /// Built when the case runs
+    Built(fn() -> RomBuilder),
+}
+
+impl Program {
+    pub fn bytes(self) -> Result<Vec<u8>, AsmError> {
+        match self {
+            Self::Bytes(b) => Ok(b.to_vec()),
+            Self::Built(f) => f().build().map(|a| a.bytes),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Case {
+    pub name: &'static str,
+    pub rom: Program,
+    /// Keys held the whole run
+    pub keys: u16,
+    /// Run once with the quirk off then on, `expect` holding both. Without
+    /// one it's run once with the default quirks.
+    pub quirk: Option<Quirk>,
+    pub expect: &'static [Expect],
+}
+
+const NONE: Expect = Expect {
+    v: &[],
+    i: None,
+    screen: None,
+};
+
+// Built with `RomBuilder`, or hand assembled with the address of each
+// instruction in the comments
+pub const CASES: &[Case] = &[
+    Case {
+        name: "flow",
+        rom: Program::Built(|| {
+            RomBuilder::new()
+                .call_label("sub")
+                .ld_v(1, 0x02)
+                .label("halt")
+                .jp_label("halt")
+                .label("sub")
+                .ld_v(0, 0x01)
+                .ret()
+        }),
+        keys: 0,
+        quirk: None,
+        expect: &[Expect {
+            v: &[(0, 1), (1, 2)],
+            ..NONE
+        }],
+    },
+    Case {
+        name: "skips",
+        rom: Program::Built(|| {
+            RomBuilder::new()
+                .ld_v(0, 0x05)
+                .ld_v(1, 0x05)
+                .se(0, 0x05)
+                .ld_v(0xA, 0x01)
+                .sne(0, 0x05)
+                .ld_v(0xB, 0x01)
+                .se_vy(0, 1)
+                .ld_v(0xC, 0x01)
+                .sne_vy(0, 1)
+                .ld_v(0xD, 0x01)
+                .label("halt")
+                .jp_label("halt")
+        }),
+        keys: 0,
+        quirk: None,
+        expect: &[Expect {
+            v: &[(0xA, 0), (0xB, 1), (0xC, 0), (0xD, 1)],
+            ..NONE
+        }],
+    },
+    Case {
+        name: "load/add",
+        rom: Program::Bytes(&[
+            0x60, 0xFF, // 200: LD V0, FF
+            0x70, 0x02, // 202: ADD V0, 02
+            0x61, 0x10, // 204: LD V1, 10
+            0x71, 0x20, // 206: ADD V1, 20
+            0x12, 0x08, // 208: JP 208
+        ]),
+        keys: 0,
+        quirk: None,
+        // 7xkk leaves VF alone
+        expect: &[Expect {
+            v: &[(0, 0x01), (1, 0x30), (0xF, 0)],
+            ..NONE
+        }],
+    },
+    Case {
+        name: "logic",
+        rom: Program::Bytes(&[
+            0x60, 0xF0, // 200: LD V0, F0
+            0x61, 0x3C, // 202: LD V1, 3C
+            0x82, 0x00, // 204: LD V2, V0
+            0x82, 0x11, // 206: OR V2, V1
+            0x83, 0x00, // 208: LD V3, V0
+            0x83, 0x12, // 20A: AND V3, V1
+            0x84, 0x00, // 20C: LD V4, V0
+            0x84, 0x13, // 20E: XOR V4, V1
+            0x12, 0x10, // 210: JP 210
+        ]),
+        keys: 0,
+        quirk: None,
+        expect: &[Expect {
+            v: &[(2, 0xFC), (3, 0x30), (4, 0xCC)],
+            ..NONE
+        }],
+    },
+    Case {
+        name: "arithmetic",
+        rom: Program::Bytes(&[
+            0x60, 0xFF, // 200: LD V0, FF
+            0x61, 0x02, // 202: LD V1, 02
+            0x80, 0x14, // 204: ADD V0, V1
+            0x8A, 0xF0, // 206: LD VA, VF
+            0x62, 0x05, // 208: LD V2, 05
+            0x63, 0x07, // 20A: LD V3, 07
+            0x82, 0x35, // 20C: SUB V2, V3
+            0x8B, 0xF0, // 20E: LD VB, VF
+            0x64, 0x05, // 210: LD V4, 05
+            0x65, 0x07, // 212: LD V5, 07
+            0x84, 0x57, // 214: SUBN V4, V5
+            0x12, 0x16, // 216: JP 216
+        ]),
+        keys: 0,
+        quirk: None,
+        expect: &[Expect {
+            v: &[
+                (0, 0x01),
+                (0xA, 1),
+                (2, 0xFE),
+                (0xB, 0),
+                (4, 0x02),
+                (0xF, 1),
+            ],
+            ..NONE
+        }],
+    },
+    Case {
+        name: "index/jump",
+        rom: Program::Built(|| {
+            RomBuilder::new()
+                .ld_v(0, 0x04)
+                .ld_i(0x321)
+                .jp_v0_label("base")
+                .ld_v(0xA, 0x01)
+                .label("base")
+                .jp_label("base")
+                .data(&[0x00, 0x00])
+                .ld_v(0xB, 0x01)
+                .label("halt")
+                .jp_label("halt")
+        }),
+        keys: 0,
+        quirk: None,
+        expect: &[Expect {
+            v: &[(0xA, 0), (0xB, 1)],
+            i: Some(0x321),
+            screen: None,
+        }],
+    },
+    Case {
+        name: "random",
+        rom: Program::Bytes(&[
+            0x60, 0xFF, // 200: LD V0, FF
+            0xC0, 0x00, // 202: RND V0, 00
+            0x12, 0x04, // 204: JP 204
+        ]),
+        keys: 0,
+        quirk: None,
+        expect: &[Expect {
+            v: &[(0, 0)],
+            ..NONE
+        }],
+    },
+    Case {
+        name: "draw",
+        rom: Program::Built(|| {
+            RomBuilder::new()
+                .ld_v(0, 0x08)
+                .ld_v(1, 0x04)
+                .ld_i_label("sprite")
+                .drw(0, 1, 3)
+                .ld_vy(0xA, 0xF)
+                .drw(0, 1, 3)
+                .ld_vy(0xB, 0xF)
+                .drw(0, 1, 3)
+                .label("halt")
+                .jp_label("halt")
+                .label("sprite")
+                .data(&[0xF0, 0x90, 0xF0])
+        }),
+        keys: 0,
+        quirk: None,
+        expect: &[Expect {
+            v: &[(0xA, 0), (0xB, 1), (0xF, 0)],
+            i: None,
+            screen: Some(0xea32_1212_8b94_08a7),
+        }],
+    },
+    Case {
+        name: "keys",
+        rom: Program::Bytes(&[
+            0x60, 0x05, // 200: LD V0, 05
+            0x61, 0x06, // 202: LD V1, 06
+            0xE0, 0x9E, // 204: SKP V0
+            0x6A, 0x01, // 206: LD VA, 01
+            0xE1, 0xA1, // 208: SKNP V1
+            0x6B, 0x01, // 20A: LD VB, 01
+            0xE1, 0x9E, // 20C: SKP V1
+            0x6C, 0x01, // 20E: LD VC, 01
+            0x12, 0x10, // 210: JP 210
+        ]),
+        keys: 1 << 5,
+        quirk: None,
+        expect: &[Expect {
+            v: &[(0xA, 0), (0xB, 0), (0xC, 1)],
+            ..NONE
+        }],
+    },
+    Case {
+        name: "timers",
+        rom: Program::Bytes(&[
+            0x60, 0x20, // 200: LD V0, 20
+            0xF0, 0x15, // 202: LD DT, V0
+            0xF0, 0x18, // 204: LD ST, V0
+            0xF1, 0x07, // 206: LD V1, DT
+            0x12, 0x08, // 208: JP 208
+        ]),
+        keys: 0,
+        quirk: None,
+        expect: &[Expect {
+            v: &[(1, 0x20)],
+            ..NONE
+        }],
+    },
+    Case {
+        name: "memory",
+        rom: Program::Bytes(&[
+            0xA3, 0x00, // 200: LD I, 300
+            0x60, 0x05, // 202: LD V0, 05
+            0xF0, 0x1E, // 204: ADD I, V0
+            0x61, 0xFE, // 206: LD V1, FE
+            0xF1, 0x33, // 208: LD B, V1
+            0xF2, 0x65, // 20A: LD V2, [I]
+            0xF0, 0x29, // 20C: LD F, V0
+            0x12, 0x0E, // 20E: JP 20E
+        ]),
+        keys: 0,
+        quirk: None,
+        // 254 in decimal, then the font's 5
+        expect: &[Expect {
+            v: &[(0, 2), (1, 5), (2, 4)],
+            i: Some(layout::font_sprite_addr(2)),
+            screen: None,
+        }],
+    },
+    Case {
+        name: "shift",
+        rom: Program::Bytes(&[
+            0x60, 0x81, // 200: LD V0, 81
+            0x61, 0x04, // 202: LD V1, 04
+            0x82, 0x00, // 204: LD V2, V0
+            0x82, 0x16, // 206: SHR V2, V1
+            0x8A, 0xF0, // 208: LD VA, VF
+            0x83, 0x00, // 20A: LD V3, V0
+            0x83, 0x1E, // 20C: SHL V3, V1
+            0x12, 0x0E, // 20E: JP 20E
+        ]),
+        keys: 0,
+        quirk: Some(Quirk::HpShift),
+        expect: &[
+            Expect {
+                v: &[(2, 0x02), (0xA, 0), (3, 0x08), (0xF, 0)],
+                ..NONE
+            },
+            Expect {
+                v: &[(2, 0x40), (0xA, 1), (3, 0x02), (0xF, 1)],
+                ..NONE
+            },
+        ],
+    },
+    Case {
+        name: "store/load",
+        rom: Program::Bytes(&[
+            0x60, 0x11, // 200: LD V0, 11
+            0x61, 0x22, // 202: LD V1, 22
+            0xA3, 0x00, // 204: LD I, 300
+            0xF1, 0x55, // 206: LD [I], V1
+            0xA3, 0x00, // 208: LD I, 300
+            0xF1, 0x65, // 20A: LD V1, [I]
+            0x12, 0x0C, // 20C: JP 20C
+        ]),
+        keys: 0,
+        quirk: Some(Quirk::MemInc),
+        expect: &[
+            Expect {
+                v: &[(0, 0x11), (1, 0x22)],
+                i: Some(0x300),
+                screen: None,
+            },
+            Expect {
+                v: &[(0, 0x11), (1, 0x22)],
+                i: Some(0x302),
+                screen: None,
+            },
+        ],
+    },
+    Case {
+        name: "clip right",
+        rom: Program::Built(|| {
+            RomBuilder::new()
+                .ld_v(0, 0x7C)
+                .ld_v(1, 0x22)
+                .ld_i_label("sprite")
+                .drw(0, 1, 1)
+                .label("halt")
+                .jp_label("halt")
+                .label("sprite")
+                .data(&[0xFF])
+        }),
+        keys: 0,
+        quirk: Some(Quirk::ClipX),
+        // Starting at (60, 2) either way
+        expect: &[
+            Expect {
+                v: &[],
+                i: None,
+                screen: Some(0xdb73_e1f5_f65d_9dd6),
+            },
+            Expect {
+                v: &[],
+                i: None,
+                screen: Some(0x3847_6f4a_3571_1106),
+            },
+        ],
+    },
+    Case {
+        name: "clip bottom",
+        rom: Program::Built(|| {
+            RomBuilder::new()
+                .ld_v(1, 0x1F)
+                .ld_i_label("sprite")
+                .drw(0, 1, 2)
+                .label("halt")
+                .jp_label("halt")
+                .label("sprite")
+                .data(&[0x80, 0x80])
+        }),
+        keys: 0,
+        quirk: Some(Quirk::ClipY),
+        expect: &[
+            Expect {
+                v: &[],
+                i: None,
+                screen: Some(0x477e_19bd_e829_6f57),
+            },
+            Expect {
+                v: &[],
+                i: None,
+                screen: Some(0xe53d_5671_faf1_8fd7),
+            },
+        ],
+    },
+    Case {
+        name: "wait key",
+        rom: Program::Bytes(&[
+            0x60, 0xFF, // 200: LD V0, FF
+            0xF0, 0x0A, // 202: LD V0, K
+            0x12, 0x04, // 204: JP 204
+        ]),
+        keys: 1 << 7,
+        quirk: Some(Quirk::KeyRelease),
+        // Held down and never let go
+        expect: &[
+            Expect {
+                v: &[(0, 7)],
+                ..NONE
+            },
+            Expect {
+                v: &[(0, 0xFF)],
+                ..NONE
+            },
+        ],
+    },
+];
+
+/// How one run of a case went
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CaseResult {
+    pub name: &'static str,
+    /// The quirk and how it was set, for cases that flip one
+    pub quirk: Option<(Quirk, bool)>,
+    /// What didn't match, empty if it passed
+    pub failures: Vec<String>,
+    /// The last instructions run, in `trace::compact` form
+    pub trace: Vec<String>,
+}
+
+impl CaseResult {
+    pub fn passed(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+/// Runs `case` with its quirk set to `on`, ignored without one
+pub fn run(case: &Case, on: bool) -> CaseResult {
+    let mut quirks = Quirks::default();
+    if let Some(q) = case.quirk {
+        q.set(&mut quirks, on);
+    }
+    let expect = &case.expect[(case.quirk.is_some() && on) as usize];
+    let mut result = CaseResult {
+        name: case.name,
+        quirk: case.quirk.map(|q| (q, on)),
+        failures: Vec::new(),
+        trace: Vec::new(),
+    };
+    let rom = match case.rom.bytes() {
+        Ok(rom) => rom,
+        Err(e) => {
+            result.failures.push(e.to_string());
+            return result;
+        }
+    };
+    let mut c8 = match Chip8::builder()
+        .rom(&rom)
+        .quirks(quirks)
+        // The timers stand still
+        .clock(Box::new(|| 0.0))
+        .validate(true)
+        .build()
+    {
+        Ok(c8) => c8,
+        Err(e) => {
+            result.failures.push(e.to_string());
+            return result;
+        }
+    };
+    c8.set_keys(case.keys);
+    let mut recent = VecDeque::with_capacity(TRACE_LEN);
+    let mut finished = false;
+    for _ in 0..STEP_LIMIT {
+        let pc = c8.pc();
+        let op = c8.peek_word(pc);
+        let res = c8.step();
+        if recent.len() == TRACE_LEN {
+            recent.pop_front();
+        }
+        recent.push_back(trace::compact(&c8, pc, op));
+        match res {
+            Ok(StepOutcome::Halted) | Ok(StepOutcome::WaitingForKey) => {
+                finished = true;
+                break;
+            }
+            Ok(_) => {}
+            Err(f) => {
+                result.failures.push(f.to_string());
+                break;
+            }
+        }
+    }
+    if !finished && result.failures.is_empty() {
+        result
+            .failures
+            .push(format!("still running after {} steps", STEP_LIMIT));
+    }
+    let v = c8.v();
+    for &(x, want) in expect.v {
+        if v[x as usize] != want {
+            result.failures.push(format!(
+                "V{:X} is {:02X}, expected {:02X}",
+                x, v[x as usize], want
+            ));
+        }
+    }
+    if let Some(want) = expect.i.filter(|&i| i != c8.i()) {
+        result
+            .failures
+            .push(format!("I is {:03X}, expected {:03X}", c8.i(), want));
+    }
+    let hash = framebuffer::hash(&c8.screen);
+    if let Some(want) = expect.screen.filter(|&h| h != hash) {
+        result
+            .failures
+            .push(format!("screen is {:016x}, expected {:016x}", hash, want));
+    }
+    if !result.passed() {
+        result.trace = recent.into_iter().collect();
+    }
+    result
+}
+
+/// Every case, quirk cases both ways
+///
+/// ```
+/// let results = chip8::selftest::run_all();
+/// assert!(results.iter().all(|r| r.passed()), "{}", chip8::selftest::Report(&results));
+/// ```
+pub fn run_all() -> Vec<CaseResult> {
+    let mut out = Vec::new();
+    for case in CASES {
+        out.push(run(case, false));
+        if case.quirk.is_some() {
+            out.push(run(case, true));
+        }
+    }
+    out
+}
+
+/// Pass or fail for each case, with what went wrong under any that failed
+pub struct Report<'a>(pub &'a [CaseResult]);
+
+impl fmt::Display for Report<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{:<16} {:<12} {:<4} on", "case", "quirk", "off")?;
+        let mut rows = self.0.iter().peekable();
+        while let Some(r) = rows.next() {
+            let mark = |r: &CaseResult| if r.passed() { "pass" } else { "FAIL" };
+            let (quirk, off, on) = match r.quirk {
+                Some((q, false)) => {
+                    let on = rows.next_if(|n| n.name == r.name);
+                    (q.name(), mark(r), on.map_or("", mark))
+                }
+                Some((q, true)) => (q.name(), "", mark(r)),
+                None => ("-", mark(r), ""),
+            };
+            let row = format!("{:<16} {:<12} {:<4} {}", r.name, quirk, off, on);
+            writeln!(f, "{}", row.trim_end())?;
+        }
+        for r in self.0.iter().filter(|r| !r.passed()) {
+            match r.quirk {
+                Some((q, on)) => {
+                    let set = if on { "on" } else { "off" };
+                    writeln!(f, "\n{} with {} {}:", r.name, q.name(), set)?
+                }
+                None => writeln!(f, "\n{}:", r.name)?,
+            }
+            for e in &r.failures {
+                writeln!(f, "  {}", e)?;
+            }
+            if !r.trace.is_empty() {
+                writeln!(f, "  last instructions:")?;
+                for line in &r.trace {
+                    writeln!(f, "    {}", line)?;
+                }
+            }
+        }
+        let failed = self.0.iter().filter(|r| !r.passed()).count();
+        write!(f, "\n{} of {} passed", self.0.len() - failed, self.0.len())
+    }
+}
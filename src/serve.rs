@@ -0,0 +1,583 @@
+//! Watching and playing over the network with `--serve`. A browser loads
+//! the page from `/` and talks to `/ws` over a WebSocket, getting frames
+//! and sending keys and commands as binary messages.
+//!
+//! Frame (server to client): `01`, frame number (u64), 32 rows (u64 each),
+//! all big-endian with the leftmost pixel in the top bit.
+//!
+//! Commands (client to server): `10 k` key k down, `11 k` key k up,
+//! `20` pause or unpause, `21` reset, `22 n` load save slot n.
+//!
+//! For watching without the page, `GET /state` is the run so far as JSON,
+//! `GET /screen.txt` the screen as text, for `watch curl`, and
+//! `GET /warnings` what's been warned about and how often. They only
+//! read. `POST /pause` and `POST /reset` are there too, but refused unless
+//! the server was started with `--allow-control`.
+use crate::layout::LORES_HEIGHT;
+use crate::warnings::Warnings;
+use crate::{framebuffer, json, text};
+use std::io;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{Receiver, Sender, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Bytes in a frame message
+pub const FRAME_LEN: usize = 1 + 8 + LORES_HEIGHT * 8;
+
+// Fastest frames are sent, about 60 Hz
+const MIN_GAP: Duration = Duration::from_micros(16_667);
+/// `/screen.txt` is drawn again at most this often, however often it's
+/// asked for
+pub const SCREEN_GAP: Duration = Duration::from_millis(250);
+
+const FRAME: u8 = 0x01;
+const KEY_DOWN: u8 = 0x10;
+const KEY_UP: u8 = 0x11;
+const PAUSE: u8 = 0x20;
+const RESET: u8 = 0x21;
+const LOAD: u8 = 0x22;
+
+pub fn encode_frame(frame: u64, screen: &[u64; 32]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(FRAME_LEN);
+    out.push(FRAME);
+    out.extend_from_slice(&frame.to_be_bytes());
+    for row in screen {
+        out.extend_from_slice(&row.to_be_bytes());
+    }
+    out
+}
+
+/// Frame number and rows of a frame message
+pub fn decode_frame(msg: &[u8]) -> Option<(u64, [u64; 32])> {
+    if msg.len() != FRAME_LEN || msg[0] != FRAME {
+        return None;
+    }
+    let word = |at: usize| {
+        let mut b = [0u8; 8];
+        b.copy_from_slice(&msg[at..at + 8]);
+        u64::from_be_bytes(b)
+    };
+    let mut screen = [0u64; 32];
+    for (n, row) in screen.iter_mut().enumerate() {
+        *row = word(9 + n * 8);
+    }
+    Some((word(1), screen))
+}
+
+/// Something a client asked for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Command {
+    KeyDown(u8),
+    KeyUp(u8),
+    Pause,
+    Reset,
+    /// Load a numbered save slot
+    Load(u8),
+}
+
+impl Command {
+    pub fn encode(&self) -> Vec<u8> {
+        match *self {
+            Self::KeyDown(k) => vec![KEY_DOWN, k],
+            Self::KeyUp(k) => vec![KEY_UP, k],
+            Self::Pause => vec![PAUSE],
+            Self::Reset => vec![RESET],
+            Self::Load(n) => vec![LOAD, n],
+        }
+    }
+
+    /// `None` for anything malformed, including keys above 0xF
+    pub fn decode(msg: &[u8]) -> Option<Self> {
+        match *msg {
+            [KEY_DOWN, k] if k < 16 => Some(Self::KeyDown(k)),
+            [KEY_UP, k] if k < 16 => Some(Self::KeyUp(k)),
+            [PAUSE] => Some(Self::Pause),
+            [RESET] => Some(Self::Reset),
+            [LOAD, n] => Some(Self::Load(n)),
+            _ => None,
+        }
+    }
+}
+
+type Clients = Arc<Mutex<Vec<SyncSender<Arc<Vec<u8>>>>>>;
+
+/// The run as of the last frame, for `/state` and `/screen.txt`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Snapshot {
+    pub frame: u64,
+    pub instructions: u64,
+    pub pc: u16,
+    pub paused: bool,
+    pub screen: [u64; 32],
+}
+
+/// An answer to a plain HTTP request
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Response {
+    pub status: u16,
+    pub content_type: &'static str,
+    pub body: String,
+}
+
+impl Response {
+    fn new(status: u16, content_type: &'static str, body: String) -> Self {
+        Self {
+            status,
+            content_type,
+            body,
+        }
+    }
+
+    fn empty(status: u16) -> Self {
+        Self::new(status, "text/plain", String::new())
+    }
+
+    /// Status line, headers and body
+    pub fn to_http(&self) -> String {
+        let reason = match self.status {
+            200 => "OK",
+            204 => "No Content",
+            403 => "Forbidden",
+            404 => "Not Found",
+            405 => "Method Not Allowed",
+            _ => "Service Unavailable",
+        };
+        format!(
+            "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\n\
+             Cache-Control: no-store\r\nConnection: close\r\n\r\n{}",
+            self.status,
+            reason,
+            self.content_type,
+            self.body.len(),
+            self.body
+        )
+    }
+}
+
+/// The HTTP routes besides the page and the WebSocket. The emulator
+/// publishes a `Snapshot` each frame, which only copies it, and requests
+/// are answered from the last one on the server's threads.
+///
+/// ```
+/// use chip8::serve::{Command, Dashboard, Snapshot, SCREEN_GAP};
+/// use std::time::{Duration, Instant};
+///
+/// let start = Instant::now();
+/// let (tx, rx) = std::sync::mpsc::channel();
+/// let dash = Dashboard::new(start, tx, false);
+/// assert_eq!(dash.respond("GET", "/state", start).status, 503);
+///
+/// let mut screen = [0u64; 32];
+/// screen[0] = 1 << 63;
+/// dash.publish(Snapshot { frame: 120, instructions: 120, pc: 0x22A, paused: false, screen });
+/// let state = dash.respond("GET", "/state", start + Duration::from_secs(6));
+/// assert_eq!((state.status, state.content_type), (200, "application/json"));
+/// assert_eq!(
+///     state.body,
+///     format!(
+///         r#"{{"frame_count":120,"instruction_count":120,"pc":"22A","screen_hash":"{:016x}","uptime_ms":6000,"paused":false}}"#,
+///         chip8::framebuffer::hash(&screen)
+///     )
+/// );
+///
+/// // The screen is drawn again once SCREEN_GAP has gone by, not before
+/// let text = dash.respond("GET", "/screen.txt", start);
+/// assert_eq!(text.body.lines().next().unwrap(), format!("\u{2588}{}", " ".repeat(63)));
+/// dash.publish(Snapshot { frame: 121, instructions: 121, pc: 0x22C, paused: false, screen: [0; 32] });
+/// assert_eq!(dash.respond("GET", "/screen.txt", start + SCREEN_GAP / 2), text);
+/// assert!(dash.respond("GET", "/screen.txt", start + SCREEN_GAP).body.trim().is_empty());
+///
+/// // Read only unless control is allowed
+/// assert_eq!(dash.respond("POST", "/reset", start).status, 403);
+/// assert_eq!(dash.respond("POST", "/state", start).status, 405);
+/// assert_eq!(dash.respond("GET", "/nope", start).status, 404);
+/// assert_eq!(dash.respond("GET", "/warnings", start).body, "[]");
+/// let mut warnings = chip8::warnings::Warnings::new();
+/// warnings.warn("unknown opcode", 0x5001, || "Skipped unknown opcode 5001".into());
+/// dash.publish_warnings(&warnings);
+/// assert!(dash.respond("GET", "/warnings", start).body.contains(r#""count":1"#));
+/// assert!(rx.try_recv().is_err());
+/// let (tx, rx) = std::sync::mpsc::channel();
+/// let dash = Dashboard::new(start, tx, true);
+/// assert_eq!(dash.respond("POST", "/pause", start).status, 204);
+/// assert_eq!(dash.respond("GET", "/pause", start).status, 405);
+/// assert_eq!(rx.try_recv(), Ok(Command::Pause));
+/// ```
+pub struct Dashboard {
+    started: Instant,
+    commands: Sender<Command>,
+    allow_control: bool,
+    latest: Mutex<Option<Snapshot>>,
+    warnings: Mutex<Warnings>,
+    // The last /screen.txt and when it was drawn
+    screen: Mutex<Option<(Instant, String)>>,
+}
+
+impl Dashboard {
+    /// Uptime counts from `started`. Commands go to `commands`, if
+    /// `allow_control`.
+    pub fn new(started: Instant, commands: Sender<Command>, allow_control: bool) -> Self {
+        Self {
+            started,
+            commands,
+            allow_control,
+            latest: Mutex::new(None),
+            warnings: Mutex::new(Warnings::new()),
+            screen: Mutex::new(None),
+        }
+    }
+
+    pub fn publish(&self, snapshot: Snapshot) {
+        *self.latest.lock().unwrap_or_else(|e| e.into_inner()) = Some(snapshot);
+    }
+
+    /// What `/warnings` will show
+    pub fn publish_warnings(&self, warnings: &Warnings) {
+        *self.warnings.lock().unwrap_or_else(|e| e.into_inner()) = warnings.clone();
+    }
+
+    fn latest(&self) -> Option<Snapshot> {
+        *self.latest.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// The answer to `method` on `path` at `now`
+    pub fn respond(&self, method: &str, path: &str, now: Instant) -> Response {
+        let command = match path {
+            "/state" | "/screen.txt" | "/warnings" if method != "GET" => {
+                return Response::empty(405)
+            }
+            "/warnings" => {
+                let w = self.warnings.lock().unwrap_or_else(|e| e.into_inner());
+                return Response::new(200, "application/json", w.to_json());
+            }
+            "/pause" | "/reset" if method != "POST" => return Response::empty(405),
+            "/pause" => Some(Command::Pause),
+            "/reset" => Some(Command::Reset),
+            _ => None,
+        };
+        if let Some(cmd) = command {
+            if !self.allow_control {
+                return Response::new(403, "text/plain", "start with --allow-control\n".into());
+            }
+            let _ = self.commands.send(cmd);
+            return Response::empty(204);
+        }
+        let snap = match (path, self.latest()) {
+            ("/state" | "/screen.txt", None) => {
+                return Response::new(503, "text/plain", "no frame yet\n".into())
+            }
+            (_, Some(snap)) => snap,
+            _ => return Response::empty(404),
+        };
+        match path {
+            "/state" => Response::new(
+                200,
+                "application/json",
+                json::Object::new()
+                    .num("frame_count", snap.frame)
+                    .num("instruction_count", snap.instructions)
+                    .str("pc", &format!("{:03X}", snap.pc))
+                    .str(
+                        "screen_hash",
+                        &format!("{:016x}", framebuffer::hash(&snap.screen)),
+                    )
+                    .num(
+                        "uptime_ms",
+                        now.saturating_duration_since(self.started).as_millis(),
+                    )
+                    .raw("paused", snap.paused.to_string())
+                    .to_string(),
+            ),
+            "/screen.txt" => {
+                let mut cached = self.screen.lock().unwrap_or_else(|e| e.into_inner());
+                match &*cached {
+                    Some((at, text)) if now.saturating_duration_since(*at) < SCREEN_GAP => {
+                        Response::new(200, "text/plain; charset=utf-8", text.clone())
+                    }
+                    _ => {
+                        let text = text::to_text(&snap.screen);
+                        *cached = Some((now, text.clone()));
+                        Response::new(200, "text/plain; charset=utf-8", text)
+                    }
+                }
+            }
+            _ => Response::empty(404),
+        }
+    }
+}
+
+/// Listens for clients on a background thread. Nothing it does blocks the
+/// caller, slow clients just miss frames.
+pub struct Server {
+    addr: SocketAddr,
+    commands: Receiver<Command>,
+    clients: Clients,
+    dashboard: Arc<Dashboard>,
+    stop: Arc<AtomicBool>,
+    last_push: Option<Instant>,
+}
+
+impl Server {
+    /// With `allow_control` the HTTP routes that change things work
+    #[cfg(feature = "serve")]
+    pub fn bind(addr: &str, allow_control: bool) -> io::Result<Self> {
+        let listener = std::net::TcpListener::bind(addr)?;
+        listener.set_nonblocking(true)?;
+        let (tx, commands) = std::sync::mpsc::channel();
+        let dashboard = Arc::new(Dashboard::new(Instant::now(), tx.clone(), allow_control));
+        let server = Self {
+            addr: listener.local_addr()?,
+            commands,
+            clients: Clients::default(),
+            dashboard: dashboard.clone(),
+            stop: Arc::new(AtomicBool::new(false)),
+            last_push: None,
+        };
+        let (clients, stop) = (server.clients.clone(), server.stop.clone());
+        std::thread::spawn(move || net::accept(listener, tx, clients, dashboard, stop));
+        Ok(server)
+    }
+
+    #[cfg(not(feature = "serve"))]
+    pub fn bind(_: &str, _: bool) -> io::Result<Self> {
+        Err(io::Error::other("built without the serve feature"))
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Commands received since the last call
+    pub fn commands(&self) -> impl Iterator<Item = Command> + '_ {
+        self.commands.try_iter()
+    }
+
+    /// What `/state` and `/screen.txt` will show
+    pub fn publish(&self, snapshot: Snapshot) {
+        self.dashboard.publish(snapshot);
+    }
+
+    /// What `/warnings` will show
+    pub fn publish_warnings(&self, warnings: &Warnings) {
+        self.dashboard.publish_warnings(warnings);
+    }
+
+    /// Queues a frame for every client, unless one went out too recently
+    pub fn push(&mut self, frame: u64, screen: &[u64; 32]) {
+        let now = Instant::now();
+        if self.last_push.is_some_and(|t| now - t < MIN_GAP) {
+            return;
+        }
+        self.last_push = Some(now);
+        let msg = Arc::new(encode_frame(frame, screen));
+        let mut clients = self.clients.lock().unwrap_or_else(|e| e.into_inner());
+        clients.retain(|c| !matches!(c.try_send(msg.clone()), Err(TrySendError::Disconnected(_))));
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+    }
+}
+
+#[cfg(feature = "serve")]
+mod net {
+    use super::{Clients, Command, Dashboard};
+    use std::io::{self, Read, Write};
+    use std::net::{Shutdown, TcpListener, TcpStream};
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::mpsc::Sender;
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+
+    const PAGE: &str = include_str!("serve.html");
+    const WS_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+    // Frames waiting to go to one client, any more and they're dropped
+    const QUEUE: usize = 4;
+
+    // Polls so the thread ends soon after the server is dropped
+    pub fn accept(
+        listener: TcpListener,
+        tx: Sender<Command>,
+        clients: Clients,
+        dashboard: Arc<Dashboard>,
+        stop: Arc<AtomicBool>,
+    ) {
+        while !stop.load(Ordering::SeqCst) {
+            match listener.accept() {
+                Ok((stream, _)) => {
+                    let (tx, clients, dashboard) = (tx.clone(), clients.clone(), dashboard.clone());
+                    std::thread::spawn(move || {
+                        let _ = client(stream, tx, clients, &dashboard);
+                    });
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                    std::thread::sleep(Duration::from_millis(50))
+                }
+                Err(_) => break,
+            }
+        }
+    }
+
+    fn base64(data: &[u8]) -> String {
+        const ABC: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+        let mut out = String::new();
+        for c in data.chunks(3) {
+            let n = (c[0] as u32) << 16
+                | (*c.get(1).unwrap_or(&0) as u32) << 8
+                | *c.get(2).unwrap_or(&0) as u32;
+            for i in 0..4 {
+                if i <= c.len() {
+                    out.push(ABC[(n >> (18 - 6 * i)) as usize & 63] as char);
+                } else {
+                    out.push('=');
+                }
+            }
+        }
+        out
+    }
+
+    // Request line and headers, up to the blank line
+    fn read_head(stream: &mut TcpStream) -> io::Result<String> {
+        let mut head = Vec::new();
+        let mut b = [0u8];
+        while !head.ends_with(b"\r\n\r\n") {
+            if head.len() > 8192 || stream.read(&mut b)? == 0 {
+                return Err(io::ErrorKind::InvalidData.into());
+            }
+            head.push(b[0]);
+        }
+        Ok(String::from_utf8_lossy(&head).into_owned())
+    }
+
+    fn client(
+        mut stream: TcpStream,
+        tx: Sender<Command>,
+        clients: Clients,
+        dashboard: &Dashboard,
+    ) -> io::Result<()> {
+        stream.set_nonblocking(false)?;
+        stream.set_read_timeout(Some(Duration::from_secs(10)))?;
+        let head = read_head(&mut stream)?;
+        let mut words = head.split_whitespace();
+        let method = words.next().unwrap_or("");
+        let path = words.next().unwrap_or("");
+        let key = head.lines().find_map(|l| {
+            let (name, value) = l.split_once(':')?;
+            name.trim()
+                .eq_ignore_ascii_case("sec-websocket-key")
+                .then(|| value.trim().to_string())
+        });
+        let key = match (path, key) {
+            ("/ws", Some(key)) => key,
+            ("/", _) => {
+                return write!(
+                    stream,
+                    "HTTP/1.1 200 OK\r\nContent-Type: text/html\r\nContent-Length: {}\r\n\
+                     Connection: close\r\n\r\n{}",
+                    PAGE.len(),
+                    PAGE
+                )
+            }
+            _ => {
+                let res = dashboard.respond(method, path, Instant::now());
+                return stream.write_all(res.to_http().as_bytes());
+            }
+        };
+        let accept = base64(&crate::info::sha1(format!("{}{}", key, WS_GUID).as_bytes()));
+        write!(
+            stream,
+            "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\n\
+             Connection: Upgrade\r\nSec-WebSocket-Accept: {}\r\n\r\n",
+            accept
+        )?;
+        stream.set_read_timeout(None)?;
+
+        let (frames, rx) = std::sync::mpsc::sync_channel::<Arc<Vec<u8>>>(QUEUE);
+        clients
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(frames);
+        let mut out = stream.try_clone()?;
+        std::thread::spawn(move || {
+            for msg in rx {
+                if write_message(&mut out, &msg).is_err() {
+                    break;
+                }
+            }
+            // Also ends the reading side
+            let _ = out.shutdown(Shutdown::Both);
+        });
+        let res = read_commands(&mut stream, &tx);
+        let _ = stream.shutdown(Shutdown::Both);
+        res
+    }
+
+    fn read_commands(stream: &mut TcpStream, tx: &Sender<Command>) -> io::Result<()> {
+        loop {
+            let mut head = [0u8; 2];
+            stream.read_exact(&mut head)?;
+            let len = match head[1] & 0x7F {
+                126 => {
+                    let mut n = [0u8; 2];
+                    stream.read_exact(&mut n)?;
+                    u16::from_be_bytes(n) as u64
+                }
+                127 => {
+                    let mut n = [0u8; 8];
+                    stream.read_exact(&mut n)?;
+                    u64::from_be_bytes(n)
+                }
+                n => n as u64,
+            };
+            // Commands are a couple of bytes, anything big isn't ours
+            if len > 1024 {
+                return Err(io::ErrorKind::InvalidData.into());
+            }
+            let mut mask = [0u8; 4];
+            if head[1] & 0x80 != 0 {
+                stream.read_exact(&mut mask)?;
+            }
+            let mut payload = vec![0u8; len as usize];
+            stream.read_exact(&mut payload)?;
+            for (n, b) in payload.iter_mut().enumerate() {
+                *b ^= mask[n % 4];
+            }
+            match head[0] & 0x0F {
+                // Close
+                0x8 => return Ok(()),
+                0x2 => {
+                    if let Some(cmd) = Command::decode(&payload) {
+                        if tx.send(cmd).is_err() {
+                            return Ok(());
+                        }
+                    }
+                }
+                // Pings and text go unanswered
+                _ => {}
+            }
+        }
+    }
+
+    // One unmasked binary message
+    fn write_message(stream: &mut TcpStream, msg: &[u8]) -> io::Result<()> {
+        let mut out = vec![0x82];
+        match msg.len() {
+            n if n < 126 => out.push(n as u8),
+            n if n <= 0xFFFF => {
+                out.push(126);
+                out.extend_from_slice(&(n as u16).to_be_bytes());
+            }
+            n => {
+                out.push(127);
+                out.extend_from_slice(&(n as u64).to_be_bytes());
+            }
+        }
+        out.extend_from_slice(msg);
+        stream.write_all(&out)
+    }
+}
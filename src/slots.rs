@@ -0,0 +1,98 @@
+use crate::state::{SaveState, StateError};
+use std::fmt;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// Number of save slots
+pub const SLOTS: u8 = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Slot {
+    /// 0 to SLOTS - 1
+    Numbered(u8),
+    Auto,
+}
+
+impl fmt::Display for Slot {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Numbered(n) => write!(f, "slot {}", n + 1),
+            Self::Auto => write!(f, "autosave"),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum SlotError {
+    Io(io::Error),
+    Empty,
+    State(StateError),
+    /// Saved while a different ROM was loaded
+    WrongRom,
+}
+
+impl fmt::Display for SlotError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "{}", e),
+            Self::Empty => write!(f, "empty"),
+            Self::State(e) => write!(f, "{}", e),
+            Self::WrongRom => write!(f, "saved from a different ROM"),
+        }
+    }
+}
+
+impl std::error::Error for SlotError {}
+
+/// Save states for one ROM, kept in a directory named after its hash
+pub struct Slots {
+    dir: PathBuf,
+    rom: u64,
+}
+
+impl Slots {
+    pub fn new(base: &Path, rom: u64) -> Self {
+        Self {
+            dir: base.join(format!("{:016x}", rom)),
+            rom,
+        }
+    }
+
+    /// Where this ROM's states go
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn path(&self, slot: Slot) -> PathBuf {
+        match slot {
+            Slot::Numbered(n) => self.dir.join(format!("slot-{}.c8s", n + 1)),
+            Slot::Auto => self.dir.join("autosave.c8s"),
+        }
+    }
+
+    /// Writes to a temporary file first, so a crash mid-write can't leave
+    /// a broken state behind
+    pub fn save(&self, slot: Slot, state: &SaveState) -> io::Result<()> {
+        std::fs::create_dir_all(&self.dir)?;
+        let path = self.path(slot);
+        let tmp = path.with_extension("tmp");
+        let mut f = std::fs::File::create(&tmp)?;
+        f.write_all(&state.to_bytes())?;
+        f.sync_all()?;
+        drop(f);
+        std::fs::rename(&tmp, &path)
+    }
+
+    pub fn load(&self, slot: Slot) -> Result<SaveState, SlotError> {
+        let data = match std::fs::read(self.path(slot)) {
+            Ok(d) => d,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Err(SlotError::Empty),
+            Err(e) => return Err(SlotError::Io(e)),
+        };
+        let state = SaveState::from_bytes(&data).map_err(SlotError::State)?;
+        if state.rom != self.rom {
+            return Err(SlotError::WrongRom);
+        }
+        Ok(state)
+    }
+}
@@ -0,0 +1,366 @@
+//! Making ST heard. There's no audio backend, so the terminal bell
+//! stands in for a tone. `Tone` makes the samples one would play.
+use std::io::Write;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU8, Ordering};
+use std::sync::Arc;
+
+/// Fewest frames between bells, however often ST starts
+pub const BELL_GAP: u64 = 30;
+
+/// Told every frame whether ST is running
+pub trait SoundSink {
+    fn update(&mut self, frame: u64, playing: bool);
+}
+
+/// Keeps quiet
+pub struct NullSink;
+
+impl SoundSink for NullSink {
+    fn update(&mut self, _: u64, _: bool) {}
+}
+
+/// Rings the bell once when ST starts, however long it runs for, and not
+/// again within `BELL_GAP` frames
+///
+/// ```
+/// use chip8::sound::{BellSink, SoundSink};
+///
+/// let mut bell = BellSink::new(Vec::new());
+/// for frame in 0..200 {
+///     bell.update(frame, (10..130).contains(&frame));
+/// }
+/// assert_eq!(bell.rings(), 1);
+/// assert_eq!(bell.into_inner(), b"\x07");
+/// ```
+pub struct BellSink<W: Write> {
+    out: W,
+    playing: bool,
+    last: Option<u64>,
+    rings: u64,
+}
+
+impl<W: Write> BellSink<W> {
+    pub fn new(out: W) -> Self {
+        Self {
+            out,
+            playing: false,
+            last: None,
+            rings: 0,
+        }
+    }
+
+    /// Times the bell has rung
+    pub fn rings(&self) -> u64 {
+        self.rings
+    }
+
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+}
+
+impl<W: Write> SoundSink for BellSink<W> {
+    fn update(&mut self, frame: u64, playing: bool) {
+        let started = playing && !self.playing;
+        self.playing = playing;
+        if started && self.last.is_none_or(|l| frame >= l + BELL_GAP) {
+            self.last = Some(frame);
+            self.rings += 1;
+            // Nobody to tell if the terminal has gone
+            let _ = self.out.write_all(b"\x07").and_then(|()| self.out.flush());
+        }
+    }
+}
+
+/// Shape of the beeper's wave
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Waveform {
+    Square,
+    Triangle,
+    Sine,
+}
+
+impl Waveform {
+    /// Level at `phase` (0 to 1) through a period, from -1 to 1. `duty`
+    /// is the part of the period a square wave is high for.
+    ///
+    /// ```
+    /// use chip8::sound::Waveform;
+    ///
+    /// let period = |w: Waveform, duty| -> Vec<f32> {
+    ///     (0..8).map(|n| w.level(n as f32 / 8.0, duty)).collect()
+    /// };
+    /// assert_eq!(period(Waveform::Square, 0.5), [1.0, 1.0, 1.0, 1.0, -1.0, -1.0, -1.0, -1.0]);
+    /// assert_eq!(period(Waveform::Square, 0.25), [1.0, 1.0, -1.0, -1.0, -1.0, -1.0, -1.0, -1.0]);
+    /// assert_eq!(period(Waveform::Triangle, 0.5), [-1.0, -0.5, 0.0, 0.5, 1.0, 0.5, 0.0, -0.5]);
+    /// let sine = [0.0, 0.7071, 1.0, 0.7071, 0.0, -0.7071, -1.0, -0.7071];
+    /// for (got, want) in period(Waveform::Sine, 0.5).iter().zip(sine) {
+    ///     assert!((got - want).abs() < 1e-4);
+    /// }
+    /// ```
+    pub fn level(self, phase: f32, duty: f32) -> f32 {
+        match self {
+            Self::Square if phase < duty => 1.0,
+            Self::Square => -1.0,
+            Self::Triangle => 1.0 - 4.0 * (phase - 0.5).abs(),
+            Self::Sine => (phase * std::f32::consts::TAU).sin(),
+        }
+    }
+}
+
+/// Milliseconds the beeper takes to fade in and out, so it doesn't click
+pub const RAMP_MS: f32 = 3.0;
+
+/// Milliseconds a volume change or mute takes to come through
+pub const GAIN_MS: f32 = 10.0;
+
+/// Master volume and mute, set by the frontend and read on the audio
+/// thread. The volume is kept as the bits of an f32 so that it fits in an
+/// atomic. Neither belongs to the machine, so resets leave them alone and
+/// save states don't have them.
+///
+/// ```
+/// use chip8::sound::Volume;
+///
+/// let volume = Volume::new(1.5);
+/// assert_eq!(volume.level(), 1.0);
+/// volume.set_level(0.25);
+/// assert_eq!(volume.gain(), 0.25);
+/// assert!(volume.toggle_mute());
+/// assert_eq!(volume.gain(), 0.0);
+///
+/// // Muting outlives a reset, and loading a state doesn't undo it
+/// let mut c8 = chip8::emu::Chip8::builder().rom(&[0x00, 0xE0]).build().unwrap();
+/// let state = c8.save_state();
+/// c8.reset();
+/// c8.load_state(&state);
+/// assert!(volume.muted());
+/// assert_eq!(volume.level(), 0.25);
+/// ```
+pub struct Volume {
+    level: AtomicU32,
+    muted: AtomicBool,
+}
+
+impl Volume {
+    /// `level` from 0 to 1, unmuted
+    pub fn new(level: f32) -> Self {
+        Self {
+            level: AtomicU32::new(level.clamp(0.0, 1.0).to_bits()),
+            muted: AtomicBool::new(false),
+        }
+    }
+
+    pub fn level(&self) -> f32 {
+        f32::from_bits(self.level.load(Ordering::Relaxed))
+    }
+
+    pub fn set_level(&self, level: f32) {
+        self.level
+            .store(level.clamp(0.0, 1.0).to_bits(), Ordering::Relaxed);
+    }
+
+    pub fn muted(&self) -> bool {
+        self.muted.load(Ordering::Relaxed)
+    }
+
+    pub fn set_muted(&self, muted: bool) {
+        self.muted.store(muted, Ordering::Relaxed);
+    }
+
+    /// Returns whether it's now muted
+    pub fn toggle_mute(&self) -> bool {
+        !self.muted.fetch_xor(true, Ordering::Relaxed)
+    }
+
+    /// Level the beeper should be heard at
+    pub fn gain(&self) -> f32 {
+        if self.muted() {
+            0.0
+        } else {
+            self.level()
+        }
+    }
+}
+
+impl Default for Volume {
+    fn default() -> Self {
+        Self::new(1.0)
+    }
+}
+
+/// Beeper samples, made on the audio thread. The emulator thread stores
+/// ST in the shared atomic every frame, so neither side waits on the
+/// other. The phase carries on through starts, stops and frequency
+/// changes, only the amplitude is gated.
+///
+/// ```
+/// use chip8::sound::Tone;
+/// use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU8, Ordering};
+/// use std::sync::Arc;
+///
+/// let st = Arc::new(AtomicU8::new(0));
+/// // 3 ms ramps are 24 samples at 8 kHz
+/// let mut tone = Tone::new(st.clone(), 8000);
+/// assert_eq!(tone.next_sample(), 0.0);
+///
+/// st.store(10, Ordering::Relaxed);
+/// let ramp: Vec<f32> = (0..24).map(|_| tone.next_sample().abs()).collect();
+/// assert!(ramp.windows(2).all(|w| w[1] > w[0]));
+/// assert!((ramp[23] - 1.0).abs() < 1e-4);
+/// assert!((tone.next_sample().abs() - 1.0).abs() < 1e-4);
+///
+/// // Changing pitch doesn't move the phase, only how fast it turns
+/// let before = tone.phase();
+/// tone.set_frequency(880.0);
+/// tone.next_sample();
+/// assert!((tone.phase() - (before + 880.0 / 8000.0)).abs() < 1e-4);
+///
+/// st.store(0, Ordering::Relaxed);
+/// let ramp: Vec<f32> = (0..24).map(|_| tone.next_sample().abs()).collect();
+/// assert!(ramp.windows(2).all(|w| w[1] < w[0]));
+/// assert_eq!(tone.next_sample(), 0.0);
+/// ```
+pub struct Tone {
+    st: Arc<AtomicU8>,
+    rate: f32,
+    freq: f32,
+    wave: Waveform,
+    duty: f32,
+    // Fraction of a period, 0 to 1
+    phase: f32,
+    amp: f32,
+    // Amplitude change per sample while ramping
+    step: f32,
+    volume: Arc<Volume>,
+    // Follows the volume's gain, by up to `gain_step` a sample
+    gain: f32,
+    gain_step: f32,
+}
+
+impl Tone {
+    /// A 440 Hz square wave with a 50% duty cycle at `sample_rate`
+    /// samples a second
+    pub fn new(st: Arc<AtomicU8>, sample_rate: u32) -> Self {
+        let rate = sample_rate.max(1) as f32;
+        Self {
+            st,
+            rate,
+            freq: 440.0,
+            wave: Waveform::Square,
+            duty: 0.5,
+            phase: 0.0,
+            amp: 0.0,
+            step: 1.0 / (rate * RAMP_MS / 1000.0).max(1.0),
+            volume: Arc::new(Volume::default()),
+            gain: 1.0,
+            gain_step: 1.0 / (rate * GAIN_MS / 1000.0).max(1.0),
+        }
+    }
+
+    /// Plays at the frontend's volume from now on. Changes to it are
+    /// eased in over `GAIN_MS` so they don't pop.
+    ///
+    /// ```
+    /// use chip8::sound::{Tone, Volume};
+    /// use std::sync::atomic::AtomicU8;
+    /// use std::sync::Arc;
+    ///
+    /// let volume = Arc::new(Volume::new(1.0));
+    /// // 10 ms is 80 samples at 8 kHz, and the 3 ms fade in is 24
+    /// let mut tone = Tone::new(Arc::new(AtomicU8::new(1)), 8000);
+    /// tone.set_volume(volume.clone());
+    /// for _ in 0..24 {
+    ///     tone.next_sample();
+    /// }
+    /// assert_eq!(tone.gain(), 1.0);
+    ///
+    /// volume.set_muted(true);
+    /// let gains: Vec<f32> = (0..80).map(|_| { tone.next_sample(); tone.gain() }).collect();
+    /// assert!(gains.windows(2).all(|w| w[1] < w[0]));
+    /// assert!((gains[39] - 0.5).abs() < 1e-4);
+    /// assert!(gains[79] < 1e-4);
+    /// tone.next_sample();
+    /// assert_eq!(tone.next_sample(), 0.0);
+    ///
+    /// // Halfway back up to a quarter takes a quarter of the time
+    /// volume.set_muted(false);
+    /// volume.set_level(0.25);
+    /// let gains: Vec<f32> = (0..20).map(|_| { tone.next_sample(); tone.gain() }).collect();
+    /// assert!((gains[9] - 0.125).abs() < 1e-4);
+    /// assert!((gains[19] - 0.25).abs() < 1e-4);
+    /// tone.next_sample();
+    /// assert!((tone.next_sample().abs() - 0.25).abs() < 1e-4);
+    /// ```
+    pub fn set_volume(&mut self, volume: Arc<Volume>) {
+        self.gain = volume.gain();
+        self.volume = volume;
+    }
+
+    /// Where the volume ramp has got to, 0 to 1
+    pub fn gain(&self) -> f32 {
+        self.gain
+    }
+
+    pub fn set_frequency(&mut self, hz: f32) {
+        self.freq = hz;
+    }
+
+    /// ```
+    /// use chip8::sound::{Tone, Waveform};
+    /// use std::sync::atomic::AtomicU8;
+    /// use std::sync::Arc;
+    ///
+    /// let mut tone = Tone::new(Arc::new(AtomicU8::new(1)), 8000);
+    /// tone.set_frequency(1000.0);
+    /// tone.set_waveform(Waveform::Triangle);
+    /// // The fade in is three whole periods
+    /// for _ in 0..24 {
+    ///     tone.next_sample();
+    /// }
+    /// let periods: Vec<f32> = (0..16).map(|_| tone.next_sample()).collect();
+    /// let period = [-0.5, 0.0, 0.5, 1.0, 0.5, 0.0, -0.5, -1.0];
+    /// assert_eq!(periods, [period, period].concat());
+    /// ```
+    pub fn set_waveform(&mut self, wave: Waveform) {
+        self.wave = wave;
+    }
+
+    /// Part of the period a square wave is high for, 0 to 1
+    pub fn set_duty(&mut self, duty: f32) {
+        self.duty = duty.clamp(0.0, 1.0);
+    }
+
+    /// How far through a period the wave is, 0 to 1
+    pub fn phase(&self) -> f32 {
+        self.phase
+    }
+
+    pub fn next_sample(&mut self) -> f32 {
+        let target = if self.st.load(Ordering::Relaxed) > 0 {
+            1.0
+        } else {
+            0.0
+        };
+        self.amp = towards(self.amp, target, self.step);
+        self.gain = towards(self.gain, self.volume.gain(), self.gain_step);
+        self.phase = (self.phase + self.freq / self.rate).fract();
+        self.wave.level(self.phase, self.duty) * self.amp * self.gain
+    }
+
+    /// Fills a buffer from the audio callback
+    pub fn fill(&mut self, out: &mut [f32]) {
+        for s in out {
+            *s = self.next_sample();
+        }
+    }
+}
+
+// One step of a linear ramp
+fn towards(from: f32, to: f32, step: f32) -> f32 {
+    if from < to {
+        (from + step).min(to)
+    } else {
+        (from - step).max(to)
+    }
+}
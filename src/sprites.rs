@@ -0,0 +1,118 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Draws of each (I, n) pair, n being the DRW height nibble
+pub type Uses = BTreeMap<(u16, u8), u64>;
+
+// Paths through the program are followed at most this many steps
+const MAX_VISITS: usize = 100_000;
+
+/// Finds DRW instructions reachable from `start` and the I value each one
+/// draws with, where that is known from an earlier Annn. Each such
+/// instruction counts as one use.
+pub fn find_static(ram: &[u8], start: u16) -> Uses {
+    let mut uses = Uses::new();
+    let mut seen = BTreeSet::new();
+    let mut work = vec![(start, None::<u16>)];
+    while let Some((pc, i)) = work.pop() {
+        if seen.len() >= MAX_VISITS || !seen.insert((pc, i)) {
+            continue;
+        }
+        let at = pc as usize;
+        if at + 1 >= ram.len() {
+            continue;
+        }
+        let op = u16::from_be_bytes([ram[at], ram[at + 1]]);
+        let next = pc.wrapping_add(2);
+        let addr = op & 0x0FFF;
+        match op & 0xF000 {
+            0x0000 if op == 0x00EE => {}
+            0x1000 => work.push((addr, i)),
+            0x2000 => {
+                work.push((addr, i));
+                // Subroutines may change I, so assume they did
+                work.push((next, None));
+            }
+            // Skips can go either way
+            0x3000 | 0x4000 | 0x5000 | 0x9000 | 0xE000 => {
+                work.push((next, i));
+                work.push((next.wrapping_add(2), i));
+            }
+            0xA000 => work.push((next, Some(addr))),
+            // Computed jumps can't be followed
+            0xB000 => {}
+            0xD000 => {
+                if let Some(i) = i {
+                    *uses.entry((i, (op & 0xF) as u8)).or_insert(0) += 1;
+                }
+                work.push((next, i));
+            }
+            // Fx1E, Fx29, Fx55 and Fx65 move I
+            0xF000 if matches!(op & 0xFF, 0x1E | 0x29 | 0x55 | 0x65) => work.push((next, None)),
+            _ => work.push((next, i)),
+        }
+    }
+    uses
+}
+
+/// A sprite found at one or more addresses
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sprite {
+    pub addrs: Vec<u16>,
+    /// Bytes per row, 2 for the 16x16 sprites drawn by Dxy0
+    pub width: usize,
+    pub data: Vec<u8>,
+    pub uses: u64,
+}
+
+impl Sprite {
+    /// Block characters, one line per row
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        for row in self.data.chunks(self.width) {
+            for byte in row {
+                for bit in (0..8).rev() {
+                    out.push(if (byte >> bit) & 1 != 0 {
+                        '\u{2588}'
+                    } else {
+                        '.'
+                    });
+                }
+            }
+            out.push('\n');
+        }
+        out
+    }
+}
+
+/// Sprite data for each use, with identical sprites merged, most used
+/// first
+pub fn atlas(ram: &[u8], uses: &Uses) -> Vec<Sprite> {
+    let mut sprites: Vec<Sprite> = Vec::new();
+    for (&(i, n), &count) in uses {
+        let (width, len) = if n == 0 { (2, 32) } else { (1, n as usize) };
+        let start = i as usize;
+        let data = match ram.get(start..start + len) {
+            Some(d) => d.to_vec(),
+            None => continue,
+        };
+        match sprites
+            .iter_mut()
+            .find(|s| s.data == data && s.width == width)
+        {
+            Some(s) => {
+                if !s.addrs.contains(&i) {
+                    s.addrs.push(i);
+                }
+                s.uses += count;
+            }
+            None => sprites.push(Sprite {
+                addrs: vec![i],
+                width,
+                data,
+                uses: count,
+            }),
+        }
+    }
+    sprites.sort_by_key(|s| std::cmp::Reverse(s.uses));
+    sprites
+}
@@ -0,0 +1,472 @@
+use crate::emu;
+use crate::json;
+use crate::layout::{LORES_HEIGHT, LORES_WIDTH, PROGRAM_START, STACK_DEPTH_CLASSIC};
+use std::fmt;
+
+const MAGIC: &[u8; 4] = b"C8ST";
+const VERSION: u8 = 5;
+// Bytes shown either side of a differing RAM run
+pub const RAM_CONTEXT: usize = 4;
+
+/// Snapshot of everything a running program can observe
+#[derive(Debug, Clone, PartialEq)]
+pub struct SaveState {
+    /// `rom_hash` of the program that was loaded, 0 if unknown
+    pub rom: u64,
+    pub v: [u8; 16],
+    pub i: u16,
+    pub dt: u8,
+    pub st: u8,
+    pub pc: u16,
+    pub sp: u8,
+    pub stk: [u16; 16],
+    /// All of RAM, 4 KB unless the machine had more
+    pub ram: Vec<u8>,
+    pub screen: [u64; 32],
+    /// Whether extension opcodes were registered, since the state may
+    /// not make sense without them
+    pub extensions: bool,
+    /// `Chip8::frames`, `Chip8::retired` and `Chip8::frame_step`, 0 in
+    /// states from before they were kept
+    pub frames: u64,
+    pub retired: u64,
+    pub frame_step: u32,
+}
+
+/// Everything zeroed and PC at 0x200
+impl Default for SaveState {
+    fn default() -> Self {
+        Self {
+            rom: 0,
+            v: [0; 16],
+            i: 0,
+            dt: 0,
+            st: 0,
+            pc: PROGRAM_START,
+            sp: 0,
+            stk: [0; STACK_DEPTH_CLASSIC],
+            ram: vec![0; emu::MIN_RAM],
+            screen: [0; LORES_HEIGHT],
+            extensions: false,
+            frames: 0,
+            retired: 0,
+            frame_step: 0,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum StateError {
+    BadMagic,
+    Version(u8),
+    Truncated,
+    /// RAM of a size no machine can have
+    RamSize(usize),
+    /// A stack pointer past the end of the stack
+    StackPointer(u8),
+}
+
+impl fmt::Display for StateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::BadMagic => write!(f, "not a save state"),
+            Self::Version(v) => write!(f, "unsupported save state version {}", v),
+            Self::Truncated => write!(f, "save state is truncated"),
+            Self::RamSize(n) => write!(f, "save state has {} bytes of RAM", n),
+            Self::StackPointer(sp) => write!(f, "save state has a stack pointer of {}", sp),
+        }
+    }
+}
+
+impl std::error::Error for StateError {}
+
+/// 64-bit FNV-1a hash identifying a ROM
+pub fn rom_hash(rom: &[u8]) -> u64 {
+    rom.iter().fold(0xCBF2_9CE4_8422_2325, |h, &b| {
+        (h ^ b as u64).wrapping_mul(0x0100_0000_01B3)
+    })
+}
+
+// Reads fixed size fields off the front of a slice
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], StateError> {
+        if self.0.len() < n {
+            return Err(StateError::Truncated);
+        }
+        let (a, b) = self.0.split_at(n);
+        self.0 = b;
+        Ok(a)
+    }
+
+    fn u8(&mut self) -> Result<u8, StateError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, StateError> {
+        let b = self.take(2)?;
+        Ok(u16::from_be_bytes([b[0], b[1]]))
+    }
+
+    fn u32(&mut self) -> Result<u32, StateError> {
+        let b = self.take(4)?;
+        Ok(u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    fn u64(&mut self) -> Result<u64, StateError> {
+        let mut b = [0u8; 8];
+        b.copy_from_slice(self.take(8)?);
+        Ok(u64::from_be_bytes(b))
+    }
+}
+
+impl SaveState {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(self.ram.len() + 512);
+        out.extend_from_slice(MAGIC);
+        out.push(VERSION);
+        out.extend_from_slice(&self.rom.to_be_bytes());
+        out.push(self.extensions as u8);
+        out.extend_from_slice(&self.v);
+        out.extend_from_slice(&self.i.to_be_bytes());
+        out.push(self.dt);
+        out.push(self.st);
+        out.extend_from_slice(&self.pc.to_be_bytes());
+        out.push(self.sp);
+        for s in &self.stk {
+            out.extend_from_slice(&s.to_be_bytes());
+        }
+        out.extend_from_slice(&(self.ram.len() as u32).to_be_bytes());
+        out.extend_from_slice(&self.ram);
+        for row in &self.screen {
+            out.extend_from_slice(&row.to_be_bytes());
+        }
+        out.extend_from_slice(&self.frames.to_be_bytes());
+        out.extend_from_slice(&self.retired.to_be_bytes());
+        out.extend_from_slice(&self.frame_step.to_be_bytes());
+        out
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Result<Self, StateError> {
+        let mut r = Reader(data);
+        if r.take(4)? != MAGIC {
+            return Err(StateError::BadMagic);
+        }
+        let version = r.u8()?;
+        // Version 1 had no ROM hash, 2 no flags, 3 always 4 KB of RAM and
+        // 4 no counters
+        let (rom, flags) = match version {
+            1 => (0, 0),
+            2 => (r.u64()?, 0),
+            3 | 4 | VERSION => (r.u64()?, r.u8()?),
+            _ => return Err(StateError::Version(version)),
+        };
+        let mut v = [0u8; 16];
+        v.copy_from_slice(r.take(16)?);
+        let i = r.u16()?;
+        let dt = r.u8()?;
+        let st = r.u8()?;
+        let pc = r.u16()?;
+        let sp = r.u8()?;
+        if sp as usize > STACK_DEPTH_CLASSIC {
+            return Err(StateError::StackPointer(sp));
+        }
+        let mut stk = [0u16; 16];
+        for s in stk.iter_mut() {
+            *s = r.u16()?;
+        }
+        let size = if version < 4 {
+            emu::MIN_RAM
+        } else {
+            r.u32()? as usize
+        };
+        if !emu::is_ram_size(size) {
+            return Err(StateError::RamSize(size));
+        }
+        let ram = r.take(size)?.to_vec();
+        let mut screen = [0u64; 32];
+        for row in screen.iter_mut() {
+            *row = r.u64()?;
+        }
+        let (frames, retired, frame_step) = if version < 5 {
+            (0, 0, 0)
+        } else {
+            (r.u64()?, r.u64()?, r.u32()?)
+        };
+        Ok(Self {
+            rom,
+            v,
+            i,
+            dt,
+            st,
+            pc,
+            sp,
+            stk,
+            ram,
+            screen,
+            extensions: flags & 1 != 0,
+            frames,
+            retired,
+            frame_step,
+        })
+    }
+
+    /// The same fields as `to_bytes`, with RAM as one hex string and
+    /// addresses in hex. The counters and `extensions` can be left out,
+    /// like in states from before they were kept.
+    ///
+    /// ```
+    /// use chip8::json;
+    /// use chip8::state::SaveState;
+    ///
+    /// let mut state = SaveState { pc: 0x2AE, frames: 90, ..SaveState::default() };
+    /// state.ram[0x200] = 0xA2;
+    /// state.screen[0] = 1 << 63;
+    /// let text = state.to_json();
+    /// assert!(text.contains(r#""pc":"2AE""#));
+    /// assert!(text.contains(&format!(r#""ram":"{}a2"#, "00".repeat(0x200))));
+    /// assert_eq!(SaveState::from_json(&json::parse(&text).unwrap()), Ok(state));
+    /// ```
+    pub fn to_json(&self) -> String {
+        let hex = |bytes: &[u8]| {
+            bytes
+                .iter()
+                .map(|b| format!("{:02x}", b))
+                .collect::<String>()
+        };
+        let addr = |n: u16| json::string(&format!("{:03X}", n));
+        json::Object::new()
+            .str("rom", &format!("{:016x}", self.rom))
+            .raw("extensions", self.extensions.to_string())
+            .raw("v", json::array(self.v.iter().map(u8::to_string)))
+            .raw("i", addr(self.i))
+            .num("dt", self.dt)
+            .num("st", self.st)
+            .raw("pc", addr(self.pc))
+            .num("sp", self.sp)
+            .raw("stk", json::array(self.stk.iter().map(|&s| addr(s))))
+            .str("ram", &hex(&self.ram))
+            .raw(
+                "screen",
+                json::array(
+                    self.screen
+                        .iter()
+                        .map(|row| json::string(&format!("{:016x}", row))),
+                ),
+            )
+            .num("frames", self.frames)
+            .num("retired", self.retired)
+            .num("frame_step", self.frame_step)
+            .to_string()
+    }
+
+    pub fn from_json(v: &json::Value) -> Result<Self, json::ReadError> {
+        use json::{ReadError, Value};
+        let field = |key| v.get(key).ok_or(ReadError::Field(key));
+        let num = |key, max: u64| match v.get(key) {
+            None => Ok(0),
+            Some(n) => n
+                .as_u64()
+                .filter(|&n| n <= max)
+                .ok_or(ReadError::Field(key)),
+        };
+        // from_str_radix takes a leading +
+        let digits = |s: &&str| !s.is_empty() && s.bytes().all(|b| b.is_ascii_hexdigit());
+        let hex = |v: &Value| {
+            v.as_str()
+                .filter(|s| s.len() == 16 && digits(s))
+                .and_then(|s| u64::from_str_radix(s, 16).ok())
+        };
+        // Exactly `N` of them
+        fn each<T: Default + Copy, const N: usize>(
+            key: &'static str,
+            v: &Value,
+            f: impl Fn(&Value) -> Option<T>,
+        ) -> Result<[T; N], ReadError> {
+            let items = v.as_array().filter(|a| a.len() == N);
+            let mut out = [T::default(); N];
+            for (o, item) in out.iter_mut().zip(items.ok_or(ReadError::Field(key))?) {
+                *o = f(item).ok_or(ReadError::Field(key))?;
+            }
+            Ok(out)
+        }
+        let addr = |v: &Value| {
+            v.as_str()
+                .filter(|s| s.len() <= 4 && digits(s))
+                .and_then(|s| u16::from_str_radix(s, 16).ok())
+        };
+        let byte = |v: &Value| v.as_u64().filter(|&n| n <= 0xFF).map(|n| n as u8);
+
+        let ram = field("ram")?
+            .as_str()
+            .filter(|s| s.len() % 2 == 0 && digits(s))
+            .map(|s| {
+                (0..s.len())
+                    .step_by(2)
+                    .map(|n| u8::from_str_radix(&s[n..n + 2], 16).unwrap())
+                    .collect::<Vec<u8>>()
+            })
+            .filter(|ram| emu::is_ram_size(ram.len()))
+            .ok_or(ReadError::Field("ram"))?;
+        let extensions = match v.get("extensions") {
+            None => false,
+            Some(e) => e.as_bool().ok_or(ReadError::Field("extensions"))?,
+        };
+        let required = |key, max: u64| field(key).and_then(|_| num(key, max));
+        Ok(Self {
+            rom: hex(field("rom")?).ok_or(ReadError::Field("rom"))?,
+            v: each("v", field("v")?, byte)?,
+            i: addr(field("i")?).ok_or(ReadError::Field("i"))?,
+            dt: required("dt", 0xFF)? as u8,
+            st: required("st", 0xFF)? as u8,
+            pc: addr(field("pc")?).ok_or(ReadError::Field("pc"))?,
+            sp: required("sp", STACK_DEPTH_CLASSIC as u64)? as u8,
+            stk: each("stk", field("stk")?, addr)?,
+            ram,
+            screen: each("screen", field("screen")?, hex)?,
+            extensions,
+            frames: num("frames", u64::MAX)?,
+            retired: num("retired", u64::MAX)?,
+            frame_step: num("frame_step", u32::MAX as u64)? as u32,
+        })
+    }
+
+    pub fn diff(&self, other: &Self) -> StateDiff {
+        self.diff_with_context(other, RAM_CONTEXT)
+    }
+
+    pub fn diff_with_context(&self, other: &Self, context: usize) -> StateDiff {
+        let mut registers = Vec::new();
+        for (n, (a, b)) in self.v.iter().zip(other.v.iter()).enumerate() {
+            if a != b {
+                registers.push((format!("V{:X}", n), *a as u16, *b as u16));
+            }
+        }
+        let mut reg = |name: &str, a: u16, b: u16| {
+            if a != b {
+                registers.push((name.to_string(), a, b));
+            }
+        };
+        reg("I", self.i, other.i);
+        reg("DT", self.dt as u16, other.dt as u16);
+        reg("ST", self.st as u16, other.st as u16);
+        reg("PC", self.pc, other.pc);
+        reg("SP", self.sp as u16, other.sp as u16);
+
+        let stack = self
+            .stk
+            .iter()
+            .zip(other.stk.iter())
+            .enumerate()
+            .filter(|(_, (a, b))| a != b)
+            .map(|(n, (a, b))| (n, *a, *b))
+            .collect();
+
+        let ram = ram_ranges(&self.ram, &other.ram, context)
+            .into_iter()
+            .map(|(start, end)| RamRange {
+                start,
+                a: self.ram[start..end].to_vec(),
+                b: other.ram[start..end].to_vec(),
+            })
+            .collect();
+
+        StateDiff {
+            registers,
+            stack,
+            ram,
+            screen: if self.screen != other.screen {
+                Some((self.screen, other.screen))
+            } else {
+                None
+            },
+        }
+    }
+}
+
+// Windows [start, end) around differing bytes, padded by context
+// and merged where they touch or overlap
+pub fn ram_ranges(a: &[u8], b: &[u8], context: usize) -> Vec<(usize, usize)> {
+    let len = a.len().min(b.len());
+    let mut ranges: Vec<(usize, usize)> = Vec::new();
+    for n in (0..len).filter(|&n| a[n] != b[n]) {
+        let start = n.saturating_sub(context);
+        let end = (n + 1 + context).min(len);
+        match ranges.last_mut() {
+            Some(last) if start <= last.1 => last.1 = end,
+            _ => ranges.push((start, end)),
+        }
+    }
+    ranges
+}
+
+/// Differing bytes, with surrounding context, starting at `start`
+#[derive(Debug, Clone, PartialEq)]
+pub struct RamRange {
+    pub start: usize,
+    pub a: Vec<u8>,
+    pub b: Vec<u8>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct StateDiff {
+    /// (name, a, b)
+    pub registers: Vec<(String, u16, u16)>,
+    /// (slot, a, b)
+    pub stack: Vec<(usize, u16, u16)>,
+    pub ram: Vec<RamRange>,
+    pub screen: Option<([u64; 32], [u64; 32])>,
+}
+
+impl StateDiff {
+    pub fn is_empty(&self) -> bool {
+        self.registers.is_empty()
+            && self.stack.is_empty()
+            && self.ram.is_empty()
+            && self.screen.is_none()
+    }
+}
+
+impl fmt::Display for StateDiff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_empty() {
+            return writeln!(f, "states are identical");
+        }
+        for (name, a, b) in &self.registers {
+            writeln!(f, "{:<3} {:04X} -> {:04X}", name, a, b)?;
+        }
+        for (n, a, b) in &self.stack {
+            writeln!(f, "stack[{:X}] {:04X} -> {:04X}", n, a, b)?;
+        }
+        for r in &self.ram {
+            writeln!(f, "ram {:03X}..{:03X}", r.start, r.start + r.a.len())?;
+            for (label, bytes) in [("a", &r.a), ("b", &r.b)].iter() {
+                write!(f, "  {}:", label)?;
+                for (n, byte) in bytes.iter().enumerate() {
+                    // Mark the bytes that actually differ
+                    let mark = if r.a[n] != r.b[n] { '*' } else { ' ' };
+                    write!(f, " {:02X}{}", byte, mark)?;
+                }
+                writeln!(f)?;
+            }
+        }
+        if let Some((a, b)) = &self.screen {
+            // Both set: full block, only a: '-', only b: '+'
+            writeln!(f, "screen (- only in a, + only in b):")?;
+            for (ra, rb) in a.iter().zip(b.iter()) {
+                for x in (0..LORES_WIDTH).rev() {
+                    let c = match ((ra >> x) & 1, (rb >> x) & 1) {
+                        (1, 1) => '\u{2588}',
+                        (1, 0) => '-',
+                        (0, 1) => '+',
+                        _ => ' ',
+                    };
+                    write!(f, "{}", c)?;
+                }
+                writeln!(f)?;
+            }
+        }
+        Ok(())
+    }
+}
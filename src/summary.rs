@@ -0,0 +1,136 @@
+use crate::emu::SmcWrite;
+use crate::frametime::Percentiles;
+use crate::json;
+use crate::opstats::OpcodeStats;
+use crate::warnings::Warnings;
+use std::fmt;
+
+/// How a headless run ended
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Summary {
+    pub frame_count: u64,
+    pub instruction_count: u64,
+    /// `Chip8::frames` and `Chip8::retired` at the end, which carry on
+    /// from a loaded state where the counts above start from 0
+    pub machine_frames: u64,
+    pub retired: u64,
+    /// Of `instruction_count`, the steps `--idle-skip` went through
+    /// without running them
+    pub idle_steps: u64,
+    /// `framebuffer::hash` of the last frame
+    pub screen_hash: u64,
+    /// The fault that stopped the program, if it didn't just run out of
+    /// frames or get quit
+    pub fault: Option<String>,
+    /// What stopped `--script`, such as a failed assert
+    pub script_error: Option<String>,
+    /// With `--opcode-stats`, only written to JSON
+    pub opcode_stats: Option<OpcodeStats>,
+    /// With `--smc`, the program's writes over its own code
+    pub smc_writes: Option<Vec<SmcWrite>>,
+    /// How long frames were busy for, over the last `frametime::WINDOW`.
+    /// Only written to JSON, as it changes from run to run.
+    pub frame_times: Option<Percentiles>,
+    /// Everything warned about, and how often
+    pub warnings: Warnings,
+}
+
+impl Summary {
+    pub fn to_json(&self) -> String {
+        let out = json::Object::new()
+            .num("frame_count", self.frame_count)
+            .num("instruction_count", self.instruction_count)
+            .num("machine_frames", self.machine_frames)
+            .num("retired", self.retired)
+            .num("idle_steps", self.idle_steps)
+            .str("screen_hash", &format!("{:016x}", self.screen_hash))
+            .opt_str("fault", self.fault.as_deref())
+            .opt_str("script_error", self.script_error.as_deref());
+        let out = match &self.opcode_stats {
+            Some(stats) => out.raw("opcode_stats", stats.to_json()),
+            None => out,
+        };
+        let out = match &self.smc_writes {
+            Some(writes) => out.raw("smc_writes", smc_json(writes)),
+            None => out,
+        };
+        let out = out.raw("warnings", self.warnings.to_json());
+        match &self.frame_times {
+            Some(p) => out.raw(
+                "frame_time_us",
+                json::Object::new()
+                    .num("p50", p.p50.as_micros())
+                    .num("p95", p.p95.as_micros())
+                    .num("max", p.max.as_micros())
+                    .to_string(),
+            ),
+            None => out,
+        }
+        .to_string()
+    }
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Ran {} frames, {} instructions, screen {:016x}",
+            self.frame_count, self.instruction_count, self.screen_hash
+        )?;
+        if self.retired != self.instruction_count {
+            write!(
+                f,
+                ", {} frames and {} instructions in all",
+                self.machine_frames, self.retired
+            )?;
+        }
+        if self.idle_steps > 0 {
+            write!(
+                f,
+                ", {} instructions skipped waiting on DT",
+                self.idle_steps
+            )?;
+        }
+        if let Some(fault) = &self.fault {
+            write!(f, ", stopped by {}", fault)?;
+        }
+        if let Some(e) = &self.script_error {
+            write!(f, ", {}", e)?;
+        }
+        if let Some(w) = &self.smc_writes {
+            write!(f, ", {} writes over code", w.len())?;
+        }
+        if !self.warnings.is_empty() {
+            write!(f, ", {} warnings", self.warnings.total())?;
+        }
+        Ok(())
+    }
+}
+
+fn smc_json(writes: &[SmcWrite]) -> String {
+    json::array(writes.iter().map(|w| {
+        json::Object::new()
+            .str("pc", &format!("{:03X}", w.pc))
+            .str("addr", &format!("{:03X}", w.addr))
+            .str("old", &format!("{:02X}", w.old))
+            .str("new", &format!("{:02X}", w.new))
+            .to_string()
+    }))
+}
+
+/// `--smc` writes over code, one per line
+pub struct SmcReport<'a>(pub &'a [SmcWrite]);
+
+impl fmt::Display for SmcReport<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{} writes over code", self.0.len())?;
+        for w in self.0 {
+            writeln!(
+                f,
+                "  {:03X}: {:03X} {:02X} -> {:02X}",
+                w.pc, w.addr, w.old, w.new
+            )?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,322 @@
+use crate::text::{pick_style, RenderStyle};
+use crate::theme::ColorDepth;
+use std::io::Write;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+// Whether the terminal has been changed and needs restoring
+static MODIFIED: AtomicBool = AtomicBool::new(false);
+
+// Whether the old title was saved by `set_title`
+static TITLED: AtomicBool = AtomicBool::new(false);
+
+// Set by SIGWINCH, starts out set so the first query always happens
+#[cfg(unix)]
+static RESIZED: AtomicBool = AtomicBool::new(true);
+
+#[cfg(unix)]
+extern "C" fn on_winch(_: libc::c_int) {
+    RESIZED.store(true, Ordering::SeqCst);
+}
+
+/// Hides the cursor until `restore`
+pub fn hide_cursor() {
+    MODIFIED.store(true, Ordering::SeqCst);
+    print!("\x1b[?25l");
+    let _ = std::io::stdout().flush();
+}
+
+#[cfg(unix)]
+static SAVED: std::sync::Mutex<Option<libc::termios>> = std::sync::Mutex::new(None);
+
+/// Sets the window title, saving the old one the first time so `restore`
+/// can put it back
+pub fn set_title(title: &str) {
+    MODIFIED.store(true, Ordering::SeqCst);
+    if !TITLED.swap(true, Ordering::SeqCst) {
+        print!("\x1b[22;0t");
+    }
+    print!("\x1b]0;{}\x07", title);
+    let _ = std::io::stdout().flush();
+}
+
+/// Asks for `\x1b[I` and `\x1b[O` as input when the terminal gains and
+/// loses focus, until `restore`. Terminals that can't just ignore it.
+pub fn report_focus() {
+    MODIFIED.store(true, Ordering::SeqCst);
+    print!("\x1b[?1004h");
+    let _ = std::io::stdout().flush();
+}
+
+/// Wraps pasted text in `\x1b[200~` and `\x1b[201~`, until `restore`.
+/// Dropping a file on most terminals pastes its path.
+pub fn bracket_paste() {
+    MODIFIED.store(true, Ordering::SeqCst);
+    print!("\x1b[?2004h");
+    let _ = std::io::stdout().flush();
+}
+
+/// Asks for mouse clicks as `mouse::event` sequences, until `restore`
+pub fn report_mouse() {
+    MODIFIED.store(true, Ordering::SeqCst);
+    print!("\x1b[?1000h\x1b[?1006h");
+    let _ = std::io::stdout().flush();
+}
+
+/// Stops input being echoed and line buffered, so keys can be read as
+/// they are pressed. Ctrl-C still raises SIGINT.
+pub fn enable_raw() {
+    #[cfg(unix)]
+    unsafe {
+        let mut t: libc::termios = std::mem::zeroed();
+        if libc::tcgetattr(libc::STDIN_FILENO, &mut t) != 0 {
+            return;
+        }
+        MODIFIED.store(true, Ordering::SeqCst);
+        if let Ok(mut saved) = SAVED.lock() {
+            saved.get_or_insert(t);
+        }
+        t.c_lflag &= !(libc::ICANON | libc::ECHO);
+        // Reads return straight away, even with nothing to read
+        t.c_cc[libc::VMIN] = 0;
+        t.c_cc[libc::VTIME] = 0;
+        libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &t);
+    }
+}
+
+/// Points stdin back at the terminal after it was used for piped input,
+/// so keys can still be read
+pub fn reattach_tty() {
+    #[cfg(unix)]
+    unsafe {
+        if libc::isatty(libc::STDIN_FILENO) == 0 {
+            let fd = libc::open(b"/dev/tty\0".as_ptr() as *const _, libc::O_RDONLY);
+            if fd >= 0 {
+                libc::dup2(fd, libc::STDIN_FILENO);
+                libc::close(fd);
+            }
+        }
+    }
+}
+
+/// Bytes typed since the last call, without blocking
+#[cfg(unix)]
+pub fn read_input() -> Vec<u8> {
+    let mut out = Vec::new();
+    let mut buf = [0u8; 64];
+    loop {
+        let n = unsafe { libc::read(libc::STDIN_FILENO, buf.as_mut_ptr() as *mut _, buf.len()) };
+        if n <= 0 {
+            break;
+        }
+        out.extend_from_slice(&buf[..n as usize]);
+    }
+    out
+}
+
+#[cfg(windows)]
+pub fn read_input() -> Vec<u8> {
+    extern "C" {
+        fn _kbhit() -> std::os::raw::c_int;
+        fn _getch() -> std::os::raw::c_int;
+    }
+    let mut out = Vec::new();
+    while unsafe { _kbhit() } != 0 {
+        out.push(unsafe { _getch() } as u8);
+    }
+    out
+}
+
+#[cfg(not(any(unix, windows)))]
+pub fn read_input() -> Vec<u8> {
+    Vec::new()
+}
+
+/// Puts the terminal back how it was found, does nothing if it already is
+pub fn restore() {
+    let _ = restore_to(&mut std::io::stdout());
+}
+
+/// `restore`, writing the escape sequences to `out`. Only the first call
+/// after the terminal was changed writes anything.
+pub fn restore_to(out: &mut dyn Write) -> std::io::Result<()> {
+    if MODIFIED.swap(false, Ordering::SeqCst) {
+        #[cfg(unix)]
+        if let Ok(Some(t)) = SAVED.lock().map(|mut s| s.take()) {
+            unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &t) };
+        }
+        write!(out, "{}", RESTORE)?;
+        if TITLED.swap(false, Ordering::SeqCst) {
+            write!(out, "\x1b[23;0t")?;
+        }
+        out.flush()?;
+    }
+    Ok(())
+}
+
+/// Reset colors, show cursor, stop reporting focus and the mouse and
+/// bracketing pastes
+pub const RESTORE: &str = "\x1b[0m\x1b[?25h\x1b[?1004l\x1b[?1000l\x1b[?1006l\x1b[?2004l";
+
+/// Restores the terminal before the panic message is printed
+pub fn install_panic_hook() {
+    let prev = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        restore();
+        prev(info);
+    }));
+}
+
+/// Starts watching for terminal resizes
+pub fn watch_resize() {
+    #[cfg(unix)]
+    unsafe {
+        libc::signal(
+            libc::SIGWINCH,
+            on_winch as extern "C" fn(libc::c_int) as libc::sighandler_t,
+        );
+    }
+}
+
+/// Whether the terminal may have been resized since the last call
+#[cfg(unix)]
+pub fn take_resized() -> bool {
+    RESIZED.swap(false, Ordering::SeqCst)
+}
+
+// No resize signal elsewhere, so compare against the last size seen
+#[cfg(not(unix))]
+pub fn take_resized() -> bool {
+    use std::sync::atomic::AtomicUsize;
+    static LAST: AtomicUsize = AtomicUsize::new(usize::MAX);
+    let now = size().map_or(0, |(c, r)| (c << 16) | r);
+    LAST.swap(now, Ordering::SeqCst) != now
+}
+
+/// Terminal size in (columns, rows), if stdout is a terminal
+#[cfg(unix)]
+pub fn size() -> Option<(usize, usize)> {
+    let mut ws: libc::winsize = unsafe { std::mem::zeroed() };
+    let ok = unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut ws) } == 0;
+    if ok && ws.ws_col > 0 && ws.ws_row > 0 {
+        Some((ws.ws_col as usize, ws.ws_row as usize))
+    } else {
+        None
+    }
+}
+
+#[cfg(windows)]
+pub fn size() -> Option<(usize, usize)> {
+    let mut info: win::ScreenBufferInfo = unsafe { std::mem::zeroed() };
+    let ok = unsafe { win::GetConsoleScreenBufferInfo(win::stdout(), &mut info) } != 0;
+    let w = &info.window;
+    if ok && w.right >= w.left && w.bottom >= w.top {
+        Some((
+            (w.right - w.left + 1) as usize,
+            (w.bottom - w.top + 1) as usize,
+        ))
+    } else {
+        None
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+pub fn size() -> Option<(usize, usize)> {
+    None
+}
+
+/// Where a `w` x `h` image goes on a terminal `cols` x `rows`, with
+/// `reserved` rows kept free under it: the densest style whose bordered
+/// image fits, and the 1-based row and column of the border's top left
+/// corner with the image centred. None if even Braille doesn't fit.
+pub fn place(
+    cols: usize,
+    rows: usize,
+    reserved: usize,
+    w: usize,
+    h: usize,
+) -> Option<(RenderStyle, usize, usize)> {
+    let rows = rows.checked_sub(reserved)?;
+    let style = pick_style(cols, rows, w, h)?;
+    let (c, r) = style.cells(w, h);
+    Some((style, (rows - r - 2) / 2 + 1, (cols - c - 2) / 2 + 1))
+}
+
+/// Makes sure escape sequences are interpreted, returning false if the
+/// console can't do that (older Windows consoles)
+#[cfg(windows)]
+pub fn enable_ansi() -> bool {
+    unsafe {
+        let out = win::stdout();
+        let mut mode: std::os::raw::c_ulong = 0;
+        win::GetConsoleMode(out, &mut mode) != 0
+            && (mode & win::ENABLE_VIRTUAL_TERMINAL_PROCESSING != 0
+                || win::SetConsoleMode(out, mode | win::ENABLE_VIRTUAL_TERMINAL_PROCESSING) != 0)
+    }
+}
+
+#[cfg(not(windows))]
+pub fn enable_ansi() -> bool {
+    true
+}
+
+/// Colors the terminal claims to have. Windows consoles that take escape
+/// sequences at all have 256, elsewhere TERM has to say so.
+pub fn color_depth() -> ColorDepth {
+    let var = |v| std::env::var(v).unwrap_or_default();
+    let term = var("TERM");
+    if cfg!(windows) && term.is_empty()
+        || !var("COLORTERM").is_empty()
+        || term.contains("256color")
+        || term.contains("direct")
+    {
+        ColorDepth::Ansi256
+    } else {
+        ColorDepth::Ansi16
+    }
+}
+
+#[cfg(windows)]
+#[allow(non_snake_case, dead_code)]
+mod win {
+    use std::os::raw::{c_int, c_short, c_ulong, c_ushort, c_void};
+
+    pub const ENABLE_VIRTUAL_TERMINAL_PROCESSING: c_ulong = 0x0004;
+    const STD_OUTPUT_HANDLE: c_ulong = -11i32 as c_ulong;
+
+    #[repr(C)]
+    pub struct Coord {
+        pub x: c_short,
+        pub y: c_short,
+    }
+
+    #[repr(C)]
+    pub struct SmallRect {
+        pub left: c_short,
+        pub top: c_short,
+        pub right: c_short,
+        pub bottom: c_short,
+    }
+
+    #[repr(C)]
+    pub struct ScreenBufferInfo {
+        pub size: Coord,
+        pub cursor: Coord,
+        pub attributes: c_ushort,
+        pub window: SmallRect,
+        pub max_window: Coord,
+    }
+
+    extern "system" {
+        fn GetStdHandle(handle: c_ulong) -> *mut c_void;
+        pub fn GetConsoleMode(console: *mut c_void, mode: *mut c_ulong) -> c_int;
+        pub fn SetConsoleMode(console: *mut c_void, mode: c_ulong) -> c_int;
+        pub fn GetConsoleScreenBufferInfo(
+            console: *mut c_void,
+            info: *mut ScreenBufferInfo,
+        ) -> c_int;
+    }
+
+    pub fn stdout() -> *mut c_void {
+        unsafe { GetStdHandle(STD_OUTPUT_HANDLE) }
+    }
+}
@@ -0,0 +1,232 @@
+//! Snapshot tests of the screen, for programs' own test suites. A
+//! fixture is the screen as text, checked in next to the tests:
+//!
+//! ```text
+//! chip8-screen 64x32
+//! ....##..........
+//! ....##..........
+//! ```
+//!
+//! with a header giving the size, then one line per row, `.` for off and
+//! `#` for on. Two-plane screens are `WxHx2` and use `2` and `3` for the
+//! other colors. Running the tests with `UPDATE_ENV` set writes the
+//! screens as they are over the fixtures instead of comparing them.
+use crate::framebuffer::{Framebuffer, Indexed};
+use std::fmt;
+use std::path::Path;
+
+/// Set to write fixtures rather than check them
+pub const UPDATE_ENV: &str = "CHIP8_UPDATE_SNAPSHOTS";
+
+const HEADER: &str = "chip8-screen";
+const COLORS: [char; 4] = ['.', '#', '2', '3'];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FixtureError {
+    /// The first line isn't `chip8-screen WxH`
+    Header,
+    /// 1-based line, and what's wrong with it
+    Line(usize, String),
+}
+
+impl fmt::Display for FixtureError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Header => write!(f, "expected a {} WxH header", HEADER),
+            Self::Line(n, msg) => write!(f, "line {}: {}", n, msg),
+        }
+    }
+}
+
+impl std::error::Error for FixtureError {}
+
+/// `fb` as a fixture
+pub fn to_fixture(fb: &impl Framebuffer) -> String {
+    let (w, h) = (fb.width(), fb.height());
+    let mut out = match fb.planes() {
+        1 => format!("{} {}x{}\n", HEADER, w, h),
+        p => format!("{} {}x{}x{}\n", HEADER, w, h, p),
+    };
+    for y in 0..h {
+        out.extend((0..w).map(|x| COLORS[fb.get(x, y) as usize & 3]));
+        out.push('\n');
+    }
+    out
+}
+
+/// A fixture back as a screen
+///
+/// ```
+/// use chip8::framebuffer::{Framebuffer, Indexed};
+/// use chip8::testing::{parse_fixture, to_fixture, FixtureError};
+///
+/// // Low and high resolution both come back the same
+/// let mut lores = [0u64; 32];
+/// lores[0] = 1 << 63;
+/// lores[31] = 0b101;
+/// let back = parse_fixture(&to_fixture(&lores)).unwrap();
+/// assert_eq!((back.width(), back.height(), back.hash()), (64, 32, lores.hash()));
+/// let mut hires = [0u128; 64];
+/// hires[63] = 1;
+/// let text = to_fixture(&hires);
+/// assert!(text.starts_with("chip8-screen 128x64\n"));
+/// assert_eq!(parse_fixture(&text).unwrap().hash(), hires.hash());
+/// let mut color = Indexed::new(4, 2, 2);
+/// color.set(1, 0, 3);
+/// color.set(2, 1, 2);
+/// assert_eq!(to_fixture(&color), "chip8-screen 4x2x2\n.3..\n..2.\n");
+/// assert_eq!(parse_fixture(&to_fixture(&color)).unwrap(), color);
+///
+/// assert_eq!(parse_fixture("screen 4x2\n"), Err(FixtureError::Header));
+/// let err = |src| parse_fixture(src).unwrap_err().to_string();
+/// assert_eq!(err("chip8-screen 4x2\n....\n..x.\n"), "line 3: x isn't a color");
+/// assert_eq!(err("chip8-screen 4x2\n....\n...\n"), "line 3: 3 pixels, not 4");
+/// assert_eq!(err("chip8-screen 4x2\n..#.\n"), "line 3: 1 rows, not 2");
+/// assert_eq!(err("chip8-screen 2x1\n.2\n"), "line 2: 2 isn't a color");
+/// ```
+pub fn parse_fixture(src: &str) -> Result<Indexed, FixtureError> {
+    let mut lines = src.lines();
+    let size = lines
+        .next()
+        .and_then(|l| l.strip_prefix(HEADER))
+        .map(|l| {
+            l.trim()
+                .split('x')
+                .map(str::parse::<usize>)
+                .collect::<Vec<_>>()
+        })
+        .ok_or(FixtureError::Header)?;
+    let (w, h, planes) = match size[..] {
+        [Ok(w), Ok(h)] => (w, h, 1),
+        [Ok(w), Ok(h), Ok(p @ 1..=2)] => (w, h, p),
+        _ => return Err(FixtureError::Header),
+    };
+    let mut fb = Indexed::new(w, h, planes);
+    let mut rows = 0;
+    for (y, line) in lines.enumerate() {
+        let n = y + 2;
+        if y >= h {
+            return Err(FixtureError::Line(n, format!("more than {} rows", h)));
+        }
+        if line.chars().count() != w {
+            return Err(FixtureError::Line(
+                n,
+                format!("{} pixels, not {}", line.chars().count(), w),
+            ));
+        }
+        for (x, c) in line.chars().enumerate() {
+            match COLORS[..1 << planes].iter().position(|&k| k == c) {
+                Some(color) => fb.set(x, y, color as u8),
+                None => return Err(FixtureError::Line(n, format!("{} isn't a color", c))),
+            }
+        }
+        rows += 1;
+    }
+    if rows < h {
+        return Err(FixtureError::Line(
+            rows + 2,
+            format!("{} rows, not {}", rows, h),
+        ));
+    }
+    Ok(fb)
+}
+
+/// Where `actual` differs from `expected`, as the two of them and their
+/// XOR one after the other, `None` if they're the same
+pub fn compare(expected: &impl Framebuffer, actual: &impl Framebuffer) -> Option<String> {
+    let (ew, eh, ep) = (expected.width(), expected.height(), expected.planes());
+    let (aw, ah, ap) = (actual.width(), actual.height(), actual.planes());
+    if (ew, eh, ep) != (aw, ah, ap) {
+        return Some(format!(
+            "expected a {}x{} screen with {} planes, got {}x{} with {}",
+            ew, eh, ep, aw, ah, ap
+        ));
+    }
+    let mut xor = Indexed::new(ew, eh, 1);
+    let mut differing = 0;
+    for y in 0..eh {
+        for x in 0..ew {
+            if expected.get(x, y) != actual.get(x, y) {
+                xor.set(x, y, 1);
+                differing += 1;
+            }
+        }
+    }
+    if differing == 0 {
+        return None;
+    }
+    // Without their headers
+    let rows = |fb: String| {
+        fb.split_once('\n')
+            .map_or(String::new(), |(_, r)| r.to_string())
+    };
+    Some(format!(
+        "{} pixels differ\nexpected:\n{}actual:\n{}xor:\n{}",
+        differing,
+        rows(to_fixture(expected)),
+        rows(to_fixture(actual)),
+        rows(to_fixture(&xor))
+    ))
+}
+
+/// Panics unless `fb` matches the fixture at `path`, or with
+/// `UPDATE_ENV` set writes it there. A missing fixture panics saying how
+/// to write it.
+///
+/// ```
+/// use chip8::emu::Chip8;
+///
+/// let rom = std::fs::read("ibm.ch8").unwrap();
+/// let mut c8 = Chip8::builder().rom(&rom).build().unwrap();
+/// c8.run_n(20).unwrap();
+/// assert_eq!(chip8::framebuffer::hash(&c8.screen), 0x5da696a83491d73a);
+/// chip8::testing::assert_screen!(c8, "fixtures/ibm.screen");
+///
+/// // A pixel out and it says where, unless it's being written
+/// let mut screen = c8.screen;
+/// screen[0] ^= 1 << 63;
+/// if std::env::var_os(chip8::testing::UPDATE_ENV).is_none() {
+///     let err = std::panic::catch_unwind(|| chip8::testing::assert_screen(&screen, "fixtures/ibm.screen"));
+///     let msg = *err.unwrap_err().downcast::<String>().unwrap();
+///     assert!(msg.starts_with("screen doesn't match fixtures/ibm.screen, 1 pixels differ\nexpected:\n....."));
+///     let xor = format!("xor:\n#{}\n{}", ".".repeat(63), format!("{}\n", ".".repeat(64)).repeat(31));
+///     assert!(msg.ends_with(&xor));
+/// }
+/// ```
+#[track_caller]
+pub fn assert_screen(fb: &impl Framebuffer, path: impl AsRef<Path>) {
+    let path = path.as_ref();
+    if std::env::var_os(UPDATE_ENV).is_some() {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).unwrap();
+        }
+        std::fs::write(path, to_fixture(fb)).unwrap();
+        return;
+    }
+    let src = match std::fs::read_to_string(path) {
+        Ok(src) => src,
+        Err(e) => panic!(
+            "can't read {}: {}, run with {}=1 to write it",
+            path.display(),
+            e,
+            UPDATE_ENV
+        ),
+    };
+    let expected = match parse_fixture(&src) {
+        Ok(fb) => fb,
+        Err(e) => panic!("{}: {}", path.display(), e),
+    };
+    if let Some(diff) = compare(&expected, fb) {
+        panic!("screen doesn't match {}, {}", path.display(), diff);
+    }
+}
+
+/// `assert_screen` on a `Chip8`'s screen
+#[macro_export]
+macro_rules! assert_screen {
+    ($c8:expr, $path:expr) => {
+        $crate::testing::assert_screen(&$c8.screen, $path)
+    };
+}
+
+pub use crate::assert_screen;
@@ -0,0 +1,156 @@
+//! The screen as text, shared by the terminal frontend and everything
+//! that writes frames to files
+use crate::framebuffer::Framebuffer;
+use crate::layout::{LORES_HEIGHT, LORES_WIDTH};
+use crate::phosphor::Phosphor;
+use crate::theme::{ColorDepth, Theme};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RenderStyle {
+    /// One block per pixel
+    Full,
+    /// Two pixels stacked in each cell
+    Half,
+    /// 2x4 pixels per Braille cell
+    Braille,
+}
+
+impl RenderStyle {
+    /// Terminal cells (columns, rows) needed for a w x h image
+    pub fn cells(self, w: usize, h: usize) -> (usize, usize) {
+        match self {
+            Self::Full => (w, h),
+            Self::Half => (w, h.div_ceil(2)),
+            Self::Braille => (w.div_ceil(2), h.div_ceil(4)),
+        }
+    }
+}
+
+/// Largest style whose bordered image fits in the terminal
+pub fn pick_style(cols: usize, rows: usize, w: usize, h: usize) -> Option<RenderStyle> {
+    [RenderStyle::Full, RenderStyle::Half, RenderStyle::Braille]
+        .iter()
+        .copied()
+        .find(|s| {
+            let (c, r) = s.cells(w, h);
+            c + 2 <= cols && r + 2 <= rows
+        })
+}
+
+const BRAILLE_DOTS: [[u32; 4]; 2] = [[0x01, 0x02, 0x04, 0x40], [0x08, 0x10, 0x20, 0x80]];
+
+// Lines of a 64x32 image in the given style
+pub(crate) fn lines(style: RenderStyle, px: impl Fn(usize, usize) -> bool) -> Vec<String> {
+    let (cols, rows) = style.cells(LORES_WIDTH, LORES_HEIGHT);
+    let get = |x: usize, y: usize| x < LORES_WIDTH && y < LORES_HEIGHT && px(x, y);
+    (0..rows)
+        .map(|r| {
+            (0..cols)
+                .map(|c| match style {
+                    RenderStyle::Full => {
+                        if get(c, r) {
+                            '\u{2588}'
+                        } else {
+                            ' '
+                        }
+                    }
+                    RenderStyle::Half => match (get(c, r * 2), get(c, r * 2 + 1)) {
+                        (true, true) => '\u{2588}',
+                        (true, false) => '\u{2580}',
+                        (false, true) => '\u{2584}',
+                        (false, false) => ' ',
+                    },
+                    RenderStyle::Braille => {
+                        let mut bits = 0;
+                        for (dx, col) in BRAILLE_DOTS.iter().enumerate() {
+                            for (dy, dot) in col.iter().enumerate() {
+                                if get(c * 2 + dx, r * 4 + dy) {
+                                    bits |= dot;
+                                }
+                            }
+                        }
+                        std::char::from_u32(0x2800 + bits).unwrap()
+                    }
+                })
+                .collect()
+        })
+        .collect()
+}
+
+// Blocks colored by brightness, the half style uses the background color
+// for the lower pixel
+fn render_colored(
+    style: RenderStyle,
+    p: &Phosphor,
+    theme: &Theme,
+    depth: ColorDepth,
+) -> Vec<String> {
+    let color = |x, y| theme.blend(p.get(x, y));
+    (0..style.cells(LORES_WIDTH, LORES_HEIGHT).1)
+        .map(|r| {
+            let mut out = String::new();
+            let mut last = None;
+            for x in 0..LORES_WIDTH {
+                let cell = match style {
+                    RenderStyle::Half => (color(x, r * 2), Some(color(x, r * 2 + 1))),
+                    _ => (color(x, r), None),
+                };
+                if last != Some(cell) {
+                    match cell {
+                        (fg, Some(bg)) => out.push_str(&format!(
+                            "\x1b[{};{}m",
+                            depth.sgr(fg, false),
+                            depth.sgr(bg, true)
+                        )),
+                        (fg, None) => out.push_str(&format!("\x1b[{}m", depth.sgr(fg, false))),
+                    }
+                    last = Some(cell);
+                }
+                out.push(if cell.1.is_some() {
+                    '\u{2580}'
+                } else {
+                    '\u{2588}'
+                });
+            }
+            out.push_str("\x1b[0m");
+            out
+        })
+        .collect()
+}
+
+// Braille cells can't be split into colors, so they stay plain
+pub(crate) fn level_lines(
+    style: RenderStyle,
+    p: &Phosphor,
+    theme: &Theme,
+    depth: ColorDepth,
+) -> Vec<String> {
+    match style {
+        RenderStyle::Braille => lines(RenderStyle::Braille, |x, y| p.get(x, y) > 0),
+        style => render_colored(style, p, theme, depth),
+    }
+}
+
+fn join(lines: Vec<String>) -> String {
+    let mut out = String::with_capacity(lines.len() * 65 * 3);
+    for line in lines {
+        out.push_str(&line);
+        out.push('\n');
+    }
+    out
+}
+
+/// The screen as text in the given style, one line per row with no escape
+/// sequences. Drawing to the terminal uses the same lines.
+pub fn render(bits: &[u64; 32], style: RenderStyle) -> String {
+    join(lines(style, |x, y| bits.get(x, y) != 0))
+}
+
+/// Phosphor levels as text in 256 colors, like `screen::Screen::draw_levels`
+pub fn render_levels(p: &Phosphor, style: RenderStyle, theme: &Theme) -> String {
+    join(level_lines(style, p, theme, ColorDepth::Ansi256))
+}
+
+pub fn to_text(bits: &[u64; 32]) -> String {
+    render(bits, RenderStyle::Full)
+}
@@ -0,0 +1,218 @@
+use crate::json;
+use std::fmt;
+
+/// Colors for pixel values 0 to 3 (off, plane 1, plane 2, both), the
+/// order Octo gives them in. Every renderer takes its colors from here.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Theme {
+    pub colors: [[u8; 3]; 4],
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self {
+            colors: [
+                [0x00, 0x00, 0x00],
+                [0xFF, 0xFF, 0xFF],
+                [0xAA, 0xAA, 0xAA],
+                [0x55, 0x55, 0x55],
+            ],
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PaletteError {
+    /// There have to be four colors
+    Count(usize),
+    /// Not #rgb or #rrggbb
+    BadColor(String),
+}
+
+impl fmt::Display for PaletteError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Count(n) => write!(f, "a palette is 4 colors, not {}", n),
+            Self::BadColor(c) => write!(f, "{} isn't a color like #0f0 or #00ff00", c),
+        }
+    }
+}
+
+impl std::error::Error for PaletteError {}
+
+// #rgb or #rrggbb
+fn parse_color(s: &str) -> Option<[u8; 3]> {
+    let hex = s.trim().strip_prefix('#')?;
+    if !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    let digit = |n: usize, len: usize| u8::from_str_radix(&hex[n * len..(n + 1) * len], 16);
+    match hex.len() {
+        3 => Some([0, 1, 2].map(|n| digit(n, 1).unwrap() * 0x11)),
+        6 => Some([0, 1, 2].map(|n| digit(n, 2).unwrap())),
+        _ => None,
+    }
+}
+
+impl Theme {
+    /// Four comma separated colors, background first:
+    ///
+    /// ```
+    /// use chip8::framebuffer::Framebuffer;
+    /// use chip8::theme::{PaletteError, Theme};
+    ///
+    /// let theme = Theme::parse("#000,#0f0,#f00,#ff0").unwrap();
+    /// assert_eq!(
+    ///     theme.rgba(),
+    ///     [[0, 0, 0, 255], [0, 255, 0, 255], [255, 0, 0, 255], [255, 255, 0, 255]]
+    /// );
+    /// let theme = Theme::parse("#102030, #405060,#708090,#a0b0c0").unwrap();
+    /// assert_eq!(theme.colors[3], [0xA0, 0xB0, 0xC0]);
+    ///
+    /// // Each index gets its own color
+    /// let mut screen = [0u64; 32];
+    /// screen[0] = 1 << 63;
+    /// let mut out = Vec::new();
+    /// screen.to_rgba(&theme.rgba(), &mut out);
+    /// assert_eq!(out[..8], [0x40, 0x50, 0x60, 255, 0x10, 0x20, 0x30, 255]);
+    ///
+    /// assert_eq!(Theme::parse("#000,#fff"), Err(PaletteError::Count(2)));
+    /// assert_eq!(Theme::parse("#000,#fff,#0000,#aaa"), Err(PaletteError::BadColor("#0000".to_string())));
+    /// ```
+    pub fn parse(s: &str) -> Result<Self, PaletteError> {
+        let parts: Vec<&str> = s.split(',').collect();
+        if parts.len() != 4 {
+            return Err(PaletteError::Count(parts.len()));
+        }
+        let mut colors = [[0u8; 3]; 4];
+        for (c, part) in colors.iter_mut().zip(&parts) {
+            *c =
+                parse_color(part).ok_or_else(|| PaletteError::BadColor(part.trim().to_string()))?;
+        }
+        Ok(Self { colors })
+    }
+
+    /// A JSON string of the colors as `parse` takes them
+    ///
+    /// ```
+    /// use chip8::json;
+    /// use chip8::theme::Theme;
+    ///
+    /// let theme = Theme::parse("#000,#0f0,#f00,#ff0").unwrap();
+    /// assert_eq!(theme.to_json(), r##""#000000,#00ff00,#ff0000,#ffff00""##);
+    /// assert_eq!(Theme::from_json(&json::parse(&theme.to_json()).unwrap()), Ok(theme));
+    /// assert!(Theme::from_json(&json::parse(r##""#000,#fff""##).unwrap()).is_err());
+    /// ```
+    pub fn to_json(&self) -> String {
+        let colors: Vec<String> = self
+            .colors
+            .iter()
+            .map(|[r, g, b]| format!("#{:02x}{:02x}{:02x}", r, g, b))
+            .collect();
+        json::string(&colors.join(","))
+    }
+
+    pub fn from_json(v: &json::Value) -> Result<Self, json::ReadError> {
+        v.as_str()
+            .and_then(|s| Self::parse(s).ok())
+            .ok_or(json::ReadError::Field("theme"))
+    }
+
+    /// The colors with full alpha, for `Framebuffer::to_rgba`
+    pub fn rgba(&self) -> [[u8; 4]; 4] {
+        self.colors.map(|[r, g, b]| [r, g, b, 0xFF])
+    }
+
+    /// Background blended towards the foreground by `level` (0 to 255)
+    pub fn blend(&self, level: u8) -> [u8; 3] {
+        let [bg, fg] = [self.colors[0], self.colors[1]];
+        let mut out = [0u8; 3];
+        for c in 0..3 {
+            let (b, f, l) = (bg[c] as i32, fg[c] as i32, level as i32);
+            out[c] = (b + (f - b) * l / 255) as u8;
+        }
+        out
+    }
+}
+
+/// How many colors the terminal can show
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorDepth {
+    Ansi16,
+    Ansi256,
+}
+
+impl ColorDepth {
+    /// SGR parameters setting the foreground, or the background with `bg`
+    ///
+    /// ```
+    /// use chip8::theme::ColorDepth;
+    ///
+    /// assert_eq!(ColorDepth::Ansi256.sgr([0xFF, 0x00, 0x00], false), "38;5;196");
+    /// assert_eq!(ColorDepth::Ansi16.sgr([0xFF, 0x00, 0x00], false), "91");
+    /// assert_eq!(ColorDepth::Ansi16.sgr([0x00, 0xAA, 0x00], true), "42");
+    /// assert_eq!(ColorDepth::Ansi16.sgr([0x55, 0x55, 0x55], false), "90");
+    /// ```
+    pub fn sgr(self, rgb: [u8; 3], bg: bool) -> String {
+        match self {
+            Self::Ansi256 => format!("{};5;{}", if bg { 48 } else { 38 }, ansi256(rgb)),
+            Self::Ansi16 => {
+                let n = ansi16(rgb);
+                let base = match (bg, n < 8) {
+                    (false, true) => 30,
+                    (false, false) => 90 - 8,
+                    (true, true) => 40,
+                    (true, false) => 100 - 8,
+                };
+                (base + n).to_string()
+            }
+        }
+    }
+}
+
+// xterm's defaults for the 16 basic colors
+const ANSI16: [[u8; 3]; 16] = [
+    [0x00, 0x00, 0x00],
+    [0xCD, 0x00, 0x00],
+    [0x00, 0xCD, 0x00],
+    [0xCD, 0xCD, 0x00],
+    [0x00, 0x00, 0xEE],
+    [0xCD, 0x00, 0xCD],
+    [0x00, 0xCD, 0xCD],
+    [0xE5, 0xE5, 0xE5],
+    [0x7F, 0x7F, 0x7F],
+    [0xFF, 0x00, 0x00],
+    [0x00, 0xFF, 0x00],
+    [0xFF, 0xFF, 0x00],
+    [0x5C, 0x5C, 0xFF],
+    [0xFF, 0x00, 0xFF],
+    [0x00, 0xFF, 0xFF],
+    [0xFF, 0xFF, 0xFF],
+];
+
+/// Nearest of the 16 basic terminal colors, 0 to 15
+pub fn ansi16(rgb: [u8; 3]) -> u8 {
+    let dist = |c: &[u8; 3]| -> i32 { (0..3).map(|i| (c[i] as i32 - rgb[i] as i32).pow(2)).sum() };
+    (0..16).min_by_key(|&n| dist(&ANSI16[n])).unwrap() as u8
+}
+
+/// Nearest color in the xterm 256 color palette
+pub fn ansi256(rgb: [u8; 3]) -> u8 {
+    let [r, g, b] = rgb;
+    if r == g && g == b {
+        // Grayscale ramp runs from 8 to 238 in steps of 10
+        return match r {
+            0..=3 => 16,
+            248..=255 => 231,
+            v => 232 + ((v as u16 - 3) / 10).min(23) as u8,
+        };
+    }
+    let q = |v: u8| {
+        if v < 48 {
+            0
+        } else {
+            ((v as u16 - 35) / 40) as u8
+        }
+    };
+    16 + 36 * q(r) + 6 * q(g) + q(b)
+}
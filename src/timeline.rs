@@ -0,0 +1,180 @@
+//! Keys to press in a headless run, given with `--input-script`. Simpler
+//! than `--script`: it only holds keys, so it's worked out in advance as
+//! the keys for every frame. Statements are split by `;` or new lines
+//! and `#` starts a comment:
+//!
+//! ```text
+//! wait N          let N frames go by
+//! press K         hold key K down until released
+//! release K       let go of key K
+//! hold K for N    hold key K for the next N frames, not waiting for them
+//! ```
+//!
+//! Keys are hex digits and counts decimal. Keys still pressed at the end
+//! stay down.
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TimelineError {
+    /// 1-based, 0 for errors not tied to a line
+    pub line: usize,
+    pub col: usize,
+    pub msg: String,
+}
+
+impl fmt::Display for TimelineError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.line == 0 {
+            write!(f, "input script: {}", self.msg)
+        } else {
+            write!(f, "input script {}:{}: {}", self.line, self.col, self.msg)
+        }
+    }
+}
+
+impl std::error::Error for TimelineError {}
+
+// Keys with a `hold` lasting past `frame`
+fn held(until: &[usize; 16], frame: usize) -> u16 {
+    (0..16)
+        .filter(|&k| until[k] > frame)
+        .fold(0, |m, k| m | 1 << k)
+}
+
+/// Keys held each frame
+///
+/// ```
+/// use chip8::timeline::Timeline;
+///
+/// let t = Timeline::parse("wait 2; press 5; hold 2 for 3\nwait 1 # one frame\nhold 2 for 3; wait 3; release 5; wait 1").unwrap();
+/// let keys: Vec<u16> = (0..8).map(|f| t.keys(f)).collect();
+/// // Overlapping holds of the same key run to whichever ends later
+/// assert_eq!(keys, [0, 0, 0x24, 0x24, 0x24, 0x24, 0, 0]);
+/// assert_eq!(t.len(), 7);
+/// assert!(!t.is_done(6) && t.is_done(7));
+///
+/// // Still pressed at the end stays down
+/// let t = Timeline::parse("press a").unwrap();
+/// assert_eq!((t.len(), t.keys(1000)), (0, 1 << 0xA));
+/// ```
+///
+/// Mistakes point at the word:
+///
+/// ```
+/// use chip8::timeline::Timeline;
+///
+/// let err = |src| Timeline::parse(src).unwrap_err().to_string();
+/// assert_eq!(err("wait 3\n  press 5; jump 2"), "input script 2:12: unknown command jump");
+/// assert_eq!(err("press g"), "input script 1:7: bad key g");
+/// assert_eq!(err("hold 1 during 5"), "input script 1:8: expected hold K for N");
+/// assert_eq!(err("wait"), "input script 1:1: expected wait N");
+/// assert_eq!(err("wait -1"), "input script 1:6: bad count -1");
+/// assert_eq!(err("wait 1\t;\trelease\t\t10"), "input script 1:19: bad key 10");
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Timeline {
+    masks: Vec<u16>,
+    // Pressed and never released
+    after: u16,
+}
+
+impl Timeline {
+    pub fn parse(src: &str) -> Result<Self, TimelineError> {
+        let mut t = Self::default();
+        let mut frame = 0;
+        // Keys pressed by `press`, and the frame each `hold` lasts until
+        let mut pressed = 0u16;
+        let mut until = [0usize; 16];
+        for (n, line) in src.lines().enumerate() {
+            let code = line.split('#').next().unwrap_or("");
+            let mut start = 0;
+            for stmt in code.split(';') {
+                // Words with their 1-based columns
+                let words: Vec<(usize, &str)> = stmt
+                    .split([' ', '\t'])
+                    .scan(start, |at, w| {
+                        let col = *at + 1;
+                        *at += w.len() + 1;
+                        Some((col, w))
+                    })
+                    .filter(|(_, w)| !w.is_empty())
+                    .collect();
+                start += stmt.len() + 1;
+                let err = |col: usize, msg: String| TimelineError {
+                    line: n + 1,
+                    col,
+                    msg,
+                };
+                let key = |(col, w): (usize, &str)| match u8::from_str_radix(w, 16) {
+                    Ok(k) if k < 16 && w.len() == 1 => Ok(k),
+                    _ => Err(err(col, format!("bad key {}", w))),
+                };
+                let count = |(col, w): (usize, &str)| {
+                    w.parse::<usize>()
+                        .map_err(|_| err(col, format!("bad count {}", w)))
+                };
+                match words.as_slice() {
+                    [] => {}
+                    [(_, "wait"), n] => {
+                        let n = count(*n)?;
+                        t.masks
+                            .extend((frame..frame + n).map(|f| pressed | held(&until, f)));
+                        frame += n;
+                    }
+                    [(_, "press"), k] => pressed |= 1 << key(*k)?,
+                    [(_, "release"), k] => pressed &= !(1 << key(*k)?),
+                    [(_, "hold"), k, (_, "for"), n] => {
+                        let k = key(*k)? as usize;
+                        until[k] = until[k].max(frame + count(*n)?);
+                    }
+                    [(col, cmd @ ("wait" | "press" | "release")), ..] => {
+                        let arg = if *cmd == "wait" { "N" } else { "K" };
+                        return Err(err(*col, format!("expected {} {}", cmd, arg)));
+                    }
+                    [(_, "hold"), rest @ ..] => {
+                        let col = rest.get(1).or(rest.first()).map_or(1, |w| w.0);
+                        return Err(err(col, "expected hold K for N".to_string()));
+                    }
+                    [(col, cmd), ..] => return Err(err(*col, format!("unknown command {}", cmd))),
+                }
+            }
+        }
+        // Holds running past the last wait still count
+        let end = until.iter().copied().max().unwrap_or(0);
+        t.masks
+            .extend((frame..end).map(|f| pressed | held(&until, f)));
+        t.after = pressed;
+        Ok(t)
+    }
+
+    pub fn load(path: &std::path::Path) -> Result<Self, TimelineError> {
+        let src = std::fs::read_to_string(path).map_err(|e| TimelineError {
+            line: 0,
+            col: 0,
+            msg: format!("{}: {}", path.display(), e),
+        })?;
+        Self::parse(&src)
+    }
+
+    /// Keys held on `frame`, counting from 0
+    pub fn keys(&self, frame: u64) -> u16 {
+        self.masks
+            .get(frame as usize)
+            .copied()
+            .unwrap_or(self.after)
+    }
+
+    /// Frames until the keys stop changing
+    pub fn len(&self) -> usize {
+        self.masks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.masks.is_empty()
+    }
+
+    /// Whether the keys won't change from `frame` on
+    pub fn is_done(&self, frame: u64) -> bool {
+        frame >= self.masks.len() as u64
+    }
+}
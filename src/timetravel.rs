@@ -0,0 +1,195 @@
+//! Stepping a paused program back through `Chip8::set_journal` and
+//! forward again, keeping count of how far it is behind where it had got
+//! to. While it's behind, changes that would make the way forward differ
+//! from the way it came, such as a poke, are refused until it's resumed
+//! back to there or the rewound point is committed to as the new head.
+use crate::breakpoints::{Breakpoints, Stop};
+use crate::emu::{Chip8, Fault, StepOutcome};
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TravelError {
+    /// Instructions back from the head
+    Rewound { behind: u64 },
+}
+
+impl fmt::Display for TravelError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Rewound { behind } => {
+                write!(f, "{} instructions back, resume or commit first", behind)
+            }
+        }
+    }
+}
+
+impl std::error::Error for TravelError {}
+
+/// How far back from the head a program has been stepped
+///
+/// ```
+/// use chip8::breakpoints::{Breakpoints, Stop};
+/// use chip8::emu::Chip8;
+/// use chip8::timetravel::{TimeTravel, TravelError};
+///
+/// let rom = [
+///     0x60, 0x00, // 200: LD V0, 00
+///     0x70, 0x01, // 202: ADD V0, 01
+///     0x81, 0x00, // 204: LD V1, V0
+///     0x12, 0x02, // 206: JP 202
+/// ];
+/// let mut c8 = Chip8::builder().rom(&rom).build().unwrap();
+/// c8.set_journal(64);
+/// let mut bps = Breakpoints::new();
+/// bps.add(0x204);
+/// for _ in 0..3 {
+///     assert!(matches!(bps.run(&mut c8, 100, Chip8::step), Ok(Stop::Breakpoint { addr: 0x204, .. })));
+/// }
+/// let head = c8.save_state();
+/// assert_eq!((c8.v()[0], c8.v()[1]), (3, 2));
+///
+/// // Back past the LD V1 and it has what it had before
+/// let mut tt = TimeTravel::new();
+/// assert_eq!(tt.back(&mut c8, 3, Chip8::step_back), 3);
+/// assert_eq!((c8.pc(), c8.v()[1], tt.behind()), (0x204, 1, 3));
+/// assert_eq!(tt.poke(&mut c8, 0x300, 1), Err(TravelError::Rewound { behind: 3 }));
+/// assert_eq!(tt.poke(&mut c8, 0x300, 1).unwrap_err().to_string(), "3 instructions back, resume or commit first");
+///
+/// // Back to the hit before, then back to the start of the journal
+/// assert_eq!(tt.back_to(&mut c8, &bps, 100, Chip8::step_back), Stop::Breakpoint { addr: 0x204, steps: 3 });
+/// assert_eq!((c8.v()[0], c8.v()[1]), (1, 0));
+/// assert_eq!(tt.back_to(&mut c8, &bps, 100, Chip8::step_back), Stop::Start { steps: 2 });
+/// assert_eq!((c8.pc(), tt.behind()), (0x200, 8));
+///
+/// // Forward to the head, which is the same hit as before
+/// tt.forward(&mut c8, Chip8::step).unwrap();
+/// assert_eq!(tt.resume(&mut c8, Chip8::step), Ok(7));
+/// assert_eq!((c8.pc(), tt.is_rewound()), (0x204, false));
+/// assert_eq!(c8.save_state(), head);
+///
+/// // A poke can't be stepped back over, so it empties the journal
+/// assert_eq!(tt.poke(&mut c8, 0x300, 1), Ok(()));
+/// assert_eq!(tt.back(&mut c8, 1, Chip8::step_back), 0);
+///
+/// // Or carry on from where it was stepped back to
+/// bps.run(&mut c8, 100, Chip8::step).unwrap();
+/// tt.back(&mut c8, 2, Chip8::step_back);
+/// tt.commit();
+/// assert_eq!(tt.set_v(&mut c8, 1, 0xAA), Ok(()));
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TimeTravel {
+    behind: u64,
+}
+
+impl TimeTravel {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Instructions back from the head, 0 at it
+    pub fn behind(&self) -> u64 {
+        self.behind
+    }
+
+    pub fn is_rewound(&self) -> bool {
+        self.behind > 0
+    }
+
+    /// Ok at the head, for frontends to check before anything else that
+    /// changes the machine, such as pressing keys
+    pub fn live(&self) -> Result<(), TravelError> {
+        match self.behind {
+            0 => Ok(()),
+            behind => Err(TravelError::Rewound { behind }),
+        }
+    }
+
+    /// Steps back up to `n` instructions with `step_back`, which is
+    /// `Chip8::step_back` or a frontend's own that also takes back what it
+    /// recorded, returning how many it could
+    pub fn back<F>(&mut self, c8: &mut Chip8, n: u64, mut step_back: F) -> u64
+    where
+        F: FnMut(&mut Chip8) -> bool,
+    {
+        let mut done = 0;
+        while done < n && step_back(c8) {
+            done += 1;
+        }
+        self.behind += done;
+        done
+    }
+
+    /// Steps back until PC is at one of `bps` or the journal runs out,
+    /// giving up after `limit`
+    pub fn back_to<F>(
+        &mut self,
+        c8: &mut Chip8,
+        bps: &Breakpoints,
+        limit: u32,
+        mut step_back: F,
+    ) -> Stop
+    where
+        F: FnMut(&mut Chip8) -> bool,
+    {
+        for steps in 1..=limit {
+            if !step_back(c8) {
+                return Stop::Start { steps: steps - 1 };
+            }
+            self.behind += 1;
+            if bps.contains(c8.pc()) {
+                return Stop::Breakpoint {
+                    addr: c8.pc(),
+                    steps,
+                };
+            }
+        }
+        Stop::Limit { steps: limit }
+    }
+
+    /// Runs one instruction with `step`, a step nearer the head. Past the
+    /// head it's simply running.
+    pub fn forward<F>(&mut self, c8: &mut Chip8, mut step: F) -> Result<StepOutcome, Fault>
+    where
+        F: FnMut(&mut Chip8) -> Result<StepOutcome, Fault>,
+    {
+        let outcome = step(c8)?;
+        self.behind = self.behind.saturating_sub(1);
+        Ok(outcome)
+    }
+
+    /// Runs forward to the head, returning how many instructions that
+    /// took. RND may not give what it did, as the RNG isn't rewound.
+    pub fn resume<F>(&mut self, c8: &mut Chip8, mut step: F) -> Result<u64, Fault>
+    where
+        F: FnMut(&mut Chip8) -> Result<StepOutcome, Fault>,
+    {
+        let n = self.behind;
+        for _ in 0..n {
+            self.forward(c8, &mut step)?;
+        }
+        Ok(n)
+    }
+
+    /// Makes where it's been stepped back to the head, leaving the rest
+    /// behind
+    pub fn commit(&mut self) {
+        self.behind = 0;
+    }
+
+    /// `Chip8::poke` at the head, emptying the journal
+    pub fn poke(&self, c8: &mut Chip8, addr: u16, val: u8) -> Result<(), TravelError> {
+        self.live()?;
+        c8.poke(addr, val);
+        c8.clear_journal();
+        Ok(())
+    }
+
+    /// `Chip8::set_v` at the head, emptying the journal
+    pub fn set_v(&self, c8: &mut Chip8, x: u8, val: u8) -> Result<(), TravelError> {
+        self.live()?;
+        c8.set_v(x, val);
+        c8.clear_journal();
+        Ok(())
+    }
+}
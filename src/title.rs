@@ -0,0 +1,94 @@
+//! The window title: the ROM, the platform it looks like it's for, and
+//! whatever the runner is doing. Everything that changes it sets a field
+//! here and the title is only sent when the text comes out different.
+use crate::info::Platform;
+
+pub struct TitleState {
+    name: String,
+    platform: Platform,
+    pub paused: bool,
+    pub fast: bool,
+    pub recording: bool,
+    // Last title handed out by `changed`
+    shown: Option<String>,
+}
+
+/// Short name for the title
+fn tag(p: Platform) -> &'static str {
+    match p {
+        Platform::Chip8 => "chip8",
+        Platform::Hires => "hires",
+        Platform::SuperChip => "schip",
+        Platform::XoChip => "xochip",
+    }
+}
+
+impl TitleState {
+    /// `name` is the file stem, as there's no database of ROM names
+    pub fn new(name: &str, platform: Platform) -> Self {
+        Self {
+            // A title is cut short at a control character
+            name: name.chars().filter(|c| !c.is_control()).collect(),
+            platform,
+            paused: false,
+            fast: false,
+            recording: false,
+            shown: None,
+        }
+    }
+
+    pub fn text(&self) -> String {
+        let mut title = format!("{} [{}]", self.name, tag(self.platform));
+        let states: Vec<&str> = [
+            (self.paused, "PAUSED"),
+            (self.fast, "FF"),
+            (self.recording, "REC"),
+        ]
+        .iter()
+        .filter(|(on, _)| *on)
+        .map(|&(_, s)| s)
+        .collect();
+        if !states.is_empty() {
+            title.push_str(" \u{2014} ");
+            title.push_str(&states.join(" "));
+        }
+        title
+    }
+
+    /// The title if it isn't the one last returned
+    ///
+    /// ```
+    /// use chip8::info::Platform;
+    /// use chip8::title::TitleState;
+    ///
+    /// let mut t = TitleState::new("Pong", Platform::SuperChip);
+    /// assert_eq!(t.changed().as_deref(), Some("Pong [schip]"));
+    /// assert_eq!(t.changed(), None);
+    ///
+    /// t.paused = true;
+    /// assert_eq!(t.changed().as_deref(), Some("Pong [schip] \u{2014} PAUSED"));
+    /// t.recording = true;
+    /// t.fast = true;
+    /// assert_eq!(t.changed().as_deref(), Some("Pong [schip] \u{2014} PAUSED FF REC"));
+    ///
+    /// // Changes that cancel out before the next check send nothing
+    /// t.fast = false;
+    /// t.fast = true;
+    /// assert_eq!(t.changed(), None);
+    /// t.paused = false;
+    /// t.fast = false;
+    /// t.recording = false;
+    /// assert_eq!(t.changed().as_deref(), Some("Pong [schip]"));
+    ///
+    /// let t = TitleState::new("bad\x07name", Platform::Chip8);
+    /// assert_eq!(t.text(), "badname [chip8]");
+    /// ```
+    pub fn changed(&mut self) -> Option<String> {
+        let title = self.text();
+        if self.shown.as_ref() == Some(&title) {
+            return None;
+        }
+        self.shown = Some(title.clone());
+        Some(title)
+    }
+}
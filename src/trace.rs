@@ -0,0 +1,251 @@
+//! Execution traces, one line per instruction.
+//!
+//! The compact format is
+//!
+//! ```text
+//! PC:0200 OP:A22A I:022A V:[00,00,00,00,00,00,00,00,00,00,00,00,00,00,00,00] SP:0 DT:00 ST:00
+//! ```
+//!
+//! where PC and OP are the instruction that ran and the rest is the state
+//! after it. All values are hex. These are the fields other emulators
+//! commonly log, so their traces can be compared with `diff`. The
+//! counters format adds `FRAME`, `STEP` and `RETIRED`, `Chip8::frames`
+//! and the rest after the instruction, which `Record` comparisons with
+//! compact traces leave out.
+
+use crate::emu::{Chip8, Fault, StepOutcome};
+use std::fmt::Write;
+use std::io;
+
+/// Line for the instruction `op` at `pc`, which `c8` has just run
+pub fn compact(c8: &Chip8, pc: u16, op: u16) -> String {
+    let mut v = String::new();
+    for (n, r) in c8.v().iter().enumerate() {
+        if n > 0 {
+            v.push(',');
+        }
+        let _ = write!(v, "{:02X}", r);
+    }
+    format!(
+        "PC:{:04X} OP:{:04X} I:{:04X} V:[{}] SP:{:X} DT:{:02X} ST:{:02X}",
+        pc,
+        op,
+        c8.i(),
+        v,
+        c8.sp(),
+        c8.delay_timer(),
+        c8.sound_timer()
+    )
+}
+
+/// `compact` with the machine's counters on the end
+///
+/// ```
+/// use chip8::emu::Chip8;
+/// use chip8::trace;
+///
+/// // ADD V0, 01; JP 200
+/// let mut c8 = Chip8::builder().rom(&[0x70, 0x01, 0x12, 0x00]).build().unwrap();
+/// c8.begin_frame(0);
+/// c8.run_n(17).unwrap();
+/// let line = trace::counted(&c8, 0x200, 0x7001);
+/// assert!(line.ends_with(" DT:00 ST:00 FRAME:0 STEP:11 RETIRED:11"), "{}", line);
+/// ```
+pub fn counted(c8: &Chip8, pc: u16, op: u16) -> String {
+    format!(
+        "{} FRAME:{:X} STEP:{:X} RETIRED:{:X}",
+        compact(c8, pc, op),
+        c8.frames(),
+        c8.frame_step(),
+        c8.retired()
+    )
+}
+
+/// Compact lines for every instruction run, written from a post-exec
+/// hook. Writing stops at the first error, kept for `take_error`.
+///
+/// ```
+/// use chip8::emu::Chip8;
+/// use chip8::trace::Logger;
+/// use std::cell::RefCell;
+/// use std::rc::Rc;
+///
+/// // LD I, 22A; LD V0, 0C; JP 204
+/// let mut c8 = Chip8::builder().rom(&[0xA2, 0x2A, 0x60, 0x0C, 0x12, 0x04]).build().unwrap();
+/// let log = Rc::new(RefCell::new(Logger::new(Vec::new())));
+/// let l = log.clone();
+/// c8.set_post_exec_hook(Box::new(move |c8, pc, op, res| l.borrow_mut().log_step(c8, pc, op, res)));
+/// c8.run_n(3).unwrap();
+/// let text = String::from_utf8(log.borrow().get_ref().clone()).unwrap();
+/// let lines: Vec<_> = text.lines().collect();
+/// assert_eq!(lines.len(), 3);
+/// assert!(lines[0].starts_with("PC:0200 OP:A22A I:022A V:[00,"));
+/// assert!(lines[1].starts_with("PC:0202 OP:600C I:022A V:[0C,"));
+///
+/// // A full disk stops it, and says so once
+/// struct Full;
+/// impl std::io::Write for Full {
+///     fn write(&mut self, _: &[u8]) -> std::io::Result<usize> {
+///         Err(std::io::Error::other("disk full"))
+///     }
+///     fn flush(&mut self) -> std::io::Result<()> {
+///         Ok(())
+///     }
+/// }
+/// let mut log = Logger::new(Full);
+/// let ran = Ok(chip8::emu::StepOutcome::Ran);
+/// log.log_step(&c8, 0x204, 0x1204, &ran);
+/// assert_eq!(log.take_error().unwrap().to_string(), "disk full");
+/// log.log_step(&c8, 0x204, 0x1204, &ran);
+/// assert!(log.take_error().is_none());
+/// ```
+pub struct Logger<W: io::Write> {
+    out: W,
+    counters: bool,
+    error: Option<io::Error>,
+    failed: bool,
+}
+
+impl<W: io::Write> Logger<W> {
+    pub fn new(out: W) -> Self {
+        Self {
+            out,
+            counters: false,
+            error: None,
+            failed: false,
+        }
+    }
+
+    /// Writes `counted` lines instead
+    pub fn counters(mut self, on: bool) -> Self {
+        self.counters = on;
+        self
+    }
+
+    /// Writes the line for a step as a post-exec hook is shown it, if the
+    /// instruction ran
+    pub fn log_step(&mut self, c8: &Chip8, pc: u16, op: u16, res: &Result<StepOutcome, Fault>) {
+        if self.failed || res.is_err() {
+            return;
+        }
+        let line = if self.counters {
+            counted(c8, pc, op)
+        } else {
+            compact(c8, pc, op)
+        };
+        if let Err(e) = writeln!(self.out, "{}", line) {
+            self.error = Some(e);
+            self.failed = true;
+        }
+    }
+
+    /// The error that stopped writing, the first time it's asked for
+    pub fn take_error(&mut self) -> Option<io::Error> {
+        self.error.take()
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.out
+    }
+}
+
+/// Fields of a trace line as numbers, in the order they appeared
+#[derive(Debug, Clone, PartialEq)]
+pub struct Record {
+    pub fields: Vec<(String, Vec<u32>)>,
+}
+
+impl Record {
+    /// Reads `KEY:VALUE` pairs, where a value is hex or a bracketed list of
+    /// hex. Spacing, case and leading zeros don't matter, and anything
+    /// that isn't a pair is skipped.
+    pub fn parse(line: &str) -> Self {
+        // Join lists up so "V:[00, 01]" is one word
+        let mut joined = String::new();
+        let mut depth = 0;
+        for c in line.chars() {
+            match c {
+                '[' => depth += 1,
+                ']' => depth -= 1,
+                c if c.is_whitespace() && depth > 0 => continue,
+                _ => {}
+            }
+            joined.push(c);
+        }
+        let fields = joined
+            .split_whitespace()
+            .filter_map(|word| {
+                let (key, value) = word.split_once(':')?;
+                let value = value.trim_start_matches('[').trim_end_matches(']');
+                let nums = value
+                    .split(',')
+                    .map(|n| u32::from_str_radix(n.trim_start_matches("0x"), 16).ok())
+                    .collect::<Option<Vec<_>>>()?;
+                Some((key.to_uppercase(), nums))
+            })
+            .collect();
+        Self { fields }
+    }
+
+    pub fn get(&self, key: &str) -> Option<&[u32]> {
+        self.fields
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_slice())
+    }
+
+    /// Fields both records have but with different values
+    pub fn differences(&self, other: &Self) -> Vec<String> {
+        self.fields
+            .iter()
+            .filter(|(k, v)| other.get(k).is_some_and(|o| o != v.as_slice()))
+            .map(|(k, _)| k.clone())
+            .collect()
+    }
+}
+
+/// Where two traces disagree, lines are 1-based
+#[derive(Debug, Clone, PartialEq)]
+pub enum Mismatch {
+    Fields {
+        line: usize,
+        fields: Vec<String>,
+    },
+    /// One trace ended at `line` while the other carried on
+    Ended {
+        line: usize,
+        ours: bool,
+    },
+}
+
+/// Up to `max` mismatches between two traces, lined up by instruction
+/// index. Blank lines are ignored.
+pub fn diff(ours: &str, theirs: &str, max: usize) -> Vec<Mismatch> {
+    let lines = |t: &str| -> Vec<Record> {
+        t.lines()
+            .filter(|l| !l.trim().is_empty())
+            .map(Record::parse)
+            .collect()
+    };
+    let (a, b) = (lines(ours), lines(theirs));
+    let mut out = Vec::new();
+    for (n, (ra, rb)) in a.iter().zip(b.iter()).enumerate() {
+        if out.len() == max {
+            return out;
+        }
+        let fields = ra.differences(rb);
+        if !fields.is_empty() {
+            out.push(Mismatch::Fields {
+                line: n + 1,
+                fields,
+            });
+        }
+    }
+    if out.len() < max && a.len() != b.len() {
+        out.push(Mismatch::Ended {
+            line: a.len().min(b.len()) + 1,
+            ours: a.len() < b.len(),
+        });
+    }
+    out
+}
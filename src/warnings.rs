@@ -0,0 +1,139 @@
+//! Warnings about things that can happen over and over, such as skipping
+//! the same unknown opcode every frame. Each one is given in full the
+//! first time and only counted after that, and the counts are listed at
+//! the end of the run.
+//!
+//! ```
+//! use chip8::warnings::Warnings;
+//!
+//! let mut w = Warnings::new();
+//! let skipped = |op: u16| move || format!("Skipped unknown opcode {:04X}", op);
+//! assert_eq!(w.warn("unknown opcode", 0x5001, skipped(0x5001)).as_deref(), Some("Skipped unknown opcode 5001"));
+//! for _ in 0..4 {
+//!     assert_eq!(w.warn("unknown opcode", 0x5001, skipped(0x5001)), None);
+//! }
+//! // Another opcode, or the same number for something else, is new
+//! assert!(w.warn("unknown opcode", 0x8008, skipped(0x8008)).is_some());
+//! assert!(w.warn("slow frame", 0x5001, || "Slow frame".to_string()).is_some());
+//! assert_eq!((w.count("unknown opcode", 0x5001), w.count("unknown opcode", 0x1234)), (5, 0));
+//! assert_eq!((w.len(), w.total()), (3, 7));
+//!
+//! // Most often first, then in the order they first happened
+//! assert_eq!(
+//!     w.to_string(),
+//!     "     count  warning\n         5  Skipped unknown opcode 5001\n         1  Skipped unknown opcode 8008\n         1  Slow frame\n"
+//! );
+//! assert_eq!(
+//!     w.to_json(),
+//!     concat!(
+//!         r#"[{"kind":"unknown opcode","id":20481,"count":5,"first":"Skipped unknown opcode 5001"},"#,
+//!         r#"{"kind":"unknown opcode","id":32776,"count":1,"first":"Skipped unknown opcode 8008"},"#,
+//!         r#"{"kind":"slow frame","id":20481,"count":1,"first":"Slow frame"}]"#
+//!     )
+//! );
+//! assert!(Warnings::new().is_empty());
+//! ```
+use crate::json;
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Entry {
+    kind: &'static str,
+    id: u64,
+    count: u64,
+    // The message from the first time
+    first: String,
+}
+
+/// Warnings by what happened and what it happened to, in the order they
+/// first happened
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Warnings {
+    entries: Vec<Entry>,
+}
+
+impl Warnings {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Counts `kind` happening to `id`, such as an unknown opcode and its
+    /// value, returning the message `detail` makes if it's the first time
+    pub fn warn(
+        &mut self,
+        kind: &'static str,
+        id: u64,
+        detail: impl FnOnce() -> String,
+    ) -> Option<String> {
+        match self
+            .entries
+            .iter_mut()
+            .find(|e| e.kind == kind && e.id == id)
+        {
+            Some(e) => {
+                e.count += 1;
+                None
+            }
+            None => {
+                let first = detail();
+                self.entries.push(Entry {
+                    kind,
+                    id,
+                    count: 1,
+                    first: first.clone(),
+                });
+                Some(first)
+            }
+        }
+    }
+
+    /// How many times `kind` has happened to `id`
+    pub fn count(&self, kind: &str, id: u64) -> u64 {
+        self.entries
+            .iter()
+            .find(|e| e.kind == kind && e.id == id)
+            .map_or(0, |e| e.count)
+    }
+
+    /// Different warnings given
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Every time anything was warned about
+    pub fn total(&self) -> u64 {
+        self.entries.iter().map(|e| e.count).sum()
+    }
+
+    // Most often first, ties in the order they first happened
+    fn sorted(&self) -> Vec<&Entry> {
+        let mut entries: Vec<&Entry> = self.entries.iter().collect();
+        entries.sort_by_key(|e| std::cmp::Reverse(e.count));
+        entries
+    }
+
+    pub fn to_json(&self) -> String {
+        json::array(self.sorted().into_iter().map(|e| {
+            json::Object::new()
+                .str("kind", e.kind)
+                .num("id", e.id)
+                .num("count", e.count)
+                .str("first", &e.first)
+                .to_string()
+        }))
+    }
+}
+
+impl fmt::Display for Warnings {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{:>10}  warning", "count")?;
+        for e in self.sorted() {
+            writeln!(f, "{:>10}  {}", e.count, e.first)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,132 @@
+//! Watch expressions, worked out again after every step and shown under
+//! the screen with the ones that changed picked out. Unlike a breakpoint
+//! they never stop anything, and one that can't be worked out shows the
+//! error in place of its value.
+use crate::emu::Chip8;
+use crate::script::{Expr, ExprError};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Watch {
+    /// As it was written
+    pub src: String,
+    expr: Expr,
+    /// None until the first `Watches::update`
+    pub value: Option<Result<u64, ExprError>>,
+    /// Different from the update before
+    pub changed: bool,
+}
+
+impl Watch {
+    /// The source and value, the value in bold if it just changed
+    pub fn line(&self) -> String {
+        let value = match &self.value {
+            None => "?".to_string(),
+            Some(Ok(n)) => format!("{:#X} ({})", n, n),
+            Some(Err(e)) => format!("error: {}", e),
+        };
+        if self.changed {
+            format!("{} = \x1b[1m{}\x1b[22m", self.src, value)
+        } else {
+            format!("{} = {}", self.src, value)
+        }
+    }
+}
+
+/// Watches in the order they were added
+///
+/// ```
+/// use chip8::emu::Chip8;
+/// use chip8::watch::Watches;
+///
+/// let rom = [
+///     0x70, 0x01, // 200: ADD V0, 01
+///     0xA3, 0xA0, // 202: LD I, 3A0
+///     0x12, 0x00, // 204: JP 200
+/// ];
+/// let mut c8 = Chip8::builder().rom(&rom).build().unwrap();
+/// let mut w = Watches::new();
+/// w.add("v0*10").unwrap();
+/// w.add("[i]").unwrap();
+/// w.add("[i*16]").unwrap();
+/// assert_eq!(w.add("v0*").unwrap_err().to_string(), "expected a value at the end of v0*");
+///
+/// // Nothing has changed the first time
+/// w.update(&c8, 0);
+/// let changed = |w: &Watches| w.iter().map(|w| w.changed).collect::<Vec<_>>();
+/// assert_eq!(changed(&w), [false, false, false]);
+/// c8.step().unwrap();
+/// w.update(&c8, 1);
+/// assert_eq!(changed(&w), [true, false, false]);
+/// assert_eq!(w.lines()[0], "v0*10 = \x1b[1m0xA (10)\x1b[22m");
+/// // I moving changes where [i] reads but not what it reads there, while
+/// // [i*16] goes past the end of RAM
+/// c8.step().unwrap();
+/// w.update(&c8, 2);
+/// assert_eq!(changed(&w), [false, false, true]);
+/// assert_eq!(w.lines()[1], "[i] = 0x0 (0)");
+/// // Errors come out in place of the value
+/// c8.execute_one(0xAFFF).unwrap();
+/// w.update(&c8, 3);
+/// assert_eq!(w.lines()[2], "[i*16] = \x1b[1merror: address FFF0 is out of range\x1b[22m");
+///
+/// assert!(w.remove(0) && !w.remove(5));
+/// assert_eq!(w.iter().map(|w| w.src.as_str()).collect::<Vec<_>>(), ["[i]", "[i*16]"]);
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Watches {
+    list: Vec<Watch>,
+}
+
+impl Watches {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds `src` to the end, unless it isn't an expression
+    pub fn add(&mut self, src: &str) -> Result<(), ExprError> {
+        self.list.push(Watch {
+            src: src.to_string(),
+            expr: src.parse()?,
+            value: None,
+            changed: false,
+        });
+        Ok(())
+    }
+
+    /// Removes the `n`th watch, from 0, returning whether there was one
+    pub fn remove(&mut self, n: usize) -> bool {
+        if n < self.list.len() {
+            self.list.remove(n);
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.list.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.list.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Watch> {
+        self.list.iter()
+    }
+
+    /// Works every watch out again on `c8`, `frame` being what `frame`
+    /// reads, marking the ones that came out different
+    pub fn update(&mut self, c8: &Chip8, frame: u64) {
+        for w in &mut self.list {
+            let value = w.expr.eval(c8, frame);
+            w.changed = w.value.as_ref().is_some_and(|old| *old != value);
+            w.value = Some(value);
+        }
+    }
+
+    /// `Watch::line` for each
+    pub fn lines(&self) -> Vec<String> {
+        self.list.iter().map(Watch::line).collect()
+    }
+}
@@ -0,0 +1,94 @@
+//! Catches a headless run stuck on Fx0A. With nothing left that could
+//! press a key, such as no terminal and a script that has finished, the
+//! program would wait until the frame limit, so the run is stopped early
+//! instead.
+use crate::emu::StepOutcome;
+use std::fmt;
+
+/// Frames spent waiting before giving up
+pub const KEY_WAIT_FRAMES: u32 = 60;
+
+/// The program is waiting for a key that can't come
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyDeadlock {
+    pub pc: u16,
+}
+
+impl fmt::Display for KeyDeadlock {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "ROM is waiting for key input at PC=0x{:03X}; provide --input-script or a key",
+            self.pc
+        )
+    }
+}
+
+impl std::error::Error for KeyDeadlock {}
+
+/// Counts frames in a row spent waiting on Fx0A
+///
+/// ```
+/// use chip8::emu::Chip8;
+/// use chip8::script::Script;
+/// use chip8::watchdog::{KeyDeadlock, KeyWatchdog};
+///
+/// // LD V0, 1; LD V1, K; JP 200
+/// let rom = [0x60, 0x01, 0xF1, 0x0A, 0x12, 0x00];
+/// // Frames until the watchdog goes off, if it does in 1000
+/// let run = |script: Option<&str>| {
+///     let mut c8 = Chip8::builder().rom(&rom).build().unwrap();
+///     let mut script = script.map(|s| Script::parse(s).unwrap());
+///     let mut dog = KeyWatchdog::new(60);
+///     for frame in 0..1000 {
+///         if let Some(sc) = &mut script {
+///             sc.before_frame(&mut c8, frame).unwrap();
+///             c8.set_keys(sc.keys());
+///         }
+///         let pc = c8.pc();
+///         let outcome = c8.step().unwrap();
+///         let live = script.as_ref().is_some_and(|sc| !sc.is_done());
+///         if let Err(e) = dog.update(&outcome, pc, live) {
+///             return Some((frame, e));
+///         }
+///     }
+///     None
+/// };
+/// // No input at all
+/// assert_eq!(run(None), Some((61, KeyDeadlock { pc: 0x202 })));
+/// // A script that has run out
+/// assert_eq!(run(Some("press 5 3\nwait 200")), Some((260, KeyDeadlock { pc: 0x202 })));
+/// // A script that presses a key after a long wait, then holds one
+/// assert_eq!(run(Some("wait 500\npress 5 1\nwait 300\nhold 7")), None);
+/// ```
+#[derive(Debug, Clone)]
+pub struct KeyWatchdog {
+    limit: u32,
+    waited: u32,
+}
+
+impl KeyWatchdog {
+    pub fn new(limit: u32) -> Self {
+        Self { limit, waited: 0 }
+    }
+
+    /// Notes how a frame's step went. `pc` is where it ran from, and
+    /// `live` is whether anything could still press a key.
+    pub fn update(
+        &mut self,
+        outcome: &StepOutcome,
+        pc: u16,
+        live: bool,
+    ) -> Result<(), KeyDeadlock> {
+        if *outcome != StepOutcome::WaitingForKey || live {
+            self.waited = 0;
+            return Ok(());
+        }
+        self.waited += 1;
+        if self.waited > self.limit {
+            Err(KeyDeadlock { pc })
+        } else {
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,99 @@
+use chip8::emu::Chip8;
+
+// Rows flagged by drawing an n-row sprite at (x, y), with wrapping or not
+fn drawn(x: u8, y: u8, n: u8, clip: bool) -> u32 {
+    let rom = [
+        0xA2,
+        0x06, // 200: LD I, 206
+        0xD0,
+        0x10 | n, // 202: DRW V0, V1, n
+        0x12,
+        0x04, // 204: JP 204
+        0xFF,
+        0x81,
+        0x81,
+        0x81,
+        0x81,
+        0x81,
+        0x81,
+        0x81, // 206: a box
+        0x81,
+        0x81,
+        0x81,
+        0x81,
+        0x81,
+        0x81,
+        0x81,
+        0xFF,
+    ];
+    let mut c8 = Chip8::builder().rom(&rom).build().unwrap();
+    let mut quirks = c8.quirks();
+    quirks.clip_x = clip;
+    quirks.clip_y = clip;
+    c8.set_quirks(quirks);
+    c8.set_v(0, x);
+    c8.set_v(1, y);
+    // Everything at first, then nothing until something's drawn
+    assert_eq!(c8.take_dirty(), u32::MAX);
+    assert_eq!(c8.take_dirty(), 0);
+    c8.run_n(1).unwrap();
+    assert_eq!(c8.take_dirty(), 0);
+    c8.run_n(1).unwrap();
+    c8.take_dirty()
+}
+
+fn rows(r: impl IntoIterator<Item = u32>) -> u32 {
+    r.into_iter().fold(0, |m, r| m | 1 << r)
+}
+
+#[test]
+fn drw_marks_the_rows_it_covers() {
+    assert_eq!(drawn(0, 0, 1, true), rows([0]));
+    assert_eq!(drawn(10, 5, 3, true), rows(5..8));
+    assert_eq!(drawn(60, 20, 15, true), rows(20..32));
+    assert_eq!(drawn(0, 17, 15, false), rows(17..32));
+    // A sprite of no rows draws nothing
+    assert_eq!(drawn(10, 5, 0, true), 0);
+}
+
+#[test]
+fn wrapped_sprites_mark_the_top_rows() {
+    assert_eq!(drawn(0, 30, 4, false), rows([30, 31, 0, 1]));
+    assert_eq!(drawn(0, 20, 15, false), rows((20..32).chain(0..3)));
+    // Starting past the bottom wraps round before drawing, either way
+    assert_eq!(drawn(0, 33, 2, true), rows([1, 2]));
+    assert_eq!(drawn(0, 33, 2, false), rows([1, 2]));
+    // Going off the side doesn't change the rows
+    assert_eq!(drawn(62, 4, 2, false), rows([4, 5]));
+    assert_eq!(drawn(62, 4, 2, true), rows([4, 5]));
+}
+
+#[test]
+fn clipped_sprites_mark_only_whats_on_screen() {
+    assert_eq!(drawn(0, 30, 4, true), rows([30, 31]));
+    assert_eq!(drawn(0, 31, 15, true), rows([31]));
+}
+
+#[test]
+fn cls_marks_everything() {
+    let rom = [
+        0xA2, 0x08, // 200: LD I, 208
+        0xD0, 0x01, // 202: DRW V0, V0, 1
+        0x00, 0xE0, // 204: CLS
+        0x12, 0x06, // 206: JP 206
+        0x80, //       208: a dot
+    ];
+    let mut c8 = Chip8::builder().rom(&rom).build().unwrap();
+    c8.take_dirty();
+    c8.run_n(2).unwrap();
+    assert_eq!(c8.take_dirty(), 1);
+    c8.run_n(1).unwrap();
+    assert_eq!(c8.take_dirty(), u32::MAX);
+    // As do resets and loading a state
+    c8.reset();
+    assert_eq!(c8.take_dirty(), u32::MAX);
+    let state = c8.save_state();
+    c8.take_dirty();
+    c8.load_state(&state);
+    assert_eq!(c8.take_dirty(), u32::MAX);
+}
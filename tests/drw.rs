@@ -0,0 +1,124 @@
+use chip8::emu::{Chip8, Quirks};
+use chip8::state::SaveState;
+
+// xorshift, so the screens and sprites are the same every run
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+}
+
+// DRW a pixel at a time, the way it's described. Returns the screen, VF
+// and the rows drawn on.
+fn reference(s: &SaveState, x: usize, y: usize, n: usize, q: Quirks) -> ([u64; 32], u8, u32) {
+    let (mut screen, mut vf, mut dirty) = (s.screen, s.v[15], 0);
+    if n == 0 {
+        return (screen, vf, dirty);
+    }
+    let (x, y) = (s.v[x] as usize % 64, s.v[y] as usize % 32);
+    vf = 0;
+    for r in 0..n {
+        let row = y + r;
+        if row >= 32 && q.clip_y {
+            continue;
+        }
+        let row = row % 32;
+        dirty |= 1 << row;
+        let byte = s.ram[(s.i as usize + r) % s.ram.len()];
+        for b in 0..8 {
+            let col = x + b;
+            if byte & 0x80 >> b == 0 || col >= 64 && q.clip_x {
+                continue;
+            }
+            let bit = 1 << (63 - col % 64);
+            if screen[row] & bit != 0 {
+                vf = 1;
+            }
+            screen[row] ^= bit;
+        }
+    }
+    (screen, vf, dirty)
+}
+
+fn draw(s: &SaveState, q: Quirks) -> ([u64; 32], u8, u32) {
+    let mut c8 = Chip8::from_state(s, q);
+    c8.take_dirty();
+    c8.run_n(1).unwrap();
+    let state = c8.save_state();
+    (state.screen, state.v[15], c8.take_dirty())
+}
+
+#[test]
+fn drw_matches_the_reference() {
+    let mut rng = Rng(0x9E37_79B9_7F4A_7C15);
+    let xs = (0..64).chain([64, 70, 127, 200, 255]);
+    let ys = [0, 1, 15, 25, 28, 31, 32, 63, 255];
+    let quirks = [(false, false), (true, false), (false, true), (true, true)];
+    let mut state = SaveState::default();
+    for x in xs {
+        for &y in ys.iter() {
+            for n in 0..16 {
+                for &(clip_x, clip_y) in quirks.iter() {
+                    let q = Quirks {
+                        clip_x,
+                        clip_y,
+                        ..Quirks::default()
+                    };
+                    for row in state.screen.iter_mut() {
+                        *row = rng.next() & rng.next();
+                    }
+                    // Sprites anywhere, some running off the end of RAM
+                    state.i = [0x300, 0xFFA, 0xFFF][(rng.next() % 3) as usize];
+                    for b in 0..16 {
+                        state.ram[(state.i as usize + b) % 0x1000] = rng.next() as u8;
+                    }
+                    state.v = [0; 16];
+                    state.v[15] = 7;
+                    state.v[0x3] = x;
+                    state.v[0xA] = y;
+                    state.ram[0x200] = 0xD3;
+                    state.ram[0x201] = 0xA0 | n as u8;
+                    assert_eq!(
+                        draw(&state, q),
+                        reference(&state, 0x3, 0xA, n, q),
+                        "x {} y {} n {} clip {:?}",
+                        x,
+                        y,
+                        n,
+                        (clip_x, clip_y)
+                    );
+                }
+            }
+        }
+    }
+}
+
+// VF as x or y is read before it's set
+#[test]
+fn drw_with_vf_as_a_coordinate() {
+    let mut rng = Rng(1);
+    let mut state = SaveState::default();
+    for vf in [0, 1, 7, 60, 63, 200] {
+        for row in state.screen.iter_mut() {
+            *row = rng.next();
+        }
+        state.i = 0x300;
+        for b in 0..16 {
+            state.ram[0x300 + b] = rng.next() as u8;
+        }
+        state.v[15] = vf;
+        state.v[2] = 30;
+        // DRW VF, V2, 9 then DRW V2, VF, 9 then DRW VF, VF, 9
+        for (x, y) in [(0xF, 0x2), (0x2, 0xF), (0xF, 0xF)] {
+            state.ram[0x200] = 0xD0 | x as u8;
+            state.ram[0x201] = (y as u8) << 4 | 9;
+            let q = Quirks::default();
+            assert_eq!(draw(&state, q), reference(&state, x, y, 9, q), "VF {}", vf);
+        }
+    }
+}
@@ -0,0 +1,247 @@
+use chip8::emu::{Chip8, HookAction, SmcWrite};
+use chip8::rom::RomError;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+#[test]
+fn smc_monitor_above_4k() {
+    let rom = [
+        0x60, 0x60, // 1200: LD V0, 60
+        0x61, 0x7B, // 1202: LD V1, 7B
+        0xAF, 0xFF, // 1204: LD I, FFF
+        0x62, 0xFF, // 1206: LD V2, FF
+        0xF2, 0x1E, // 1208: ADD I, V2
+        0xF2, 0x1E, // 120A: ADD I, V2
+        0x62, 0x03, // 120C: LD V2, 03
+        0xF2, 0x1E, // 120E: ADD I, V2, making I 1200
+        0xF1, 0x55, // 1210: LD [I], V1, making 1200 LD V0, 7B
+    ];
+    let mut c8 = Chip8::builder()
+        .rom(&rom)
+        .ram_size(0x10000)
+        .load_address(0x1200)
+        .build()
+        .unwrap();
+    c8.set_smc_monitor(true);
+    c8.run_n(9).unwrap();
+    assert_eq!(c8.pc(), 0x1212);
+    assert_eq!(c8.coverage(), Some(vec![(0x1200, 0x1211)]));
+    assert_eq!(
+        c8.smc_writes(),
+        [SmcWrite {
+            pc: 0x1210,
+            addr: 0x1201,
+            old: 0x60,
+            new: 0x7B
+        }]
+    );
+}
+
+#[test]
+fn smc_monitor_after_loading_a_64k_state() {
+    let big = Chip8::builder().ram_size(0x10000).build().unwrap();
+    let mut state = big.save_state();
+    // F000: LD V0, 61  F002: LD [I], V0, making F000 LD V1, 61
+    state.ram[0xF000..0xF004].copy_from_slice(&[0x60, 0x61, 0xF0, 0x55]);
+    state.pc = 0xF000;
+    state.i = 0xF000;
+    let mut c8 = Chip8::builder().rom(&[0x12, 0x00]).build().unwrap();
+    c8.set_smc_monitor(true);
+    c8.run_n(3).unwrap();
+    c8.load_state(&state);
+    assert_eq!(c8.save_state().ram.len(), 0x10000);
+    c8.run_n(2).unwrap();
+    assert_eq!(c8.pc(), 0xF004);
+    assert_eq!(c8.coverage(), Some(vec![(0xF000, 0xF003)]));
+    assert_eq!(
+        c8.smc_writes(),
+        [SmcWrite {
+            pc: 0xF002,
+            addr: 0xF000,
+            old: 0x60,
+            new: 0x61
+        }]
+    );
+}
+
+#[test]
+fn smc_monitor_when_run_fast() {
+    let rom = [
+        0xA2, 0x0D, // 200: LD I, 20D
+        0x70, 0x01, // 202: ADD V0, 1
+        0xF0, 0x55, // 204: LD [I], V0, changing what 20C loads
+        0x12, 0x08, // 206: JP 208
+        0x12, 0x0A, // 208: JP 20A
+        0x12, 0x0C, // 20A: JP 20C
+        0x61, 0x00, // 20C: LD V1, 00
+        0x12, 0x00, // 20E: JP 200
+    ];
+    // Invariant checks, on in debug builds, would take the slow path
+    let run = |fast: bool| {
+        let mut c8 = Chip8::builder().rom(&rom).validate(false).build().unwrap();
+        c8.set_smc_monitor(true);
+        if fast {
+            assert_eq!(c8.run_steps_fast(20).unwrap(), 20);
+        } else {
+            for _ in 0..20 {
+                c8.step().unwrap();
+            }
+        }
+        (c8.coverage(), c8.smc_writes().to_vec(), c8.save_state())
+    };
+    let stepped = run(false);
+    assert_eq!(stepped.0, Some(vec![(0x200, 0x20F)]));
+    // Not the first time round, before 20D had run
+    assert_eq!(stepped.1.len(), 2);
+    assert_eq!(run(true), stepped);
+}
+
+#[test]
+fn step_back_over_call_drw_ret() {
+    let rom = [
+        0xA2, 0x0A, // 200: LD I, 20A
+        0x22, 0x06, // 202: CALL 206
+        0x12, 0x04, // 204: JP 204
+        0xD0, 0x15, // 206: DRW V0, V1, 5
+        0x00, 0xEE, // 208: RET
+        0xF0, 0x90, 0x90, 0x90, 0xF0,
+    ];
+    let mut c8 = Chip8::builder().rom(&rom).build().unwrap();
+    c8.set_journal(16);
+    let mut states = vec![c8.save_state()];
+    for _ in 0..5 {
+        c8.step().unwrap();
+        states.push(c8.save_state());
+    }
+    assert_eq!(c8.pc(), 0x204);
+    for want in states.iter().rev().skip(1) {
+        assert!(c8.step_back());
+        assert_eq!(&c8.save_state(), want);
+    }
+    assert!(!c8.step_back());
+    // Running it again goes the same way, RET included
+    for want in &states[1..] {
+        c8.step().unwrap();
+        assert_eq!(&c8.save_state(), want);
+    }
+}
+
+#[test]
+fn eti660_loads_and_starts_at_600() {
+    let rom = std::fs::read(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/data/eti660.ch8"
+    ))
+    .unwrap();
+    let rng = Box::new(rand::rngs::mock::StepRng::new(0, 1));
+    let mut c8 = Chip8::with_addrs(&[], &rom, 0x600, 0x600, rng).unwrap();
+    assert_eq!(c8.pc(), 0x600);
+    assert!((0x200..0x600).all(|a| c8.peek(a) == 0));
+    assert!((0..rom.len()).all(|n| c8.peek(0x600 + n as u16) == rom[n]));
+
+    let fetched = Rc::new(RefCell::new(Vec::new()));
+    let f = fetched.clone();
+    c8.set_pre_exec_hook(Box::new(move |_, pc, op| {
+        f.borrow_mut().push((pc, op));
+        HookAction::Continue
+    }));
+    // Stops at the jump to itself
+    c8.run_n(5).unwrap();
+    assert_eq!(
+        fetched.borrow()[..],
+        [
+            (0x600, 0x6005),
+            (0x602, 0xA608),
+            (0x604, 0xD005),
+            (0x606, 0x1606)
+        ]
+    );
+    // The sprite at 608 was found through the 0x600 address
+    assert_eq!(c8.screen[5] >> (63 - 5 - 3), 0b1111);
+
+    // Loaded at 0x200 the jump goes nowhere it should
+    let mut wrong = Chip8::builder().rom(&rom).build().unwrap();
+    wrong.run_n(4).unwrap();
+    assert_eq!(wrong.pc(), 0x606);
+    assert_ne!(wrong.screen, c8.screen);
+
+    // There's less room above 0x600
+    let rng = Box::new(rand::rngs::mock::StepRng::new(0, 1));
+    assert!(matches!(
+        Chip8::with_addrs(&[], &[0; 0xA01], 0x600, 0x600, rng),
+        Err(chip8::emu::BuildError::Rom(RomError::DoesNotFit {
+            load: 0x600,
+            len: 0xA01
+        }))
+    ));
+}
+
+// Sets registers and timers, calls two deep and draws before stopping
+const GAME: [u8; 24] = [
+    0x60, 0xAA, // 200: LD V0, AA
+    0x6E, 0x0B, // 202: LD VE, 0B
+    0xA2, 0x00, // 204: LD I, 200
+    0x61, 0x30, // 206: LD V1, 30
+    0xF1, 0x15, // 208: LD DT, V1
+    0xF1, 0x18, // 20A: LD ST, V1
+    0x22, 0x10, // 20C: CALL 210
+    0x00, 0x00, //
+    0x22, 0x14, // 210: CALL 214
+    0x00, 0x00, //
+    0xD0, 0xE5, // 214: DRW V0, VE, 5
+    0x12, 0x16, // 216: JP 216
+];
+
+// Nothing of `GAME` is left but what's in RAM
+fn assert_fresh(c8: &Chip8) {
+    let state = c8.save_state();
+    assert_eq!((c8.v(), c8.i(), c8.pc(), c8.sp()), ([0; 16], 0, 0x200, 0));
+    assert_eq!((c8.delay_timer(), c8.sound_timer()), (0, 0));
+    assert!(state.stk.iter().all(|&a| a == 0), "{:?}", state.stk);
+    assert_eq!(c8.screen, [0; 32]);
+    assert_eq!((c8.frames(), c8.retired(), c8.frame_step()), (0, 0, 0));
+}
+
+fn run_game() -> Chip8 {
+    let mut c8 = Chip8::builder().rom(&GAME).build().unwrap();
+    c8.set_journal(64);
+    c8.set_smc_monitor(true);
+    c8.begin_frame(0);
+    c8.run_n(20).unwrap();
+    c8.end_frame();
+    assert_eq!((c8.pc(), c8.sp(), c8.v()[0]), (0x216, 2, 0xAA));
+    assert!(c8.frames() == 1 && c8.retired() > 0);
+    assert!(c8.delay_timer() > 0 && c8.sound_timer() > 0);
+    assert_ne!(c8.screen, [0; 32]);
+    c8
+}
+
+#[test]
+fn nothing_leaks_into_the_next_rom() {
+    let mut c8 = run_game();
+    c8.load_rom(&[0x12, 0x00]).unwrap();
+    assert_fresh(&c8);
+    assert_eq!(c8.save_state().rom, chip8::state::rom_hash(&[0x12, 0x00]));
+    // The rest of the old program is gone
+    assert!((0x202..0x1000).all(|a| c8.peek(a) == 0));
+    // Stepping back can't go into the old game, and what it ran isn't
+    // counted as run in the new one
+    assert!(!c8.step_back());
+    assert_eq!(c8.coverage(), Some(vec![]));
+    c8.run_n(3).unwrap();
+    assert_eq!(c8.coverage(), Some(vec![(0x200, 0x201)]));
+}
+
+#[test]
+fn nothing_leaks_past_a_reset() {
+    let mut c8 = run_game();
+    c8.reset();
+    assert_fresh(&c8);
+    assert!(!c8.step_back());
+    // The program is kept, and runs the same again
+    assert!((0..GAME.len()).all(|n| c8.peek(0x200 + n as u16) == GAME[n]));
+    let again = run_game();
+    c8.run_n(20).unwrap();
+    assert_eq!(c8.screen, again.screen);
+    assert_eq!(c8.v(), again.v());
+}
@@ -0,0 +1,37 @@
+#![cfg(feature = "terminal")]
+use std::process::{Command, Stdio};
+
+// Exit code of a headless run of a ROM under the crate root
+fn run(rom: &str, frames: u32) -> Option<i32> {
+    Command::new(env!("CARGO_BIN_EXE_chip8"))
+        .arg(format!("{}/{}", env!("CARGO_MANIFEST_DIR"), rom))
+        .args(["--headless", "--max-frames", &frames.to_string()])
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .unwrap()
+        .code()
+}
+
+#[test]
+fn ran_to_the_frame_limit() {
+    assert_eq!(run("ibm.ch8", 10), Some(0));
+}
+
+#[test]
+fn unreadable_rom() {
+    assert_eq!(run("tests/data/missing.ch8", 10), Some(1));
+}
+
+#[test]
+fn fault() {
+    // LD V0, 1 then FFFF
+    assert_eq!(run("tests/data/fault.ch8", 200), Some(2));
+}
+
+#[test]
+fn key_deadlock() {
+    // LD V0, 1; LD V1, K; JP 200 with no keys coming
+    assert_eq!(run("tests/data/deadlock.ch8", 200), Some(3));
+}
@@ -0,0 +1,83 @@
+#![cfg(feature = "terminal")]
+use std::process::{Command, Output, Stdio};
+
+// A headless JSON run of ibm.ch8 with `args` added
+fn run(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_chip8"))
+        .arg(format!("{}/ibm.ch8", env!("CARGO_MANIFEST_DIR")))
+        .args(["--headless", "--json", "--max-frames", "100"])
+        .args(args)
+        .stdin(Stdio::null())
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn idle_skip_changes_nothing_the_game_sees() {
+    let plain = run(&[]);
+    let skipping = run(&["--idle-skip"]);
+    assert!(plain.status.success() && skipping.status.success());
+    // Frame times are left out, they change from run to run
+    let counts = |out: &Output| {
+        let json = String::from_utf8(out.stdout.clone()).unwrap();
+        json[..json.find("\"frame_time_us\"").unwrap()].to_string()
+    };
+    assert_eq!(counts(&plain), counts(&skipping));
+    assert!(skipping.stderr.is_empty());
+}
+
+#[test]
+fn idle_skip_is_off_for_movies() {
+    let path = std::env::temp_dir().join(format!("chip8-idle-{}.c8rec", std::process::id()));
+    let out = run(&["--idle-skip", "--record-input", path.to_str().unwrap()]);
+    let _ = std::fs::remove_file(&path);
+    assert!(out.status.success());
+    let err = String::from_utf8(out.stderr).unwrap();
+    assert!(
+        err.contains("Warning: --idle-skip is off with netplay and movies"),
+        "{}",
+        err
+    );
+}
+
+// What comes after `"key":` in a summary
+fn field(out: &Output, key: &str) -> String {
+    let json = String::from_utf8(out.stdout.clone()).unwrap();
+    let at = json.find(&format!("\"{}\":", key)).unwrap() + key.len() + 3;
+    json[at..].split([',', '}']).next().unwrap().to_string()
+}
+
+#[test]
+fn dt_waits_are_skipped() {
+    // LD V0, 30; LD DT, V0; then LD V1, DT; SE V1, 0; JP 204 until it's
+    // run out, then draws the digit in V2, adds 1 to it and waits again
+    let run = |args: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_chip8"))
+            .arg(format!(
+                "{}/tests/data/dt_wait.ch8",
+                env!("CARGO_MANIFEST_DIR")
+            ))
+            .args(["--headless", "--json", "--max-frames", "300"])
+            .args(["--steps-per-frame", "20"])
+            .args(args)
+            .stdin(Stdio::null())
+            .output()
+            .unwrap()
+    };
+    let (plain, skipping) = (run(&[]), run(&["--idle-skip"]));
+    assert!(plain.status.success() && skipping.status.success());
+    for key in [
+        "instruction_count",
+        "retired",
+        "machine_frames",
+        "screen_hash",
+    ] {
+        assert_eq!(field(&plain, key), field(&skipping, key), "{}", key);
+    }
+    assert_eq!(field(&plain, "idle_steps"), "0");
+    // 10 frames a wait, most of them gone through without running
+    let count = |key| field(&skipping, key).parse::<u64>().unwrap();
+    let (all, idle) = (count("instruction_count"), count("idle_steps"));
+    assert_eq!(all, 300 * 20);
+    assert!(all - idle < all / 4, "{} of {}", idle, all);
+}
@@ -0,0 +1,114 @@
+use chip8::info::{Info, Platform, Scan};
+
+fn read(name: &str) -> Vec<u8> {
+    std::fs::read(format!(
+        "{}/tests/data/info/{}",
+        env!("CARGO_MANIFEST_DIR"),
+        name
+    ))
+    .unwrap()
+}
+
+fn info(name: &str) -> Info {
+    Info::new(&read(&format!("{}.ch8", name)))
+}
+
+fn scan(chip8: usize, superchip: usize, xochip: usize, invalid: usize) -> Scan {
+    Scan {
+        chip8,
+        superchip,
+        xochip,
+        invalid,
+    }
+}
+
+#[test]
+fn chip8() {
+    let i = info("chip8");
+    assert_eq!(
+        (i.platform, i.scan, i.size),
+        (Platform::Chip8, scan(4, 0, 0, 0), 8)
+    );
+    assert!(i.warnings.is_empty());
+}
+
+#[test]
+fn hires() {
+    let i = info("hires");
+    assert_eq!((i.platform, i.scan), (Platform::Hires, scan(50, 0, 0, 0)));
+    assert_eq!(i.warnings.len(), 1);
+    assert!(i.warnings[0].starts_with("starts with JP 260"));
+}
+
+#[test]
+fn superchip() {
+    let i = info("superchip");
+    // The sprite at the end is the word that isn't anything
+    assert_eq!(
+        (i.platform, i.scan),
+        (Platform::SuperChip, scan(2, 4, 0, 1))
+    );
+    assert_eq!(
+        i.warnings,
+        ["1 words aren't instructions on any platform (data, or not a ROM)"]
+    );
+}
+
+#[test]
+fn xochip() {
+    let i = info("xochip");
+    assert_eq!((i.platform, i.scan), (Platform::XoChip, scan(4, 0, 3, 0)));
+    assert!(i.warnings.is_empty());
+}
+
+#[test]
+fn junk() {
+    let i = info("junk");
+    assert_eq!(i.platform, Platform::Chip8);
+    assert_eq!(
+        i.warnings,
+        [
+            "odd length, the last byte can't be an instruction",
+            "the first word isn't an instruction on any platform"
+        ]
+    );
+}
+
+#[test]
+fn too_big() {
+    let i = Info::new(&[0x12; 0xE02]);
+    assert!(i
+        .warnings
+        .contains(&"3586 bytes is more than the 3584 that fit at 0x200 in 4 KB".to_string()));
+}
+
+// Both forms of `chip8 info` for every fixture, against the .txt and .json
+// files next to it
+#[cfg(feature = "terminal")]
+#[test]
+fn command_output() {
+    for name in ["chip8", "hires", "superchip", "xochip", "junk"] {
+        let rom = format!(
+            "{}/tests/data/info/{}.ch8",
+            env!("CARGO_MANIFEST_DIR"),
+            name
+        );
+        for (args, ext) in [(&[][..], "txt"), (&["--json"][..], "json")] {
+            let out = std::process::Command::new(env!("CARGO_BIN_EXE_chip8"))
+                .arg("info")
+                .args(args)
+                .arg(&rom)
+                .output()
+                .unwrap();
+            assert!(out.status.success());
+            let want = read(&format!("{}.{}", name, ext));
+            assert_eq!(
+                String::from_utf8(out.stdout).unwrap(),
+                String::from_utf8(want).unwrap(),
+                "{}.{}",
+                name,
+                ext
+            );
+        }
+    }
+}
@@ -0,0 +1,201 @@
+use chip8::emu::{Chip8, LoadStoreIncrement, Quirks};
+use chip8::json::{self, ReadError};
+use chip8::keypad::{Keymap, LAYOUTS};
+use chip8::state::SaveState;
+use chip8::theme::Theme;
+
+fn data(name: &str) -> String {
+    std::fs::read_to_string(format!(
+        "{}/tests/data/json/{}",
+        env!("CARGO_MANIFEST_DIR"),
+        name
+    ))
+    .unwrap()
+}
+
+fn ibm(steps: u32) -> Chip8 {
+    let rom = std::fs::read(concat!(env!("CARGO_MANIFEST_DIR"), "/ibm.ch8")).unwrap();
+    let mut c8 = Chip8::builder().build().unwrap();
+    c8.load_rom(&rom).unwrap();
+    c8.run_n(steps).unwrap();
+    c8
+}
+
+#[test]
+fn quirks_round_trip() {
+    for bits in 0..64 {
+        let q = Quirks::from_bits(bits);
+        let read = Quirks::from_json(&json::parse(&q.to_json()).unwrap());
+        assert_eq!(read, Ok(q), "{:06b}", bits);
+    }
+    for name in Quirks::PRESETS {
+        let q = Quirks::preset(name).unwrap();
+        assert_eq!(
+            Quirks::from_json(&json::parse(&q.to_json()).unwrap()),
+            Ok(q)
+        );
+    }
+}
+
+#[test]
+fn themes_round_trip() {
+    let mut theme = Theme::default();
+    assert_eq!(
+        Theme::from_json(&json::parse(&theme.to_json()).unwrap()),
+        Ok(theme)
+    );
+    for n in 0..=255u8 {
+        theme.colors = [[n, 0, 255 - n], [1, n, 2], [n, n, n], [0xAB, 0xCD, n]];
+        assert_eq!(
+            Theme::from_json(&json::parse(&theme.to_json()).unwrap()),
+            Ok(theme)
+        );
+    }
+}
+
+#[test]
+fn keymaps_round_trip() {
+    for (name, _) in LAYOUTS.iter() {
+        let map = Keymap::preset(name).unwrap();
+        assert_eq!(
+            Keymap::from_json(&json::parse(&map.to_json()).unwrap()),
+            Ok(map),
+            "{}",
+            name
+        );
+    }
+    // Every key moved
+    let mut map = Keymap::default();
+    for (k, c) in "mnbvcxzlkjhgfdsa".chars().enumerate() {
+        map.set(k as u8, c);
+    }
+    assert_eq!(
+        Keymap::from_json(&json::parse(&map.to_json()).unwrap()),
+        Ok(map)
+    );
+}
+
+#[test]
+fn save_states_round_trip() {
+    let state = ibm(40).save_state();
+    let read = SaveState::from_json(&json::parse(&state.to_json()).unwrap());
+    assert_eq!(read.as_ref(), Ok(&state));
+    // The same state as the binary form
+    assert_eq!(read.unwrap().to_bytes(), state.to_bytes());
+
+    let mut big = Chip8::builder().ram_size(0x10000).build().unwrap();
+    big.load_rom(&[0x12, 0x00]).unwrap();
+    let mut state = big.save_state();
+    state.ram[0xFFFF] = 0x5A;
+    state.stk = [0xFFFF; 16];
+    state.v = [0xFF; 16];
+    state.extensions = true;
+    state.frames = u32::MAX as u64 * 3;
+    state.retired = 1 << 52;
+    state.frame_step = u32::MAX;
+    state.rom = u64::MAX;
+    state.screen = [u64::MAX; 32];
+    let read = SaveState::from_json(&json::parse(&state.to_json()).unwrap());
+    assert_eq!(read, Ok(state));
+}
+
+#[test]
+fn bad_save_states_are_turned_down() {
+    let text = ibm(10).save_state().to_json();
+    let bad = |from: &str, to: &str| {
+        assert!(text.contains(from), "{}", from);
+        SaveState::from_json(&json::parse(&text.replacen(from, to, 1)).unwrap())
+    };
+    assert_eq!(bad(r#""pc":"#, r#""pd":"#), Err(ReadError::Field("pc")));
+    assert_eq!(
+        bad(r#""pc":"#, r#""pc":1,"x":"#),
+        Err(ReadError::Field("pc"))
+    );
+    assert_eq!(bad(r#""i":""#, r#""i":"+"#), Err(ReadError::Field("i")));
+    assert_eq!(bad(r#""dt":0"#, r#""dt":256"#), Err(ReadError::Field("dt")));
+    assert_eq!(bad(r#""sp":0"#, r#""sp":17"#), Err(ReadError::Field("sp")));
+    assert!(bad(r#""sp":0"#, r#""sp":16"#).is_ok());
+    assert_eq!(bad(r#""v":["#, r#""v":[0,"#), Err(ReadError::Field("v")));
+    assert_eq!(
+        bad(r#""stk":["#, r#""stk":["10000","#),
+        Err(ReadError::Field("stk"))
+    );
+    assert_eq!(
+        bad(r#""ram":""#, r#""ram":"00"#),
+        Err(ReadError::Field("ram"))
+    );
+    assert_eq!(
+        bad(r#""ram":"00"#, r#""ram":"0g"#),
+        Err(ReadError::Field("ram"))
+    );
+    assert_eq!(
+        bad(r#""ram":""#, r#""ram":"0"#),
+        Err(ReadError::Field("ram"))
+    );
+    assert_eq!(
+        bad(r#""screen":[""#, r#""screen":["0"#),
+        Err(ReadError::Field("screen"))
+    );
+    assert_eq!(
+        bad(r#""rom":""#, r#""rom":"x"#),
+        Err(ReadError::Field("rom"))
+    );
+    assert_eq!(
+        bad(r#""extensions":false"#, r#""extensions":0"#),
+        Err(ReadError::Field("extensions"))
+    );
+    assert_eq!(
+        SaveState::from_json(&json::parse("[]").unwrap()),
+        Err(ReadError::Field("ram"))
+    );
+    assert_eq!(ReadError::Field("ram").to_string(), "missing or bad ram");
+}
+
+// Written before key_release and mem_inc_x were quirks, with a palette
+// in short colors and only the keys that were moved
+#[test]
+fn old_config_still_reads() {
+    let config = json::parse(&data("config-old.json")).unwrap();
+    let quirks = Quirks::from_json(config.get("quirks").unwrap()).unwrap();
+    assert_eq!(
+        quirks,
+        Quirks {
+            clip_x: false,
+            clip_y: true,
+            hp_shift: false,
+            mem_inc: LoadStoreIncrement::XPlusOne,
+            key_release: false,
+        }
+    );
+    let theme = Theme::from_json(config.get("theme").unwrap()).unwrap();
+    assert_eq!(
+        theme,
+        Theme::parse("#000000,#00ff00,#ff0000,#ffff00").unwrap()
+    );
+    let keymap = Keymap::from_json(config.get("keymap").unwrap()).unwrap();
+    let mut moved = Keymap::default();
+    moved.set(0x5, 'j');
+    moved.set(0x8, 'k');
+    assert_eq!(keymap, moved);
+    assert_eq!((keymap.map('J'), keymap.map('s')), (Some(0x5), None));
+}
+
+// Written before the counters and `extensions` were kept
+#[test]
+fn old_save_state_still_reads() {
+    let text = data("state-old.json");
+    assert!(!text.contains("frames") && !text.contains("extensions"));
+    let state = SaveState::from_json(&json::parse(&text).unwrap()).unwrap();
+    let now = ibm(40).save_state();
+    assert_eq!((state.frames, state.retired, state.frame_step), (0, 0, 0));
+    assert!(!state.extensions);
+    assert_eq!(
+        state,
+        SaveState {
+            frames: 0,
+            retired: 0,
+            frame_step: 0,
+            ..now
+        }
+    );
+}
@@ -0,0 +1,95 @@
+use chip8::keypad::{KeySource, Keypad, Release, Turbo, HOLD};
+use std::time::{Duration, Instant};
+
+const TICK: Duration = Duration::from_nanos(1);
+
+#[test]
+fn held_keys_let_go_on_the_tick() {
+    let start = Instant::now();
+    let mut keys = Keypad::new(Release::default());
+    keys.press(0x5, start);
+    assert_eq!(keys.mask(start), 1 << 0x5);
+    assert_eq!(keys.mask(start + HOLD - TICK), 1 << 0x5);
+    assert_eq!(keys.mask(start + HOLD), 0);
+    // Gone for good once seen let go, even asked about earlier
+    assert_eq!(keys.mask(start), 0);
+}
+
+#[test]
+fn repeats_push_the_release_back() {
+    let start = Instant::now();
+    let mut keys = Keypad::new(Release::default());
+    keys.press(0xA, start);
+    keys.press(0xA, start + HOLD / 2);
+    assert_eq!(keys.mask(start + HOLD), 1 << 0xA);
+    assert_eq!(keys.mask(start + HOLD / 2 + HOLD - TICK), 1 << 0xA);
+    assert_eq!(keys.mask(start + HOLD / 2 + HOLD), 0);
+}
+
+#[test]
+fn movement_and_action_keys_have_their_own_ticks() {
+    let start = Instant::now();
+    let (movement, action) = (Duration::from_millis(500), Duration::from_millis(120));
+    let mut keys = Keypad::new(Release::After { movement, action });
+    // 4 moves, 5 acts, and 0x14 is 4 again
+    for key in [0x4, 0x5, 0x14] {
+        keys.press(key, start);
+    }
+    assert_eq!(keys.mask(start), 1 << 0x4 | 1 << 0x5);
+    assert_eq!(keys.mask(start + action - TICK), 1 << 0x4 | 1 << 0x5);
+    assert_eq!(keys.mask(start + action), 1 << 0x4);
+    assert_eq!(keys.mask(start + movement - TICK), 1 << 0x4);
+    assert_eq!(keys.mask(start + movement), 0);
+    for key in [0x2, 0x6, 0x8] {
+        keys.press(key, start);
+        assert_eq!(keys.mask(start + action), 1 << key);
+        assert_eq!(keys.mask(start + movement), 0);
+    }
+}
+
+#[test]
+fn latched_keys_wait_for_another_key() {
+    let start = Instant::now();
+    let mut keys = Keypad::new(Release::Latched);
+    keys.press(0x7, start);
+    assert_eq!(keys.mask(start + Duration::from_secs(3600)), 1 << 0x7);
+    keys.press(0x7, start);
+    assert_eq!(keys.mask(start), 1 << 0x7);
+    keys.press(0xF, start);
+    assert_eq!(keys.mask(start), 1 << 0xF);
+    keys.release_all();
+    assert_eq!(keys.mask(start), 0);
+    assert_eq!(keys.mask(start + HOLD), 0);
+}
+
+#[test]
+fn letting_go_of_everything() {
+    let start = Instant::now();
+    let mut keys = Keypad::new(Release::default());
+    keys.press(0x1, start);
+    keys.press(0x2, start);
+    keys.release_all();
+    assert_eq!(keys.mask(start), 0);
+    keys.press(0x3, start);
+    assert_eq!(keys.mask(start + HOLD - TICK), 1 << 0x3);
+}
+
+#[test]
+fn turbo_at_15_hz_toggles_every_2_frames_at_60() {
+    let mut turbo = Turbo::default();
+    turbo.set(0x5, 15);
+    let held = 1 << 0x5 | 1 << 0x6;
+    let frames: Vec<u16> = (0..12).map(|f| turbo.apply(held, f, 60)).collect();
+    let (on, off) = (held, 1 << 0x6);
+    assert_eq!(
+        frames,
+        [on, on, off, off, on, on, off, off, on, on, off, off]
+    );
+    // Keys not held stay up, and other keys aren't touched
+    assert_eq!(turbo.apply(1 << 0x6, 2, 60), 1 << 0x6);
+    assert_eq!(turbo.apply(0, 0, 60), 0);
+    assert!(turbo.active(held));
+    assert!(!turbo.active(1 << 0x6));
+    turbo.set(0x5, 0);
+    assert_eq!(turbo.apply(held, 2, 60), held);
+}
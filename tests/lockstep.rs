@@ -0,0 +1,65 @@
+use chip8::lockstep::{compare, Config, LockstepError};
+
+fn rom(name: &str) -> Vec<u8> {
+    std::fs::read(format!("{}/{}", env!("CARGO_MANIFEST_DIR"), name)).unwrap()
+}
+
+// LD V1, 3; SHR V0, V1; JP 204
+const REPORT: &str = "diverged after 2 steps
+V0  0000 -> 0001
+VF  0000 -> 0001
+recent a: 200:6103 202:8016
+recent b: 200:6103 202:8016
+";
+
+#[test]
+fn same_config_never_diverges() {
+    let a = Config::new(&[], &rom("ibm.ch8"));
+    assert!(compare(&a, &a, &[(0, 0), (50, 1 << 5), (60, 0)], 2000).is_ok());
+}
+
+#[test]
+fn quirk_mismatch_is_reported() {
+    let a = Config::new(&[], &rom("tests/data/shift.ch8"));
+    let mut b = a.clone();
+    assert!(b.quirks.toggle("hp_shift"));
+    let err = compare(&a, &b, &[], 100).unwrap_err();
+    match &err {
+        LockstepError::Diverged(d) => {
+            assert_eq!(d.step, 2);
+            assert_eq!((d.history_a.len(), d.history_b.len()), (2, 2));
+            assert!(d.faults.is_none());
+        }
+        e => panic!("{}", e),
+    }
+    assert_eq!(err.to_string(), REPORT);
+}
+
+#[cfg(feature = "terminal")]
+#[test]
+fn quirk_mismatch_from_the_command_line() {
+    let run = |extra: &[&str]| {
+        std::process::Command::new(env!("CARGO_BIN_EXE_chip8"))
+            .arg("lockstep")
+            .arg(format!(
+                "{}/tests/data/shift.ch8",
+                env!("CARGO_MANIFEST_DIR")
+            ))
+            .arg("100")
+            .args(extra)
+            .output()
+            .unwrap()
+    };
+    let same = run(&[]);
+    assert!(same.status.success());
+    assert_eq!(same.stderr, b"No divergence\n");
+    let flipped = run(&["hp_shift"]);
+    assert_eq!(flipped.status.code(), Some(1));
+    assert_eq!(
+        String::from_utf8(flipped.stderr).unwrap(),
+        format!("Error: {}\n", REPORT)
+    );
+    let unknown = run(&["no_such_quirk"]);
+    assert_eq!(unknown.status.code(), Some(1));
+    assert_eq!(unknown.stderr, b"Error: unknown quirk no_such_quirk\n");
+}
@@ -0,0 +1,245 @@
+use chip8::emu::{Chip8, Quirks};
+use chip8::movie::{replay, FrameAdvance, Header, Movie, MovieError};
+use chip8::renderer::Renderer;
+
+// Counts up in V0 and draws it as a digit, so where a reset lands shows
+const ROM: [u8; 14] = [
+    0x00, 0xE0, // CLS
+    0xF0, 0x29, // LD F, V0
+    0xD1, 0x15, // DRW V1, V1, 5
+    0x70, 0x01, // ADD V0, 1
+    0x40, 0x0A, // SNE V0, 10
+    0x60, 0x00, // LD V0, 0
+    0x12, 0x00, // JP 200
+];
+
+struct Last([u64; 32]);
+
+impl Renderer for Last {
+    fn render(&mut self, _: u64, bits: &[u64; 32], _: u32) -> std::io::Result<()> {
+        self.0 = *bits;
+        Ok(())
+    }
+}
+
+fn record(resets: &[u64], frames: u64) -> (Movie, Chip8) {
+    let header = Header::new(&ROM, Quirks::default(), 3, 0x200);
+    let mut c8 = header.builder(&ROM).build().unwrap();
+    c8.set_journal(64);
+    let mut movie = Movie::new(header).with_rng_trail();
+    let mut fa = FrameAdvance::new();
+    for n in 0..frames {
+        if resets.contains(&n) {
+            c8.reset();
+            movie.push_reset();
+        }
+        fa.set_pending(n as u16);
+        fa.advance(&mut c8, Some(&mut movie)).unwrap();
+    }
+    (movie, c8)
+}
+
+#[test]
+fn resets_replay_where_they_were_recorded() {
+    let (movie, c8) = record(&[7, 20, 33], 40);
+    let bytes = movie.to_bytes();
+    let read = Movie::from_bytes(&bytes).unwrap();
+    assert_eq!(read, movie);
+    for n in 0..45 {
+        assert_eq!(
+            read.reset_before(n),
+            [7, 20, 33].contains(&n),
+            "frame {}",
+            n
+        );
+    }
+
+    let mut again = movie.header().builder(&ROM).build().unwrap();
+    let mut last = Last([0; 32]);
+    replay(&mut again, &read, &mut last, |_| {}).unwrap();
+    assert_eq!(last.0, c8.screen);
+    assert_eq!(again.save_state(), c8.save_state());
+
+    // Left out, the replay ends up somewhere else
+    let (unreset, _) = record(&[], 40);
+    let mut moved = unreset.clone();
+    moved.set_screen(&c8.screen);
+    let mut again = movie.header().builder(&ROM).build().unwrap();
+    let res = replay(&mut again, &moved, &mut Last([0; 32]), |_| {});
+    assert!(matches!(
+        res,
+        Err(chip8::movie::ReplayError::Movie(MovieError::Desync { .. }))
+    ));
+}
+
+#[test]
+fn resets_twice_in_a_row_count_once() {
+    let header = Header::new(&ROM, Quirks::default(), 0, 0x200);
+    let mut movie = Movie::new(header);
+    movie.push(0);
+    movie.push_reset();
+    movie.push_reset();
+    movie.push(0);
+    assert!(movie.reset_before(1));
+    // One run of keys, the count and one frame
+    assert_eq!(
+        movie.to_bytes().len(),
+        Movie::new(header).to_bytes().len() + 6 + 4 + 8
+    );
+}
+
+#[test]
+fn rewinding_keeps_resets_before_whats_kept() {
+    let (mut movie, _) = record(&[5, 10], 15);
+    movie.truncate(10);
+    assert!(movie.reset_before(10));
+    movie.truncate(9);
+    assert!(!movie.reset_before(10));
+    assert!(movie.reset_before(5));
+    movie.truncate(0);
+    assert!(!movie.reset_before(5));
+    // Without resets it's the same as before there were any
+    let (none, _) = record(&[], 15);
+    let (mut cut, _) = record(&[5], 20);
+    cut.truncate(4);
+    let (four, _) = record(&[], 4);
+    let len = four.to_bytes().len();
+    // All but the screen hash and checksum
+    assert_eq!(cut.to_bytes()[..len - 16], four.to_bytes()[..len - 16]);
+    assert!(none.to_bytes()[..len - 16] != four.to_bytes()[..len - 16]);
+}
+
+#[test]
+fn bad_reset_lists_are_corrupt() {
+    let (movie, _) = record(&[3, 6], 10);
+    let bytes = movie.to_bytes();
+    // The resets sit between the RNG trail and the screen hash
+    let at = bytes.len() - 16 - 16;
+    assert_eq!(bytes[at..at + 8], 3u64.to_be_bytes());
+    let fix = |mut b: Vec<u8>| {
+        let body = b.len() - 8;
+        let sum = chip8::state::rom_hash(&b[..body]);
+        b[body..].copy_from_slice(&sum.to_be_bytes());
+        Movie::from_bytes(&b)
+    };
+    assert_eq!(fix(bytes.clone()), Ok(movie));
+    // Out of order
+    let mut bad = bytes.clone();
+    bad[at + 7] = 6;
+    assert_eq!(fix(bad), Err(MovieError::Corrupt));
+    // After the end
+    let mut bad = bytes.clone();
+    bad[at + 15] = 11;
+    assert_eq!(fix(bad), Err(MovieError::Corrupt));
+    // More than there are
+    let mut bad = bytes;
+    bad[at - 1] = 3;
+    assert!(fix(bad).is_err());
+}
+
+fn temp(name: &str) -> std::path::PathBuf {
+    std::env::temp_dir().join(format!("chip8-{}-{}.c8rec", name, std::process::id()))
+}
+
+#[test]
+fn round_trip_through_a_file() {
+    let (movie, _) = record(&[9], 30);
+    let path = temp("round-trip");
+    std::fs::write(&path, movie.to_bytes()).unwrap();
+    let read = Movie::from_bytes(&std::fs::read(&path).unwrap());
+    std::fs::remove_file(&path).unwrap();
+    let read = read.unwrap();
+    assert_eq!(read, movie);
+    assert_eq!(read.to_bytes(), movie.to_bytes());
+    assert_eq!(read.check_rom(&ROM), Ok(()));
+    assert_eq!(read.rng_trail().map(<[u16]>::len), Some(30));
+}
+
+#[test]
+fn malformed_files_are_turned_down() {
+    let (movie, _) = record(&[9], 30);
+    let bytes = movie.to_bytes();
+    assert_eq!(Movie::from_bytes(b""), Err(MovieError::Truncated));
+    assert_eq!(Movie::from_bytes(b"PK\x03\x04"), Err(MovieError::BadMagic));
+    for len in 0..bytes.len() {
+        assert!(Movie::from_bytes(&bytes[..len]).is_err(), "{} bytes", len);
+    }
+    // Anything after the checksum
+    let mut long = bytes.clone();
+    long.push(0);
+    assert_eq!(Movie::from_bytes(&long), Err(MovieError::Corrupt));
+    // Any byte changed past the version
+    for n in 6..bytes.len() {
+        let mut bad = bytes.clone();
+        bad[n] ^= 0x40;
+        assert!(Movie::from_bytes(&bad).is_err(), "byte {}", n);
+    }
+    // A header too short for the fields 1.0 had
+    let mut short = bytes.clone();
+    short[6..8].copy_from_slice(&10u16.to_be_bytes());
+    assert_eq!(Movie::from_bytes(&short), Err(MovieError::Corrupt));
+}
+
+#[cfg(feature = "terminal")]
+mod command_line {
+    use super::temp;
+    use chip8::movie::Movie;
+    use std::process::{Command, Output, Stdio};
+
+    fn run(args: &[&str]) -> Output {
+        Command::new(env!("CARGO_BIN_EXE_chip8"))
+            .arg(concat!(env!("CARGO_MANIFEST_DIR"), "/ibm.ch8"))
+            .args(["--headless", "--json"])
+            .args(args)
+            .stdin(Stdio::null())
+            .output()
+            .unwrap()
+    }
+
+    fn screen_hash(out: &Output) -> String {
+        let json = String::from_utf8(out.stdout.clone()).unwrap();
+        let at = json.find("\"screen_hash\":\"").unwrap() + 15;
+        json[at..at + 16].to_string()
+    }
+
+    #[test]
+    fn recorded_then_played() {
+        let path = temp("recorded");
+        let file = path.to_str().unwrap();
+        let recorded = run(&["--max-frames", "40", "--record-input", file]);
+        assert!(recorded.status.success());
+        let movie = Movie::from_bytes(&std::fs::read(&path).unwrap()).unwrap();
+        assert_eq!(movie.len(), 40);
+        let ibm = std::fs::read(concat!(env!("CARGO_MANIFEST_DIR"), "/ibm.ch8")).unwrap();
+        assert_eq!(movie.check_rom(&ibm), Ok(()));
+        assert!(movie.verify(&[0; 32]).is_err());
+
+        let played = run(&["--max-frames", "40", "--play-input", file]);
+        std::fs::remove_file(&path).unwrap();
+        assert!(played.status.success());
+        assert_eq!(screen_hash(&played), screen_hash(&recorded));
+        assert!(String::from_utf8_lossy(&played.stderr).contains("REPLAY MATCHED"));
+    }
+
+    #[test]
+    fn malformed_files_are_json_errors() {
+        let path = temp("malformed");
+        let file = path.to_str().unwrap();
+        for (bytes, error) in [
+            (&b""[..], "movie is truncated"),
+            (b"C8MV\x01", "movie is truncated"),
+            (b"GIF89a", "not a movie"),
+            (b"C8MV\x09\x00", "unsupported movie version 9"),
+        ] {
+            std::fs::write(&path, bytes).unwrap();
+            let out = run(&["--play-input", file]);
+            assert_eq!(out.status.code(), Some(1));
+            assert!(out.stdout.is_empty());
+            assert_eq!(
+                String::from_utf8(out.stderr).unwrap(),
+                format!("{{\"error\":\"{}\"}}\n", error)
+            );
+        }
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,165 @@
+use chip8::emu::{Chip8, LoadStoreIncrement, Quirks};
+use chip8::octo::{assemble, Cart, OctoError, Options};
+use chip8::recorder::Recorder;
+use chip8::rom::{parse_hex, RomError, RomSource};
+use chip8::theme::Theme;
+use std::path::PathBuf;
+
+fn data(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/data")
+        .join(name)
+}
+
+fn read(name: &str) -> Vec<u8> {
+    std::fs::read(data(name)).unwrap()
+}
+
+// The ROM cart.8o should assemble to, written out by hand
+fn expected() -> Vec<u8> {
+    parse_hex(&String::from_utf8(read("cart.hex")).unwrap()).unwrap()
+}
+
+// A GIF with `payload` hidden in it the way Octo does, with the pixels'
+// other bits left 0
+fn cart(payload: &[u8]) -> Vec<u8> {
+    let mut bytes = (payload.len() as u32).to_be_bytes().to_vec();
+    bytes.extend_from_slice(payload);
+    let mut pixels: Vec<u8> = bytes
+        .iter()
+        .flat_map(|b| [b >> 6, b >> 4, b >> 2, *b].map(|p| p & 3))
+        .collect();
+    let height = pixels.len() / 64 + 1;
+    pixels.resize(64 * height, 0);
+    let mut gif = Recorder::new(Vec::new(), 64, height, 1, 60, &Theme::default()).unwrap();
+    gif.frame(&pixels).unwrap();
+    gif.finish().unwrap()
+}
+
+#[test]
+fn reads_cart() {
+    let cart = Cart::read(&read("cart.gif")).unwrap();
+    assert_eq!(cart.program, String::from_utf8(read("cart.8o")).unwrap());
+    let o = cart.options;
+    assert_eq!(o.tickrate, 15);
+    assert!(o.shift && o.load_store && o.clip);
+    assert_eq!(
+        o.theme,
+        Theme::parse("#101010,#00ff00,#ff00ff,#0000ff").unwrap()
+    );
+}
+
+#[test]
+fn assembles_cart() {
+    let src = String::from_utf8(read("cart.8o")).unwrap();
+    assert_eq!(assemble(&src).unwrap(), expected());
+}
+
+#[test]
+fn loads_cart_as_a_rom() {
+    let rom = RomSource::parse(data("cart.gif").to_str().unwrap())
+        .load()
+        .unwrap();
+    assert_eq!(rom.bytes, expected());
+    let quirks = rom.options.unwrap().quirks(Quirks::default());
+    assert_eq!(
+        quirks,
+        Quirks {
+            clip_x: true,
+            clip_y: true,
+            hp_shift: true,
+            mem_inc: LoadStoreIncrement::None,
+            key_release: false,
+        }
+    );
+
+    // The shift quirk it came with shifts V3 itself: 0x81 >> 1
+    let mut c8 = Chip8::builder()
+        .rom(&rom.bytes)
+        .quirks(quirks)
+        .build()
+        .unwrap();
+    c8.run_n(12).unwrap();
+    assert_eq!(c8.v()[3], 0x40);
+    assert_eq!(c8.pc(), 0x21C);
+}
+
+#[test]
+fn options_left_out_are_octos() {
+    let gif = cart(br#"{"program": ": main jump main", "options": {"clipQuirks": true}}"#);
+    let c = Cart::read(&gif).unwrap();
+    assert_eq!(assemble(&c.program).unwrap(), [0x12, 0x02, 0x12, 0x02]);
+    assert_eq!(
+        c.options,
+        Options {
+            clip: true,
+            ..Options::default()
+        }
+    );
+    // Without shiftQuirks, Vy is shifted into Vx
+    let q = c.options.quirks(Quirks::default());
+    assert!(!q.hp_shift && q.clip_x);
+    assert_eq!(q.mem_inc, LoadStoreIncrement::XPlusOne);
+
+    let bare = cart(br#"{"program": ": main"}"#);
+    assert_eq!(Cart::read(&bare).unwrap().options, Options::default());
+}
+
+#[test]
+fn broken_carts() {
+    let err = |gif: &[u8]| Cart::read(gif).unwrap_err();
+    assert_eq!(err(b"GIF89a"), OctoError::Gif("truncated"));
+    assert_eq!(err(b"PNG"), OctoError::Gif("no GIF header"));
+    let mut cut = read("cart.gif");
+    cut.truncate(cut.len() / 2);
+    assert_eq!(err(&cut), OctoError::Gif("truncated"));
+    // A picture with nothing hidden in it
+    let blank = Recorder::new(Vec::new(), 8, 8, 1, 60, &Theme::default())
+        .and_then(|mut r| r.frame(&[0; 64]).and_then(|_| r.finish()))
+        .unwrap();
+    assert!(matches!(err(&blank), OctoError::Payload(_)));
+    assert_eq!(
+        err(&cart(br#"{"options": {}}"#)),
+        OctoError::Payload("no program".to_string())
+    );
+    assert_eq!(
+        err(&cart(b"{\"program\": ")),
+        OctoError::Payload("bad JSON at byte 12".to_string())
+    );
+
+    // Source that doesn't assemble fails loading with the line
+    let path = std::env::temp_dir().join(format!("chip8-cart-{}.gif", std::process::id()));
+    std::fs::write(&path, cart(br#"{"program": ": main\n  v0 := 1\n  hires"}"#)).unwrap();
+    let res = RomSource::File(path.clone()).resolve();
+    std::fs::remove_file(&path).unwrap();
+    match res {
+        Err(RomError::OctoCart(e)) => {
+            assert_eq!(e.to_string(), "Octo source line 3: hires isn't supported")
+        }
+        r => panic!("{:?}", r),
+    }
+}
+
+#[test]
+fn hex_files() {
+    let rom = RomSource::parse(data("cart.hex").to_str().unwrap())
+        .load()
+        .unwrap();
+    assert_eq!(rom.bytes[..6], [0x12, 0x06, 0xF0, 0x90, 0x90, 0xF0]);
+    assert_eq!(rom.bytes.len(), 0x30);
+    assert_eq!(rom.options, None);
+
+    assert_eq!(
+        parse_hex("0x00e0 # clear\n\n  A2 2a").unwrap(),
+        [0, 0xE0, 0xA2, 0x2A]
+    );
+    let err = |text| parse_hex(text).unwrap_err().to_string();
+    assert_eq!(err("00E0\n123"), "line 2: 123 has an odd number of digits");
+    assert_eq!(err("# fine\nzz"), "line 2: zz isn't hex");
+    // Nothing but comments is an empty ROM
+    let path = std::env::temp_dir().join(format!("chip8-empty-{}.hex", std::process::id()));
+    std::fs::write(&path, "# nothing\n").unwrap();
+    let res = RomSource::File(path.clone()).resolve();
+    std::fs::remove_file(&path).unwrap();
+    assert!(matches!(res, Err(RomError::Empty)));
+}
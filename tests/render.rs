@@ -0,0 +1,57 @@
+use chip8::phosphor::Phosphor;
+use chip8::text::{render, render_levels, RenderStyle};
+use chip8::theme::Theme;
+
+// Corners, a diagonal and a block starting on an odd row and column, so
+// every style has cells only partly lit
+fn fixture() -> [u64; 32] {
+    let mut bits = [0u64; 32];
+    let mut set = |x: usize, y: usize| bits[y] |= 1 << (63 - x);
+    for (x, y) in [(0, 0), (63, 0), (0, 31), (63, 31)] {
+        set(x, y);
+    }
+    for y in 0..32 {
+        set(y + 16, y);
+    }
+    for y in 3..8 {
+        for x in 5..10 {
+            set(x, y);
+        }
+    }
+    bits
+}
+
+// Against the file of that name in tests/data/render
+fn check(name: &str, got: String) {
+    let path = format!("{}/tests/data/render/{}", env!("CARGO_MANIFEST_DIR"), name);
+    assert_eq!(got, std::fs::read_to_string(path).unwrap(), "{}", name);
+}
+
+#[test]
+fn plain_styles() {
+    check("full.txt", render(&fixture(), RenderStyle::Full));
+    check("half.txt", render(&fixture(), RenderStyle::Half));
+    check("braille.txt", render(&fixture(), RenderStyle::Braille));
+}
+
+#[test]
+fn fading_styles() {
+    // The fixture then the fixture moved right a pixel, leaving its left
+    // edges fading
+    let mut p = Phosphor::new(2);
+    p.update(&fixture());
+    p.update(&fixture().map(|row| row >> 1));
+    let theme = Theme::default();
+    check(
+        "full.levels.txt",
+        render_levels(&p, RenderStyle::Full, &theme),
+    );
+    check(
+        "half.levels.txt",
+        render_levels(&p, RenderStyle::Half, &theme),
+    );
+    check(
+        "braille.levels.txt",
+        render_levels(&p, RenderStyle::Braille, &theme),
+    );
+}
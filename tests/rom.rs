@@ -0,0 +1,212 @@
+use chip8::inflate::inflate;
+use chip8::rom::{RomError, RomSource, Zip};
+use std::path::PathBuf;
+
+fn data(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/data")
+        .join(name)
+}
+
+fn root(name: &str) -> Vec<u8> {
+    std::fs::read(PathBuf::from(env!("CARGO_MANIFEST_DIR")).join(name)).unwrap()
+}
+
+#[test]
+fn inflate_fixed_and_dynamic_huffman() {
+    // BC_test.ch8 squeezed by zlib with each kind of block
+    let bc = root("BC_test.ch8");
+    for name in ["bc_test.fixed.deflate", "bc_test.dynamic.deflate"] {
+        let packed = std::fs::read(data(name)).unwrap();
+        assert_eq!(inflate(&packed, 4096).unwrap(), bc, "{}", name);
+    }
+}
+
+#[test]
+fn inflate_stored_blocks() {
+    // A stored block of 3 bytes followed by a last, empty one
+    let packed = [0, 3, 0, 0xFC, 0xFF, 1, 2, 3, 1, 0, 0, 0xFF, 0xFF];
+    assert_eq!(inflate(&packed, 16).unwrap(), [1, 2, 3]);
+}
+
+#[test]
+fn inflate_rejects_bad_streams() {
+    let packed = std::fs::read(data("bc_test.dynamic.deflate")).unwrap();
+    assert_eq!(
+        inflate(&packed[..packed.len() / 2], 4096),
+        Err("compressed data is truncated")
+    );
+    assert_eq!(inflate(&packed, 100), Err("decompressed data is too large"));
+    // Block type 3 doesn't exist
+    assert_eq!(inflate(&[0x07], 16), Err("bad block type"));
+    // LEN and NLEN disagree
+    assert_eq!(
+        inflate(&[1, 3, 0, 0, 0, 1, 2, 3], 16),
+        Err("stored block length is corrupt")
+    );
+    assert_eq!(
+        inflate(&[1, 3, 0, 0xFC, 0xFF, 1], 16),
+        Err("stored block is truncated")
+    );
+}
+
+#[test]
+fn zip_stored_entry() {
+    let zip = Zip::open(&data("stored.zip")).unwrap();
+    let names: Vec<_> = zip.entries.iter().map(|e| e.name.as_str()).collect();
+    assert_eq!(names, ["readme.txt", "ibm.ch8"]);
+    assert_eq!(zip.read("readme.txt").unwrap(), b"IBM logo\n");
+    // The only ROM inside is picked without being named
+    let rom = RomSource::parse(data("stored.zip").to_str().unwrap());
+    assert_eq!(rom.resolve().unwrap(), root("ibm.ch8"));
+}
+
+#[test]
+fn zip_deflated_entry() {
+    let zip = Zip::open(&data("deflated.zip")).unwrap();
+    assert_eq!(zip.entries[0].size, 470);
+    assert_eq!(zip.read("BC_test.ch8").unwrap(), root("BC_test.ch8"));
+}
+
+#[test]
+fn zip_with_several_roms() {
+    let path = data("two.zip");
+    let err = RomSource::Zip(path.clone(), None).resolve().unwrap_err();
+    assert_eq!(err.to_string(), "bad zip archive: more than one ROM inside");
+    let maze = RomSource::Zip(path, Some("maze.ch8".to_string()));
+    assert_eq!(maze.resolve().unwrap(), root("maze.ch8"));
+    assert_eq!(maze.name(), "maze");
+}
+
+#[test]
+fn corrupt_zip() {
+    let zip = std::fs::read(data("deflated.zip")).unwrap();
+    let err = |data: Vec<u8>| match Zip::parse(data) {
+        Err(RomError::Zip(e)) => e,
+        Err(e) => panic!("{}", e),
+        Ok(_) => panic!("parsed"),
+    };
+    assert_eq!(
+        err(b"not a zip at all, just some text".to_vec()),
+        "no central directory"
+    );
+    // The central directory said to be past the end
+    let mut past = zip.clone();
+    let eocd = zip.len() - 22;
+    past[eocd + 16..eocd + 20].copy_from_slice(&0xFFFFu32.to_le_bytes());
+    assert_eq!(err(past), "truncated");
+    // Or somewhere that isn't one
+    let mut moved = zip.clone();
+    moved[eocd + 16..eocd + 20].copy_from_slice(&[0; 4]);
+    assert_eq!(err(moved), "corrupt central directory");
+
+    // The directory is fine but the entry it points to isn't
+    let mut broken = zip.clone();
+    broken[0] = b'X';
+    let parsed = Zip::parse(broken).unwrap();
+    assert!(matches!(
+        parsed.read("BC_test.ch8"),
+        Err(RomError::Zip(e)) if e == "corrupt local header"
+    ));
+    let mut garbled = zip;
+    // Flips bits in the middle of the compressed data
+    for b in &mut garbled[60..120] {
+        *b ^= 0x55;
+    }
+    assert!(Zip::parse(garbled).unwrap().read("BC_test.ch8").is_err());
+}
+
+#[test]
+fn source_kinds() {
+    assert_eq!(RomSource::parse("-"), RomSource::Stdin);
+    assert_eq!(RomSource::parse("-").name(), "stdin");
+    assert_eq!(
+        RomSource::parse("http://example.com/roms/pong.ch8").name(),
+        "pong"
+    );
+    assert!(matches!(
+        RomSource::parse("pack.ZIP"),
+        RomSource::Zip(_, None)
+    ));
+    assert!(matches!(RomSource::parse("pong.ch8"), RomSource::File(_)));
+}
+
+#[cfg(feature = "terminal")]
+mod stdin {
+    use std::io::Write;
+    use std::process::{Command, Output, Stdio};
+
+    // Runs `chip8 -` headless for a few frames with `rom` piped in
+    fn run(rom: &[u8]) -> Output {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_chip8"))
+            .args(["-", "--headless", "--max-frames", "30"])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+        child.stdin.take().unwrap().write_all(rom).unwrap();
+        child.wait_with_output().unwrap()
+    }
+
+    #[test]
+    fn rom_from_stdin() {
+        let file = Command::new(env!("CARGO_BIN_EXE_chip8"))
+            .arg(format!("{}/ibm.ch8", env!("CARGO_MANIFEST_DIR")))
+            .args(["--headless", "--max-frames", "30"])
+            .stdin(Stdio::null())
+            .output()
+            .unwrap();
+        let piped = run(&super::root("ibm.ch8"));
+        assert!(piped.status.success());
+        assert_eq!(piped.stdout, file.stdout);
+    }
+
+    #[test]
+    fn empty_stdin() {
+        let out = run(&[]);
+        assert_eq!(out.status.code(), Some(1));
+        assert!(String::from_utf8_lossy(&out.stderr).contains("ROM is empty"));
+    }
+}
+
+#[cfg(feature = "http")]
+mod http {
+    use chip8::rom::RomSource;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+
+    // Answers one request with `response`, returning the URL to fetch
+    fn serve(response: &'static [u8]) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/roms/pong.ch8", listener.local_addr().unwrap());
+        std::thread::spawn(move || {
+            let (mut s, _) = listener.accept().unwrap();
+            // All of the request is read first, or closing with some of it
+            // unread resets the connection before the response gets there
+            let mut req = Vec::new();
+            let mut buf = [0; 1024];
+            while !req.ends_with(b"\r\n\r\n") {
+                match s.read(&mut buf) {
+                    Ok(0) | Err(_) => break,
+                    Ok(n) => req.extend_from_slice(&buf[..n]),
+                }
+            }
+            s.write_all(response).unwrap();
+        });
+        url
+    }
+
+    #[test]
+    fn download() {
+        let url = serve(b"HTTP/1.0 200 OK\r\nContent-Length: 2\r\n\r\n\x12\x00");
+        assert_eq!(RomSource::parse(&url).resolve().unwrap(), [0x12, 0x00]);
+    }
+
+    #[test]
+    fn not_found() {
+        let url = serve(b"HTTP/1.0 404 Not Found\r\n\r\n");
+        let err = RomSource::parse(&url).resolve().unwrap_err();
+        assert_eq!(err.to_string(), "download failed: HTTP/1.0 404 Not Found");
+    }
+}
@@ -0,0 +1,161 @@
+use chip8::framebuffer::{Framebuffer, Indexed};
+use chip8::inflate::inflate;
+use chip8::screenshot;
+use chip8::theme::Theme;
+
+// Worked out bit by bit rather than with the encoder's own table
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &b in data {
+        for n in 0..8 {
+            let bit = (crc ^ (b as u32 >> n)) & 1;
+            crc = (crc >> 1) ^ if bit == 1 { 0xEDB8_8320 } else { 0 };
+        }
+    }
+    !crc
+}
+
+fn be32(d: &[u8], at: usize) -> u32 {
+    u32::from_be_bytes([d[at], d[at + 1], d[at + 2], d[at + 3]])
+}
+
+struct Png {
+    width: usize,
+    height: usize,
+    palette: Vec<u8>,
+    // Palette index of every pixel, row by row
+    pixels: Vec<u8>,
+}
+
+// Reads the indexed, unfiltered PNGs screenshots are written as, checking
+// the signature and every chunk's CRC
+fn decode(png: &[u8]) -> Png {
+    assert_eq!(
+        png[..8],
+        [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1A, b'\n']
+    );
+    let mut at = 8;
+    let (mut ihdr, mut palette, mut idat) = (Vec::new(), Vec::new(), Vec::new());
+    let mut kinds = Vec::new();
+    while at < png.len() {
+        let len = be32(png, at) as usize;
+        let body = &png[at + 4..at + 8 + len];
+        let crc = be32(png, at + 8 + len);
+        assert_eq!(crc, crc32(body), "CRC of {:?}", &body[..4]);
+        let (kind, data) = body.split_at(4);
+        match kind {
+            b"IHDR" => ihdr = data.to_vec(),
+            b"PLTE" => palette = data.to_vec(),
+            b"IDAT" => idat.extend_from_slice(data),
+            _ => {}
+        }
+        kinds.push(String::from_utf8_lossy(kind).into_owned());
+        at += 12 + len;
+    }
+    assert_eq!(kinds.first().map(String::as_str), Some("IHDR"));
+    assert_eq!(kinds.last().map(String::as_str), Some("IEND"));
+    let width = be32(&ihdr, 0) as usize;
+    let height = be32(&ihdr, 4) as usize;
+    // 8 bits, indexed
+    assert_eq!(ihdr[8..], [8, 3, 0, 0, 0]);
+
+    // zlib header, deflate data, then the Adler-32 of what it inflates to
+    assert_eq!(idat[0] & 0x0F, 8);
+    assert_eq!(u16::from_be_bytes([idat[0], idat[1]]) % 31, 0);
+    let raw = inflate(&idat[2..idat.len() - 4], 1 << 20).unwrap();
+    let (mut a, mut b) = (1u32, 0u32);
+    for &d in &raw {
+        a = (a + d as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    assert_eq!(idat[idat.len() - 4..], ((b << 16) | a).to_be_bytes());
+
+    assert_eq!(raw.len(), (width + 1) * height);
+    let mut pixels = Vec::new();
+    for row in raw.chunks(width + 1) {
+        // No filter
+        assert_eq!(row[0], 0);
+        pixels.extend_from_slice(&row[1..]);
+    }
+    Png {
+        width,
+        height,
+        palette,
+        pixels,
+    }
+}
+
+#[test]
+fn lores_png() {
+    let mut screen = [0u64; 32];
+    screen[0] = 1 << 63;
+    screen[31] = 1;
+    screen[10] = 0xF0 << 32;
+    let theme = Theme::parse("#000,#0f0,#f00,#ff0").unwrap();
+    let png = decode(&screenshot::encode_png(
+        64,
+        32,
+        3,
+        &screenshot::pixels(&screen),
+        &theme,
+    ));
+    assert_eq!((png.width, png.height), (192, 96));
+    assert_eq!(png.palette, theme.colors.concat());
+    let at = |x: usize, y: usize| png.pixels[y * png.width + x];
+    // Each pixel is 3x3
+    for (x, y) in [(0, 0), (2, 2), (191, 93), (189, 95), (72, 30), (83, 32)] {
+        assert_eq!(at(x, y), 1, "({}, {})", x, y);
+    }
+    for (x, y) in [(3, 0), (0, 3), (188, 95), (71, 30), (84, 30), (100, 50)] {
+        assert_eq!(at(x, y), 0, "({}, {})", x, y);
+    }
+    assert_eq!(png.pixels.iter().filter(|&&p| p == 1).count(), 6 * 9);
+}
+
+#[test]
+fn hires_four_color_png() {
+    let mut screen = Indexed::new(128, 64, 2);
+    screen.set(0, 0, 1);
+    screen.set(127, 0, 2);
+    screen.set(64, 32, 3);
+    screen.set(127, 63, 1);
+    let theme = Theme::default();
+    let png = decode(&screenshot::encode_png(
+        128,
+        64,
+        1,
+        &screen.indices(),
+        &theme,
+    ));
+    assert_eq!((png.width, png.height), (128, 64));
+    assert_eq!(png.pixels, screen.indices());
+    let at = |x: usize, y: usize| png.pixels[y * png.width + x];
+    assert_eq!(
+        [at(0, 0), at(127, 0), at(64, 32), at(127, 63), at(1, 1)],
+        [1, 2, 3, 1, 0]
+    );
+    assert_eq!(png.palette[9..], [0x55, 0x55, 0x55]);
+}
+
+#[test]
+fn save_by_extension_and_numbering() {
+    let dir = std::env::temp_dir().join(format!("chip8-shots-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir(&dir).unwrap();
+    let mut screen = [0u64; 32];
+    screen[5] = 1 << 60;
+    let theme = Theme::default();
+    let first = screenshot::save(&dir, &screen, 2, &theme).unwrap();
+    let second = screenshot::save(&dir, &screen, 2, &theme).unwrap();
+    assert_eq!(first, dir.join("shot-0001.png"));
+    assert_eq!(second, dir.join("shot-0002.png"));
+    let png = decode(&std::fs::read(&first).unwrap());
+    assert_eq!((png.width, png.height), (128, 64));
+    assert_eq!(png.pixels[10 * 128 + 6], 1);
+
+    let txt = screenshot::save(&dir.join("shot.txt"), &screen, 2, &theme).unwrap();
+    let text = std::fs::read_to_string(txt).unwrap();
+    assert!(!text.starts_with("\u{89}PNG"));
+    assert_eq!(text, chip8::text::to_text(&screen));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
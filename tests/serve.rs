@@ -0,0 +1,427 @@
+use chip8::serve::{decode_frame, encode_frame, Command, FRAME_LEN};
+
+fn screen() -> [u64; 32] {
+    let mut screen = [0u64; 32];
+    for (n, row) in screen.iter_mut().enumerate() {
+        *row = 0x0123_4567_89AB_CDEFu64.rotate_left(n as u32 * 3) ^ n as u64;
+    }
+    screen
+}
+
+#[test]
+fn frames_round_trip() {
+    let screen = screen();
+    let msg = encode_frame(0x0102_0304_0506_0708, &screen);
+    assert_eq!(msg.len(), FRAME_LEN);
+    assert_eq!(msg[..9], [0x01, 1, 2, 3, 4, 5, 6, 7, 8]);
+    assert_eq!(msg[9..17], screen[0].to_be_bytes());
+    assert_eq!(msg[FRAME_LEN - 8..], screen[31].to_be_bytes());
+    assert_eq!(decode_frame(&msg), Some((0x0102_0304_0506_0708, screen)));
+    assert_eq!(decode_frame(&encode_frame(0, &[0; 32])), Some((0, [0; 32])));
+}
+
+#[test]
+fn truncated_and_oversized_frames_are_turned_down() {
+    let msg = encode_frame(7, &screen());
+    for len in 0..msg.len() {
+        assert_eq!(decode_frame(&msg[..len]), None, "{} bytes", len);
+    }
+    let mut long = msg.clone();
+    long.push(0);
+    assert_eq!(decode_frame(&long), None);
+    let mut twice = msg.clone();
+    twice.extend_from_slice(&msg);
+    assert_eq!(decode_frame(&twice), None);
+    // Right length, wrong tag
+    for tag in [0x00, 0x02, 0x10, 0xFF] {
+        let mut bad = msg.clone();
+        bad[0] = tag;
+        assert_eq!(decode_frame(&bad), None, "tag {:02X}", tag);
+    }
+}
+
+#[test]
+fn commands_round_trip() {
+    let mut all = vec![Command::Pause, Command::Reset];
+    for k in 0..16 {
+        all.extend([Command::KeyDown(k), Command::KeyUp(k)]);
+    }
+    all.extend((0..=255).map(Command::Load));
+    for cmd in all {
+        assert_eq!(Command::decode(&cmd.encode()), Some(cmd));
+    }
+    assert_eq!(Command::KeyDown(0xA).encode(), [0x10, 0xA]);
+    assert_eq!(Command::KeyUp(0x3).encode(), [0x11, 0x3]);
+    assert_eq!(Command::Pause.encode(), [0x20]);
+    assert_eq!(Command::Reset.encode(), [0x21]);
+    assert_eq!(Command::Load(9).encode(), [0x22, 9]);
+}
+
+#[test]
+fn malformed_commands_are_turned_down() {
+    for msg in [
+        &[][..],
+        // Truncated
+        &[0x10],
+        &[0x11],
+        &[0x22],
+        // Oversized
+        &[0x10, 1, 0],
+        &[0x11, 1, 0],
+        &[0x20, 0],
+        &[0x21, 0x21],
+        &[0x22, 1, 2],
+        // No such key
+        &[0x10, 16],
+        &[0x11, 0xFF],
+        // Unknown
+        &[0x00],
+        &[0x01, 0],
+        &[0x23, 0],
+        &[0xFF],
+    ] {
+        assert_eq!(Command::decode(msg), None, "{:02X?}", msg);
+    }
+}
+
+#[cfg(feature = "serve")]
+mod socket {
+    use chip8::serve::{decode_frame, Command, Server, FRAME_LEN};
+    use std::io::{Read, Write};
+    use std::net::TcpStream;
+    use std::time::{Duration, Instant};
+
+    fn connect(server: &Server) -> TcpStream {
+        let stream = TcpStream::connect(server.local_addr()).unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        stream
+    }
+
+    fn read_head(stream: &mut TcpStream) -> String {
+        let mut head = Vec::new();
+        let mut b = [0u8];
+        while !head.ends_with(b"\r\n\r\n") {
+            assert_eq!(stream.read(&mut b).unwrap(), 1);
+            head.push(b[0]);
+        }
+        String::from_utf8(head).unwrap()
+    }
+
+    // The key and answer from RFC 6455
+    fn handshake(server: &Server) -> TcpStream {
+        let mut stream = connect(server);
+        stream
+            .write_all(
+                b"GET /ws HTTP/1.1\r\nHost: localhost\r\nUpgrade: websocket\r\n\
+                  Connection: Upgrade\r\nsec-websocket-key: dGhlIHNhbXBsZSBub25jZQ==\r\n\
+                  Sec-WebSocket-Version: 13\r\n\r\n",
+            )
+            .unwrap();
+        let head = read_head(&mut stream);
+        assert!(
+            head.starts_with("HTTP/1.1 101 Switching Protocols\r\n"),
+            "{}",
+            head
+        );
+        assert!(head.contains("Sec-WebSocket-Accept: s3pPLMBiTxaQ9kYGzzhZRbK+xOo=\r\n"));
+        // The client is listed just after the answer goes out
+        std::thread::sleep(Duration::from_millis(100));
+        stream
+    }
+
+    // Clients mask what they send
+    fn send(stream: &mut TcpStream, opcode: u8, payload: &[u8]) {
+        let mask = [0x12, 0x34, 0x56, 0x78];
+        let mut out = vec![0x80 | opcode, 0x80 | payload.len() as u8];
+        out.extend_from_slice(&mask);
+        out.extend(payload.iter().enumerate().map(|(n, b)| b ^ mask[n % 4]));
+        stream.write_all(&out).unwrap();
+    }
+
+    fn wait_for(server: &Server, count: usize) -> Vec<Command> {
+        let start = Instant::now();
+        let mut got = Vec::new();
+        while got.len() < count && start.elapsed() < Duration::from_secs(5) {
+            got.extend(server.commands());
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        got
+    }
+
+    #[test]
+    fn frames_go_out_as_binary_messages() {
+        let mut server = Server::bind("127.0.0.1:0", false).unwrap();
+        let mut stream = handshake(&server);
+        let mut screen = [0u64; 32];
+        screen[5] = 0xF0F0;
+        server.push(42, &screen);
+        // Unmasked, binary, 16 bit length
+        let mut head = [0u8; 4];
+        stream.read_exact(&mut head).unwrap();
+        assert_eq!(head[..2], [0x82, 126]);
+        assert_eq!(u16::from_be_bytes([head[2], head[3]]) as usize, FRAME_LEN);
+        let mut msg = vec![0u8; FRAME_LEN];
+        stream.read_exact(&mut msg).unwrap();
+        assert_eq!(decode_frame(&msg), Some((42, screen)));
+        // Too soon after the last one
+        server.push(43, &screen);
+        stream
+            .set_read_timeout(Some(Duration::from_millis(200)))
+            .unwrap();
+        assert!(stream.read(&mut head).is_err());
+    }
+
+    #[test]
+    fn commands_come_in_and_junk_is_skipped() {
+        let server = Server::bind("127.0.0.1:0", false).unwrap();
+        let mut stream = handshake(&server);
+        send(&mut stream, 0x2, &Command::KeyDown(0xC).encode());
+        // Truncated, oversized and unknown messages, then text and a ping
+        send(&mut stream, 0x2, &[0x10]);
+        send(&mut stream, 0x2, &[0x10, 0xC, 0]);
+        send(&mut stream, 0x2, &[0x7F]);
+        send(&mut stream, 0x1, &[0x21]);
+        send(&mut stream, 0x9, &[0x21]);
+        send(&mut stream, 0x2, &Command::KeyUp(0xC).encode());
+        send(&mut stream, 0x2, &Command::Load(3).encode());
+        assert_eq!(
+            wait_for(&server, 3),
+            [Command::KeyDown(0xC), Command::KeyUp(0xC), Command::Load(3)]
+        );
+        // A close ends it
+        send(&mut stream, 0x8, &[]);
+        assert_eq!(stream.read(&mut [0u8; 16]).unwrap(), 0);
+    }
+
+    #[test]
+    fn oversized_messages_end_the_connection() {
+        let server = Server::bind("127.0.0.1:0", false).unwrap();
+        let mut stream = handshake(&server);
+        // Says 2000 bytes follow, far more than any command
+        stream.write_all(&[0x82, 0x80 | 126, 0x07, 0xD0]).unwrap();
+        assert_eq!(stream.read(&mut [0u8; 16]).unwrap(), 0);
+        assert!(server.commands().next().is_none());
+    }
+
+    #[test]
+    fn truncated_messages_are_not_commands() {
+        let server = Server::bind("127.0.0.1:0", false).unwrap();
+        let mut stream = handshake(&server);
+        // Two payload bytes promised, one sent, then the client goes
+        stream.write_all(&[0x82, 0x82, 0, 0, 0, 0, 0x10]).unwrap();
+        stream.shutdown(std::net::Shutdown::Write).unwrap();
+        assert_eq!(stream.read(&mut [0u8; 16]).unwrap(), 0);
+        assert!(server.commands().next().is_none());
+    }
+
+    #[test]
+    fn plain_requests_get_plain_answers() {
+        let server = Server::bind("127.0.0.1:0", false).unwrap();
+        let mut stream = connect(&server);
+        stream.write_all(b"GET /state HTTP/1.1\r\n\r\n").unwrap();
+        let mut res = String::new();
+        stream.read_to_string(&mut res).unwrap();
+        assert!(
+            res.starts_with("HTTP/1.1 503 Service Unavailable\r\n"),
+            "{}",
+            res
+        );
+        assert!(res.ends_with("\r\n\r\nno frame yet\n"));
+        // A WebSocket path without a key isn't an upgrade
+        let mut stream = connect(&server);
+        stream.write_all(b"GET /ws HTTP/1.1\r\n\r\n").unwrap();
+        let mut res = String::new();
+        stream.read_to_string(&mut res).unwrap();
+        assert!(res.starts_with("HTTP/1.1 404 Not Found\r\n"), "{}", res);
+    }
+}
+
+mod netplay {
+    use chip8::emu::Quirks;
+    use chip8::netplay::{Netplay, NetplayError, Settings};
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+
+    const ROM: u64 = 0xC8C8_0000_1234_5678;
+
+    fn settings() -> Settings {
+        Settings {
+            seed: 0xDEAD_BEEF,
+            quirks: Quirks::default(),
+            delay: 2,
+            check_every: 30,
+        }
+    }
+
+    fn hello(rom: u64, s: Settings) -> Vec<u8> {
+        let mut hello = vec![0x00, 1];
+        hello.extend_from_slice(&rom.to_be_bytes());
+        hello.extend_from_slice(&s.seed.to_be_bytes());
+        hello.push(s.quirks.to_bits());
+        hello.push(s.delay);
+        hello.extend_from_slice(&s.check_every.to_be_bytes());
+        hello
+    }
+
+    // A host that sends `msg` then waits for the other side to hang up
+    fn fake_host(msg: Vec<u8>) -> (String, std::thread::JoinHandle<Vec<u8>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let host = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            stream.write_all(&msg).unwrap();
+            stream.shutdown(std::net::Shutdown::Write).unwrap();
+            let mut reply = Vec::new();
+            let _ = stream.read_to_end(&mut reply);
+            reply
+        });
+        (addr, host)
+    }
+
+    fn join(msg: Vec<u8>) -> (Result<Settings, NetplayError>, Vec<u8>) {
+        let (addr, host) = fake_host(msg);
+        // Dropped here, which hangs up
+        let res = Netplay::join(&addr, ROM).map(|np| np.settings());
+        (res, host.join().unwrap())
+    }
+
+    #[test]
+    fn joining_takes_on_the_hosts_settings() {
+        let (res, reply) = join(hello(ROM, settings()));
+        assert_eq!(res.unwrap(), settings());
+        // Tag, version and ROM hash back
+        assert_eq!(reply[..2], [0x00, 1]);
+        assert_eq!(reply[2..], ROM.to_be_bytes());
+    }
+
+    #[test]
+    fn bad_hellos_are_turned_down() {
+        let (res, _) = join(hello(ROM ^ 1, settings()));
+        assert!(matches!(res, Err(NetplayError::RomMismatch)));
+        let mut bad = hello(ROM, settings());
+        bad[1] = 2;
+        assert!(matches!(join(bad).0, Err(NetplayError::Protocol(_))));
+        let mut bad = hello(ROM, settings());
+        bad[0] = 0x01;
+        assert!(matches!(join(bad).0, Err(NetplayError::Protocol(_))));
+        for (delay, check_every) in [(0, 30), (4, 30), (2, 0)] {
+            let s = Settings {
+                delay,
+                check_every,
+                ..settings()
+            };
+            assert!(matches!(
+                join(hello(ROM, s)).0,
+                Err(NetplayError::Protocol(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn truncated_hellos_are_a_disconnect() {
+        let full = hello(ROM, settings());
+        for len in [0, 1, 10, full.len() - 1] {
+            let (res, reply) = join(full[..len].to_vec());
+            assert!(
+                matches!(res, Err(NetplayError::Disconnected)),
+                "{} bytes",
+                len
+            );
+            assert!(reply.is_empty());
+        }
+    }
+
+    // Joins a host that sends `after` once the hello is done, and runs
+    // until the first frame that needs the host's keys
+    fn third_frame(after: &[u8]) -> Result<u16, NetplayError> {
+        let mut msg = hello(ROM, settings());
+        msg.extend_from_slice(after);
+        let (addr, host) = fake_host(msg);
+        let res = Netplay::join(&addr, ROM).and_then(|mut np| {
+            for _ in 0..2 {
+                np.exchange(0)?;
+                np.end_frame(7)?;
+            }
+            np.exchange(0)
+        });
+        host.join().unwrap();
+        res
+    }
+
+    #[test]
+    fn input_messages_are_parsed() {
+        let input = |frame: u64, keys: u16| {
+            let mut msg = vec![0x01];
+            msg.extend_from_slice(&frame.to_be_bytes());
+            msg.extend_from_slice(&keys.to_be_bytes());
+            msg
+        };
+        let mut hash = vec![0x02];
+        hash.extend_from_slice(&0u64.to_be_bytes());
+        hash.extend_from_slice(&7u64.to_be_bytes());
+        let mut msgs = input(2, 0x8001);
+        assert_eq!(third_frame(&msgs).unwrap(), 0x8001);
+        // Out of order, with the screen hash between
+        msgs = [input(3, 0x0F00), hash.clone(), input(2, 0x00F0)].concat();
+        assert_eq!(third_frame(&msgs).unwrap(), 0x00F0);
+
+        // Truncated, at every length
+        let whole = [hash.clone(), input(2, 0x8001)].concat();
+        for len in 0..whole.len() {
+            let res = third_frame(&whole[..len]);
+            assert!(
+                matches!(res, Err(NetplayError::Disconnected)),
+                "{} bytes",
+                len
+            );
+        }
+        // A different screen
+        hash[16] = 8;
+        assert!(matches!(third_frame(&hash), Err(NetplayError::Desync(0))));
+        let mut unknown = input(2, 0);
+        unknown[0] = 0x03;
+        match third_frame(&unknown) {
+            Err(NetplayError::Protocol(e)) => assert_eq!(e, "unknown message 03"),
+            res => panic!("{:?}", res.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn host_and_join_shake_hands() {
+        // Somewhere free to host on
+        let addr = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .to_string();
+        let at = addr.clone();
+        let host = std::thread::spawn(move || {
+            let mut np = Netplay::host(&at, ROM, settings())?;
+            let mut held = Vec::new();
+            for keys in [0x0001, 0x0002, 0x0004] {
+                held.push(np.exchange(keys)?);
+                np.end_frame(7)?;
+            }
+            Ok::<_, NetplayError>(held)
+        });
+        let mut np = loop {
+            match Netplay::join(&addr, ROM) {
+                Err(NetplayError::Io(_)) => {
+                    std::thread::sleep(std::time::Duration::from_millis(10))
+                }
+                res => break res.unwrap(),
+            }
+        };
+        assert_eq!(np.settings(), settings());
+        // Nothing for the first `delay` frames, then both sides' keys
+        let mut held = Vec::new();
+        for keys in [0x0100, 0x0200, 0x0400] {
+            held.push(np.exchange(keys).unwrap());
+            np.end_frame(7).unwrap();
+        }
+        assert_eq!(held, [0, 0, 0x0101]);
+        assert_eq!(host.join().unwrap().unwrap(), held);
+    }
+}
@@ -0,0 +1,111 @@
+use chip8::emu::Chip8;
+use chip8::state::{ram_ranges, RamRange, SaveState, StateError};
+
+// 64 zero bytes with the given ones set
+fn ram(set: &[usize]) -> Vec<u8> {
+    let mut ram = vec![0; 64];
+    for &n in set {
+        ram[n] = 1;
+    }
+    ram
+}
+
+#[test]
+fn same_ram() {
+    assert_eq!(ram_ranges(&ram(&[]), &ram(&[]), 4), []);
+}
+
+#[test]
+fn adjacent_bytes_are_one_range() {
+    assert_eq!(ram_ranges(&ram(&[]), &ram(&[20, 21, 22]), 0), [(20, 23)]);
+    assert_eq!(ram_ranges(&ram(&[]), &ram(&[20, 21, 22]), 2), [(18, 25)]);
+}
+
+#[test]
+fn overlapping_context_merges() {
+    // 8 apart with 4 either side, so the windows overlap
+    assert_eq!(ram_ranges(&ram(&[]), &ram(&[20, 27]), 4), [(16, 32)]);
+    // Windows that only touch merge too
+    assert_eq!(ram_ranges(&ram(&[]), &ram(&[20, 29]), 4), [(16, 34)]);
+}
+
+#[test]
+fn isolated_bytes_stay_apart() {
+    assert_eq!(
+        ram_ranges(&ram(&[]), &ram(&[10, 30]), 4),
+        [(6, 15), (26, 35)]
+    );
+    assert_eq!(
+        ram_ranges(&ram(&[]), &ram(&[10, 30]), 0),
+        [(10, 11), (30, 31)]
+    );
+}
+
+#[test]
+fn context_stops_at_the_ends() {
+    assert_eq!(ram_ranges(&ram(&[]), &ram(&[1, 62]), 4), [(0, 6), (58, 64)]);
+}
+
+#[test]
+fn longer_ram_is_cut_to_the_shorter() {
+    let mut long = ram(&[5]);
+    long.extend([1; 16]);
+    assert_eq!(ram_ranges(&ram(&[]), &long, 0), [(5, 6)]);
+}
+
+#[test]
+fn diff_shows_ranges_with_both_sides() {
+    let a = Chip8::builder().build().unwrap().save_state();
+    let mut b = a.clone();
+    b.ram[0x300] = 0xAA;
+    b.ram[0x302] = 0xBB;
+    b.ram[0x400] = 0xCC;
+    let diff = a.diff_with_context(&b, 1);
+    assert!(diff.registers.is_empty() && diff.stack.is_empty() && diff.screen.is_none());
+    assert_eq!(
+        diff.ram,
+        [
+            RamRange {
+                start: 0x2FF,
+                a: vec![0; 5],
+                b: vec![0, 0xAA, 0, 0xBB, 0],
+            },
+            RamRange {
+                start: 0x3FF,
+                a: vec![0; 3],
+                b: vec![0, 0xCC, 0],
+            },
+        ]
+    );
+}
+
+#[test]
+fn corrupt_stack_pointers() {
+    let state = SaveState {
+        sp: 40,
+        ..SaveState::default()
+    };
+    assert!(matches!(
+        SaveState::from_bytes(&state.to_bytes()),
+        Err(StateError::StackPointer(40))
+    ));
+    assert_eq!(
+        StateError::StackPointer(40).to_string(),
+        "save state has a stack pointer of 40"
+    );
+    let full = SaveState {
+        sp: 16,
+        ..SaveState::default()
+    };
+    assert_eq!(SaveState::from_bytes(&full.to_bytes()).unwrap(), full);
+
+    // Loaded anyway, it's kept to the stack
+    let mut c8 = Chip8::builder().rom(&[0x00, 0xEE]).build().unwrap();
+    c8.load_state(&SaveState {
+        ram: c8.save_state().ram,
+        ..state
+    });
+    assert_eq!(c8.save_state().sp, 16);
+    c8.step().unwrap();
+    assert_eq!(c8.save_state().sp, 15);
+}
@@ -0,0 +1,86 @@
+#![cfg(feature = "terminal")]
+use std::process::{Command, Output, Stdio};
+
+// A headless JSON run of a ROM under the crate root with `args` added
+fn run(rom: &str, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_chip8"))
+        .arg(format!("{}/{}", env!("CARGO_MANIFEST_DIR"), rom))
+        .args(["--headless", "--json"])
+        .args(args)
+        .stdin(Stdio::null())
+        .output()
+        .unwrap()
+}
+
+// The number after `"key":` in a summary
+fn field(out: &Output, key: &str) -> String {
+    let json = String::from_utf8(out.stdout.clone()).unwrap();
+    let at = json.find(&format!("\"{}\":", key)).unwrap() + key.len() + 3;
+    json[at..].split([',', '}']).next().unwrap().to_string()
+}
+
+#[test]
+fn a_frame_runs_its_whole_budget() {
+    let plain = run("ibm.ch8", &["--max-frames", "100"]);
+    let fast = run(
+        "ibm.ch8",
+        &["--max-frames", "10", "--steps-per-frame", "10"],
+    );
+    // --smc has every step run on its own
+    let careful = run(
+        "ibm.ch8",
+        &["--max-frames", "10", "--steps-per-frame", "10", "--smc"],
+    );
+    for out in [&plain, &fast, &careful] {
+        assert!(out.status.success());
+        assert_eq!(field(out, "screen_hash"), field(&plain, "screen_hash"));
+    }
+    assert_eq!(field(&fast, "frame_count"), "10");
+    assert_eq!(
+        field(&fast, "instruction_count"),
+        field(&careful, "instruction_count")
+    );
+    // ibm.ch8 ends jumping to itself, which stops the frame early
+    let count: u64 = field(&fast, "instruction_count").parse().unwrap();
+    assert!(count > 20 && count < 100, "{}", count);
+}
+
+#[test]
+fn the_key_watchdog_still_goes_off() {
+    // LD V0, 1; LD V1, K; JP 200 with no keys coming
+    for args in [
+        &["--steps-per-frame", "8"][..],
+        &["--steps-per-frame", "8", "--smc"],
+    ] {
+        let out = run(
+            "tests/data/deadlock.ch8",
+            &[&["--max-frames", "200"], args].concat(),
+        );
+        assert_eq!(out.status.code(), Some(3));
+    }
+}
+
+#[test]
+fn steps_per_frame_is_off_for_movies() {
+    let path = std::env::temp_dir().join(format!("chip8-spf-{}.c8rec", std::process::id()));
+    let out = run(
+        "ibm.ch8",
+        &[
+            "--max-frames",
+            "10",
+            "--steps-per-frame",
+            "10",
+            "--record-input",
+            path.to_str().unwrap(),
+        ],
+    );
+    let _ = std::fs::remove_file(&path);
+    assert!(out.status.success());
+    assert_eq!(field(&out, "instruction_count"), "10");
+    let err = String::from_utf8(out.stderr).unwrap();
+    assert!(
+        err.contains("Warning: --steps-per-frame is off with netplay and movies"),
+        "{}",
+        err
+    );
+}
@@ -0,0 +1,79 @@
+#![cfg(feature = "terminal")]
+use chip8::layout::{HIRES_HEIGHT, HIRES_WIDTH, LORES_HEIGHT, LORES_WIDTH};
+use chip8::term::place;
+use chip8::text::RenderStyle::{Braille, Full, Half};
+
+fn lores(
+    cols: usize,
+    rows: usize,
+    reserved: usize,
+) -> Option<(chip8::text::RenderStyle, usize, usize)> {
+    place(cols, rows, reserved, LORES_WIDTH, LORES_HEIGHT)
+}
+
+#[test]
+fn too_small() {
+    // Braille with its border is 34x10
+    assert_eq!(lores(33, 10, 0), None);
+    assert_eq!(lores(34, 9, 0), None);
+    assert_eq!(lores(34, 10, 1), None);
+    assert_eq!(lores(0, 0, 0), None);
+    // The status line taking more rows than there are
+    assert_eq!(lores(80, 2, 3), None);
+}
+
+#[test]
+fn exact_fit() {
+    assert_eq!(lores(34, 10, 0), Some((Braille, 1, 1)));
+    assert_eq!(lores(66, 18, 0), Some((Half, 1, 1)));
+    assert_eq!(lores(66, 34, 0), Some((Full, 1, 1)));
+    assert_eq!(lores(66, 36, 2), Some((Full, 1, 1)));
+    // A column or row short drops to the next style down
+    assert_eq!(lores(65, 34, 0), Some((Braille, 13, 16)));
+    assert_eq!(lores(66, 33, 0), Some((Half, 8, 1)));
+}
+
+#[test]
+fn oversized_is_centred() {
+    assert_eq!(lores(200, 60, 0), Some((Full, 14, 68)));
+    assert_eq!(lores(80, 24, 0), Some((Half, 4, 8)));
+    // The rows kept free come off the bottom
+    assert_eq!(lores(80, 24, 2), Some((Half, 3, 8)));
+    // An odd row or column left over goes after the image
+    assert_eq!(lores(67, 35, 0), Some((Full, 1, 1)));
+}
+
+#[test]
+fn hires() {
+    let hires = |cols, rows| place(cols, rows, 0, HIRES_WIDTH, HIRES_HEIGHT);
+    assert_eq!(hires(130, 66), Some((Full, 1, 1)));
+    assert_eq!(hires(130, 65), Some((Half, 16, 1)));
+    assert_eq!(hires(129, 66), Some((Braille, 25, 32)));
+    assert_eq!(hires(80, 24), Some((Braille, 4, 8)));
+    assert_eq!(hires(65, 18), None);
+    assert_eq!(hires(66, 17), None);
+}
+
+#[test]
+fn restores_once() {
+    use chip8::term;
+    let restored = || {
+        let mut out = Vec::new();
+        term::restore_to(&mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    };
+    term::hide_cursor();
+    term::set_title("chip8");
+    assert_eq!(restored(), format!("{}\x1b[23;0t", term::RESTORE));
+    assert_eq!(restored(), "");
+    term::hide_cursor();
+    assert_eq!(restored(), term::RESTORE);
+    assert_eq!(restored(), "");
+
+    // The hook restores before the panic is reported, leaving nothing
+    // for afterwards
+    term::install_panic_hook();
+    term::hide_cursor();
+    assert!(std::panic::catch_unwind(|| panic!("on purpose")).is_err());
+    assert_eq!(restored(), "");
+}
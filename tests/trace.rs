@@ -0,0 +1,90 @@
+use chip8::trace::{diff, Mismatch};
+
+fn data(name: &str) -> String {
+    std::fs::read_to_string(format!(
+        "{}/tests/data/{}",
+        env!("CARGO_MANIFEST_DIR"),
+        name
+    ))
+    .unwrap()
+}
+
+// ibm.theirs.trace is the first 11 instructions of ibm.ch8 as another
+// emulator might log them, in lower case with 0x and spaces, with V0 one
+// short from the sixth instruction on
+#[test]
+fn fixture_pair_diverges_at_the_sixth_instruction() {
+    let (ours, theirs) = (data("ibm.trace"), data("ibm.theirs.trace"));
+    assert_eq!(
+        diff(&ours, &theirs, 1),
+        [Mismatch::Fields {
+            line: 6,
+            fields: vec!["V".to_string()]
+        }]
+    );
+    let all = diff(&ours, &theirs, 100);
+    assert_eq!(all.len(), 7);
+    assert!(all[..6]
+        .iter()
+        .all(|m| matches!(m, Mismatch::Fields { fields, .. } if fields == &["V"])));
+    assert_eq!(
+        all[6],
+        Mismatch::Ended {
+            line: 12,
+            ours: false
+        }
+    );
+    // Against itself there's nothing, whatever the spacing
+    assert_eq!(diff(&theirs, &theirs.replace(", ", ","), 100), []);
+    assert_eq!(diff(&ours, &ours, 100), []);
+}
+
+// The fixture is what a compact trace of ibm.ch8 gives now
+#[cfg(feature = "terminal")]
+#[test]
+fn fixture_matches_a_fresh_trace() {
+    let path = std::env::temp_dir().join(format!("chip8-trace-{}.log", std::process::id()));
+    let status = std::process::Command::new(env!("CARGO_BIN_EXE_chip8"))
+        .arg(format!("{}/ibm.ch8", env!("CARGO_MANIFEST_DIR")))
+        .args([
+            "--headless",
+            "--max-frames",
+            "12",
+            "--trace-format",
+            "compact",
+            "--trace",
+        ])
+        .arg(&path)
+        .stdin(std::process::Stdio::null())
+        .output()
+        .unwrap()
+        .status;
+    assert!(status.success());
+    let fresh = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(fresh, data("ibm.trace"));
+}
+
+#[cfg(feature = "terminal")]
+#[test]
+fn trace_diff_prints_the_divergence() {
+    let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/data/");
+    let out = std::process::Command::new(env!("CARGO_BIN_EXE_chip8"))
+        .arg("trace-diff")
+        .arg(format!("{}ibm.trace", dir))
+        .arg(format!("{}ibm.theirs.trace", dir))
+        .arg("1")
+        .output()
+        .unwrap();
+    assert!(out.status.success());
+    let ours = data("ibm.trace");
+    let theirs = data("ibm.theirs.trace");
+    let (ours, theirs): (Vec<_>, Vec<_>) = (ours.lines().collect(), theirs.lines().collect());
+    assert_eq!(
+        String::from_utf8(out.stdout).unwrap(),
+        format!(
+            "instruction 6, differs in V\n  before: {}\n  ours:   {}\n  theirs: {}\n",
+            ours[4], ours[5], theirs[5]
+        )
+    );
+}